@@ -0,0 +1,74 @@
+//! Shortening throughput, run with `cargo bench --features bench`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use itertools::Itertools;
+use shorten::abbrev::Abbreviator;
+use shorten::shortener::Shortener;
+use std::hint::black_box;
+
+const TITLES: &str = include_str!("fixtures/titles.txt");
+const REGEX_RULES: &str = include_str!("fixtures/regex.lst");
+const MAX_LENGTH: usize = 30;
+
+/// `count` exact rules: the words of the titles first, so that rules fire,
+/// then made-up terms that never match.
+fn exact_rules(count: usize) -> Vec<String> {
+    let words = TITLES
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|word| word.chars().count() > 4)
+        .unique()
+        .map(|word| format!("{word} = {}", word.chars().take(4).collect::<String>()));
+    let filler = (0..).map(|index| format!("Term{index} = t{index}"));
+    words.chain(filler).take(count).collect()
+}
+
+fn shortener(rules: impl Iterator<Item = impl AsRef<str>>) -> Shortener {
+    let rules = rules.collect_vec();
+    let abbreviator = Abbreviator::from_lines(rules.iter().map(AsRef::as_ref)).unwrap();
+    Shortener::with_abbreviator(MAX_LENGTH, abbreviator).unwrap()
+}
+
+fn shorten_all(shortener: &Shortener, lines: &[&str]) {
+    for line in lines {
+        black_box(shortener.shorten(black_box(line)));
+    }
+}
+
+fn bench_rule_counts(c: &mut Criterion) {
+    let lines = TITLES.lines().collect_vec();
+    let mut group = c.benchmark_group("exact_rules");
+    group.throughput(Throughput::Elements(lines.len() as u64));
+    for count in [10, 100, 1000] {
+        let shortener = shortener(exact_rules(count).into_iter());
+        group.bench_with_input(BenchmarkId::from_parameter(count), &lines, |b, lines| {
+            b.iter(|| shorten_all(&shortener, lines));
+        });
+    }
+    group.finish();
+}
+
+fn bench_regex_rules(c: &mut Criterion) {
+    let lines = TITLES.lines().collect_vec();
+    let shortener = shortener(REGEX_RULES.lines());
+    let mut group = c.benchmark_group("regex_rules");
+    group.throughput(Throughput::Elements(lines.len() as u64));
+    group.bench_function("titles", |b| b.iter(|| shorten_all(&shortener, &lines)));
+    group.finish();
+}
+
+fn bench_long_lines(c: &mut Criterion) {
+    let shortener = shortener(exact_rules(100).into_iter());
+    let mut group = c.benchmark_group("long_lines");
+    for repeats in [10, 100] {
+        let line = TITLES.lines().cycle().take(repeats).join(" ");
+        group.throughput(Throughput::Bytes(line.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(repeats), &line, |b, line| {
+            b.iter(|| black_box(shortener.shorten_to(black_box(line), line.len() / 2)));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_rule_counts, bench_regex_rules, bench_long_lines);
+criterion_main!(benches);
@@ -0,0 +1,28 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "c-header")]
+    write_c_header();
+}
+
+/// Writes the C header declaring the functions of `src/ffi.rs`. The header is
+/// committed, and cbindgen leaves it alone unless its contents change, so
+/// builds only touch the source tree when the bindings do.
+#[cfg(feature = "c-header")]
+fn write_c_header() {
+    println!("cargo:rerun-if-changed=src/ffi.rs");
+    println!("cargo:rerun-if-changed=src/shortener.rs");
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").expect("set by cargo");
+    let config = cbindgen::Config {
+        language: cbindgen::Language::C,
+        include_guard: Some("SHORTEN_H".to_string()),
+        cpp_compat: true,
+        usize_is_size_t: true,
+        ..Default::default()
+    };
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Failed to generate the C header")
+        .write_to_file(std::path::Path::new(&crate_dir).join("include/shorten.h"));
+}
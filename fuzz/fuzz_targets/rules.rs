@@ -0,0 +1,19 @@
+//! Arbitrary rules files: loading them may fail but never panics, and
+//! neither does shortening with the rules that load.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shorten::abbrev::Abbreviator;
+use shorten::shortener::Shortener;
+
+fuzz_target!(|data: &[u8]| {
+    let text = String::from_utf8_lossy(data);
+    let Ok(abbreviator) = Abbreviator::from_lines(text.lines()) else {
+        return;
+    };
+    let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+    for line in text.lines() {
+        shortener.shorten(line);
+    }
+});
@@ -0,0 +1,45 @@
+//! Arbitrary lines shortened to an arbitrary length with rules that never
+//! lengthen a word nor attach it to another: the line may not get longer.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use shorten::abbrev::Abbreviator;
+use shorten::shortener::Shortener;
+use shorten::width;
+use std::sync::LazyLock;
+
+const RULES: &str = r#"
+Architecture = arch | A
+Learning = learn
+Session = sesn
+Department = 部
+Weekly = 毎週
+Monthly All Hands Meeting = MAHM
+Rescheduled =
+[Monthly] = [M]
+「Weekly」 = 「W」
+/^Meet(ing)?s?$/i = Mtg
+/^(\d+)(st|nd|rd|th)$/ = {match}
+(/\[.*rescheduled.*\]/i) =
+!keep ProjectX
+"#;
+
+static SHORTENER: LazyLock<Shortener> = LazyLock::new(|| {
+    let abbreviator = Abbreviator::from_lines(RULES.lines()).unwrap();
+    Shortener::with_abbreviator(0, abbreviator).unwrap()
+});
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&max_length, data)) = data.split_first() else {
+        return;
+    };
+    let Ok(line) = std::str::from_utf8(data) else {
+        return;
+    };
+    let shortened = SHORTENER.shorten_to(line, max_length.into()).text;
+    assert!(
+        width::length(&shortened) <= width::length(line),
+        "{line:?} was lengthened to {shortened:?}"
+    );
+});
@@ -0,0 +1,67 @@
+#ifndef SHORTEN_H
+#define SHORTEN_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * Shortens lines with the rules of an [`Abbreviator`] and the passes it is
+ * configured with.
+ *
+ * A shortener is `Send` and `Sync`, so one loaded ruleset can serve many
+ * threads, and cheap to clone: the clones share the compiled rules and the
+ * stop words, and only get a cache of their own.
+ */
+typedef struct Shortener Shortener;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a shortener for lines of at most `max_length` characters, using
+ * the given rules in the `abbrev.lst` format, or the rules of the config
+ * directory when `rules` is NULL. Free it with `shorten_free`.
+ *
+ * # Safety
+ *
+ * `rules` must be NULL or a valid NUL-terminated string.
+ */
+struct Shortener *shorten_new(size_t max_length, const char *rules);
+
+/**
+ * Frees a shortener created by `shorten_new`. Does nothing on NULL.
+ *
+ * # Safety
+ *
+ * `shortener` must be NULL or come from `shorten_new`, and not be used afterwards.
+ */
+void shorten_free(struct Shortener *shortener);
+
+/**
+ * Shortens a line, returning a new string to free with `shorten_string_free`.
+ *
+ * # Safety
+ *
+ * `shortener` must come from `shorten_new` and `text` must be a valid
+ * NUL-terminated string.
+ */
+char *shorten_line(const struct Shortener *shortener, const char *text);
+
+/**
+ * Frees a string returned by `shorten_line`. Does nothing on NULL.
+ *
+ * # Safety
+ *
+ * `text` must be NULL or come from `shorten_line`, and not be used afterwards.
+ */
+void shorten_string_free(char *text);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SHORTEN_H */
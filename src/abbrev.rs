@@ -1,172 +1,2138 @@
-use std::collections::{HashMap, HashSet};
-use std::path::Path;
-use eyre::{bail, Context, ContextCompat};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use aho_corasick::AhoCorasick;
 use itertools::Itertools;
-use regex::{Regex, RegexBuilder};
+use regex::{Regex, RegexSet, RegexSetBuilder};
+use serde::Deserialize;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::{is_nfc_quick, is_nfkc_quick, IsNormalized, UnicodeNormalization};
+use crate::case::{to_title_case, CasePolicy, CaseRules, TextCase};
+use crate::builtin;
+#[cfg(feature = "cjk")]
+use crate::cjk;
+use crate::config;
+use crate::enclosing::{self, Enclosing};
+use crate::error::{ParseErrorKind, Result, ShortenError};
+use crate::fallback::{self, Fallback};
+use crate::inflect::{self, Inflection};
+use crate::locale::{self, Locale};
+use crate::rule::{Matcher, Rule};
+use crate::template::Template;
+use crate::toml_rules;
+use crate::width;
+
+/// The most memory the regexes of all the regex rules may take together, the
+/// default limit of a single regex
+const REGEX_SET_SIZE_LIMIT: usize = 10 * (1 << 20);
 
 pub struct Abbreviation<'a> {
-    pub text: &'a str,
+    /// The replacement, with its placeholders expanded
+    pub text: Cow<'a, str>,
+    /// More aggressive replacements, for lines that still don't fit
+    pub escalations: Vec<Cow<'a, str>>,
     pub attach_to_previous: bool,
+    pub attach_to_next: bool,
+    pub priority: i32,
+    /// The abbreviation only applies to lines more than this much too long
+    pub min_overflow: usize,
+    /// The matcher of the rule that produced this abbreviation, as written in the rules
+    pub matcher: &'a str,
 }
 
-#[derive(Default)]
+impl Abbreviation<'_> {
+    /// The abbreviation of a singular, for its plural or possessive.
+    fn inflected(mut self, inflection: Inflection) -> Self {
+        self.text = Cow::Owned(inflect::apply(&self.text, inflection));
+        for escalation in &mut self.escalations {
+            *escalation = Cow::Owned(inflect::apply(escalation, inflection));
+        }
+        self
+    }
+}
+
+/// Holds the loaded rules.
+///
+/// When several rules match the same text, the one with the highest priority
+/// (`!N` prefix in the rules file, 0 by default) wins. Ties are resolved by
+/// preferring remove rules, then exact rules, then regex rules, and finally
+/// the regex that appears first in the file. A later definition of the same
+/// exact matcher overrides an earlier one with the same priority.
+///
+/// Rules and text are compared in Unicode NFC, so a rule written with a
+/// precomposed "é" matches text where it is an "e" and a combining accent,
+/// and the [`Normalization::Compatibility`] form also folds compatibility
+/// characters (like the "ﬁ" ligature or fullwidth letters). With
+/// `@ignore-diacritics`, exact rules also match text that differs in its
+/// accents only, so `São Paulo = SP` matches "Sao Paulo".
+///
+/// The compiled rules are behind an `Arc`, so cloning an abbreviator to share
+/// it between threads (it is `Send` and `Sync`) doesn't copy them.
+///
+/// Exact rules may match phrases of any length, while regex rules are only
+/// tried against single words and pairs of words. Multi-word phrases are
+/// found with an Aho-Corasick automaton built once when the rules are loaded,
+/// so a whole line is scanned for all of them in a single pass.
+#[derive(Clone, Default)]
 pub struct Abbreviator {
+    /// The compiled rules, shared by the clones of the abbreviator
+    matchers: Arc<Matchers>,
+    enclosing: Enclosing,
+    /// The case policy of the rules that don't have their own
+    case: CasePolicy,
+    /// The words given a priority with `@low` and `@high`, lowercased
+    word_priorities: HashMap<String, WordPriority>,
+    /// The locale chosen with [`Abbreviator::with_locale`]
+    locale: Option<Locale>,
+    /// The command set with `@fallback-cmd`
+    fallback: Option<Fallback>,
+    /// The commands registered with `@hook`, by name, in order
+    hooks: Vec<(String, Fallback)>,
+    /// The files the rules were loaded from, included ones after the file including them
+    files: Vec<PathBuf>,
+}
+
+#[derive(Clone, Default)]
+struct Matchers {
+    keys: Keys,
     has_matchers: bool,
+    max_phrase_words: usize,
+    /// Matches the normalized multi-word exact and remove matchers
+    phrases: Option<AhoCorasick>,
+    /// Finds the single-word CJK exact and remove matchers inside longer words
+    #[cfg(feature = "cjk")]
+    cjk_dictionary: Option<AhoCorasick>,
     replace_matchers: HashMap<String, Abbrev>,
-    remove_matchers: HashSet<String>,
+    remove_matchers: HashMap<String, Abbrev>,
     regex_matchers: Vec<Abbrev>,
+    /// All of `regex_matchers`, to find the first one that matches in one go,
+    /// unless they don't fit in a set together
+    regex_set: Option<RegexSet>,
+    /// `!keep` rules, protecting words from being altered
+    kept_words: HashMap<String, Abbrev>,
+    kept_patterns: Vec<Abbrev>,
+    /// The matchers of the deprecated rules, as written
+    deprecated: HashSet<String>,
+    /// `!except` rules, by normalized word or phrase
+    exceptions: HashMap<String, Abbrev>,
+    max_exception_words: usize,
+    /// Segment rules, in resolution order
+    segment_matchers: Vec<Abbrev>,
+    /// Whole-line rules, in resolution order
+    line_matchers: Vec<Abbrev>,
+    /// Rules overridden by a later duplicate, kept around for [`Abbreviator::with_groups`]
+    overridden: Vec<Abbrev>,
 }
 
 impl Abbreviator {
-    pub fn from_lines<'a, I: Iterator<Item = &'a str>>(lines: I) -> eyre::Result<Abbreviator> {
-        let mut replace_matchers = HashMap::new();
-        let mut remove_matchers = HashSet::new();
-        let mut regex_matchers = Vec::new();
+    pub fn from_lines<'a, I: Iterator<Item = &'a str>>(lines: I) -> Result<Abbreviator> {
+        let mut parser = LineParser::default();
+        let mut abbrevs = Vec::new();
+        let mut settings = Vec::new();
+        for (order, line) in lines.enumerate() {
+            let parsed = parser.parse_line(line, order).map_err(|err| err.at_line(order))?;
+            match parsed {
+                ParsedLine::Rule(abbrev) => abbrevs.push(*abbrev),
+                ParsedLine::Setting(setting) => settings.push(setting),
+                ParsedLine::Include(_) => {
+                    return Err(ShortenError::from(ParseErrorKind::IncludeWithoutFile).at_line(order))
+                }
+                ParsedLine::Builtin(packs) => {
+                    for pack in packs {
+                        abbrevs.extend(builtin::rules(&pack)?);
+                    }
+                }
+                ParsedLine::Empty => {}
+            }
+        }
 
-        for line in lines {
-            let line = line.trim();
+        // Rules are ordered as if packs were pasted in place of their `@builtin`
+        for (order, abbrev) in abbrevs.iter_mut().enumerate() {
+            abbrev.order = order;
+        }
+        Self::from_abbrevs(abbrevs, Keys::default())?.with_settings(settings)
+    }
 
-            // Skip empty lines and comments
-            if line.is_empty() || line.starts_with('#') {
-                continue;
-            }
+    pub fn from_rules<I: IntoIterator<Item = Rule>>(rules: I) -> Result<Abbreviator> {
+        let abbrevs = rules
+            .into_iter()
+            .enumerate()
+            .map(|(order, rule)| Abbrev::compile(&rule, order))
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::from_abbrevs(abbrevs, Keys::default())
+    }
+
+    fn from_abbrevs(abbrevs: Vec<Abbrev>, keys: Keys) -> Result<Abbreviator> {
+        let mut replace_matchers = HashMap::new();
+        let mut remove_matchers = HashMap::new();
+        let mut regex_matchers = Vec::new();
+        let mut kept_words = HashMap::new();
+        let mut kept_patterns = Vec::new();
+        let mut exceptions = HashMap::new();
+        let mut segment_matchers = Vec::new();
+        let mut line_matchers = Vec::new();
+        let mut overridden = Vec::new();
+        let deprecated = abbrevs.iter().filter(|abbrev| abbrev.rule.deprecated);
+        let deprecated = deprecated.map(|abbrev| abbrev.source.clone()).collect();
 
-            let abbrev = parse_abbrev(line)?;
+        for abbrev in abbrevs {
             match &abbrev.matcher {
+                AbbrevMatcher::Lowercase(matcher) if abbrev.keep => {
+                    let key = keys.normalize(matcher);
+                    if let Some(existing) = kept_words.insert(key, abbrev) {
+                        overridden.push(existing);
+                    }
+                }
+                AbbrevMatcher::Regex(_) if abbrev.keep => kept_patterns.push(abbrev),
+                AbbrevMatcher::Lowercase(matcher) if abbrev.except => {
+                    let key = keys.normalize(matcher);
+                    if let Some(existing) = exceptions.insert(key, abbrev) {
+                        overridden.push(existing);
+                    }
+                }
                 AbbrevMatcher::Lowercase(matcher) => {
-                    if abbrev.abbrev.is_empty() {
-                        remove_matchers.insert(matcher.clone());
+                    let matchers = if abbrev.abbrev.is_empty() {
+                        &mut remove_matchers
                     } else {
-                        replace_matchers.insert(matcher.clone(), abbrev);
+                        &mut replace_matchers
+                    };
+                    let key = keys.normalize(matcher);
+                    // A later duplicate replaces an earlier one unless it has a lower priority
+                    let keep_existing = matchers
+                        .get(&key)
+                        .is_some_and(|existing: &Abbrev| existing.priority > abbrev.priority);
+                    if keep_existing {
+                        overridden.push(abbrev);
+                    } else if let Some(existing) = matchers.insert(key, abbrev) {
+                        overridden.push(existing);
                     }
                 }
                 AbbrevMatcher::Regex(_) => {
                     regex_matchers.push(abbrev);
                 }
+                AbbrevMatcher::Segment(_) => segment_matchers.push(abbrev),
+                AbbrevMatcher::Line(_) => line_matchers.push(abbrev),
             }
         }
 
+        // Regexes are tried in resolution order, so the first one that matches is the best one
+        regex_matchers.sort_by_key(|abbrev| (Reverse(abbrev.priority), abbrev.order));
+        segment_matchers.sort_by_key(|abbrev| (Reverse(abbrev.priority), abbrev.order));
+        line_matchers.sort_by_key(|abbrev| (Reverse(abbrev.priority), abbrev.order));
+
+        // Each regex fits the size limit on its own, but they may not all fit in one set.
+        // Building the set costs about as much as compiling them all again, while going
+        // over the limit fails early, so large sets fall back to trying each regex.
+        let regex_set = RegexSetBuilder::new(regex_matchers.iter().map(|abbrev| {
+            match &abbrev.matcher {
+                AbbrevMatcher::Regex(re) => re.as_str(),
+                _ => unreachable!("only regex matchers are collected"),
+            }
+        }))
+        .size_limit(REGEX_SET_SIZE_LIMIT)
+        .build()
+        .ok();
+
         let empty = replace_matchers.is_empty()
             && remove_matchers.is_empty()
             && regex_matchers.is_empty();
 
-        Ok(Abbreviator { has_matchers: !empty, replace_matchers, remove_matchers, regex_matchers })
+        let max_phrase_words = replace_matchers
+            .keys()
+            .chain(remove_matchers.keys())
+            .map(|matcher| matcher.split_whitespace().count())
+            .max()
+            .unwrap_or(0);
+
+        // Phrases marked with `~` are also found in their plural and possessive forms
+        let phrase_patterns = replace_matchers
+            .iter()
+            .chain(&remove_matchers)
+            .flat_map(|(matcher, abbrev)| {
+                let phrase = keys.normalize(matcher);
+                let forms = if abbrev.rule.inflect { inflect::forms(&phrase) } else { Vec::new() };
+                std::iter::once(phrase).chain(forms)
+            })
+            .filter(|matcher| matcher.contains(' '))
+            .collect_vec();
+        let phrases = match phrase_patterns.is_empty() {
+            true => None,
+            false => Some(
+                AhoCorasick::new(phrase_patterns)
+                    .map_err(|err| ParseErrorKind::TooManyPhrases(err.to_string()))?,
+            ),
+        };
+        let max_exception_words =
+            exceptions.keys().map(|phrase| phrase.split(' ').count()).max().unwrap_or(0);
+        #[cfg(feature = "cjk")]
+        let cjk_dictionary = {
+            let matchers = replace_matchers.keys().chain(remove_matchers.keys());
+            cjk::dictionary(matchers.map(String::as_str))
+                .map_err(|err| ParseErrorKind::TooManyCjkWords(err.to_string()))?
+        };
+
+        let matchers = Matchers {
+            keys,
+            has_matchers: !empty,
+            max_phrase_words,
+            phrases,
+            #[cfg(feature = "cjk")]
+            cjk_dictionary,
+            replace_matchers,
+            remove_matchers,
+            regex_matchers,
+            regex_set,
+            kept_words,
+            kept_patterns,
+            deprecated,
+            exceptions,
+            max_exception_words,
+            segment_matchers,
+            line_matchers,
+            overridden,
+        };
+        Ok(Abbreviator {
+            matchers: Arc::new(matchers),
+            enclosing: Enclosing::default(),
+            case: CasePolicy::default(),
+            word_priorities: HashMap::new(),
+            locale: None,
+            fallback: None,
+            hooks: Vec::new(),
+            files: Vec::new(),
+        })
     }
 
-    pub fn try_from_file(file_path: &Path) -> eyre::Result<Abbreviator> {
-        let file = std::fs::read_to_string(file_path)
-            .context("Failed to read abbreviations file")?;
+    fn with_settings(mut self, settings: Vec<Setting>) -> Result<Abbreviator> {
+        let mut timeout = None;
+        for setting in settings {
+            match setting {
+                Setting::Enclose(pairs) => {
+                    for (opener, closer) in pairs {
+                        self.enclosing.add(opener, closer);
+                    }
+                }
+                Setting::EncloseOnly(pairs) => {
+                    self.enclosing = Enclosing::none();
+                    for (opener, closer) in pairs {
+                        self.enclosing.add(opener, closer);
+                    }
+                }
+                Setting::Case(case) => self.case = case,
+                Setting::Normalization(normalization) => {
+                    self = self.with_normalization(normalization)?;
+                }
+                Setting::IgnoreDiacritics => self = self.with_diacritics_ignored(true)?,
+                Setting::WordPriority(priority, words) => {
+                    for word in words {
+                        self.set_word_priority(&word, priority);
+                    }
+                }
+                Setting::FallbackCommand(command) => self.fallback = Some(Fallback::new(command)),
+                Setting::FallbackTimeout(duration) => timeout = Some(duration),
+                Setting::Hook(name, command) => self.hooks.push((name, Fallback::new(command))),
+            }
+        }
+        if let Some(timeout) = timeout {
+            self.fallback = self.fallback.take().map(|fallback| fallback.with_timeout(timeout));
+            for (_, hook) in &mut self.hooks {
+                *hook = hook.clone().with_timeout(timeout);
+            }
+        }
+        Ok(self)
+    }
 
-        Self::from_lines(file.lines())
+    /// The characters enclosing words, which default to brackets and quotes
+    /// and can be changed with `@enclose` directives.
+    pub fn enclosing(&self) -> &Enclosing {
+        &self.enclosing
     }
 
-    pub fn abbreviate(&self, text: &str) -> Option<Abbreviation> {
-        if !self.has_matchers {
-            return None;
+    pub fn set_enclosing(&mut self, enclosing: Enclosing) {
+        self.enclosing = enclosing;
+    }
+
+    /// The case policy of the rules that don't have their own, smart unless
+    /// changed with `@case`.
+    pub fn case(&self) -> CasePolicy {
+        self.case
+    }
+
+    pub fn set_case(&mut self, case: CasePolicy) {
+        self.case = case;
+    }
+
+    /// The priority of the word, ignoring its case and the punctuation around
+    /// it, when `@low` or `@high` gave it one.
+    pub fn word_priority(&self, word: &str) -> Option<WordPriority> {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        self.word_priorities.get(&word.to_lowercase()).copied()
+    }
+
+    /// Whether some words have a priority, in which case lines that are still
+    /// too long after all other passes lose the words without one as well.
+    pub fn has_word_priorities(&self) -> bool {
+        !self.word_priorities.is_empty()
+    }
+
+    pub fn set_word_priority(&mut self, word: &str, priority: WordPriority) {
+        self.word_priorities.insert(word.to_lowercase(), priority);
+    }
+
+    /// The command shortening the lines that are still too long after all
+    /// passes, set with `@fallback-cmd`.
+    pub fn fallback(&self) -> Option<&Fallback> {
+        self.fallback.as_ref()
+    }
+
+    pub fn set_fallback(&mut self, fallback: Option<Fallback>) {
+        self.fallback = fallback;
+    }
+
+    /// The commands registered as custom passes with `@hook`, by name.
+    pub fn hooks(&self) -> &[(String, Fallback)] {
+        &self.hooks
+    }
+
+    pub fn set_hooks(&mut self, hooks: Vec<(String, Fallback)>) {
+        self.hooks = hooks;
+    }
+
+    /// The rules files [`Abbreviator::try_from_file`] read, the file itself
+    /// first and the files it includes after it.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    /// The case policy a rule follows.
+    pub fn case_of(&self, abbrev: &Abbrev) -> CasePolicy {
+        abbrev.rule.case.unwrap_or(self.case)
+    }
+
+    /// The form rules and text are brought to before they are compared.
+    pub fn normalization(&self) -> Normalization {
+        self.matchers.keys.normalization
+    }
+
+    /// Rebuilds the abbreviator to compare rules and text in another
+    /// normalization form, which `@normalize nfkc` does in the rules.
+    pub fn with_normalization(self, normalization: Normalization) -> Result<Abbreviator> {
+        let keys = Keys { normalization, ..self.matchers.keys };
+        self.with_keys(keys)
+    }
+
+    /// Whether exact rules match text that only differs from them in its
+    /// diacritics.
+    pub fn ignores_diacritics(&self) -> bool {
+        self.matchers.keys.ignore_diacritics
+    }
+
+    /// Rebuilds the abbreviator for exact rules to match text regardless of
+    /// its diacritics (or not), which `@ignore-diacritics` does in the rules.
+    /// Regex rules still see the accents.
+    pub fn with_diacritics_ignored(self, ignore: bool) -> Result<Abbreviator> {
+        let keys = Keys { ignore_diacritics: ignore, ..self.matchers.keys };
+        self.with_keys(keys)
+    }
+
+    fn with_keys(self, keys: Keys) -> Result<Abbreviator> {
+        if keys == self.matchers.keys {
+            return Ok(self);
         }
+        self.rebuild(keys, Some)
+    }
+
+    /// Rebuilds the abbreviator with only the rules that pass the filter,
+    /// keeping the settings.
+    fn retain(self, mut filter: impl FnMut(&Abbrev) -> bool) -> Result<Abbreviator> {
+        let keys = self.matchers.keys;
+        self.rebuild(keys, |abbrev| filter(&abbrev).then_some(abbrev))
+    }
+
+    /// Rebuilds the abbreviator with the rules `select` returns, keeping the
+    /// settings.
+    fn rebuild(
+        mut self,
+        keys: Keys,
+        select: impl FnMut(Abbrev) -> Option<Abbrev>,
+    ) -> Result<Abbreviator> {
+        let matchers = std::mem::take(&mut self.matchers);
+        let abbrevs = Self::into_abbrevs(matchers).filter_map(select).collect();
+        Ok(Abbreviator { matchers: Self::from_abbrevs(abbrevs, keys)?.matchers, ..self })
+    }
 
-        let lowercase = text.to_lowercase()
-            .replace('-', " ")
-            .split_whitespace()
-            .join(" ");
+    /// The locale the output is written for, if one was chosen.
+    pub fn locale(&self) -> Option<&Locale> {
+        self.locale.as_ref()
+    }
+
+    pub(crate) fn case_rules(&self) -> CaseRules {
+        self.locale.as_ref().map_or(CaseRules::Standard, Locale::case_rules)
+    }
+
+    /// Writes the output for the locale, on freshly loaded rules: the rules
+    /// with replacements for it (`dept | de:Abt.`) use them instead of their
+    /// own, and case conversions and the names of the dates pass follow it.
+    pub fn with_locale(self, locale: Option<Locale>) -> Result<Abbreviator> {
+        let Some(locale) = locale else {
+            return Ok(Abbreviator { locale: None, ..self });
+        };
+        let keys = self.matchers.keys;
+        let mut abbreviator = self.rebuild(keys, |abbrev| {
+            let tagged = abbrev.localized.iter().map(|(tag, localized)| (tag.as_str(), localized));
+            let Some(localized) = locale.pick(tagged).cloned() else {
+                return Some(abbrev);
+            };
+            let rule = Rule { localized: abbrev.rule.localized, ..localized.rule };
+            // The order of the rule changes when included files are loaded
+            let (order, location) = (abbrev.order, abbrev.location);
+            Some(Abbrev { order, location, localized: abbrev.localized, rule, ..localized })
+        })?;
+        abbreviator.locale = Some(locale);
+        Ok(abbreviator)
+    }
 
-        if self.remove_matchers.contains(&lowercase) {
-            return Some(Abbreviation { text: "", attach_to_previous: true });
+    /// Keeps only the rules of the selected groups.
+    ///
+    /// When `only` is not empty, grouped rules are kept only if their group is
+    /// listed in it, and rules whose group is listed in `skip` are dropped.
+    /// Rules outside of any group are always kept.
+    pub fn with_groups<S: AsRef<str>>(self, only: &[S], skip: &[S]) -> Result<Abbreviator> {
+        let contains = |groups: &[S], group: &str| groups.iter().any(|g| g.as_ref() == group);
+        self.retain(|abbrev| match &abbrev.group {
+            Some(group) => (only.is_empty() || contains(only, group)) && !contains(skip, group),
+            None => true,
+        })
+    }
+
+    /// Keeps only the rules that apply in one of the active contexts, along
+    /// with the rules that are not restricted to any context.
+    pub fn with_contexts<S: AsRef<str>>(self, active: &[S]) -> Result<Abbreviator> {
+        self.retain(|abbrev| {
+            abbrev.contexts.is_empty()
+                || abbrev
+                    .contexts
+                    .iter()
+                    .any(|context| active.iter().any(|a| a.as_ref() == context))
+        })
+    }
+
+    /// Every rule, including the ones overridden by a later duplicate
+    fn into_abbrevs(matchers: Arc<Matchers>) -> impl Iterator<Item = Abbrev> {
+        // The rules are only copied when a clone of the abbreviator still uses them
+        let matchers = Arc::unwrap_or_clone(matchers);
+        matchers
+            .replace_matchers
+            .into_values()
+            .chain(matchers.remove_matchers.into_values())
+            .chain(matchers.regex_matchers)
+            .chain(matchers.kept_words.into_values())
+            .chain(matchers.kept_patterns)
+            .chain(matchers.exceptions.into_values())
+            .chain(matchers.segment_matchers)
+            .chain(matchers.line_matchers)
+            .chain(matchers.overridden)
+            .sorted_by_key(|abbrev| abbrev.order)
+    }
+
+    /// All the loaded rules, in the order they were defined
+    pub fn rules(&self) -> Vec<&Abbrev> {
+        self.matchers.replace_matchers
+            .values()
+            .chain(self.matchers.remove_matchers.values())
+            .chain(&self.matchers.regex_matchers)
+            .chain(self.matchers.kept_words.values())
+            .chain(&self.matchers.kept_patterns)
+            .chain(self.matchers.exceptions.values())
+            .chain(&self.matchers.segment_matchers)
+            .chain(&self.matchers.line_matchers)
+            .sorted_by_key(|abbrev| abbrev.order)
+            .collect()
+    }
+
+    /// All the loaded rules, including the ones overridden by a later
+    /// duplicate, in the order they were defined
+    pub fn all_rules(&self) -> Vec<&Abbrev> {
+        let rules = self.rules().into_iter().chain(&self.matchers.overridden);
+        rules.sorted_by_key(|abbrev| abbrev.order).collect()
+    }
+
+    /// The number of words in the longest phrase matched by any rule, and at least 2
+    pub fn max_phrase_words(&self) -> usize {
+        self.matchers.max_phrase_words.max(2)
+    }
+
+    /// For every word, the lengths (in words) of the phrases starting at it
+    /// that match a multi-word exact or remove rule.
+    ///
+    /// The words are normalized the same way as in [`Abbreviator::abbreviate`]
+    /// and scanned as one line, and only matches that start and end on word
+    /// boundaries are kept.
+    pub fn phrase_lengths(&self, words: &[&str]) -> Vec<Vec<usize>> {
+        let mut lengths = vec![Vec::new(); words.len()];
+        let Some(phrases) = &self.matchers.phrases else {
+            return lengths;
+        };
+
+        let mut line = String::new();
+        let mut spans = Vec::with_capacity(words.len());
+        for word in words {
+            let normalized = self.matchers.keys.normalize(word);
+            if normalized.is_empty() {
+                spans.push(None);
+                continue;
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            spans.push(Some((line.len(), line.len() + normalized.len())));
+            line.push_str(&normalized);
         }
 
-        let abbrev = self.replace_matchers.get(&lowercase);
-        if let Some(abbrev) = abbrev {
-            return Some(abbrev.with_matching_case_to(text));
+        // Words that normalize to nothing (like a lone dash) may start a phrase
+        // at the next word or end it at the previous one, as with a lookup
+        let starts = (0..words.len())
+            .map(|word| {
+                spans[word..].iter().flatten().next().map_or(line.len(), |span| span.0)
+            })
+            .collect_vec();
+        let ends = (0..words.len())
+            .map(|word| spans[..=word].iter().flatten().last().map_or(0, |span| span.1))
+            .collect_vec();
+
+        for found in phrases.find_overlapping_iter(&line) {
+            let first_start = starts.partition_point(|&start| start < found.start());
+            let first_end = ends.partition_point(|&end| end < found.end());
+            let starting = (first_start..words.len())
+                .take_while(|&word| starts[word] == found.start());
+            for start in starting {
+                let ending = (first_end.max(start)..words.len())
+                    .take_while(|&word| ends[word] == found.end());
+                lengths[start].extend(ending.map(|end| end - start + 1));
+            }
         }
 
-        for abbrev in &self.regex_matchers {
-            let AbbrevMatcher::Regex(re) = &abbrev.matcher else {
-                continue
-            };
+        lengths
+    }
+
+    /// The CJK words of the rules, to look for in unspaced CJK text.
+    #[cfg(feature = "cjk")]
+    pub(crate) fn cjk_dictionary(&self) -> Option<&AhoCorasick> {
+        self.matchers.cjk_dictionary.as_ref()
+    }
+
+    /// Whether a `!keep` rule protects the word from being altered.
+    pub fn is_kept(&self, word: &str) -> bool {
+        let word = self.normalization().apply(word);
+        let kept = |word: &str| self.matchers.kept_words.get(&self.matchers.keys.normalize(word));
+        kept(&word).is_some()
+            || inflect::stems(&word)
+                .iter()
+                .any(|(stem, _)| kept(stem).is_some_and(|abbrev| abbrev.rule.inflect))
+            || self.matchers.kept_patterns.iter().any(|abbrev| {
+                matches!(&abbrev.matcher, AbbrevMatcher::Regex(re) if re.is_match(&word))
+            })
+    }
 
-            if re.is_match(text) {
-                return Some(abbrev.with_matching_case_to(text));
+    /// Whether the rule with this matcher, as reported by the applied rules,
+    /// is deprecated.
+    pub fn is_deprecated(&self, matcher: &str) -> bool {
+        self.matchers.deprecated.contains(matcher)
+    }
+
+    /// The number of words of the longest `!except` word or phrase the words
+    /// start with, if they start with one.
+    pub fn exception_length(&self, words: &[&str]) -> Option<usize> {
+        let max_words = self.matchers.max_exception_words.min(words.len());
+        (1..=max_words).rev().find(|&len| {
+            let phrase = self.normalization().apply(&words[..len].join(" ")).into_owned();
+            self.matchers.exceptions.contains_key(&self.matchers.keys.normalize(&phrase))
+        })
+    }
+
+    /// Abbreviates a word or phrase with the exact rules only, like the words
+    /// of a `!except` phrase.
+    pub fn abbreviate_exact(&self, words: &[&str]) -> Option<Abbreviation<'_>> {
+        self.lookup(&words.join(" "), false)
+    }
+
+    /// Abbreviates a whole enclosed segment, brackets included, with the
+    /// segment rules. Other rules are not tried.
+    pub fn abbreviate_segment(&self, segment: &str) -> Option<Abbreviation<'_>> {
+        let segment = &*self.normalization().apply(segment);
+        let best = self.matchers.segment_matchers.iter().find(|abbrev| {
+            matches!(&abbrev.matcher, AbbrevMatcher::Segment(re) if re.is_match(segment))
+        })?;
+        if best.abbrev.is_empty() {
+            return Some(Abbreviation {
+                text: Cow::Borrowed(""),
+                escalations: Vec::new(),
+                attach_to_previous: true,
+                attach_to_next: false,
+                priority: best.priority,
+                min_overflow: best.min_overflow,
+                matcher: &best.source,
+            });
+        }
+        Some(best.with_matching_case_to(segment, self.case, self.case_rules()))
+    }
+
+    /// Rewrites a whole line with the first whole-line rule matching it, using
+    /// the first of its levels that fits in `max_length` (or the last one).
+    /// Groups of the match given as `$1` or `${name}` keep their case.
+    pub fn rewrite_line(&self, line: &str, max_length: usize) -> Option<Abbreviation<'_>> {
+        let overflow = width::length(line).saturating_sub(max_length);
+        let line = &*self.normalization().apply(line);
+        let line_matchers = &self.matchers.line_matchers;
+        let (best, captures) = line_matchers.iter().find_map(|abbrev| match &abbrev.matcher {
+            AbbrevMatcher::Line(re) if overflow > abbrev.min_overflow => {
+                Some((abbrev, re.captures(line)?))
             }
+            _ => None,
+        })?;
+        let levels = [best].into_iter().chain(&best.escalations).map(|level| {
+            let replacement = match &level.template {
+                Some(template) => template.expand(line),
+                None => level.abbrev.clone(),
+            };
+            let mut expanded = String::new();
+            captures.expand(&replacement, &mut expanded);
+            expanded
+        });
+        let mut rewritten = String::new();
+        for level in levels {
+            rewritten = level;
+            if width::length(&rewritten) <= max_length {
+                break;
+            }
+        }
+        Some(Abbreviation {
+            text: Cow::Owned(rewritten),
+            escalations: Vec::new(),
+            attach_to_previous: false,
+            attach_to_next: false,
+            priority: best.priority,
+            min_overflow: best.min_overflow,
+            matcher: &best.source,
+        })
+    }
+
+    /// Whether any segment rule is loaded.
+    pub fn has_segment_rules(&self) -> bool {
+        !self.matchers.segment_matchers.is_empty()
+    }
+
+    /// Abbreviates a phrase made of consecutive words.
+    pub fn abbreviate_words(&self, words: &[&str]) -> Option<Abbreviation<'_>> {
+        self.lookup(&words.join(" "), words.len() <= 2)
+    }
+
+    /// Loads the rules from a file, in the TOML format if it has a `.toml`
+    /// extension and in the `abbrev.lst` format otherwise.
+    ///
+    /// `@include other.lst` lines load another file in place, looking for it
+    /// next to the including file first and in the config directories second.
+    pub fn try_from_file(file_path: &Path) -> Result<Abbreviator> {
+        let (mut abbrevs, mut settings, mut files) = (Vec::new(), Vec::new(), Vec::new());
+        let loaded = (&mut abbrevs, &mut settings, &mut files);
+        load_file(file_path, &mut Vec::new(), loaded)?;
+
+        // Rules are ordered as if included files and packs were pasted in place of their
+        // `@include` and `@builtin`
+        for (order, abbrev) in abbrevs.iter_mut().enumerate() {
+            abbrev.order = order;
+        }
+        let abbreviator = Self::from_abbrevs(abbrevs, Keys::default())?.with_settings(settings)?;
+        Ok(Abbreviator { files, ..abbreviator })
+    }
+
+    pub fn abbreviate(&self, text: &str) -> Option<Abbreviation<'_>> {
+        self.lookup(text, true)
+    }
+
+    fn lookup(&self, text: &str, allow_regex: bool) -> Option<Abbreviation<'_>> {
+        if !self.matchers.has_matchers {
+            return None;
+        }
+
+        let text = &*self.normalization().apply(text);
+        let lowercase = self.matchers.keys.normalize(text);
+
+        let regex_matchers = &self.matchers.regex_matchers;
+        let regex_match = match &self.matchers.regex_set {
+            _ if !allow_regex => None,
+            Some(set) => set.matches(text).iter().next().map(|index| &regex_matchers[index]),
+            None => regex_matchers.iter().find(|abbrev| {
+                matches!(&abbrev.matcher, AbbrevMatcher::Regex(re) if re.is_match(text))
+            }),
+        };
+
+        let best = [
+            self.matchers.remove_matchers.get(&lowercase),
+            self.matchers.replace_matchers.get(&lowercase),
+            regex_match,
+        ]
+        .into_iter()
+        .enumerate()
+        .filter_map(|(kind, abbrev)| abbrev.map(|abbrev| (kind, abbrev)))
+        .min_by_key(|(kind, abbrev)| (Reverse(abbrev.priority), *kind, abbrev.order))
+        .map(|(_, abbrev)| abbrev);
+        match best {
+            Some(best) => Some(self.abbreviation(best, text)),
+            None => self.lookup_inflected(text),
+        }
+    }
+
+    /// Looks up the singulars of a plural or possessive among the exact rules
+    /// marked with `~`, inflecting their abbreviation like the text.
+    fn lookup_inflected(&self, text: &str) -> Option<Abbreviation<'_>> {
+        inflect::stems(text).into_iter().find_map(|(stem, inflection)| {
+            let key = self.matchers.keys.normalize(&stem);
+            let matchers = [&self.matchers.remove_matchers, &self.matchers.replace_matchers];
+            let best = matchers
+                .into_iter()
+                .enumerate()
+                .filter_map(|(kind, matchers)| Some((kind, matchers.get(&key)?)))
+                .filter(|(_, abbrev)| abbrev.rule.inflect)
+                .min_by_key(|(kind, abbrev)| (Reverse(abbrev.priority), *kind, abbrev.order))?
+                .1;
+            Some(self.abbreviation(best, &stem).inflected(inflection))
+        })
+    }
+
+    /// The abbreviation of the text by the rule.
+    fn abbreviation<'a>(&'a self, best: &'a Abbrev, text: &str) -> Abbreviation<'a> {
+        if best.abbrev.is_empty() {
+            return Abbreviation {
+                text: Cow::Borrowed(""),
+                escalations: Vec::new(),
+                attach_to_previous: true,
+                attach_to_next: false,
+                priority: best.priority,
+                min_overflow: best.min_overflow,
+                matcher: &best.source,
+            };
+        }
+
+        best.with_matching_case_to(text, self.case, self.case_rules())
+    }
+}
+
+/// Builds an [`Abbreviator`] from rules written in code, without going
+/// through one of the rules formats:
+///
+/// ```
+/// use shorten::abbrev::AbbreviatorBuilder;
+///
+/// let abbreviator = AbbreviatorBuilder::new()
+///     .exact("Architecture", "arch")
+///     .remove("Rescheduled")
+///     .regex(r"(?i)^sprint\d+$", "S")
+///     .attach("Section", "課")
+///     .build()
+///     .unwrap();
+/// assert_eq!(abbreviator.abbreviate("Sprint12").unwrap().text, "S");
+/// ```
+///
+/// Other options, like priorities and groups, are set on a [`Rule`] added with
+/// [`AbbreviatorBuilder::rule`].
+#[derive(Default)]
+pub struct AbbreviatorBuilder {
+    rules: Vec<Rule>,
+}
+
+impl AbbreviatorBuilder {
+    pub fn new() -> AbbreviatorBuilder {
+        AbbreviatorBuilder::default()
+    }
+
+    /// Abbreviates a word or phrase, like `Architecture = arch`.
+    pub fn exact(self, text: impl Into<String>, abbrev: impl Into<String>) -> Self {
+        self.rule(Rule::new(Matcher::Exact(text.into()), abbrev))
+    }
+
+    /// Removes a word or phrase, like `Rescheduled =`.
+    pub fn remove(self, text: impl Into<String>) -> Self {
+        self.rule(Rule::new(Matcher::Exact(text.into()), ""))
+    }
+
+    /// Abbreviates the words matching a regex, like `/pattern/ = abbrev`.
+    pub fn regex(self, pattern: impl Into<String>, abbrev: impl Into<String>) -> Self {
+        let matcher = Matcher::Regex { pattern: pattern.into(), flags: String::new() };
+        self.rule(Rule::new(matcher, abbrev))
+    }
+
+    /// Abbreviates a word or phrase and attaches the abbreviation to the
+    /// previous word, like `Section = <+課`.
+    pub fn attach(self, text: impl Into<String>, abbrev: impl Into<String>) -> Self {
+        let rule = Rule::new(Matcher::Exact(text.into()), abbrev);
+        self.rule(Rule { attach_to_previous: true, ..rule })
+    }
+
+    /// Abbreviates a word or phrase and attaches the abbreviation to the next
+    /// word, like `Number = #+>`.
+    pub fn attach_next(self, text: impl Into<String>, abbrev: impl Into<String>) -> Self {
+        let rule = Rule::new(Matcher::Exact(text.into()), abbrev);
+        self.rule(Rule { attach_to_next: true, ..rule })
+    }
+
+    /// Protects a word or phrase from being altered, like `!keep ProjectX`.
+    pub fn keep(self, text: impl Into<String>) -> Self {
+        let rule = Rule::new(Matcher::Exact(text.into()), "");
+        self.rule(Rule { keep: true, ..rule })
+    }
+
+    /// Leaves a word or phrase to the exact rules, like
+    /// `!except Security Architecture`.
+    pub fn except(self, text: impl Into<String>) -> Self {
+        let rule = Rule::new(Matcher::Exact(text.into()), "");
+        self.rule(Rule { except: true, ..rule })
+    }
+
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Compiles the rules, failing on the first invalid one.
+    pub fn build(self) -> Result<Abbreviator> {
+        Abbreviator::from_rules(self.rules)
+    }
+}
+
+/// The serialized form of an [`Abbreviator`]: its rules, including the
+/// overridden ones, and its enclosing characters. The `@fallback-cmd` is left
+/// out, and rejected, since a snapshot can come from anyone able to write to
+/// the daemon's socket and the command is run by the shell.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Snapshot {
+    rules: Vec<Rule>,
+    #[serde(default = "default_pairs")]
+    enclosing: Vec<(char, char)>,
+    #[serde(default)]
+    case: CasePolicy,
+    #[serde(default)]
+    normalization: Normalization,
+    #[serde(default)]
+    ignore_diacritics: bool,
+    #[serde(default)]
+    low_words: Vec<String>,
+    #[serde(default)]
+    high_words: Vec<String>,
+    #[serde(default)]
+    locale: Option<String>,
+}
+
+#[cfg(feature = "serde")]
+fn default_pairs() -> Vec<(char, char)> {
+    Enclosing::default().pairs().collect()
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Abbreviator {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let rules = self.all_rules().into_iter().map(|abbrev| abbrev.rule.clone()).collect();
+        let enclosing = self.enclosing.pairs().collect();
+        let words = |priority| {
+            let words = self.word_priorities.iter().filter(|(_, &other)| other == priority);
+            words.map(|(word, _)| word.clone()).sorted().collect()
+        };
+        let snapshot = Snapshot {
+            rules,
+            enclosing,
+            case: self.case,
+            locale: self.locale.as_ref().map(Locale::to_string),
+            normalization: self.normalization(),
+            ignore_diacritics: self.ignores_diacritics(),
+            low_words: words(WordPriority::Low),
+            high_words: words(WordPriority::High),
+        };
+        snapshot.serialize(serializer)
+    }
+}
+
+/// Compiles the rules of a snapshot, so that invalid rules fail deserialization.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Abbreviator {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot = Snapshot::deserialize(deserializer)?;
+        let mut abbreviator =
+            Abbreviator::from_rules(snapshot.rules).map_err(serde::de::Error::custom)?;
+        let mut enclosing = Enclosing::none();
+        for (opener, closer) in snapshot.enclosing {
+            enclosing.add(opener, closer);
+        }
+        abbreviator.set_enclosing(enclosing);
+        abbreviator.set_case(snapshot.case);
+        for word in snapshot.low_words {
+            abbreviator.set_word_priority(&word, WordPriority::Low);
+        }
+        for word in snapshot.high_words {
+            abbreviator.set_word_priority(&word, WordPriority::High);
+        }
+        let keys = Keys {
+            normalization: snapshot.normalization,
+            ignore_diacritics: snapshot.ignore_diacritics,
+        };
+        let locale = snapshot.locale.map(|name| {
+            Locale::parse(&name).ok_or(ParseErrorKind::InvalidLocale(name))
+        });
+        let locale = locale.transpose().map_err(serde::de::Error::custom)?;
+        let abbreviator = abbreviator.with_keys(keys).and_then(|abbreviator| {
+            abbreviator.with_locale(locale)
+        });
+        abbreviator.map_err(serde::de::Error::custom)
+    }
+}
+
+/// How rules and the text looked up with them are brought to the form of the
+/// keys of the exact matchers.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+struct Keys {
+    normalization: Normalization,
+    /// "São" and "Sao" are the same key
+    ignore_diacritics: bool,
+}
+
+impl Keys {
+    /// Folds text and joins its words with single spaces, treating dashes as
+    /// spaces.
+    fn normalize(self, text: &str) -> String {
+        self.fold(text).replace('-', " ").split_whitespace().join(" ")
+    }
+
+    /// Brings text to the normalization form and lowercases it, dropping the
+    /// diacritics when they are ignored.
+    fn fold(self, text: &str) -> String {
+        let text = self.normalization.apply(text);
+        if !self.ignore_diacritics || text.is_ascii() {
+            return text.to_lowercase();
+        }
+        let stripped: String = text.nfd().filter(|&c| !is_combining_mark(c)).nfc().collect();
+        stripped.to_lowercase()
+    }
+}
+
+/// How readily a word is dropped from lines that are still too long after
+/// abbreviation: `@low` words go first, then the words without a priority,
+/// and `@high` words never do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WordPriority {
+    Low,
+    High,
+}
+
+/// The Unicode normalization form rules and text are compared in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Normalization {
+    /// NFC: "e" and a combining accent is "é"
+    #[default]
+    #[serde(rename = "nfc")]
+    Canonical,
+    /// NFKC, which also folds compatibility characters: "ﬁ" is "fi" and "Ａ" is "A"
+    #[serde(rename = "nfkc")]
+    Compatibility,
+}
+
+impl Normalization {
+    /// Parses a form as written in the rules files, `nfc` or `nfkc`.
+    pub fn from_name(name: &str) -> Option<Normalization> {
+        match name {
+            "nfc" => Some(Normalization::Canonical),
+            "nfkc" => Some(Normalization::Compatibility),
+            _ => None,
         }
+    }
 
-        None
+    /// The text in the normalization form, borrowed when it already is.
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        let normalized = match self {
+            Normalization::Canonical => is_nfc_quick(text.chars()),
+            Normalization::Compatibility => is_nfkc_quick(text.chars()),
+        };
+        match (self, normalized) {
+            (_, IsNormalized::Yes) => Cow::Borrowed(text),
+            (Normalization::Canonical, _) => Cow::Owned(text.nfc().collect()),
+            (Normalization::Compatibility, _) => Cow::Owned(text.nfkc().collect()),
+        }
     }
 }
 
-enum AbbrevMatcher {
+#[derive(Clone)]
+pub enum AbbrevMatcher {
     Lowercase(String),
     Regex(Regex),
+    /// Matches whole enclosed segments
+    Segment(Regex),
+    /// Matches whole lines
+    Line(Regex),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleKind {
+    Exact,
+    Remove,
+    Regex,
+    Keep,
+    Except,
+    Segment,
+    Line,
 }
 
-struct Abbrev {
+impl std::fmt::Display for RuleKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            RuleKind::Exact => "exact",
+            RuleKind::Remove => "remove",
+            RuleKind::Regex => "regex",
+            RuleKind::Keep => "keep",
+            RuleKind::Except => "except",
+            RuleKind::Segment => "segment",
+            RuleKind::Line => "line",
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Abbrev {
     pub matcher: AbbrevMatcher,
+    /// The matcher definition as written in the rules
+    pub source: String,
     pub abbrev: String,
+    /// Versions of a lowercase abbreviation for title-case and ALL-CAPS text,
+    /// under the smart case policy
     pub title_case_version: Option<String>,
+    pub upper_case_version: Option<String>,
+    /// The more aggressive abbreviations, as rules of their own
+    pub escalations: Vec<Abbrev>,
+    /// The rule as it is in other locales, by the tag of the locale
+    pub(crate) localized: Vec<(String, Abbrev)>,
     pub attach_to_previous: bool,
+    pub attach_to_next: bool,
+    pub priority: i32,
+    /// The rule only fires on lines more than this much too long
+    pub min_overflow: usize,
+    /// Position of the rule in its source, used to break priority ties
+    pub order: usize,
+    pub location: Location,
+    pub group: Option<String>,
+    /// The rule only applies when one of these contexts is active (always, if empty)
+    pub contexts: Vec<String>,
+    /// A `!keep` rule, protecting the words it matches
+    pub keep: bool,
+    /// A `!except` rule, leaving the words it matches to the exact rules
+    pub except: bool,
+    /// The placeholders of the abbreviation, if it has any
+    pub(crate) template: Option<Template>,
+    /// The rule the abbreviation was compiled from
+    pub rule: Rule,
 }
 
 impl Abbrev {
-    fn with_matching_case_to(&self, original_text: &str) -> Abbreviation {
-        let is_title_case = original_text.chars().next().map(|c| c.is_uppercase()).unwrap_or(false);
-        match (is_title_case, &self.title_case_version) {
-            (true, Some(title_case)) => Abbreviation { text: title_case, attach_to_previous: self.attach_to_previous },
-            _ => Abbreviation { text: &self.abbrev, attach_to_previous: self.attach_to_previous },
+    pub fn compile(rule: &Rule, order: usize) -> Result<Abbrev> {
+        let abbrev = rule.replacement.as_str();
+        let match_case = matches!(rule.case, None | Some(CasePolicy::Smart))
+            && CasePolicy::Smart.follows_case(abbrev);
+
+        let (title_case_version, upper_case_version) = if match_case {
+            (Some(to_title_case(abbrev)), Some(abbrev.to_uppercase()))
+        } else {
+            (None, None)
+        };
+
+        if rule.inflect && !matches!(rule.matcher, Matcher::Exact(_)) {
+            return Err(ParseErrorKind::InflectPattern.into());
+        }
+        if rule.except && !matches!(rule.matcher, Matcher::Exact(_)) {
+            return Err(ParseErrorKind::ExceptPattern.into());
+        }
+        let matcher = match &rule.matcher {
+            Matcher::Exact(text) => AbbrevMatcher::Lowercase(text.to_lowercase()),
+            Matcher::Regex { pattern, flags } => AbbrevMatcher::Regex(compile_regex(pattern, flags)?),
+            Matcher::Segment { .. } if rule.keep => return Err(ParseErrorKind::KeepSegment.into()),
+            Matcher::Segment { pattern, flags } => {
+                AbbrevMatcher::Segment(compile_regex(pattern, flags)?)
+            }
+            Matcher::Line { .. } if rule.keep => return Err(ParseErrorKind::KeepLine.into()),
+            Matcher::Line { pattern, flags } => AbbrevMatcher::Line(compile_regex(pattern, flags)?),
+        };
+
+        Ok(Abbrev {
+            matcher,
+            source: match rule.inflect {
+                true => format!("{}~", rule.matcher.source()),
+                false => rule.matcher.source(),
+            },
+            abbrev: abbrev.to_string(),
+            title_case_version,
+            upper_case_version,
+            escalations: rule
+                .escalations
+                .iter()
+                .map(|escalation| {
+                    let replacement = escalation.clone();
+                    let rule = Rule { replacement, escalations: vec![], ..rule.clone() };
+                    Abbrev::compile(&rule, order)
+                })
+                .collect::<Result<_>>()?,
+            localized: rule
+                .localized
+                .iter()
+                .filter(|(_, levels)| !levels.is_empty())
+                .map(|(tag, levels)| {
+                    let replacement = levels[0].clone();
+                    let escalations = levels[1..].to_vec();
+                    let localized = BTreeMap::new();
+                    let rule = Rule { replacement, escalations, localized, ..rule.clone() };
+                    Ok((tag.clone(), Abbrev::compile(&rule, order)?))
+                })
+                .collect::<Result<_>>()?,
+            attach_to_previous: rule.attach_to_previous,
+            attach_to_next: rule.attach_to_next,
+            priority: rule.priority,
+            min_overflow: rule.min_overflow,
+            order,
+            location: Location { file: None, line: order + 1 },
+            group: rule.group.clone(),
+            contexts: rule.contexts.clone(),
+            keep: rule.keep,
+            except: rule.except,
+            template: Template::parse(abbrev)?,
+            rule: rule.clone(),
+        })
+    }
+
+    pub fn kind(&self) -> RuleKind {
+        match self.matcher {
+            _ if self.keep => RuleKind::Keep,
+            _ if self.except => RuleKind::Except,
+            AbbrevMatcher::Regex(_) => RuleKind::Regex,
+            AbbrevMatcher::Segment(_) => RuleKind::Segment,
+            AbbrevMatcher::Line(_) => RuleKind::Line,
+            AbbrevMatcher::Lowercase(_) if self.abbrev.is_empty() => RuleKind::Remove,
+            AbbrevMatcher::Lowercase(_) => RuleKind::Exact,
+        }
+    }
+
+    fn with_matching_case_to(
+        &self,
+        original_text: &str,
+        default: CasePolicy,
+        rules: CaseRules,
+    ) -> Abbreviation<'_> {
+        let case = TextCase::of(original_text);
+        let policy = self.rule.case.unwrap_or(default);
+        // The versions are converted with the standard rules
+        let versions = match rules {
+            CaseRules::Standard => (&self.title_case_version, &self.upper_case_version),
+            CaseRules::Turkic => (&None, &None),
+        };
+        let text = match (&self.template, policy, case, versions) {
+            (Some(template), ..) => {
+                let expanded = template.expand(original_text);
+                Cow::Owned(match (policy, case) {
+                    (CasePolicy::Smart, _) if self.title_case_version.is_none() => expanded,
+                    (CasePolicy::Smart, TextCase::Title) => rules.to_title_case(&expanded),
+                    (CasePolicy::Smart, TextCase::Upper) => rules.to_uppercase(&expanded),
+                    _ => policy.apply(&expanded, case, rules).into_owned(),
+                })
+            }
+            (None, CasePolicy::Smart, TextCase::Title, (Some(title_case), _)) => {
+                Cow::Borrowed(title_case.as_str())
+            }
+            (None, CasePolicy::Smart, TextCase::Upper, (_, Some(upper_case))) => {
+                Cow::Borrowed(upper_case.as_str())
+            }
+            (None, ..) => policy.apply(&self.abbrev, case, rules),
+        };
+        Abbreviation {
+            text,
+            escalations: self
+                .escalations
+                .iter()
+                .map(|escalation| {
+                    escalation.with_matching_case_to(original_text, default, rules).text
+                })
+                .collect(),
+            attach_to_previous: self.attach_to_previous,
+            attach_to_next: self.attach_to_next,
+            priority: self.priority,
+            min_overflow: self.min_overflow,
+            matcher: &self.source,
         }
     }
 }
 
-fn parse_abbrev(line: &str) -> eyre::Result<Abbrev> {
-    let eq_pos = line
-        .find('=')
-        .context("Invalid abbreviation, no '=' found")?;
-    let (matcher_def, abbrev) = line.split_at(eq_pos);
-    let matcher = matcher_def.trim();
-    let abbrev = abbrev[1..].trim();
-    let (abbrev, attach_to_previous) = match abbrev.strip_prefix("<+") {
-        Some(abbrev) => (abbrev, true),
-        None => (abbrev, false),
+/// Where a rule is defined, for error messages.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Location {
+    /// The rules file, unless the rules were not loaded from a file
+    pub file: Option<Arc<Path>>,
+    /// 1-based line number
+    pub line: usize,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}", file.display(), self.line),
+            None => write!(f, "line {}", self.line),
+        }
+    }
+}
+
+/// Compiles a rule's regex, with its flags kept inline so the pattern can be
+/// reused in a `RegexSet`.
+fn compile_regex(pattern: &str, flags: &str) -> Result<Regex> {
+    let source = match flags.contains('i') {
+        true => format!("(?i){pattern}"),
+        false => pattern.to_string(),
     };
+    Regex::new(&Normalization::Canonical.apply(&source)).map_err(|err| {
+        // Syntax errors span several lines pointing at the error, ending with its description
+        let err = err.to_string();
+        let reason = err.lines().last().unwrap_or_default();
+        let reason = reason.trim_start_matches("error: ").to_string();
+        ParseErrorKind::InvalidRegex { pattern: pattern.to_string(), reason }.into()
+    })
+}
 
-    let match_case = abbrev
-        .chars()
-        .next()
-        .map(|c| c.is_lowercase())
-        .unwrap_or(false);
-
-    let title_case_version = if match_case {
-        let mut title_case = abbrev.to_string();
-        if !abbrev.is_empty() {
-            title_case[0..1].make_ascii_uppercase();
-            Some(title_case)
-        } else {
-            None
+/// Loads the rules of a file and the files it includes, in order, along with
+/// the paths of the files.
+///
+/// `chain` holds the files currently being loaded, to detect include cycles.
+fn load_file(
+    path: &Path,
+    chain: &mut Vec<PathBuf>,
+    (abbrevs, settings, files): (&mut Vec<Abbrev>, &mut Vec<Setting>, &mut Vec<PathBuf>),
+) -> Result<()> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if chain.contains(&canonical) {
+        let cycle = chain.iter().chain([&canonical]).cloned().collect();
+        return Err(ParseErrorKind::IncludeCycle(cycle).into());
+    }
+
+    let file = std::fs::read_to_string(path)
+        .map_err(|source| ShortenError::Io { path: path.to_path_buf(), source })?;
+    let file = strip_bom(&file);
+    files.push(path.to_path_buf());
+    let file_path = Arc::<Path>::from(path);
+    let locate = |mut abbrev: Abbrev| {
+        abbrev.location.file = Some(file_path.clone());
+        abbrev
+    };
+
+    if path.extension().is_some_and(|extension| extension == "toml") {
+        let parsed = toml_rules::parse(file).map_err(|err| err.in_file(path))?;
+        for pack in parsed.builtin {
+            abbrevs.extend(builtin::rules(&pack)?);
+        }
+        for (line, rule) in parsed.rules {
+            let abbrev = Abbrev::compile(&rule, line)
+                .map_err(|err| err.at_line(line).in_file(path))?;
+            abbrevs.push(locate(abbrev));
         }
+        settings.extend(parsed.settings);
+        return Ok(());
+    }
+
+    chain.push(canonical);
+    let mut parser = LineParser::default();
+    for (order, line) in file.lines().enumerate() {
+        let parsed = parser
+            .parse_line(line, order)
+            .map_err(|err| err.at_line(order).in_file(path))?;
+        match parsed {
+            ParsedLine::Rule(abbrev) => abbrevs.push(locate(*abbrev)),
+            ParsedLine::Setting(setting) => settings.push(setting),
+            ParsedLine::Include(include) => {
+                let included = resolve_include(path, &include)
+                    .map_err(|err| err.at_line(order).in_file(path))?;
+                let loaded = (&mut *abbrevs, &mut *settings, &mut *files);
+                load_file(&included, chain, loaded).map_err(|error| {
+                    ShortenError::Included { from: path.to_path_buf(), error: Box::new(error) }
+                })?;
+            }
+            ParsedLine::Builtin(packs) => {
+                for pack in packs {
+                    abbrevs.extend(builtin::rules(&pack)?);
+                }
+            }
+            ParsedLine::Empty => {}
+        }
+    }
+    chain.pop();
+
+    Ok(())
+}
+
+/// The text of a rules file without the byte order mark Windows editors start
+/// UTF-8 files with. Their `\r\n` line endings need nothing, since lines are
+/// trimmed.
+pub fn strip_bom(text: &str) -> &str {
+    text.strip_prefix('\u{feff}').unwrap_or(text)
+}
+
+/// The lines of rules written on one line, like
+/// `--rules-inline 'Architecture = arch; Meeting ='`: they are separated by
+/// `;`, and `\;` stands for a semicolon of a rule.
+pub fn inline_lines(rules: &str) -> Vec<String> {
+    let (mut lines, mut line) = (Vec::new(), String::new());
+    let mut chars = rules.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.next_if_eq(&';').is_some() => line.push(';'),
+            ';' => lines.push(std::mem::take(&mut line)),
+            c => line.push(c),
+        }
+    }
+    lines.push(line);
+    lines.iter().map(|line| line.trim().to_string()).collect()
+}
+
+/// Finds an included file next to the including one, or in the config directories.
+pub(crate) fn resolve_include(including: &Path, include: &Path) -> Result<PathBuf> {
+    let sibling = including.parent().unwrap_or(Path::new("")).join(include);
+    if sibling.exists() {
+        return Ok(sibling);
+    }
+
+    config::find_config_file(include)?
+        .ok_or_else(|| ParseErrorKind::IncludeNotFound(include.to_path_buf()).into())
+}
+
+/// A parsed line of an `abbrev.lst` file.
+pub enum ParsedLine {
+    /// Blank lines, comments and group headers
+    Empty,
+    Rule(Box<Abbrev>),
+    /// An `@include path` directive
+    Include(PathBuf),
+    /// A `@builtin months, weekdays` directive, with the names of the packs
+    Builtin(Vec<String>),
+    Setting(Setting),
+}
+
+/// A directive changing how the rules are applied.
+pub enum Setting {
+    /// `@enclose ⟦⟧ ⦅⦆`: more characters enclosing words, as opener/closer pairs
+    Enclose(Vec<(char, char)>),
+    /// `@enclose-only () []`: replaces the default enclosing characters
+    EncloseOnly(Vec<(char, char)>),
+    /// `@case mirror`: the case policy of the rules that don't have their own
+    Case(CasePolicy),
+    /// `@normalize nfkc`: the normalization form rules and text are compared in
+    Normalization(Normalization),
+    /// `@ignore-diacritics`: exact rules match text regardless of its accents
+    IgnoreDiacritics,
+    /// `@low the, of` and `@high ProjectX`: the order words are dropped in
+    WordPriority(WordPriority, Vec<String>),
+    /// `@fallback-cmd ~/bin/shorten.sh`: shortens the lines that are still too long
+    FallbackCommand(String),
+    /// `@fallback-timeout 500ms`: how long the fallback command and hooks may run
+    FallbackTimeout(std::time::Duration),
+    /// `@hook ticket ~/bin/ticket.sh`: a command replacing the words the rules
+    /// left alone
+    Hook(String, String),
+}
+
+impl Setting {
+    /// A `@hook`, which needs the `script-hooks` feature: each word it gets
+    /// starts a shell.
+    pub(crate) fn hook(name: String, command: String) -> Result<Setting> {
+        match cfg!(feature = "script-hooks") {
+            true => Ok(Setting::Hook(name, command)),
+            false => Err(ParseErrorKind::ScriptHooksDisabled(name).into()),
+        }
+    }
+}
+
+/// Parses the lines of an `abbrev.lst` file one at a time, keeping track of
+/// the `[group:name]` section the rules belong to.
+#[derive(Default)]
+pub struct LineParser {
+    group: Option<String>,
+    /// The `# desc:` and `# deprecated` comments waiting for their rule
+    description: Option<String>,
+    deprecated: bool,
+}
+
+impl LineParser {
+    pub fn parse_line(&mut self, line: &str, order: usize) -> Result<ParsedLine> {
+        let line = line.trim();
+
+        // Skip empty lines and comments, keeping the annotations of the next rule
+        if let Some(comment) = line.strip_prefix('#') {
+            let comment = comment.trim();
+            if let Some(description) = comment.strip_prefix("desc:") {
+                let description = description.trim();
+                match &mut self.description {
+                    Some(lines) => lines.extend([" ", description]),
+                    None => self.description = Some(description.to_string()),
+                }
+            } else if comment == "deprecated" {
+                self.deprecated = true;
+            }
+            return Ok(ParsedLine::Empty);
+        }
+        if line.is_empty() {
+            return Ok(ParsedLine::Empty);
+        }
+
+        if let Some(group) = parse_group_header(line) {
+            self.group = Some(group.to_string());
+            return Ok(ParsedLine::Empty);
+        }
+
+        if let Some(pairs) = line.strip_prefix("@enclose-only ") {
+            return Ok(ParsedLine::Setting(Setting::EncloseOnly(enclosing::parse_pairs(pairs)?)));
+        }
+        if let Some(pairs) = line.strip_prefix("@enclose ") {
+            return Ok(ParsedLine::Setting(Setting::Enclose(enclosing::parse_pairs(pairs)?)));
+        }
+        if let Some(name) = line.strip_prefix("@case ") {
+            let case = CasePolicy::from_name(name.trim())
+                .ok_or_else(|| ParseErrorKind::InvalidCasePolicy(name.trim().to_string()))?;
+            return Ok(ParsedLine::Setting(Setting::Case(case)));
+        }
+        if let Some(name) = line.strip_prefix("@normalize ") {
+            let normalization = Normalization::from_name(name.trim())
+                .ok_or_else(|| ParseErrorKind::InvalidNormalization(name.trim().to_string()))?;
+            return Ok(ParsedLine::Setting(Setting::Normalization(normalization)));
+        }
+        if line == "@ignore-diacritics" {
+            return Ok(ParsedLine::Setting(Setting::IgnoreDiacritics));
+        }
+        if let Some(command) = line.strip_prefix("@fallback-cmd ") {
+            return Ok(ParsedLine::Setting(Setting::FallbackCommand(command.trim().to_string())));
+        }
+        if let Some(hook) = line.strip_prefix("@hook ") {
+            let Some((name, command)) = hook.trim().split_once(char::is_whitespace) else {
+                return Err(ParseErrorKind::InvalidHook(hook.trim().to_string()).into());
+            };
+            let (name, command) = (name.to_string(), command.trim().to_string());
+            return Ok(ParsedLine::Setting(Setting::hook(name, command)?));
+        }
+        if let Some(timeout) = line.strip_prefix("@fallback-timeout ") {
+            let timeout = fallback::parse_timeout(timeout)
+                .ok_or_else(|| ParseErrorKind::InvalidTimeout(timeout.trim().to_string()))?;
+            return Ok(ParsedLine::Setting(Setting::FallbackTimeout(timeout)));
+        }
+        let directives = [("@low ", WordPriority::Low), ("@high ", WordPriority::High)];
+        for (directive, priority) in directives {
+            if let Some(words) = line.strip_prefix(directive) {
+                let words = words.split([',', ' ']).filter(|word| !word.is_empty());
+                let words = words.map(str::to_string).collect();
+                return Ok(ParsedLine::Setting(Setting::WordPriority(priority, words)));
+            }
+        }
+
+        if let Some(packs) = line.strip_prefix("@builtin ") {
+            return Ok(ParsedLine::Builtin(builtin::parse_names(packs)?));
+        }
+        let (directive, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        if directive == "@include" {
+            let include = argument.trim();
+            if include.is_empty() {
+                return Err(ParseErrorKind::MissingInclude.into());
+            }
+            return Ok(ParsedLine::Include(PathBuf::from(include)));
+        }
+        // Rules always have a `=`, unlike directives, so `@channel = @ch` is still a rule
+        if directive.starts_with('@') && !directive.starts_with("@iflen>") && !line.contains('=') {
+            return Err(ParseErrorKind::UnknownDirective(directive.to_string()).into());
+        }
+
+        let metadata = (self.description.take(), std::mem::take(&mut self.deprecated));
+        let abbrev = parse_abbrev(line, order, self.group.as_deref(), metadata)?;
+        Ok(ParsedLine::Rule(Box::new(abbrev)))
+    }
+}
+
+/// The name in a `[group:name]` header line.
+fn parse_group_header(line: &str) -> Option<&str> {
+    let group = line.strip_prefix("[group:")?.strip_suffix(']')?.trim();
+    (!line.contains('=') && !group.is_empty()).then_some(group)
+}
+
+fn parse_abbrev(
+    line: &str,
+    order: usize,
+    group: Option<&str>,
+    (description, deprecated): (Option<String>, bool),
+) -> Result<Abbrev> {
+    let prefixes = parse_prefixes(line)?;
+    let line = prefixes.line;
+    let (matcher, abbrev, attach_to_previous) = if prefixes.keep || prefixes.except {
+        (line, "", false)
     } else {
-        None
+        let eq_pos = line
+            .find('=')
+            .ok_or(ParseErrorKind::MissingEquals)?;
+        let (matcher_def, abbrev) = line.split_at(eq_pos);
+        let abbrev = abbrev[1..].trim();
+        match abbrev.strip_prefix("<+") {
+            Some(abbrev) => (matcher_def.trim(), abbrev, true),
+            None => (matcher_def.trim(), abbrev, false),
+        }
+    };
+    let (abbrev, attach_to_next) = match abbrev.strip_suffix("+>") {
+        Some(abbrev) => (abbrev.trim_end(), true),
+        None => (abbrev, false),
     };
+    let (abbrev, escalations, localized) = parse_levels(abbrev);
 
-    if let Some(regex_matcher) = matcher.strip_prefix('/') {
-        let Some(closing_pos) = regex_matcher.find('/') else {
-            bail!("Invalid regex, no closing '/' found");
-        };
-        let flags = &regex_matcher[closing_pos + 1..];
-        let re = RegexBuilder::new(&regex_matcher[..closing_pos])
-            .case_insensitive(flags.contains('i'))
-            .build()?;
+    let (matcher, inflect) = parse_inflected_matcher(matcher)?;
+    let rule = Rule {
+        matcher,
+        replacement: abbrev.to_string(),
+        escalations,
+        localized,
+        attach_to_previous,
+        attach_to_next,
+        priority: prefixes.priority,
+        min_overflow: prefixes.min_overflow,
+        case: None,
+        group: group.map(str::to_string),
+        contexts: prefixes.contexts,
+        keep: prefixes.keep,
+        except: prefixes.except,
+        inflect,
+        description,
+        deprecated,
+    };
+    Abbrev::compile(&rule, order)
+}
 
-        Ok(Abbrev {
-            matcher: AbbrevMatcher::Regex(re),
-            abbrev: abbrev.to_string(),
-            title_case_version,
-            attach_to_previous,
-        })
+/// Splits the tiers of abbreviations, like `arch | A.`, into the first one,
+/// the escalations and the levels for other locales, like the `de:Abt.` of
+/// `dept | de:Abt.`.
+pub(crate) fn parse_levels(levels: &str) -> (&str, Vec<String>, BTreeMap<String, Vec<String>>) {
+    let mut levels = levels.split(" | ").map(str::trim);
+    let first = levels.next().unwrap_or_default();
+    let (mut escalations, mut localized) = (Vec::new(), BTreeMap::<_, Vec<_>>::new());
+    for level in levels {
+        match locale::split_tagged(level) {
+            Some((tag, level)) => localized.entry(tag.to_string()).or_default().push(level.into()),
+            None => escalations.push(level.to_string()),
+        }
+    }
+    (first, escalations, localized)
+}
+
+/// Parses a matcher, along with the `~` after an exact one that makes it
+/// match plurals and possessives too.
+pub(crate) fn parse_inflected_matcher(matcher: &str) -> Result<(Matcher, bool)> {
+    Ok(match parse_matcher(matcher)? {
+        Matcher::Exact(text) if text.len() > 1 && text.ends_with('~') => {
+            (Matcher::Exact(text[..text.len() - 1].trim_end().to_string()), true)
+        }
+        matcher => (matcher, false),
+    })
+}
+
+/// Parses a matcher as written in an `abbrev.lst` file: `(/segment/flags)`,
+/// `/regex/flags`, `^line$` or the exact text.
+pub(crate) fn parse_matcher(matcher: &str) -> Result<Matcher> {
+    let segment = matcher.strip_prefix("(/").and_then(|segment| segment.strip_suffix(')'));
+    Ok(if let Some(segment) = segment {
+        let (pattern, flags) = parse_regex(segment)?;
+        Matcher::Segment { pattern, flags }
+    } else if let Some(regex_matcher) = matcher.strip_prefix('/') {
+        let (pattern, flags) = parse_regex(regex_matcher)?;
+        Matcher::Regex { pattern, flags }
+    } else if matcher.len() > 1 && matcher.starts_with('^') && matcher.ends_with('$') {
+        Matcher::Line { pattern: matcher.to_string(), flags: String::new() }
     } else {
-        Ok(Abbrev {
-            matcher: AbbrevMatcher::Lowercase(matcher.to_lowercase()),
-            abbrev: abbrev.to_string(),
-            title_case_version,
-            attach_to_previous,
-        })
+        Matcher::Exact(matcher.to_string())
+    })
+}
+
+/// Splits `pattern/flags`, the rest of a regex matcher after its opening '/'.
+fn parse_regex(regex_matcher: &str) -> Result<(String, String)> {
+    let Some(closing_pos) = regex_matcher.find('/') else {
+        return Err(ParseErrorKind::UnclosedRegex.into());
+    };
+    let (pattern, flags) = (&regex_matcher[..closing_pos], &regex_matcher[closing_pos + 1..]);
+    Ok((pattern.to_string(), flags.to_string()))
+}
+
+/// What precedes the matcher on a rule line.
+struct Prefixes<'a> {
+    priority: i32,
+    /// From `@iflen>N`
+    min_overflow: usize,
+    contexts: Vec<String>,
+    /// A `!keep Word` rule, which has no `= abbreviation` part
+    keep: bool,
+    /// A `!except Word` rule, which has none either
+    except: bool,
+    /// The rest of the line
+    line: &'a str,
+}
+
+/// Strips the optional `!N` priority, `!keep` or `!except`, `[ctx:a,b]` context and
+/// `@iflen>N` condition prefixes from a rule line, in any order.
+fn parse_prefixes(line: &str) -> Result<Prefixes<'_>> {
+    let mut prefixes = Prefixes {
+        priority: 0,
+        min_overflow: 0,
+        contexts: Vec::new(),
+        keep: false,
+        except: false,
+        line,
+    };
+    loop {
+        let line = prefixes.line;
+        if let Some(rest) = line.strip_prefix('!') {
+            let (value, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if value == "keep" {
+                prefixes.keep = true;
+            } else if value == "except" {
+                prefixes.except = true;
+            } else {
+                prefixes.priority = value
+                    .parse()
+                    .map_err(|_| ParseErrorKind::InvalidPriority(value.to_string()))?;
+            }
+            prefixes.line = rest.trim_start();
+        } else if let Some(rest) = line.strip_prefix("[ctx:") {
+            let (tags, rest) = rest
+                .split_once(']')
+                .ok_or(ParseErrorKind::UnclosedContext)?;
+            prefixes.contexts.extend(
+                tags.split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_string),
+            );
+            prefixes.line = rest.trim_start();
+        } else if let Some(rest) = line.strip_prefix("@iflen>") {
+            let (value, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            prefixes.min_overflow = value
+                .parse()
+                .map_err(|_| ParseErrorKind::InvalidLengthCondition(value.to_string()))?;
+            prefixes.line = rest.trim_start();
+        } else {
+            return Ok(prefixes);
+        }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::{inline_lines, Abbreviator, AbbreviatorBuilder, Normalization};
+    use crate::locale::Locale;
+    use crate::rule::{Matcher, Rule};
+
+    #[test]
+    fn test_priority() {
+        let abbreviator = Abbreviator::from_lines(
+            r#"
+                /^Arch/i = ar
+                Architecture = arch
+                !5 /ure$/ = ure
+                !1 Session = sesn
+                Session =
+            "#
+            .lines(),
+        )
+        .unwrap();
+
+        let abbreviate = |text| abbreviator.abbreviate(text).map(|abbrev| abbrev.text.into_owned());
+        assert_eq!(abbreviate("architecture").as_deref(), Some("ure"));
+        assert_eq!(abbreviate("archive").as_deref(), Some("ar"));
+        assert_eq!(abbreviate("ARCHIVE").as_deref(), Some("AR"));
+        assert_eq!(abbreviate("ARCHITECTURE").as_deref(), Some("ARCH"));
+        assert_eq!(abbreviate("Session").as_deref(), Some("Sesn"));
+        assert!(Abbreviator::from_lines(["!x Session = sesn"].into_iter()).is_err());
+        let Err(err) = Abbreviator::from_lines(["Session = sesn", "/(sess/ = S"].into_iter()) else {
+            panic!("invalid regex accepted");
+        };
+        assert_eq!(format!("{err:#}"), "line 2: Invalid regex '(sess': unclosed group");
+    }
+
+    #[test]
+    fn test_regex_set() {
+        let lines = (0..50).map(|index| format!("/^term{index}$/ = t{index}"));
+        let mut lines = lines.collect::<Vec<_>>();
+        lines.extend([
+            "/^Meet(ing)?s?$/ = Mtg".to_string(),
+            "/^meet/i = mt".to_string(),
+            "/^(\\d+)(st|nd|rd|th)$/ = #{match}".to_string(),
+        ]);
+        let abbreviator = Abbreviator::from_lines(lines.iter().map(String::as_str)).unwrap();
+
+        let abbreviate = |text| abbreviator.abbreviate(text).map(|abbrev| abbrev.text.into_owned());
+        // The first matching regex of the file wins, each with its own flags
+        assert_eq!(abbreviate("Meetings").as_deref(), Some("Mtg"));
+        assert_eq!(abbreviate("meetings").as_deref(), Some("mt"));
+        assert_eq!(abbreviate("MEETUP").as_deref(), Some("MT"));
+        assert_eq!(abbreviate("term42").as_deref(), Some("t42"));
+        assert_eq!(abbreviate("TERM42"), None);
+        assert_eq!(abbreviate("23rd").as_deref(), Some("#23rd"));
+        assert_eq!(abbreviate("Architecture"), None);
+
+        // Regexes that only go over the size limit together are tried one by one
+        let lines = ["/^a\\w{100}$/ = A", "/^b\\w{100}$/ = B"];
+        let lines = lines.into_iter().chain(["/^\\w{100}c$/ = C", "/^\\w{101}$/ = D"]);
+        let abbreviator = Abbreviator::from_lines(lines).unwrap();
+        assert!(abbreviator.matchers.regex_set.is_none());
+        let abbreviate = |text: &str| abbreviator.abbreviate(text).map(|abbrev| abbrev.text);
+        assert_eq!(abbreviate(&format!("b{}", "x".repeat(100))).as_deref(), Some("B"));
+        assert_eq!(abbreviate(&format!("b{}c", "x".repeat(99))).as_deref(), Some("B"));
+        assert_eq!(abbreviate(&format!("{}c", "x".repeat(100))).as_deref(), Some("C"));
+        assert_eq!(abbreviate("Architecture"), None);
+    }
+
+    #[test]
+    fn test_matching_case() {
+        let abbreviator = Abbreviator::from_lines(
+            ["Architecture = arch", "École = éc", "Department = DEPT"].into_iter(),
+        )
+        .unwrap();
+
+        let abbreviate = |text| abbreviator.abbreviate(text).map(|abbrev| abbrev.text.into_owned());
+        assert_eq!(abbreviate("architecture").as_deref(), Some("arch"));
+        assert_eq!(abbreviate("Architecture").as_deref(), Some("Arch"));
+        assert_eq!(abbreviate("ARCHITECTURE").as_deref(), Some("ARCH"));
+        assert_eq!(abbreviate("ÉCOLE").as_deref(), Some("ÉC"));
+        assert_eq!(abbreviate("École").as_deref(), Some("Éc"));
+        assert_eq!(abbreviate("department").as_deref(), Some("DEPT"));
+    }
+
+    #[test]
+    fn test_groups() {
+        let lines = r#"
+            Architecture = arch
+
+            [group:japanese]
+            Section = <+課
+            Weekly = 毎週
+
+            [group:corp]
+            Weekly = wkly
+        "#;
+        let load = || Abbreviator::from_lines(lines.lines()).unwrap();
+
+        let abbreviator = load();
+        assert_eq!(abbreviator.abbreviate("weekly").map(|abbrev| abbrev.text).as_deref(), Some("wkly"));
+
+        let abbreviator = load().with_groups(&["japanese"], &[]).unwrap();
+        assert_eq!(abbreviator.abbreviate("weekly").map(|abbrev| abbrev.text).as_deref(), Some("毎週"));
+        assert_eq!(abbreviator.abbreviate("architecture").map(|abbrev| abbrev.text).as_deref(), Some("arch"));
+
+        let abbreviator = load().with_groups(&[], &["japanese", "corp"]).unwrap();
+        assert!(abbreviator.abbreviate("weekly").is_none());
+        assert!(abbreviator.abbreviate("section").is_none());
+    }
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("shorten-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("extra")).unwrap();
+        std::fs::write(dir.join("abbrev.lst"), "Architecture = arch\n@include extra/more.lst\n").unwrap();
+        let more = "Session = sesn | de:Sitz.\n@include cycle.lst\n";
+        std::fs::write(dir.join("extra/more.lst"), more).unwrap();
+        std::fs::write(dir.join("extra/cycle.lst"), "Section = <+課\n").unwrap();
+
+        let abbreviator = Abbreviator::try_from_file(&dir.join("abbrev.lst")).unwrap();
+        assert_eq!(abbreviator.rules().len(), 3);
+        let files = ["abbrev.lst", "extra/more.lst", "extra/cycle.lst"].map(|file| dir.join(file));
+        assert_eq!(abbreviator.files(), files);
+        assert_eq!(abbreviator.abbreviate("session").map(|abbrev| abbrev.text).as_deref(), Some("sesn"));
+        let german = abbreviator.with_locale(Locale::parse("de")).unwrap();
+        let orders = german.rules().iter().map(|abbrev| abbrev.order).collect::<Vec<_>>();
+        assert_eq!(orders, [0, 1, 2]);
+
+        std::fs::write(dir.join("extra/cycle.lst"), "@include ../abbrev.lst\n").unwrap();
+        let Err(err) = Abbreviator::try_from_file(&dir.join("abbrev.lst")) else {
+            panic!("include cycle not detected");
+        };
+        assert!(format!("{err:#}").contains("Include cycle"), "{err:#}");
+        assert!(format!("{err:#}").contains("In file included from"), "{err:#}");
+
+        std::fs::write(dir.join("extra/cycle.lst"), "Section <+課\n").unwrap();
+        let Err(err) = Abbreviator::try_from_file(&dir.join("abbrev.lst")) else {
+            panic!("invalid rule accepted");
+        };
+        assert!(format!("{err:#}").contains("cycle.lst:1: Invalid abbreviation"), "{err:#}");
+
+        // The file name is a separate word
+        std::fs::write(dir.join("extra/cycle.lst"), "Section = <+課\n").unwrap();
+        std::fs::write(dir.join("abbrev.lst"), "@includeextra/more.lst\n").unwrap();
+        let Err(err) = Abbreviator::try_from_file(&dir.join("abbrev.lst")) else {
+            panic!("unknown directive accepted");
+        };
+        assert!(format!("{err:#}").contains("Unknown directive '@includeextra/more.lst'"));
+        let rules = "@include\textra/more.lst\n@channel = @ch\n";
+        std::fs::write(dir.join("abbrev.lst"), rules).unwrap();
+        let abbreviator = Abbreviator::try_from_file(&dir.join("abbrev.lst")).unwrap();
+        let abbreviate = |text| abbreviator.abbreviate(text).map(|abbrev| abbrev.text);
+        assert_eq!(abbreviate("@channel").as_deref(), Some("@ch"));
+        assert_eq!(abbreviate("session").as_deref(), Some("sesn"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_windows_files() {
+        let dir = std::env::temp_dir().join(format!("shorten-windows-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rules = "\u{feff}Architecture = arch\r\n/^Sess/ = S\r\nReview = rvw\r";
+        std::fs::write(dir.join("abbrev.lst"), rules).unwrap();
+        let toml = "\u{feff}[[rule]]\r\nmatcher = \"Architecture\"\r\nreplacement = \"arch\"\r\n";
+        std::fs::write(dir.join("abbrev.toml"), toml).unwrap();
+
+        let abbreviator = Abbreviator::try_from_file(&dir.join("abbrev.lst")).unwrap();
+        let rules = abbreviator.rules();
+        let sources = rules.iter().map(|abbrev| abbrev.source.as_str()).collect::<Vec<_>>();
+        assert_eq!(sources, ["Architecture", "/^Sess/", "Review"]);
+        assert_eq!(abbreviator.abbreviate("review").unwrap().text, "rvw");
+        let abbreviator = Abbreviator::try_from_file(&dir.join("abbrev.toml")).unwrap();
+        assert_eq!(abbreviator.abbreviate("architecture").unwrap().text, "arch");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_inline_lines() {
+        let lines = inline_lines("Architecture=arch; Meeting=;/\\bQ(\\d)\\;/i = Q$1");
+        assert_eq!(lines, ["Architecture=arch", "Meeting=", "/\\bQ(\\d);/i = Q$1"]);
+        let abbreviator = Abbreviator::from_lines(lines.iter().map(String::as_str)).unwrap();
+        let abbreviate = |text| abbreviator.abbreviate(text).map(|abbrev| abbrev.text.to_string());
+        assert_eq!(abbreviate("Architecture").as_deref(), Some("Arch"));
+        assert_eq!(abbreviate("Meeting").as_deref(), Some(""));
+    }
+
+    #[test]
+    fn test_contexts() {
+        let lines = r#"
+            Architecture = arch
+            [ctx:work] Architecture = ARC
+            !2 [ctx:home, laptop] Session = sesn
+            [Monthly] = [M]
+        "#;
+        let load = || Abbreviator::from_lines(lines.lines()).unwrap();
+        let abbreviate = |abbreviator: &Abbreviator, text| {
+            abbreviator.abbreviate(text).map(|abbrev| abbrev.text.to_string())
+        };
+
+        let abbreviator = load().with_contexts::<&str>(&[]).unwrap();
+        assert_eq!(abbreviate(&abbreviator, "architecture").as_deref(), Some("arch"));
+        assert_eq!(abbreviate(&abbreviator, "session"), None);
+        assert_eq!(abbreviate(&abbreviator, "[Monthly]").as_deref(), Some("[M]"));
+
+        let abbreviator = load().with_contexts(&["work", "laptop"]).unwrap();
+        assert_eq!(abbreviate(&abbreviator, "architecture").as_deref(), Some("ARC"));
+        assert_eq!(abbreviate(&abbreviator, "session").as_deref(), Some("sesn"));
+    }
+
+    #[test]
+    fn test_locales() {
+        let lines = r#"
+            Department = dept | de:Abt. | D. | pt-BR:depto
+            istanbul = ist
+        "#;
+        let load = |locale: &str| {
+            let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+            abbreviator.with_locale(Locale::parse(locale)).unwrap()
+        };
+        let abbreviate = |abbreviator: &Abbreviator, text| {
+            let abbrev = abbreviator.abbreviate(text).unwrap();
+            (abbrev.text.to_string(), abbrev.escalations.join(", "))
+        };
+
+        let abbreviator = load("");
+        assert_eq!(abbreviate(&abbreviator, "Department"), ("Dept".into(), "D.".into()));
+        assert_eq!(abbreviate(&abbreviator, "Istanbul").0, "Ist");
+        let abbreviator = load("de_AT.UTF-8");
+        assert_eq!(abbreviate(&abbreviator, "Department"), ("Abt.".into(), "".into()));
+        assert_eq!(abbreviate(&load("pt-BR"), "DEPARTMENT").0, "DEPTO");
+        assert_eq!(abbreviate(&load("pt-PT"), "Department").0, "Dept");
+        assert_eq!(abbreviate(&load("tr"), "Istanbul").0, "İst");
+    }
+
+    #[test]
+    fn test_malformed_rules() {
+        let lines = [
+            "=", "= x", "!", "!5", "!keep", "/(/ = x", "/^a/ = {match", "(/ =", "(/x/", "[ = ]",
+            "「 = 」", "« =", "%include", "^(.*)$ = $9", "/^(a)$/ = $1$2", "Arch\u{301} = a",
+        ];
+        for line in lines {
+            let Ok(abbreviator) = Abbreviator::from_lines([line].into_iter()) else {
+                continue;
+            };
+            for text in ["a", "Arch", "「", "«Arch", "x y"] {
+                abbreviator.abbreviate(text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_phrase_lengths() {
+        let lines = r#"
+            Monthly All = MA
+            Monthly All Hands = MAH
+            All-Hands = AH
+            Follow Up = FU
+        "#;
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let words = ["MONTHLY", "all", "hands", "-", "follow-up", "upkeep"];
+        assert_eq!(
+            abbreviator.phrase_lengths(&words),
+            vec![vec![2, 3, 4], vec![2, 3], vec![], vec![2], vec![1], vec![]],
+        );
+    }
+
+    #[test]
+    fn test_keep() {
+        let lines = r#"
+            ProjectX = px
+            !keep ProjectX
+            [ctx:work] !keep /^[A-Z]+-\d+$/
+        "#;
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        assert!(abbreviator.is_kept("projectx"));
+        assert!(abbreviator.is_kept("OPS-123"));
+        assert!(!abbreviator.is_kept("ops-123"));
+        assert_eq!(abbreviator.rules().len(), 3);
+
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let abbreviator = abbreviator.with_contexts::<&str>(&[]).unwrap();
+        assert!(!abbreviator.is_kept("OPS-123"));
+    }
+
+    #[test]
+    fn test_builder() {
+        let abbreviator = AbbreviatorBuilder::new()
+            .exact("Architecture", "arch")
+            .remove("Rescheduled")
+            .attach("Section", "課")
+            .keep("ProjectX")
+            .rule(Rule { priority: 5, ..Rule::new(Matcher::Exact("Architecture".into()), "A") })
+            .build()
+            .unwrap();
+        let abbreviate = |text| {
+            abbreviator.abbreviate(text).map(|abbrev| (abbrev.text, abbrev.attach_to_previous))
+        };
+        assert_eq!(abbreviate("Architecture"), Some(("A".into(), false)));
+        assert_eq!(abbreviate("rescheduled"), Some(("".into(), true)));
+        assert_eq!(abbreviate("Section"), Some(("課".into(), true)));
+        assert!(abbreviator.is_kept("projectx"));
+        assert!(AbbreviatorBuilder::new().regex("(sess", "S").build().is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_snapshot() {
+        let lines = "Architecture = arch | A\n/^sess/i = S\n!keep ProjectX\n@enclose-only []";
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let json = serde_json::to_string(&abbreviator).unwrap();
+        let restored: Abbreviator = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&restored).unwrap(), json);
+        assert_eq!(restored.abbreviate("Sessions").unwrap().text, "S");
+        assert!(restored.is_kept("projectx"));
+        assert!(!restored.enclosing().is_opener('('));
+        assert!(restored.enclosing().is_opener('['));
+
+        let json = r#"{"rules": [{"matcher": {"exact": "Section"}, "replacement": "課",
+                        "attach_to_previous": true}]}"#;
+        let abbreviator: Abbreviator = serde_json::from_str(json).unwrap();
+        assert!(abbreviator.abbreviate("Section").unwrap().attach_to_previous);
+        assert!(abbreviator.enclosing().is_opener('('));
+        let invalid = r#"{"rules": [{"matcher": {"regex": {"pattern": "(sess"}}}]}"#;
+        assert!(serde_json::from_str::<Abbreviator>(invalid).is_err());
+    }
+
+    #[test]
+    fn test_enclose() {
+        let lines = ["@enclose 「」 `", "[group:work]", "Architecture = arch"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter())
+            .unwrap()
+            .with_groups::<&str>(&[], &["work"])
+            .unwrap();
+        assert!(abbreviator.enclosing().is_opener('「'));
+        assert!(abbreviator.enclosing().is_closer('`'));
+        assert!(abbreviator.enclosing().is_opener('('));
+
+        let abbreviator = Abbreviator::from_lines(["@enclose-only «»"].into_iter()).unwrap();
+        assert!(abbreviator.enclosing().is_opener('«'));
+        assert!(!abbreviator.enclosing().is_opener('('));
+        assert!(Abbreviator::from_lines(["@enclose <<>>"].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_normalization() {
+        let lines = ["Café = C", "/^São$/ = S", "Crème Brûlée = CB", "file = f"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        assert_eq!(abbreviator.abbreviate("Cafe\u{301}").unwrap().text, "C");
+        assert_eq!(abbreviator.abbreviate("Sa\u{303}o").unwrap().text, "S");
+        assert_eq!(abbreviator.phrase_lengths(&["Cre\u{300}me", "Bru\u{302}le\u{301}e"])[0], [2]);
+        assert!(abbreviator.abbreviate("ﬁle").is_none());
+
+        let abbreviator = abbreviator.with_normalization(Normalization::Compatibility).unwrap();
+        assert_eq!(abbreviator.abbreviate("ﬁle").unwrap().text, "f");
+        let lines = ["@normalize nfkc", "Ａ = a"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        assert_eq!(abbreviator.abbreviate("A").unwrap().text, "A");
+        let Err(err) = Abbreviator::from_lines(["@normalize nfd"].into_iter()) else {
+            panic!("unknown forms are rejected");
+        };
+        let expected = "line 1: Invalid normalization form 'nfd', expected nfc or nfkc";
+        assert_eq!(err.to_string(), expected);
+    }
+
+    #[test]
+    fn test_inflections() {
+        let lines = ["Session~ = sesn", "Class~ = cls", "Category~ = cat", "Audience = audn"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        assert_eq!(abbreviator.abbreviate("Sessions").unwrap().text, "Sesns");
+        assert_eq!(abbreviator.abbreviate("CLASSES").unwrap().text, "CLSES");
+        assert_eq!(abbreviator.abbreviate("categories’").unwrap().text, "cats’");
+        assert_eq!(abbreviator.abbreviate("Sessions").unwrap().matcher, "Session~");
+        assert!(abbreviator.abbreviate("Audiences").is_none());
+        let regex = Matcher::Regex { pattern: "^sesn$".into(), flags: String::new() };
+        let rule = Rule { inflect: true, ..Rule::new(regex, "s") };
+        assert!(AbbreviatorBuilder::new().rule(rule).build().is_err());
+    }
+
+    #[test]
+    fn test_ignore_diacritics() {
+        let lines = ["São Paulo = SP", "Café = C", "/^Crème$/ = Cr"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        assert!(abbreviator.abbreviate("Cafe").is_none());
+
+        let abbreviator = abbreviator.with_diacritics_ignored(true).unwrap();
+        assert_eq!(abbreviator.abbreviate("Cafe").unwrap().text, "C");
+        assert_eq!(abbreviator.abbreviate("cafè").unwrap().text, "C");
+        assert_eq!(abbreviator.phrase_lengths(&["Sao", "Paulo"])[0], [2]);
+        assert!(abbreviator.abbreviate("Creme").is_none());
+        let abbreviator = Abbreviator::from_lines(["@ignore-diacritics", "Café = C"].into_iter());
+        assert!(abbreviator.unwrap().ignores_diacritics());
+    }
+
+    #[test]
+    fn test_rule_metadata() {
+        let lines = [
+            "# desc: Until the team is renamed,",
+            "# desc: then use A.",
+            "# deprecated",
+            "Architecture = arch",
+            "Session = sesn",
+        ];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        let rules = abbreviator.rules();
+        let description = rules[0].rule.description.as_deref();
+        assert_eq!(description, Some("Until the team is renamed, then use A."));
+        assert!(rules[0].rule.deprecated && !rules[1].rule.deprecated);
+        assert_eq!(rules[1].rule.description, None);
+        assert_eq!(abbreviator.abbreviate("Architecture").unwrap().text, "Arch");
+        assert!(abbreviator.is_deprecated("Architecture") && !abbreviator.is_deprecated("Session"));
+    }
+}
@@ -0,0 +1,121 @@
+//! ANSI escape sequences, like the colors of prompt strings, which take no
+//! room on screen and must never be split.
+
+use crate::shortener::Span;
+
+/// A text with its escape sequences taken out.
+pub(crate) struct Stripped<'a> {
+    pub plain: String,
+    /// The escape sequences, along with their byte offsets in the plain text
+    pub sequences: Vec<(usize, &'a str)>,
+}
+
+impl Stripped<'_> {
+    /// The byte offset in the original text of an offset in the plain text,
+    /// after the escape sequences in front of it.
+    pub fn original_offset(&self, offset: usize) -> usize {
+        let before = self.sequences.iter().take_while(|(at, _)| *at <= offset);
+        offset + before.map(|(_, sequence)| sequence.len()).sum::<usize>()
+    }
+}
+
+/// Takes the escape sequences out of the text, or returns `None` when it has none.
+pub(crate) fn strip(text: &str) -> Option<Stripped<'_>> {
+    if !text.contains('\x1b') {
+        return None;
+    }
+
+    let mut plain = String::with_capacity(text.len());
+    let mut sequences = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('\x1b') {
+        plain.push_str(&rest[..start]);
+        let end = start + sequence_len(&rest[start..]);
+        sequences.push((plain.len(), &rest[start..end]));
+        rest = &rest[end..];
+    }
+    plain.push_str(rest);
+    Some(Stripped { plain, sequences })
+}
+
+/// The length in bytes of the escape sequence at the start of the text.
+pub(crate) fn sequence_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    match bytes.get(1) {
+        // CSI, like the SGR "ESC[1;34m": parameters and intermediates, then a final byte
+        Some(b'[') => match bytes[2..].iter().position(|b| !(0x20..=0x3f).contains(b)) {
+            Some(index) if (0x40..=0x7e).contains(&bytes[index + 2]) => index + 3,
+            Some(index) => index + 2,
+            None => bytes.len(),
+        },
+        // OSC, like hyperlinks and window titles, ended by BEL or ST
+        Some(b']') => {
+            let bel = text[2..].find('\x07').map(|index| index + 3);
+            let st = text[2..].find("\x1b\\").map(|index| index + 4);
+            bel.into_iter().chain(st).min().unwrap_or(bytes.len())
+        }
+        Some(_) => 1 + text[1..].chars().next().map_or(0, char::len_utf8),
+        None => 1,
+    }
+}
+
+/// Puts the escape sequences back into the shortened plain text, next to the
+/// words they were next to. The sequences inside a replaced word go before
+/// its replacement, and the ones of dropped or cut words where the words were,
+/// so every sequence is emitted and the colors end up reset as they were.
+pub(crate) fn restyle(stripped: &Stripped<'_>, shortened: &str, spans: &[Span]) -> String {
+    let len = stripped.sequences.iter().map(|(_, sequence)| sequence.len()).sum::<usize>();
+    let mut styled = String::with_capacity(shortened.len() + len);
+    let mut end = 0;
+    for &(offset, sequence) in &stripped.sequences {
+        let position = position(&stripped.plain, shortened, spans, offset).max(end);
+        styled.push_str(&shortened[end..position]);
+        styled.push_str(sequence);
+        end = position;
+    }
+    styled.push_str(&shortened[end..]);
+    styled
+}
+
+/// Where an offset of the plain text ended up in the shortened text.
+fn position(plain: &str, shortened: &str, spans: &[Span], offset: usize) -> usize {
+    let Some(span) = spans.iter().find(|span| span.original.end >= offset) else {
+        return shortened.len();
+    };
+    let (original, output) = (&span.original, &span.shortened);
+    if offset <= original.start {
+        output.start
+    } else if offset == original.end {
+        output.end
+    } else if plain[original.clone()].starts_with(&shortened[output.clone()]) {
+        // The word was kept, or only cut
+        (output.start + offset - original.start).min(output.end)
+    } else {
+        output.start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::shortener::Shortener;
+
+    #[test]
+    fn test_shorten_ansi() {
+        let rules = ["Architecture = arch", "Rescheduled ="];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(16, abbreviator).unwrap().ansi(true);
+        let shorten = |text| shortener.shorten(text).into_owned();
+
+        let text = "\x1b[1;34mArchitecture\x1b[0m Learning";
+        assert_eq!(shorten(text), "\x1b[1;34mArch\x1b[0m Learning");
+        assert_eq!(shorten("\x1b[1mLearning Session\x1b[0m"), "\x1b[1mLearning Session\x1b[0m");
+        let text = "\x1b[9mRescheduled\x1b[0m Architecture \x1b[32mLearning\x1b[0m Session";
+        assert_eq!(shorten(text), "\x1b[9m\x1b[0mArch \x1b[32mLearning\x1b[0m Session");
+
+        // Cut text keeps its sequences, and the reset after it
+        let shortener = shortener.truncate(true);
+        let text = "\x1b[32mLearning Audience\x1b[0m \x1b[1mSession\x1b[0m Notes";
+        assert_eq!(shortener.shorten(text), "\x1b[32mLearning Audienc\x1b[0m\x1b[1m\x1b[0m");
+    }
+}
@@ -0,0 +1,84 @@
+//! The rule packs shipped with shorten, so that rules for common domains work
+//! before any are written: `@builtin months, weekdays` (or
+//! `builtin = ["months", "weekdays"]` in TOML) loads the rules of the packs in
+//! place, as if their files were included. The packs are the files of
+//! `packs/`, built in with the `builtin` feature, which is on by default.
+
+use crate::abbrev::{Abbrev, LineParser, Location, ParsedLine};
+use crate::error::{ParseErrorKind, Result};
+use std::path::Path;
+use std::sync::Arc;
+
+#[cfg(feature = "builtin")]
+const PACKS: [(&str, &str); 5] = [
+    ("months", include_str!("../packs/months.lst")),
+    ("weekdays", include_str!("../packs/weekdays.lst")),
+    ("us-states", include_str!("../packs/us-states.lst")),
+    ("countries", include_str!("../packs/countries.lst")),
+    ("meetings", include_str!("../packs/meetings.lst")),
+];
+
+#[cfg(not(feature = "builtin"))]
+const PACKS: [(&str, &str); 0] = [];
+
+/// The names of the built-in packs.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    PACKS.iter().map(|(name, _)| *name)
+}
+
+/// The packs of a list like `months, weekdays`, checking that they exist.
+pub(crate) fn parse_names(list: &str) -> Result<Vec<String>> {
+    let names = list.split([',', ' ']).filter(|name| !name.is_empty());
+    names
+        .map(|name| match PACKS.iter().any(|(pack, _)| *pack == name) {
+            true => Ok(name.to_string()),
+            false => Err(ParseErrorKind::UnknownPack(name.to_string()).into()),
+        })
+        .collect()
+}
+
+/// The rules of a pack, located in `builtin:NAME`.
+pub(crate) fn rules(name: &str) -> Result<Vec<Abbrev>> {
+    let Some((_, text)) = PACKS.iter().find(|(pack, _)| *pack == name) else {
+        return Err(ParseErrorKind::UnknownPack(name.to_string()).into());
+    };
+    let file = Arc::<Path>::from(Path::new(&format!("builtin:{name}")));
+    let mut parser = LineParser::default();
+    let mut abbrevs = Vec::new();
+    for (order, line) in text.lines().enumerate() {
+        // Packs only have rules and comments
+        if let ParsedLine::Rule(abbrev) = parser.parse_line(line, order)? {
+            let location = Location { file: Some(file.clone()), line: order + 1 };
+            abbrevs.push(Abbrev { location, ..*abbrev });
+        }
+    }
+    Ok(abbrevs)
+}
+
+#[cfg(all(test, feature = "builtin"))]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::builtin::{names, rules};
+
+    #[test]
+    fn test_builtin() {
+        for name in names() {
+            assert!(!rules(name).unwrap().is_empty(), "{name}");
+        }
+
+        let lines = ["@builtin months, us-states", "New York = NYC"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        let abbreviate = |text| abbreviator.abbreviate(text).map(|abbrev| abbrev.text.to_string());
+        assert_eq!(abbreviate("September").as_deref(), Some("Sep"));
+        assert_eq!(abbreviate("New York").as_deref(), Some("NYC"));
+        let texas = abbreviator.rules().into_iter().find(|abbrev| abbrev.rule.replacement == "TX");
+        assert_eq!(texas.unwrap().location.to_string(), "builtin:us-states:45");
+
+        let Err(err) = Abbreviator::from_lines(["@builtin planets"].into_iter()) else {
+            panic!("unknown pack accepted");
+        };
+        let expected = "line 1: Unknown rule pack 'planets', expected one of months, weekdays, \
+                        us-states, countries, meetings";
+        assert_eq!(err.to_string(), expected);
+    }
+}
@@ -0,0 +1,80 @@
+//! A bounded cache evicting the least recently used entries.
+
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+pub(crate) struct LruCache<K, V> {
+    capacity: usize,
+    /// The values along with the tick they were last used at
+    entries: HashMap<K, (V, u64)>,
+    /// The keys by the tick they were last used at, oldest first
+    recency: BTreeMap<u64, K>,
+    tick: u64,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> LruCache<K, V> {
+    pub fn new(capacity: usize) -> LruCache<K, V> {
+        LruCache { capacity, entries: HashMap::new(), recency: BTreeMap::new(), tick: 0 }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let (value, used) = self.entries.get_mut(key)?;
+        self.tick += 1;
+        let key = self.recency.remove(used).expect("every entry has a tick");
+        self.recency.insert(self.tick, key);
+        *used = self.tick;
+        Some(value.clone())
+    }
+
+    /// Adds or replaces an entry, evicting the least recently used one when full.
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.tick += 1;
+        if let Some((_, used)) = self.entries.insert(key.clone(), (value, self.tick)) {
+            self.recency.remove(&used);
+        } else if self.entries.len() > self.capacity {
+            let (_, oldest) = self.recency.pop_first().expect("the cache is not empty");
+            self.entries.remove(&oldest);
+        }
+        self.recency.insert(self.tick, key);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::LruCache;
+
+    #[test]
+    fn test_lru_cache() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a", 1);
+        cache.insert("b", 2);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        // "b" is the least recently used
+        cache.insert("c", 3);
+        assert_eq!(cache.get(&"b"), None);
+        assert_eq!(cache.get(&"a"), Some(1));
+        assert_eq!(cache.get(&"c"), Some(3));
+
+        cache.insert("c", 4);
+        cache.insert("d", 5);
+        assert_eq!(cache.get(&"a"), None);
+        assert_eq!(cache.get(&"c"), Some(4));
+
+        cache.clear();
+        assert_eq!(cache.get(&"c"), None);
+    }
+}
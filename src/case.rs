@@ -0,0 +1,191 @@
+//! Unicode-aware detection and conversion of letter case.
+
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// How a rule adapts the case of its abbreviation to the matched text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[serde(rename_all = "kebab-case")]
+pub enum CasePolicy {
+    /// Abbreviations starting with a lowercase letter follow the case of the
+    /// matched text (title case or ALL-CAPS), others are kept as written.
+    #[default]
+    Smart,
+    /// The abbreviation is always kept as written.
+    Preserve,
+    /// The abbreviation is always lowercased.
+    ForceLower,
+    /// The abbreviation is always uppercased.
+    ForceUpper,
+    /// The abbreviation takes the case of the matched text, however it is
+    /// written: ARCHITECTURE → ARCH, architecture → arch, Architecture → Arch.
+    Mirror,
+}
+
+impl CasePolicy {
+    /// Parses a policy as written in the rules files, like `force-lower`.
+    pub fn from_name(name: &str) -> Option<CasePolicy> {
+        match name {
+            "smart" => Some(CasePolicy::Smart),
+            "preserve" => Some(CasePolicy::Preserve),
+            "force-lower" => Some(CasePolicy::ForceLower),
+            "force-upper" => Some(CasePolicy::ForceUpper),
+            "mirror" => Some(CasePolicy::Mirror),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CasePolicy::Smart => "smart",
+            CasePolicy::Preserve => "preserve",
+            CasePolicy::ForceLower => "force-lower",
+            CasePolicy::ForceUpper => "force-upper",
+            CasePolicy::Mirror => "mirror",
+        }
+    }
+
+    /// Whether the abbreviation changes with the case of the matched text.
+    pub fn follows_case(self, abbrev: &str) -> bool {
+        match self {
+            CasePolicy::Smart => abbrev.chars().next().is_some_and(char::is_lowercase),
+            CasePolicy::Mirror => true,
+            CasePolicy::Preserve | CasePolicy::ForceLower | CasePolicy::ForceUpper => false,
+        }
+    }
+
+    /// The abbreviation as it replaces text of the given case, converted with
+    /// the case rules of the language.
+    pub fn apply(self, abbrev: &str, matched: TextCase, rules: CaseRules) -> Cow<'_, str> {
+        match (self, matched) {
+            (CasePolicy::Preserve, _) => Cow::Borrowed(abbrev),
+            (CasePolicy::Smart, _) if !self.follows_case(abbrev) => Cow::Borrowed(abbrev),
+            (CasePolicy::Smart, TextCase::Lower) => Cow::Borrowed(abbrev),
+            (CasePolicy::Smart, TextCase::Title) => Cow::Owned(rules.to_title_case(abbrev)),
+            (CasePolicy::ForceLower, _) | (CasePolicy::Mirror, TextCase::Lower) => {
+                Cow::Owned(rules.to_lowercase(abbrev))
+            }
+            (CasePolicy::ForceUpper, _)
+            | (CasePolicy::Smart | CasePolicy::Mirror, TextCase::Upper) => {
+                Cow::Owned(rules.to_uppercase(abbrev))
+            }
+            (CasePolicy::Mirror, TextCase::Title) => {
+                Cow::Owned(rules.to_title_case(&rules.to_lowercase(abbrev)))
+            }
+        }
+    }
+}
+
+/// How letters change case, which depends on the language.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CaseRules {
+    /// The Unicode default conversions
+    #[default]
+    Standard,
+    /// Turkish and Azerbaijani, where i uppercases to İ and I lowercases to ı
+    Turkic,
+}
+
+impl CaseRules {
+    pub fn to_uppercase(self, text: &str) -> String {
+        match self {
+            CaseRules::Standard => text.to_uppercase(),
+            CaseRules::Turkic => text.replace('i', "İ").to_uppercase(),
+        }
+    }
+
+    pub fn to_lowercase(self, text: &str) -> String {
+        match self {
+            CaseRules::Standard => text.to_lowercase(),
+            CaseRules::Turkic => text.replace('I', "ı").replace('İ', "i").to_lowercase(),
+        }
+    }
+
+    /// Uppercases the first character of the text.
+    pub fn to_title_case(self, text: &str) -> String {
+        match (self, text.strip_prefix('i')) {
+            (CaseRules::Turkic, Some(rest)) => format!("İ{rest}"),
+            _ => to_title_case(text),
+        }
+    }
+}
+
+/// The letter case of a piece of text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextCase {
+    /// Starts with a lowercase letter, or has no letters at all
+    Lower,
+    /// Starts with an uppercase letter
+    Title,
+    /// Every letter is uppercase, and there is more than one of them
+    Upper,
+}
+
+impl TextCase {
+    pub fn of(text: &str) -> TextCase {
+        let mut cased = text.chars().filter(|c| c.is_lowercase() || c.is_uppercase());
+        let Some(first) = cased.next() else {
+            return TextCase::Lower;
+        };
+        if !first.is_uppercase() {
+            return TextCase::Lower;
+        }
+
+        let mut rest = cased.peekable();
+        if rest.peek().is_some() && rest.all(char::is_uppercase) {
+            TextCase::Upper
+        } else {
+            TextCase::Title
+        }
+    }
+}
+
+/// Uppercases the first character of the text.
+pub fn to_title_case(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::case::{to_title_case, CasePolicy, CaseRules, TextCase};
+
+    #[test]
+    fn test_text_case() {
+        assert_eq!(TextCase::of("architecture"), TextCase::Lower);
+        assert_eq!(TextCase::of("Architecture"), TextCase::Title);
+        assert_eq!(TextCase::of("ARCHITECTURE"), TextCase::Upper);
+        assert_eq!(TextCase::of("A"), TextCase::Title);
+        assert_eq!(TextCase::of("ÉCOLE"), TextCase::Upper);
+        assert_eq!(TextCase::of("(Über)"), TextCase::Title);
+        assert_eq!(to_title_case("élan"), "Élan");
+        assert_eq!(to_title_case("ßtraße"), "SStraße");
+    }
+
+    #[test]
+    fn test_case_policies() {
+        let apply = |policy: CasePolicy, abbrev, matched| {
+            policy.apply(abbrev, matched, CaseRules::Standard).into_owned()
+        };
+        assert_eq!(apply(CasePolicy::Smart, "arch", TextCase::Upper), "ARCH");
+        assert_eq!(apply(CasePolicy::Smart, "Arch", TextCase::Upper), "Arch");
+        assert_eq!(apply(CasePolicy::Preserve, "arch", TextCase::Title), "arch");
+        assert_eq!(apply(CasePolicy::ForceLower, "Arch", TextCase::Upper), "arch");
+        assert_eq!(apply(CasePolicy::ForceUpper, "arch", TextCase::Lower), "ARCH");
+        assert_eq!(apply(CasePolicy::Mirror, "Arch", TextCase::Lower), "arch");
+        assert_eq!(apply(CasePolicy::Mirror, "ARCH", TextCase::Title), "Arch");
+        assert_eq!(apply(CasePolicy::Mirror, "arch", TextCase::Upper), "ARCH");
+        let turkish = |policy: CasePolicy, abbrev, matched| {
+            policy.apply(abbrev, matched, CaseRules::Turkic).into_owned()
+        };
+        assert_eq!(turkish(CasePolicy::Smart, "ist", TextCase::Title), "İst");
+        assert_eq!(turkish(CasePolicy::Smart, "diyarbakır", TextCase::Upper), "DİYARBAKIR");
+        assert_eq!(turkish(CasePolicy::Mirror, "IŞIK", TextCase::Lower), "ışık");
+        let name = CasePolicy::from_name("force-upper").map(CasePolicy::name);
+        assert_eq!(name, Some("force-upper"));
+    }
+}
@@ -0,0 +1,437 @@
+use crate::abbrev::{Abbrev, AbbrevMatcher, Abbreviator, LineParser, ParsedLine, RuleKind};
+use crate::rule::Rule;
+use crate::rule_tests::RuleTest;
+use crate::shortener::{Shortener, Strategy};
+use crate::width;
+use crate::toml_rules;
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/// A problem found in a rules file.
+#[derive(Debug)]
+pub struct Issue {
+    /// 1-based line number of the offending rule, or 0 if it concerns the whole file
+    pub line: usize,
+    /// The file the offending rule is in, when known
+    pub file: Option<Arc<Path>>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Validates the lines of a rules file, reporting every problem instead of
+/// stopping at the first one.
+pub fn check_lines<'a, I: Iterator<Item = &'a str>>(lines: I) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut rules = Vec::new();
+    let mut parser = LineParser::default();
+    for (order, line) in lines.enumerate() {
+        match parser.parse_line(line, order) {
+            Ok(ParsedLine::Rule(rule)) => rules.push(*rule),
+            Ok(
+                ParsedLine::Include(_)
+                | ParsedLine::Builtin(_)
+                | ParsedLine::Setting(_)
+                | ParsedLine::Empty,
+            ) => {}
+            Err(err) => issues.push(Issue {
+                line: order + 1,
+                file: None,
+                severity: Severity::Error,
+                message: format!("{err:#}"),
+            }),
+        }
+    }
+
+    check_rules(&rules.iter().collect::<Vec<_>>(), &mut issues);
+    issues
+}
+
+/// Validates a rules file in the TOML format.
+pub fn check_toml(text: &str) -> Vec<Issue> {
+    let parsed = match toml_rules::parse(text) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            // The whole file is rejected, the error message carries the line number
+            return vec![Issue {
+                line: 0,
+                file: None,
+                severity: Severity::Error,
+                message: format!("{err:#}"),
+            }];
+        }
+    };
+
+    check_rules_at(&parsed.rules)
+}
+
+/// Validates rules read from elsewhere, along with the (0-based) line each one is on.
+pub fn check_rules_at(parsed: &[(usize, Rule)]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let mut rules = Vec::new();
+    for &(line, ref rule) in parsed {
+        match Abbrev::compile(rule, line) {
+            Ok(rule) => rules.push(rule),
+            Err(err) => issues.push(Issue {
+                line: line + 1,
+                file: None,
+                severity: Severity::Error,
+                message: format!("{err:#}"),
+            }),
+        }
+    }
+
+    check_rules(&rules.iter().collect::<Vec<_>>(), &mut issues);
+    issues
+}
+
+/// Finds the conflicts between the rules of an abbreviator: rules that load
+/// fine but never fire, or make the text longer.
+pub fn check_abbreviator(abbreviator: &Abbreviator) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    check_rules(&abbreviator.all_rules(), &mut issues);
+    issues
+}
+
+/// Finds the rules of an abbreviator that get in each other's way once they
+/// are applied: distinct matchers sharing an abbreviation, which can't be told
+/// apart when reading or expanding it, and abbreviations that another rule
+/// matches in turn, making chains. With `cascades`, every exact matcher is
+/// also shortened twice, to find the ones that the second pass changes again.
+pub fn lint(abbreviator: &Abbreviator, cascades: bool) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let rules = abbreviator.rules();
+    let exact = rules.iter().filter(|rule| rule.kind() == RuleKind::Exact).collect::<Vec<_>>();
+    let warn = |rule: &Abbrev, message| Issue {
+        line: rule.location.line,
+        file: rule.location.file.clone(),
+        severity: Severity::Warning,
+        message,
+    };
+
+    let mut by_abbrev: HashMap<String, Vec<&Abbrev>> = HashMap::new();
+    for rule in &exact {
+        by_abbrev.entry(rule.abbrev.to_lowercase()).or_default().push(rule);
+    }
+    for sharing in by_abbrev.values() {
+        let mut matchers = HashSet::new();
+        for rule in sharing.iter().filter(|rule| matchers.insert(rule.source.to_lowercase())) {
+            let Some(first) = sharing.iter().find(|first| first.order < rule.order) else {
+                continue;
+            };
+            issues.push(warn(rule, format!(
+                "abbreviation '{}' of '{}' is also the abbreviation of '{}' on {}",
+                rule.abbrev, rule.source, first.source, first.location
+            )));
+        }
+    }
+
+    let mut chained = HashSet::new();
+    for rule in &exact {
+        let Some(next) = abbreviator.abbreviate(&rule.abbrev) else {
+            continue;
+        };
+        if next.matcher == rule.source || next.text.to_lowercase() == rule.abbrev.to_lowercase() {
+            continue;
+        }
+        chained.insert(rule.order);
+        issues.push(warn(rule, format!(
+            "abbreviation '{}' of '{}' is matched by the rule '{}' in turn, which makes it '{}'",
+            rule.abbrev, rule.source, next.matcher, next.text
+        )));
+    }
+
+    if cascades {
+        let shortener = Shortener::with_abbreviator(0, abbreviator.clone())
+            .expect("shorteners of a loaded abbreviator are always valid")
+            .strategy(Strategy::All);
+        for rule in exact.iter().filter(|rule| !chained.contains(&rule.order)) {
+            let once = shortener.shorten(&rule.source);
+            let twice = shortener.shorten(&once);
+            if twice != once {
+                issues.push(warn(rule, format!(
+                    "'{}' is shortened to '{once}', which a second pass shortens to '{twice}'",
+                    rule.source
+                )));
+            }
+        }
+    }
+
+    issues.sort_by_key(|issue| (issue.file.clone(), issue.line));
+    issues
+}
+
+/// Finds the texts whose shortened form the shortener would change once
+/// more: the exact matchers of its rules and the texts of the tests, each
+/// shortened to every length it doesn't fit in, reported at the first length
+/// where shortening the output again changes it.
+pub fn verify_idempotent(shortener: &Shortener, tests: &[RuleTest]) -> Vec<Issue> {
+    let rules = shortener.abbreviator().rules();
+    let matchers = rules
+        .iter()
+        .filter(|rule| rule.kind() == RuleKind::Exact)
+        .map(|rule| (rule.source.as_str(), rule.location.file.clone(), rule.location.line));
+    let tests = tests.iter().map(|test| {
+        let file = test.file.as_deref().map(Arc::from);
+        (test.text.as_str(), file, test.line)
+    });
+
+    let mut issues = Vec::new();
+    for (text, file, line) in matchers.chain(tests) {
+        for max_length in (1..width::length(text)).rev() {
+            let once = shortener.shorten_to(text, max_length).text;
+            let twice = shortener.shorten_to(&once, max_length).text;
+            if twice != once {
+                issues.push(Issue {
+                    line,
+                    file,
+                    severity: Severity::Warning,
+                    message: format!(
+                        "'{text}' is shortened to '{once}' in {max_length} columns, which \
+                         shortening again makes '{twice}'"
+                    ),
+                });
+                break;
+            }
+        }
+    }
+    issues.sort_by_key(|issue| (issue.file.clone(), issue.line));
+    issues
+}
+
+fn check_rules(rules: &[&Abbrev], issues: &mut Vec<Issue>) {
+    check_duplicates(rules, issues);
+    check_shadowed_by_regex(rules, issues);
+    check_lengths(rules, issues);
+
+    issues.sort_by_key(|issue| issue.line);
+}
+
+/// Reports exact and remove rules of the same group sharing a matcher, since
+/// only one of them can ever fire, and matchers that are both removed and replaced.
+fn check_duplicates(rules: &[&Abbrev], issues: &mut Vec<Issue>) {
+    let mut by_matcher: HashMap<(Option<&str>, &str), Vec<&Abbrev>> = HashMap::new();
+    let replacing = |rule: &&&Abbrev| !matches!(rule.kind(), RuleKind::Keep | RuleKind::Except);
+    for rule in rules.iter().filter(replacing) {
+        if let AbbrevMatcher::Lowercase(matcher) = &rule.matcher {
+            by_matcher.entry((rule.group.as_deref(), matcher)).or_default().push(rule);
+        }
+    }
+
+    for duplicates in by_matcher.values().filter(|duplicates| duplicates.len() > 1) {
+        let winner = winner(duplicates);
+        for rule in duplicates.iter().filter(|rule| rule.order != winner.order) {
+            let rule_text = match (rule.kind(), winner.kind()) {
+                (RuleKind::Remove, RuleKind::Exact) | (RuleKind::Exact, RuleKind::Remove) => {
+                    format!("matcher '{}' is both removed and replaced, this rule", rule.source)
+                }
+                _ => format!("duplicate matcher '{}'", rule.source),
+            };
+            issues.push(Issue {
+                line: rule.location.line,
+                file: rule.location.file.clone(),
+                severity: Severity::Warning,
+                message: format!("{rule_text} never fires, overridden by {}", winner.location),
+            });
+        }
+    }
+}
+
+/// Picks the rule that wins among rules with the same matcher, following the
+/// same resolution order as the abbreviator.
+fn winner<'a>(duplicates: &[&'a Abbrev]) -> &'a Abbrev {
+    duplicates
+        .iter()
+        .copied()
+        .min_by_key(|rule| (Reverse(rule.priority), rule.kind() != RuleKind::Remove, Reverse(rule.order)))
+        .expect("duplicates are never empty")
+}
+
+/// Reports exact and remove rules whose matcher is always taken by a higher priority regex.
+fn check_shadowed_by_regex(rules: &[&Abbrev], issues: &mut Vec<Issue>) {
+    let regexes = rules
+        .iter()
+        .filter_map(|rule| match &rule.matcher {
+            AbbrevMatcher::Regex(re) if !rule.keep => Some((rule, re)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let shadowable = |rule: &&&Abbrev| matches!(rule.kind(), RuleKind::Exact | RuleKind::Remove);
+    for rule in rules.iter().filter(shadowable) {
+        let shadowing = regexes
+            .iter()
+            .find(|(regex, re)| regex.priority > rule.priority && re.is_match(&rule.source));
+        if let Some((regex, _)) = shadowing {
+            issues.push(Issue {
+                line: rule.location.line,
+                file: rule.location.file.clone(),
+                severity: Severity::Warning,
+                message: format!(
+                    "rule '{}' never fires, shadowed by higher priority regex '{}' on {}",
+                    rule.source,
+                    regex.source,
+                    regex.location
+                ),
+            });
+        }
+    }
+}
+
+/// Reports exact rules that make the text longer.
+fn check_lengths(rules: &[&Abbrev], issues: &mut Vec<Issue>) {
+    for rule in rules.iter().filter(|rule| rule.kind() == RuleKind::Exact) {
+        if rule.abbrev.chars().count() > rule.source.chars().count() {
+            issues.push(Issue {
+                line: rule.location.line,
+                file: rule.location.file.clone(),
+                severity: Severity::Warning,
+                message: format!(
+                    "abbreviation '{}' is longer than its matcher '{}'",
+                    rule.abbrev, rule.source
+                ),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::check::{check_abbreviator, check_lines, lint, verify_idempotent, Severity};
+    use crate::rule_tests;
+    use crate::shortener::Shortener;
+
+    #[test]
+    fn test_check_lines() {
+        let issues = check_lines(
+            r#"
+                Architecture = arch
+                Architecture = archi
+                Session
+                !5 /^sess/i = S
+                Session = sesn
+                Go = going
+            "#
+            .lines(),
+        );
+
+        let issues = issues
+            .iter()
+            .map(|issue| (issue.line, issue.severity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            issues,
+            vec![
+                (2, Severity::Warning),
+                (4, Severity::Error),
+                (6, Severity::Warning),
+                (7, Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_abbreviator() {
+        let lines = ["Architecture = arch", "!1 Architecture =", "Meeting = mtg"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        let messages = check_abbreviator(&abbreviator)
+            .into_iter()
+            .map(|issue| issue.message)
+            .collect::<Vec<_>>();
+        let expected = "matcher 'Architecture' is both removed and replaced, this rule never fires, \
+                        overridden by line 2";
+        assert_eq!(messages, [expected]);
+
+        // Rules of included files are located in them
+        let dir = std::env::temp_dir().join(format!("shorten-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (path, included) = (dir.join("abbrev.lst"), dir.join("extra.lst"));
+        std::fs::write(&path, "Architecture = arch\n@include extra.lst\n").unwrap();
+        std::fs::write(&included, "Meeting = mtg\nArchitecture = archi\n").unwrap();
+        let abbreviator = Abbreviator::try_from_file(&path).unwrap();
+        let issues = check_abbreviator(&abbreviator);
+        let [issue] = &issues[..] else {
+            panic!("expected a single issue, got {}", issues.len());
+        };
+        assert_eq!((issue.file.as_deref(), issue.line), (Some(&*path), 1));
+        let overridden = format!("overridden by {}:2", included.display());
+        assert!(issue.message.ends_with(&overridden), "{}", issue.message);
+
+        std::fs::write(&included, "Meeting = mtg\nArchitecture\n").unwrap();
+        let Err(err) = Abbreviator::try_from_file(&path) else {
+            panic!("invalid rule accepted");
+        };
+        let located = format!("{}:2: ", included.display());
+        assert!(format!("{err:#}").contains(&located), "{err:#}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_lint() {
+        let lines = [
+            "Architecture = arch",
+            "Archive = ARCH",
+            "arch = A",
+            "Project Review = Proj Rvw",
+            "Review = Rvw",
+            "Rvw = R",
+            "Meeting = mtg",
+        ];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        let messages = |cascades| {
+            let issues = lint(&abbreviator, cascades).into_iter();
+            issues.map(|issue| format!("{}: {}", issue.line, issue.message)).collect::<Vec<_>>()
+        };
+        let expected = [
+            "1: abbreviation 'arch' of 'Architecture' is matched by the rule 'arch' in turn, \
+             which makes it 'A'",
+            "2: abbreviation 'ARCH' of 'Archive' is also the abbreviation of 'Architecture' on \
+             line 1",
+            "2: abbreviation 'ARCH' of 'Archive' is matched by the rule 'arch' in turn, which \
+             makes it 'A'",
+            "5: abbreviation 'Rvw' of 'Review' is matched by the rule 'Rvw' in turn, which makes \
+             it 'R'",
+        ];
+        assert_eq!(messages(false), expected);
+        let cascade = "4: 'Project Review' is shortened to 'Proj Rvw', which a second pass \
+                       shortens to 'Proj R'";
+        assert_eq!(messages(true)[3], cascade);
+        assert_eq!(messages(true).len(), 5);
+    }
+
+    #[test]
+    fn test_verify_idempotent() {
+        let rules = "Architecture = arch\nReview = Rvw\nRvw = R\n\
+                     #test \"Review Notes\" => \"Rvw Notes\"";
+        let abbreviator = Abbreviator::from_lines(rules.lines()).unwrap();
+        let tests = rule_tests::parse(rules).unwrap();
+        let shortener = Shortener::with_abbreviator(60, abbreviator).unwrap();
+        let issues = verify_idempotent(&shortener, &tests);
+        let messages = issues.iter().map(|issue| (issue.line, issue.message.as_str()));
+        let expected = [
+            (2, "'Review' is shortened to 'Rvw' in 2 columns, which shortening again makes 'R'"),
+            (4, "'Review Notes' is shortened to 'Rvw Notes' in 8 columns, which shortening again \
+                 makes 'R Notes'"),
+        ];
+        assert_eq!(messages.collect::<Vec<_>>(), expected);
+        assert!(verify_idempotent(&shortener.idempotent(true), &tests).is_empty());
+    }
+}
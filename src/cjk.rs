@@ -0,0 +1,56 @@
+//! Segmentation of unspaced CJK text, like "第3回定例会議のお知らせ", into
+//! the words of the rules it contains, so that rules can match inside it.
+
+use aho_corasick::{AhoCorasick, BuildError, MatchKind};
+
+/// Whether the character is Chinese, Japanese or Korean.
+pub(crate) fn is_cjk(c: char) -> bool {
+    matches!(
+        c,
+        '\u{1100}'..='\u{11ff}' // Hangul Jamo
+            | '\u{3040}'..='\u{30ff}' // Hiragana and Katakana
+            | '\u{3130}'..='\u{318f}' // Hangul Compatibility Jamo
+            | '\u{31f0}'..='\u{31ff}' // Katakana Phonetic Extensions
+            | '\u{3400}'..='\u{4dbf}' // CJK Unified Ideographs Extension A
+            | '\u{4e00}'..='\u{9fff}' // CJK Unified Ideographs
+            | '\u{ac00}'..='\u{d7af}' // Hangul Syllables
+            | '\u{f900}'..='\u{faff}' // CJK Compatibility Ideographs
+            | '\u{ff66}'..='\u{ff9f}' // Halfwidth Katakana
+            | '\u{20000}'..='\u{2ffff}' // Supplementary Ideographic Planes
+    )
+}
+
+/// Finds the single-word matchers holding CJK text, longest first, or `None`
+/// when there are none.
+pub(crate) fn dictionary<'a>(
+    matchers: impl Iterator<Item = &'a str>,
+) -> Result<Option<AhoCorasick>, BuildError> {
+    let words = matchers
+        .filter(|matcher| matcher.chars().any(is_cjk) && !matcher.contains(char::is_whitespace))
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        return Ok(None);
+    }
+    AhoCorasick::builder()
+        .match_kind(MatchKind::LeftmostLongest)
+        .ascii_case_insensitive(true)
+        .build(words)
+        .map(Some)
+}
+
+/// Splits a word into the dictionary words it contains and the text between them.
+pub(crate) fn segment<'a>(word: &'a str, dictionary: &AhoCorasick) -> Vec<&'a str> {
+    let mut pieces = Vec::new();
+    let mut end = 0;
+    for found in dictionary.find_iter(word) {
+        if found.start() > end {
+            pieces.push(&word[end..found.start()]);
+        }
+        pieces.push(&word[found.range()]);
+        end = found.end();
+    }
+    if end < word.len() {
+        pieces.push(&word[end..]);
+    }
+    pieces
+}
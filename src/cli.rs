@@ -0,0 +1,966 @@
+use clap::error::{ContextKind, ErrorKind};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use shorten::abbrev::{self, Abbreviator, RuleKind};
+use shorten::case::CasePolicy;
+use eyre::{bail, WrapErr};
+use shorten::import::RulesFormat;
+use shorten::locale::Locale;
+use shorten::{check, config};
+use shorten::shortener::{
+    Acronyms, Anchor, Clock, Dates, Mode, Shortener, Strategy, DEFAULT_DATE_FORMAT,
+};
+use std::ffi::OsString;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(name = "shorten", version, about = "Shorten text using configurable abbreviations")]
+pub struct Cli {
+    #[command(flatten)]
+    pub rules: RulesArgs,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+/// Options selecting which rules are loaded
+#[derive(Args)]
+pub struct RulesArgs {
+    /// Abbreviations file to use instead of the one in the config directory, or
+    /// `-` to read the rules from stdin (e.g. a heredoc, with the input in files)
+    #[arg(long, visible_alias = "rules", global = true, env = "SHORTEN_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Rules to use instead of an abbreviations file, separated by `;` (`\;`
+    /// for a semicolon of a rule), like `'Architecture = arch; Meeting ='`
+    #[arg(long, global = true, value_name = "RULES")]
+    pub rules_inline: Option<String>,
+
+    /// Only use the rules of this group (and rules outside of any group); can be repeated
+    #[arg(long, global = true, value_name = "GROUP")]
+    pub only_group: Vec<String>,
+
+    /// Don't use the rules of this group; can be repeated
+    #[arg(long, global = true, value_name = "GROUP")]
+    pub skip_group: Vec<String>,
+
+    /// Activate the rules tagged with this context; can be repeated
+    #[arg(long, global = true, value_name = "TAG")]
+    pub context: Vec<String>,
+
+    /// Match exact rules regardless of the diacritics of the text, like
+    /// `@ignore-diacritics` in the rules
+    #[arg(long, global = true)]
+    pub ignore_diacritics: bool,
+
+    /// Write for this locale (like de or tr_TR.UTF-8): its replacements in the
+    /// rules (`dept | de:Abt.`), case conversions and month and day names
+    #[arg(long, global = true, env = "SHORTEN_LOCALE", value_parser = parse_locale)]
+    pub locale: Option<Locale>,
+
+    /// Fail on conflicting rules (duplicates, rules that never fire, abbreviations
+    /// longer than their matcher) instead of warning about them
+    #[arg(long, global = true)]
+    pub strict: bool,
+}
+
+impl RulesArgs {
+    pub fn load(&self) -> eyre::Result<Abbreviator> {
+        let abbreviator = match self.ad_hoc_rules()? {
+            Some((rules, _)) => Abbreviator::from_lines(rules.lines())?,
+            None => config::load_abbreviator(self.config.as_deref())?,
+        };
+        self.checked(abbreviator)
+    }
+
+    /// The rules given with `--rules-inline` or read from stdin with `--config -`,
+    /// as the text of a rules file, along with the name they are reported under.
+    pub fn ad_hoc_rules(&self) -> eyre::Result<Option<(String, PathBuf)>> {
+        if let Some(rules) = &self.rules_inline {
+            let rules = abbrev::inline_lines(rules).join("\n");
+            return Ok(Some((rules, PathBuf::from("<inline>"))));
+        }
+        if !self.reads_stdin() {
+            return Ok(None);
+        }
+        let mut rules = String::new();
+        let read = std::io::stdin().read_to_string(&mut rules);
+        read.wrap_err("Failed to read the rules from stdin")?;
+        Ok(Some((abbrev::strip_bom(&rules).to_string(), PathBuf::from("<stdin>"))))
+    }
+
+    /// Whether the rules are read from stdin, with `--config -`.
+    pub fn reads_stdin(&self) -> bool {
+        self.rules_inline.is_none() && self.config.as_deref() == Some(Path::new("-"))
+    }
+
+    /// Whether the rules are in a file, which can be watched for changes.
+    pub fn has_file(&self) -> bool {
+        self.rules_inline.is_none() && !self.reads_stdin()
+    }
+
+    /// Warns about the conflicting rules of freshly loaded rules (or fails with
+    /// `--strict`), then applies the group and context selection.
+    pub fn checked(&self, abbreviator: Abbreviator) -> eyre::Result<Abbreviator> {
+        let issues = check::check_abbreviator(&abbreviator)
+            .into_iter()
+            .map(|issue| match &issue.file {
+                Some(file) => format!("{}:{}: {}", file.display(), issue.line, issue.message),
+                None => format!("line {}: {}", issue.line, issue.message),
+            })
+            .collect::<Vec<_>>();
+        if self.strict && !issues.is_empty() {
+            bail!("{} conflicting rules:\n{}", issues.len(), issues.join("\n"));
+        }
+        for issue in &issues {
+            eprintln!("warning: {issue}");
+        }
+        Ok(self.select(abbreviator)?)
+    }
+
+    /// Applies the group and context selection to freshly loaded rules.
+    pub fn select(&self, abbreviator: Abbreviator) -> shorten::error::Result<Abbreviator> {
+        let abbreviator = abbreviator
+            .with_groups(&self.only_group, &self.skip_group)?
+            .with_contexts(&self.context)?
+            .with_locale(self.locale.clone())?;
+        match self.ignore_diacritics {
+            true => abbreviator.with_diacritics_ignored(true),
+            false => Ok(abbreviator),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Shorten lines read from stdin
+    Run(RunArgs),
+    /// Validate the abbreviations file, exiting with an error if problems are found
+    Check(CheckArgs),
+    /// List the loaded rules
+    Rules(ListArgs),
+    /// Expand the abbreviations in lines read from stdin back into the text they
+    /// stand for, reporting ambiguous abbreviations
+    Expand,
+    /// Serve shortening requests on a Unix socket, so callers don't pay for
+    /// starting a process and loading the rules every time
+    #[cfg(unix)]
+    Daemon(DaemonArgs),
+    /// Send lines to a running daemon and print them shortened
+    #[cfg(unix)]
+    Client(ClientArgs),
+    /// Print a short window name for the current tmux pane, from its title or
+    /// its working directory, ready for `automatic-rename-format`
+    TmuxWindowName(TmuxArgs),
+    /// Propose abbreviations for frequent words and phrases read from stdin
+    Suggest(SuggestArgs),
+    /// Shorten a corpus read from stdin and report as JSON how many times each
+    /// rule fired, which lines are still too long and how long the lines end up
+    Coverage(CoverageArgs),
+    /// Shorten a corpus and compare the result with the expected lines, to
+    /// find out what changes with new rules or a new version
+    Snapshot(SnapshotArgs),
+    /// Run the `#test "text" => "shortened"` lines of the rules file and of the
+    /// files it includes
+    Test(TestArgs),
+    /// Convert a spreadsheet export of long forms, short forms and flags, or an
+    /// espanso match file, into a checked rules file, printed on stdout
+    Import(ImportArgs),
+    /// Print the rules in the format of another tool
+    Export(ExportArgs),
+    /// Shorten lines as they are typed, to try out changes to the rules
+    Repl(ReplArgs),
+}
+
+#[derive(Args)]
+pub struct DaemonArgs {
+    /// Socket to listen on (`$XDG_RUNTIME_DIR/shorten.sock` by default)
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub options: ShortenArgs,
+}
+
+#[derive(Args)]
+pub struct ClientArgs {
+    /// Socket of the daemon (`$XDG_RUNTIME_DIR/shorten.sock` by default)
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+
+    /// Maximum length of the output lines
+    #[arg(env = "SHORTEN_MAX_LENGTH")]
+    pub desired_max_length: usize,
+
+    /// Text to shorten, instead of the lines read from stdin
+    pub text: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct TmuxArgs {
+    /// Title of the pane (asked to tmux when neither --title nor --path is given)
+    #[arg(long)]
+    pub title: Option<String>,
+
+    /// Working directory of the pane, used when the title is empty or the host name
+    #[arg(long)]
+    pub path: Option<String>,
+
+    /// Maximum length of the window name
+    #[arg(long, default_value_t = 20)]
+    pub width: usize,
+}
+
+#[derive(Args)]
+pub struct SuggestArgs {
+    /// Only suggest words and phrases appearing at least this many times
+    #[arg(long, default_value_t = 2)]
+    pub min_count: usize,
+
+    /// The maximum number of suggestions
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+}
+
+#[derive(Args)]
+pub struct CoverageArgs {
+    /// Maximum length of the output lines
+    #[arg(env = "SHORTEN_MAX_LENGTH")]
+    pub desired_max_length: usize,
+
+    #[command(flatten)]
+    pub options: ShortenArgs,
+}
+
+#[derive(Args)]
+pub struct SnapshotArgs {
+    /// Maximum length of the output lines
+    #[arg(env = "SHORTEN_MAX_LENGTH")]
+    pub desired_max_length: usize,
+
+    /// The corpus to shorten, one text per line
+    #[arg(long, value_name = "FILE")]
+    pub input: PathBuf,
+
+    /// The lines the corpus is expected to be shortened to
+    #[arg(long, value_name = "FILE")]
+    pub expected: PathBuf,
+
+    /// Write the shortened corpus to the expected file instead of comparing
+    #[arg(long)]
+    pub update: bool,
+
+    #[command(flatten)]
+    pub options: ShortenArgs,
+}
+
+#[derive(Args)]
+pub struct TestArgs {
+    #[command(flatten)]
+    pub options: ShortenArgs,
+}
+
+#[derive(Args)]
+pub struct ListArgs {
+    /// Only list the rules of this group (can be repeated)
+    #[arg(long, value_name = "NAME")]
+    pub group: Vec<String>,
+
+    /// Only list the rules whose matcher contains TEXT, ignoring case
+    #[arg(long, value_name = "TEXT")]
+    pub matcher: Option<String>,
+
+    /// Only list the rules of this type (can be repeated)
+    #[arg(long = "type", value_enum, value_name = "TYPE")]
+    pub kind: Vec<RuleKindArg>,
+
+    /// Print one JSON object per rule
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct ReplArgs {
+    /// Maximum length of the output lines, until changed with `:set len N`
+    #[arg(env = "SHORTEN_MAX_LENGTH", default_value_t = 30)]
+    pub desired_max_length: usize,
+
+    #[command(flatten)]
+    pub options: ShortenArgs,
+}
+
+#[derive(Args)]
+pub struct ImportArgs {
+    /// Format of the export (by default from its extension: TSV for `.tsv`,
+    /// espanso for `.yml` and `.yaml`, CSV otherwise)
+    #[arg(long, value_enum)]
+    pub format: Option<ImportFormatArg>,
+
+    /// Format of the rules file to print
+    #[arg(long, value_enum, default_value_t = RulesFormatArg::Lst)]
+    pub to: RulesFormatArg,
+
+    /// The first row holds the column names
+    #[arg(long)]
+    pub header: bool,
+
+    /// The CSV or TSV export, or the espanso match file
+    pub file: PathBuf,
+}
+
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Also look for rules that get in each other's way: matchers sharing an
+    /// abbreviation, and abbreviations that another rule matches in turn
+    #[arg(long)]
+    pub lint: bool,
+    /// With --lint, shorten every matcher twice to find the ones that change
+    /// again on the second pass
+    #[arg(long, requires = "lint")]
+    pub cascades: bool,
+    /// Shorten every matcher and #test text to each length, and report the
+    /// ones that shortening again changes
+    #[arg(long)]
+    pub verify_idempotent: bool,
+    #[command(flatten)]
+    pub options: ShortenArgs,
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Format to print the rules in
+    #[arg(long, value_enum, default_value_t = ExportFormatArg::Espanso)]
+    pub format: ExportFormatArg,
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Maximum length of the output lines
+    #[arg(
+        required_unless_present_any = ["length_prefix", "directives"],
+        env = "SHORTEN_MAX_LENGTH"
+    )]
+    pub desired_max_length: Option<usize>,
+
+    /// Files to shorten instead of stdin, one after the other
+    #[arg(value_name = "FILE", requires = "desired_max_length")]
+    pub files: Vec<PathBuf>,
+
+    /// Shorten the files in place (keeping a copy of each under its name with
+    /// SUFFIX, when given), replacing them only once they are fully written
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        requires = "files",
+        conflicts_with_all = ["json", "diff"]
+    )]
+    pub in_place: Option<String>,
+
+    /// Read each line as `<len>\t<text>` and shorten the text to its own length
+    /// (lines without a prefix use the default length, if given); `<len>:right\t`
+    /// or `<len>:left\t` also sets the anchor of the line
+    #[arg(long)]
+    pub length_prefix: bool,
+
+    /// Follow `#!shorten` lines in the input: `#!shorten len=N` shortens the
+    /// next lines to N (`len=default` goes back to the default length), and
+    /// `#!shorten skip` passes them through untouched until `#!shorten resume`
+    #[arg(long)]
+    pub directives: bool,
+
+    /// What to do with blank (empty or whitespace-only) lines: `keep` writes
+    /// them as read when they fit and empty otherwise, `drop` leaves them out
+    /// of the output, and any other value is written instead of them, e.g.
+    /// `--blank=-` for a status bar that must keep its line count
+    #[arg(
+        long,
+        value_name = "keep|drop|TEXT",
+        default_value = "keep",
+        value_parser = parse_blank
+    )]
+    pub blank: BlankArg,
+
+    /// Only shorten the Nth (1-based) field of each line, passing the other
+    /// fields and the delimiters through as they are
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub field: Option<usize>,
+
+    /// The delimiter of the fields for --field (`\t` by default) or --csv (`,`
+    /// by default), `\t` or a single character
+    #[arg(long, value_name = "CHAR", value_parser = parse_delimiter)]
+    pub delimiter: Option<char>,
+
+    /// Read the input as CSV records and write them back as valid CSV, only
+    /// shortening the fields of the --column columns
+    #[cfg(feature = "csv")]
+    #[arg(
+        long,
+        requires = "column",
+        conflicts_with_all = [
+            "field", "json", "diff", "length_prefix", "directives", "null", "pad", "mapping"
+        ]
+    )]
+    pub csv: bool,
+
+    /// A column to shorten with --csv, by header name or 1-based index; can be
+    /// repeated
+    #[cfg(feature = "csv")]
+    #[arg(long, value_name = "COLUMN", requires = "csv")]
+    pub column: Vec<String>,
+
+    /// Write the first CSV record through as is, as a header (implied by column
+    /// names)
+    #[cfg(feature = "csv")]
+    #[arg(long, requires = "csv")]
+    pub header: bool,
+
+    #[command(flatten)]
+    pub options: ShortenArgs,
+
+    /// Reload the abbreviations file whenever it, or a file it includes, changes
+    #[arg(long)]
+    pub watch_config: bool,
+
+    /// Emit one JSON object per line, with its length and whether it is still
+    /// over budget
+    #[arg(long, conflicts_with = "field")]
+    pub json: bool,
+
+    /// Report the rules applied to each line
+    #[arg(long)]
+    pub explain: bool,
+
+    /// Write what it takes to restore each output line to FILE, one JSON array
+    /// of `[start, end, original]` edits per line: replacing the bytes from
+    /// start to end of the line by the original text, from the last edit to the
+    /// first, gives back the line as it was read (an edit removes the --pad fill)
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["diff", "json"])]
+    pub mapping: Option<PathBuf>,
+
+    /// Print what changed in each line instead of the shortened lines, word by
+    /// word (the default) or as `-` and `+` lines
+    #[arg(
+        long,
+        value_enum,
+        value_name = "STYLE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "word",
+        conflicts_with = "json"
+    )]
+    pub diff: Option<DiffArg>,
+
+    /// Pad the shortened lines to exactly their maximum length, on the right
+    /// (the default) or on the left, for fixed-width columns
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SIDE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "right",
+        conflicts_with_all = ["json", "diff"]
+    )]
+    pub pad: Option<PadArg>,
+
+    /// The character --pad fills lines with
+    #[arg(long, value_name = "CHAR", default_value_t = ' ', requires = "pad")]
+    pub pad_char: char,
+
+    /// Print a summary on stderr once the input is processed: how many lines
+    /// were changed or are still too long, and which rules fired the most or never
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Exit with an error when some lines are still too long once shortened
+    #[arg(long)]
+    pub fail_over_budget: bool,
+
+    /// Read and write NUL-separated records instead of lines
+    #[arg(long, short = '0')]
+    pub null: bool,
+
+    /// End the output lines with `\r\n`, for Windows consumers (input lines
+    /// may end with either)
+    #[arg(long, conflicts_with = "null")]
+    pub crlf: bool,
+
+    /// Shorten lines on N threads, keeping their order; 0 uses one per CPU
+    #[arg(long, short = 'j', value_name = "N", default_value_t = 1)]
+    pub jobs: usize,
+}
+
+/// Options controlling how lines are shortened
+#[derive(Args)]
+pub struct ShortenArgs {
+    /// Kind of text being shortened
+    #[arg(long, value_enum, env = "SHORTEN_MODE", default_value_t = ModeArg::Text)]
+    pub mode: ModeArg,
+
+    /// Which of the matching abbreviations to apply
+    #[arg(long, value_enum, default_value_t = StrategyArg::All)]
+    pub strategy: StrategyArg,
+
+    /// Only abbreviate as much as needed for the line to fit (same as `--strategy minimal`)
+    #[arg(long, conflicts_with = "strategy")]
+    pub minimal: bool,
+
+    /// Write spelled-out numbers in digits and drop thousands separators in
+    /// lines that are still too long after abbreviation
+    #[arg(long)]
+    pub numbers: bool,
+
+    /// Write the dates of lines that are too long in the strftime FORMAT
+    /// ("%b %-d" by default, like Sep 25) and their times compactly (10–11am)
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = DEFAULT_DATE_FORMAT
+    )]
+    pub dates: Option<String>,
+
+    /// The clock of the times written by --dates
+    #[arg(long, value_enum, default_value_t = ClockArg::TwelveHour, requires = "dates")]
+    pub clock: ClockArg,
+
+    /// Drop stop words from lines that are still too long after abbreviation
+    #[arg(long)]
+    pub drop_stop_words: bool,
+
+    /// Stop words file to use instead of the config directory's or the built-in one
+    /// (implies --drop-stop-words)
+    #[arg(long, value_name = "FILE")]
+    pub stop_words: Option<PathBuf>,
+
+    /// Drop the vowels of unabbreviated words longer than LEN in lines that are
+    /// still too long after abbreviation (8 by default)
+    #[arg(
+        long,
+        value_name = "LEN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "8"
+    )]
+    pub drop_vowels: Option<usize>,
+
+    /// Collapse runs of MIN to MAX long words into their initials in lines that
+    /// are still too long after abbreviation (2-4 words by default)
+    #[arg(
+        long,
+        value_name = "MIN-MAX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "2-4",
+        value_parser = parse_acronyms
+    )]
+    pub acronym: Option<Acronyms>,
+
+    /// Keep the original tabs and runs of spaces, only replacing the abbreviated words
+    #[arg(long)]
+    pub preserve_spacing: bool,
+
+    /// Write the lines where no rule fires and that need no truncation exactly
+    /// as read, even when they are too long
+    #[arg(long)]
+    pub preserve_unmatched: bool,
+
+    /// Shorten the lines that are still too long again until they stop
+    /// changing, so that shortening the output again leaves it as it is
+    #[arg(long)]
+    pub idempotent: bool,
+
+    /// Replace tabs with spaces up to the next stop every N columns (8 by
+    /// default) before measuring the lines
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "8"
+    )]
+    pub expand_tabs: Option<usize>,
+
+    /// Drop control characters (carriage returns, bells, escape sequences
+    /// unless --ansi) before measuring the lines; tabs not expanded become spaces
+    #[arg(long)]
+    pub strip_controls: bool,
+
+    /// Characters splitting words that no rule matches as a whole, so each
+    /// part can be abbreviated (an empty string disables splitting)
+    #[arg(long, value_name = "CHARS", default_value = "/|:·-_")]
+    pub separators: String,
+
+    /// Never alter this word (on top of the `!keep` rules); can be repeated
+    #[arg(long, value_name = "WORD")]
+    pub keep: Vec<String>,
+
+    /// Cut lines that are still too long after abbreviation
+    #[arg(long)]
+    pub truncate: bool,
+
+    /// Append MARKER ("…" by default) to lines that lost words or were cut
+    #[arg(
+        long,
+        value_name = "MARKER",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "…"
+    )]
+    pub elide: Option<String>,
+
+    /// Keep words joined by no-break spaces together, and ideographic spaces between words
+    #[arg(long)]
+    pub unicode_spaces: bool,
+
+    /// Find the CJK words of the rules inside unspaced CJK text, e.g. Japanese titles
+    #[cfg(feature = "cjk")]
+    #[arg(long)]
+    pub segment_cjk: bool,
+
+    /// How abbreviations follow the case of the words they replace, for the
+    /// rules without a case policy of their own (overrides `@case`)
+    #[arg(long, value_enum)]
+    pub case: Option<CaseArg>,
+
+    /// Protect this end of the line when words are dropped and when truncating
+    #[arg(long, value_enum)]
+    pub anchor: Option<AnchorArg>,
+
+    /// Leave ANSI escape sequences (colors) out of the length and never cut
+    /// through them, e.g. for prompt segments
+    #[arg(long)]
+    pub ansi: bool,
+
+    /// Remember the last N shortened lines, for inputs repeating the same lines
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub cache: usize,
+}
+
+impl ShortenArgs {
+    /// Builds a shortener applying these options to the loaded rules.
+    pub fn shortener(
+        &self,
+        abbreviator: Abbreviator,
+        desired_max_length: usize,
+    ) -> eyre::Result<Shortener> {
+        let strategy = if self.minimal { Strategy::Minimal } else { self.strategy.into() };
+        let stop_words = (self.drop_stop_words || self.stop_words.is_some())
+            .then(|| config::load_stop_words(self.stop_words.as_deref()))
+            .transpose()?;
+        let dates = self.dates.as_deref().map(|format| Dates::new(format, self.clock.into()));
+        let shortener = Shortener::with_abbreviator(desired_max_length, abbreviator)?
+            .strategy(strategy)
+            .mode(self.mode.into())
+            .numbers(self.numbers)
+            .dates(dates.transpose()?)
+            .stop_words(stop_words)
+            .drop_vowels(self.drop_vowels)
+            .acronyms(self.acronym)
+            .keep(&self.keep)
+            .preserve_spacing(self.preserve_spacing)
+            .preserve_unmatched(self.preserve_unmatched)
+            .idempotent(self.idempotent)
+            .expand_tabs(self.expand_tabs)
+            .strip_controls(self.strip_controls)
+            .separators(&self.separators)
+            .truncate(self.truncate)
+            .elision_marker(self.elide.clone())
+            .anchor(self.anchor.map(Anchor::from))
+            .unicode_spaces(self.unicode_spaces)
+            .case(self.case.map(CasePolicy::from))
+            .ansi(self.ansi)
+            .cache(self.cache);
+        #[cfg(feature = "cjk")]
+        let shortener = shortener.cjk_segmentation(self.segment_cjk);
+        Ok(shortener)
+    }
+}
+
+impl Cli {
+    /// Parses the command line, treating arguments without a subcommand (e.g.
+    /// `shorten <len>`) as arguments to `shorten run`
+    /// so the original single-argument usage keeps working.
+    pub fn parse_args() -> Cli {
+        Cli::parse_args_from(std::env::args_os().collect())
+    }
+
+    /// Parses the given command line like [`Cli::parse_args`]. With the length
+    /// in `SHORTEN_MAX_LENGTH`, a first argument of `shorten run` that isn't a
+    /// length is the first file to shorten.
+    pub fn parse_args_from(mut args: Vec<OsString>) -> Cli {
+        if needs_implicit_run(&args) {
+            args.insert(1, OsString::from("run"));
+        }
+        let err = match Cli::try_parse_from(&args) {
+            Ok(cli) => return cli,
+            Err(err) => err,
+        };
+        let invalid_length = err.kind() == ErrorKind::ValueValidation
+            && err
+                .get(ContextKind::InvalidArg)
+                .is_some_and(|arg| arg.to_string().contains("DESIRED_MAX_LENGTH"));
+        let length = std::env::var_os("SHORTEN_MAX_LENGTH");
+        match (length, args.iter().position(|arg| arg == "run")) {
+            (Some(length), Some(run)) if invalid_length => {
+                args.insert(run + 1, length);
+                Cli::parse_from(args)
+            }
+            _ => err.exit(),
+        }
+    }
+}
+
+fn needs_implicit_run(args: &[OsString]) -> bool {
+    let command = Cli::command();
+    let args = args.iter().skip(1).filter_map(|arg| arg.to_str()).collect::<Vec<_>>();
+    let has_subcommand = args.iter().any(|arg| {
+        command
+            .get_subcommands()
+            .any(|subcommand| *arg == subcommand.get_name())
+    });
+    let wants_help = args
+        .iter()
+        .any(|arg| matches!(*arg, "-h" | "--help" | "-V" | "--version"));
+    // `SHORTEN_MAX_LENGTH=20 shorten` shortens stdin like `shorten 20`
+    let has_length = !args.is_empty() || std::env::var_os("SHORTEN_MAX_LENGTH").is_some();
+    has_length && !has_subcommand && !wants_help
+}
+
+/// Parses a delimiter, writing a tab as `\t`.
+fn parse_delimiter(value: &str) -> Result<char, String> {
+    let mut chars = value.chars();
+    match (value, chars.next(), chars.next()) {
+        ("\\t", _, _) => Ok('\t'),
+        (_, Some(delimiter), None) => Ok(delimiter),
+        _ => Err(format!("expected a single character or \\t, found {value:?}")),
+    }
+}
+
+/// Parses a language tag or a locale as written in `LANG`.
+fn parse_locale(value: &str) -> Result<Locale, String> {
+    let error = || format!("expected a locale like de or pt_BR, found {value:?}");
+    Locale::parse(value).ok_or_else(error)
+}
+
+/// Parses the `keep` or `drop` action of blank lines, or their placeholder.
+fn parse_blank(value: &str) -> Result<BlankArg, String> {
+    Ok(match value {
+        "keep" => BlankArg::Keep,
+        "drop" => BlankArg::Drop,
+        placeholder => BlankArg::Placeholder(placeholder.to_string()),
+    })
+}
+
+/// Parses a `MIN-MAX` (or a single `N`) number of words.
+fn parse_acronyms(value: &str) -> Result<Acronyms, String> {
+    let (min, max) = value.split_once('-').unwrap_or((value, value));
+    let parse = |words: &str| {
+        words.trim().parse::<usize>().map_err(|err| format!("{words:?}: {err}"))
+    };
+    let (min_words, max_words) = (parse(min)?, parse(max)?);
+    if min_words == 0 || min_words > max_words {
+        return Err(format!("invalid range of words {value:?}"));
+    }
+    Ok(Acronyms { min_words, max_words })
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum DiffArg {
+    /// `[-removed-]{+added+}` within the line
+    Word,
+    /// The original line prefixed by `-` and the shortened one by `+`
+    Unified,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum BlankArg {
+    Keep,
+    Drop,
+    Placeholder(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PadArg {
+    /// Fill after the text, aligning lines on the left
+    Right,
+    /// Fill before the text, aligning lines on the right
+    Left,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RuleKindArg {
+    Exact,
+    Remove,
+    Regex,
+    Keep,
+    Except,
+    Segment,
+    Line,
+}
+
+impl From<RuleKindArg> for RuleKind {
+    fn from(kind: RuleKindArg) -> RuleKind {
+        match kind {
+            RuleKindArg::Exact => RuleKind::Exact,
+            RuleKindArg::Remove => RuleKind::Remove,
+            RuleKindArg::Regex => RuleKind::Regex,
+            RuleKindArg::Keep => RuleKind::Keep,
+            RuleKindArg::Except => RuleKind::Except,
+            RuleKindArg::Segment => RuleKind::Segment,
+            RuleKindArg::Line => RuleKind::Line,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ImportFormatArg {
+    /// Comma-separated values
+    Csv,
+    /// Tab-separated values
+    Tsv,
+    /// An espanso match file (YAML)
+    Espanso,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormatArg {
+    /// An espanso match file, expanding `:abbreviation` into the long form
+    Espanso,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RulesFormatArg {
+    /// The `abbrev.lst` format
+    Lst,
+    /// The `abbrev.toml` format
+    Toml,
+}
+
+impl From<RulesFormatArg> for RulesFormat {
+    fn from(format: RulesFormatArg) -> RulesFormat {
+        match format {
+            RulesFormatArg::Lst => RulesFormat::Lst,
+            RulesFormatArg::Toml => RulesFormat::Toml,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum StrategyArg {
+    /// Abbreviate every word that has a matching rule
+    All,
+    /// Abbreviate by priority, then left to right, until the line fits
+    Minimal,
+    /// Apply the fewest abbreviations that make the line fit
+    Optimal,
+}
+
+impl From<StrategyArg> for Strategy {
+    fn from(strategy: StrategyArg) -> Self {
+        match strategy {
+            StrategyArg::All => Strategy::All,
+            StrategyArg::Minimal => Strategy::Minimal,
+            StrategyArg::Optimal => Strategy::Optimal,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum AnchorArg {
+    /// Keep the start of the line
+    Left,
+    /// Keep the end of the line
+    Right,
+}
+
+impl From<AnchorArg> for Anchor {
+    fn from(anchor: AnchorArg) -> Self {
+        match anchor {
+            AnchorArg::Left => Anchor::Left,
+            AnchorArg::Right => Anchor::Right,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ClockArg {
+    /// 10–11am
+    #[value(name = "12h")]
+    TwelveHour,
+    /// 10:00–11:00
+    #[value(name = "24h")]
+    TwentyFourHour,
+}
+
+impl From<ClockArg> for Clock {
+    fn from(clock: ClockArg) -> Self {
+        match clock {
+            ClockArg::TwelveHour => Clock::TwelveHour,
+            ClockArg::TwentyFourHour => Clock::TwentyFourHour,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CaseArg {
+    /// Lowercase abbreviations follow the case of the words, others are kept
+    Smart,
+    /// Keep abbreviations as written
+    Preserve,
+    /// Lowercase abbreviations
+    ForceLower,
+    /// Uppercase abbreviations
+    ForceUpper,
+    /// Give abbreviations the case of the words, however they are written
+    Mirror,
+}
+
+impl From<CaseArg> for CasePolicy {
+    fn from(case: CaseArg) -> Self {
+        match case {
+            CaseArg::Smart => CasePolicy::Smart,
+            CaseArg::Preserve => CasePolicy::Preserve,
+            CaseArg::ForceLower => CasePolicy::ForceLower,
+            CaseArg::ForceUpper => CasePolicy::ForceUpper,
+            CaseArg::Mirror => CasePolicy::Mirror,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ModeArg {
+    /// Free text, abbreviated word by word
+    Text,
+    /// Filesystem paths, shortened fish-style
+    Path,
+    /// Code identifiers, split at case changes and separators and joined back
+    Identifier,
+    /// URLs, without their scheme, "www." and query, keeping the host intact
+    Url,
+    /// Mail subjects, collapsing their Re:/Fwd: prefixes and repeated tags
+    Subject,
+    /// Git branches, keeping their ticket ID intact
+    GitRef,
+}
+
+impl From<ModeArg> for Mode {
+    fn from(mode: ModeArg) -> Self {
+        match mode {
+            ModeArg::Text => Mode::Text,
+            ModeArg::Path => Mode::Path,
+            ModeArg::Identifier => Mode::Identifier,
+            ModeArg::Url => Mode::Url,
+            ModeArg::Subject => Mode::Subject,
+            ModeArg::GitRef => Mode::GitRef,
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::abbrev::Abbreviator;
+use crate::stop_words::StopWords;
+use crate::error::{Result, ShortenError};
+use itertools::Itertools;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// The abbreviations file in the user's config directory (which may not exist).
+///
+/// `abbrev.toml` is preferred over `abbrev.lst` when both exist.
+pub fn default_abbrev_path() -> Result<PathBuf> {
+    let toml_path = config_file("abbrev.toml")?;
+    if toml_path.exists() {
+        return Ok(toml_path);
+    }
+    config_file("abbrev.lst")
+}
+
+/// A file in the user's config directory (which may not exist): the first of
+/// the config directories holding it, or else the preferred one.
+fn config_file(name: &str) -> Result<PathBuf> {
+    let dirs = config_dirs(Platform::current(), env_path);
+    let Some(preferred) = dirs.first() else {
+        return Err(ShortenError::ConfigNotFound);
+    };
+    Ok(find_in(&dirs, Path::new(name)).unwrap_or_else(|| preferred.join(name)))
+}
+
+/// The socket of `shorten daemon`, in the runtime directory or else in the
+/// temporary directory.
+#[cfg(unix)]
+pub fn default_socket_path() -> Result<PathBuf> {
+    if let Some(runtime_dir) = env_path("XDG_RUNTIME_DIR").filter(|dir| dir.is_absolute()) {
+        return Ok(runtime_dir.join("shorten.sock"));
+    }
+    let name = match std::env::var("USER") {
+        Ok(user) if !user.is_empty() => format!("shorten-{user}.sock"),
+        _ => "shorten.sock".to_string(),
+    };
+    Ok(std::env::temp_dir().join(name))
+}
+
+/// Looks for a file in the config directories.
+pub fn find_config_file(path: &Path) -> Result<Option<PathBuf>> {
+    Ok(find_in(&config_dirs(Platform::current(), env_path), path))
+}
+
+fn find_in(dirs: &[PathBuf], path: &Path) -> Option<PathBuf> {
+    dirs.iter().map(|dir| dir.join(path)).find(|path| path.exists())
+}
+
+/// The conventions for config directories.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Platform {
+    /// The XDG base directories, e.g. `~/.config/shorten`
+    Xdg,
+    /// `~/Library/Application Support/shorten`, then the XDG directories
+    MacOs,
+    /// `%APPDATA%\shorten`
+    Windows,
+}
+
+impl Platform {
+    fn current() -> Platform {
+        if cfg!(target_os = "macos") {
+            Platform::MacOs
+        } else if cfg!(windows) {
+            Platform::Windows
+        } else {
+            Platform::Xdg
+        }
+    }
+}
+
+/// The config directories of shorten on a platform, preferred first, given
+/// how to read the environment variables holding paths.
+fn config_dirs(platform: Platform, env: impl Fn(&str) -> Option<PathBuf>) -> Vec<PathBuf> {
+    let home = env("HOME");
+    let mut dirs = Vec::new();
+    match platform {
+        Platform::Windows => dirs.extend(env("APPDATA")),
+        Platform::MacOs => {
+            dirs.extend(home.as_ref().map(|home| home.join("Library/Application Support")));
+        }
+        Platform::Xdg => {}
+    }
+    if platform != Platform::Windows {
+        let config_home = env("XDG_CONFIG_HOME").filter(|dir| dir.is_absolute());
+        dirs.extend(config_home.or_else(|| home.map(|home| home.join(".config"))));
+        let config_dirs = env("XDG_CONFIG_DIRS").unwrap_or_else(|| PathBuf::from("/etc/xdg"));
+        let config_dirs = std::env::split_paths(&config_dirs).filter(|dir| dir.is_absolute());
+        dirs.extend(config_dirs);
+    }
+    dirs.into_iter().map(|dir| dir.join("shorten")).collect()
+}
+
+/// A path from an environment variable, unless it is unset or empty.
+fn env_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from)
+}
+
+/// Loads the abbreviations from `path`, or from the default abbreviations file.
+///
+/// A missing default file results in an empty [`Abbreviator`], while an
+/// explicitly requested file must exist.
+pub fn load_abbreviator(path: Option<&Path>) -> Result<Abbreviator> {
+    let abbrev_path = match path {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let default_path = default_abbrev_path()?;
+            if !default_path.exists() {
+                return Ok(Abbreviator::default());
+            }
+            default_path
+        }
+    };
+
+    Abbreviator::try_from_file(&abbrev_path)
+}
+
+/// Loads the stop words from `path`, from `stop-words.txt` in the config
+/// directory, or falls back to the built-in articles, prepositions and
+/// conjunctions.
+pub fn load_stop_words(path: Option<&Path>) -> Result<StopWords> {
+    let stop_words_path = match path {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let default_path = config_file("stop-words.txt")?;
+            if !default_path.exists() {
+                return Ok(StopWords::droppable());
+            }
+            default_path
+        }
+    };
+
+    StopWords::try_from_file(&stop_words_path)
+}
+
+/// Detects changes to the abbreviations file, and to the files it includes, by
+/// polling their modification times.
+pub struct ConfigWatcher {
+    path: Option<PathBuf>,
+    /// The abbreviations file first, then the files it included when last loaded
+    watched: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl ConfigWatcher {
+    /// Watches `path`, or the default abbreviations file, and the files the
+    /// abbreviator loaded from it for changes.
+    pub fn new(path: Option<&Path>, abbreviator: &Abbreviator) -> Result<ConfigWatcher> {
+        let file = match path {
+            Some(path) => path.to_path_buf(),
+            None => default_abbrev_path()?,
+        };
+        let watched = watch([file].iter().chain(abbreviator.files()));
+        Ok(ConfigWatcher { path: path.map(Path::to_path_buf), watched })
+    }
+
+    /// Reloads the abbreviations if any of the files changed since the last
+    /// poll, then watches the files the reloaded rules include.
+    pub fn poll(&mut self) -> Option<Result<Abbreviator>> {
+        let changed = self.watched.iter().any(|(path, modified)| modified_time(path) != *modified);
+        if !changed {
+            return None;
+        }
+
+        let reloaded = load_abbreviator(self.path.as_deref());
+        let file = self.watched[0].0.clone();
+        self.watched = match &reloaded {
+            Ok(abbreviator) => watch([file].iter().chain(abbreviator.files())),
+            // Until the rules load again, the files are the ones they included last
+            Err(_) => watch(&self.watched.iter().map(|(path, _)| path.clone()).collect_vec()),
+        };
+        Some(reloaded)
+    }
+}
+
+/// The files along with their current modification times, without duplicates.
+fn watch<'a>(files: impl IntoIterator<Item = &'a PathBuf>) -> Vec<(PathBuf, Option<SystemTime>)> {
+    files.into_iter().unique().map(|path| (path.clone(), modified_time(path))).collect()
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    path.metadata().and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{config_dirs, load_abbreviator, ConfigWatcher, Platform};
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_config_dirs() {
+        let env = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                vars.iter().find(|(var, _)| *var == name).map(|(_, value)| PathBuf::from(value))
+            }
+        };
+        let paths = |paths: &[&str]| paths.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        let linux = env(&[("HOME", "/home/me"), ("XDG_CONFIG_DIRS", "/etc/a:relative:/etc/b")]);
+        let expected = paths(&["/home/me/.config/shorten", "/etc/a/shorten", "/etc/b/shorten"]);
+        assert_eq!(config_dirs(Platform::Xdg, linux), expected);
+        let linux = env(&[("XDG_CONFIG_HOME", "/cfg")]);
+        assert_eq!(config_dirs(Platform::Xdg, linux), paths(&["/cfg/shorten", "/etc/xdg/shorten"]));
+
+        let macos = env(&[("HOME", "/Users/me")]);
+        let expected = paths(&[
+            "/Users/me/Library/Application Support/shorten",
+            "/Users/me/.config/shorten",
+            "/etc/xdg/shorten",
+        ]);
+        assert_eq!(config_dirs(Platform::MacOs, macos), expected);
+
+        let windows = env(&[("APPDATA", r"C:\Users\me\AppData\Roaming"), ("HOME", "/home/me")]);
+        let expected = PathBuf::from(r"C:\Users\me\AppData\Roaming").join("shorten");
+        assert_eq!(config_dirs(Platform::Windows, windows), [expected]);
+        assert_eq!(config_dirs(Platform::Windows, env(&[])), Vec::<PathBuf>::new());
+    }
+
+    #[test]
+    fn test_config_watcher() {
+        let dir = std::env::temp_dir().join(format!("shorten-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (path, included) = (dir.join("abbrev.lst"), dir.join("extra.lst"));
+        std::fs::write(&path, "Architecture = arch\n@include extra.lst\n").unwrap();
+        std::fs::write(&included, "Session = sesn\n").unwrap();
+        // Modification times may not change between writes on coarse clocks
+        let touch = |path: &PathBuf, text: &str, seconds: u64| {
+            std::fs::write(path, text).unwrap();
+            let file = std::fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(SystemTime::now() + Duration::from_secs(seconds)).unwrap();
+        };
+
+        let abbreviator = load_abbreviator(Some(&path)).unwrap();
+        let mut watcher = ConfigWatcher::new(Some(&path), &abbreviator).unwrap();
+        assert!(watcher.poll().is_none());
+
+        touch(&included, "Session = S\n", 10);
+        let abbreviator = watcher.poll().unwrap().unwrap();
+        let abbrev = abbreviator.abbreviate("session").map(|abbrev| abbrev.text.into_owned());
+        assert_eq!(abbrev.as_deref(), Some("S"));
+        assert!(watcher.poll().is_none());
+
+        // A file the rules include after a reload is watched too
+        touch(&path, "@include extra.lst\n@include more.lst\n", 20);
+        let more = dir.join("more.lst");
+        std::fs::write(&more, "Learning = learn\n").unwrap();
+        assert!(watcher.poll().unwrap().is_ok());
+        touch(&more, "Learning = L\n", 30);
+        let abbreviator = watcher.poll().unwrap().unwrap();
+        let abbrev = abbreviator.abbreviate("learning").map(|abbrev| abbrev.text.into_owned());
+        assert_eq!(abbrev.as_deref(), Some("L"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,186 @@
+//! `shorten daemon` and `shorten client`: shortening over a Unix socket.
+//!
+//! Requests are lines of `<len>\t<text>`, each answered by the shortened text
+//! on a line of its own. Lines without a length prefix are sent back unchanged.
+//!
+//! With the `serde` feature, a `rules\t<json>` line replaces the rules with
+//! those of a JSON abbreviator snapshot, until the config changes, and is
+//! answered by `ok` or by `error: ` and the reason the rules were rejected.
+//! The `@fallback-cmd` and `@hook` commands of the rules file are kept:
+//! snapshots can't set any.
+
+use crate::cli::{ClientArgs, DaemonArgs, RulesArgs};
+use crate::{shorten_request, split_length_prefix};
+use eyre::{bail, Context, ContextCompat};
+#[cfg(feature = "serde")]
+use shorten::abbrev::Abbreviator;
+use shorten::config;
+use shorten::shortener::Shortener;
+use std::borrow::Cow;
+use std::io::{stdin, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, PoisonError, RwLock};
+
+pub fn daemon(rules: &RulesArgs, args: DaemonArgs) -> eyre::Result<()> {
+    let socket = socket_path(args.socket)?;
+    if socket.exists() {
+        if UnixStream::connect(&socket).is_ok() {
+            bail!("A daemon is already listening on {}", socket.display());
+        }
+        // Left behind by a daemon that didn't shut down cleanly
+        std::fs::remove_file(&socket)?;
+    }
+    let listener = UnixListener::bind(&socket)
+        .with_context(|| format!("Failed to listen on {}", socket.display()))?;
+
+    let abbreviator = rules.load()?;
+    let mut watcher = rules
+        .has_file()
+        .then(|| config::ConfigWatcher::new(rules.config.as_deref(), &abbreviator))
+        .transpose()?;
+    let shortener = args.options.shortener(abbreviator, usize::MAX)?;
+    let shortener = Arc::new(RwLock::new(shortener));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("Failed to accept a connection: {err}");
+                continue;
+            }
+        };
+
+        if let Some(reloaded) = watcher.as_mut().and_then(|watcher| watcher.poll()) {
+            match reloaded.and_then(|abbreviator| rules.select(abbreviator)) {
+                Ok(abbreviator) => {
+                    let mut shortener = shortener.write().unwrap_or_else(PoisonError::into_inner);
+                    shortener.set_abbreviator(abbreviator);
+                }
+                Err(err) => eprintln!("Keeping previous abbreviations: {err:#}"),
+            }
+        }
+
+        let shortener = Arc::clone(&shortener);
+        std::thread::spawn(move || {
+            let served = stream
+                .try_clone()
+                .and_then(|input| serve(&shortener, BufReader::new(input), stream));
+            if let Err(err) = served {
+                eprintln!("Connection failed: {err}");
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Answers the requests of one connection until the client closes it.
+fn serve(
+    shortener: &RwLock<Shortener>,
+    input: impl BufRead,
+    mut output: impl Write,
+) -> std::io::Result<()> {
+    for line in input.lines() {
+        let line = line?;
+        let mut response = match split_length_prefix(&line) {
+            Some(request) => {
+                let shortener = shortener.read().unwrap_or_else(PoisonError::into_inner);
+                shorten_request(&shortener, request).text.into_owned()
+            }
+            #[cfg(feature = "serde")]
+            None if line.starts_with("rules\t") => {
+                match serde_json::from_str::<Abbreviator>(&line["rules\t".len()..]) {
+                    Ok(mut abbreviator) => {
+                        let mut shortener =
+                            shortener.write().unwrap_or_else(PoisonError::into_inner);
+                        abbreviator.set_fallback(shortener.abbreviator().fallback().cloned());
+                        abbreviator.set_hooks(shortener.abbreviator().hooks().to_vec());
+                        shortener.set_abbreviator(abbreviator);
+                        "ok".to_string()
+                    }
+                    Err(err) => format!("error: {err}"),
+                }
+            }
+            None => line,
+        };
+        response.push('\n');
+        output.write_all(response.as_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn client(args: ClientArgs) -> eyre::Result<()> {
+    let socket = socket_path(args.socket)?;
+    let mut requests = UnixStream::connect(&socket)
+        .with_context(|| format!("No daemon listening on {}", socket.display()))?;
+    let mut responses = BufReader::new(requests.try_clone()?).lines();
+    let mut send = |text: &str| -> eyre::Result<()> {
+        // A request is a single line
+        let text = match text.contains(['\n', '\r']) {
+            true => Cow::Owned(text.replace(['\n', '\r'], " ")),
+            false => Cow::Borrowed(text),
+        };
+        writeln!(requests, "{}\t{text}", args.desired_max_length)?;
+        let response = responses.next().context("The daemon closed the connection")??;
+        println!("{response}");
+        Ok(())
+    };
+
+    if args.text.is_empty() {
+        for line in stdin().lock().lines() {
+            send(&line?)?;
+        }
+    } else {
+        send(&args.text.join(" "))?;
+    }
+    Ok(())
+}
+
+fn socket_path(socket: Option<PathBuf>) -> eyre::Result<PathBuf> {
+    Ok(socket.map_or_else(config::default_socket_path, Ok)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::daemon::serve;
+    use shorten::abbrev::Abbreviator;
+    use shorten::shortener::Shortener;
+    use std::sync::RwLock;
+
+    #[test]
+    fn test_serve() {
+        let abbreviator = Abbreviator::from_lines(["Architecture = arch"].into_iter()).unwrap();
+        let shortener = RwLock::new(Shortener::with_abbreviator(usize::MAX, abbreviator).unwrap());
+        let requests = "10\tArchitecture Review\n40\tArchitecture Review\nArchitecture\n\
+            10:right\tArchitecture Review\n10:up\tArchitecture Review\n";
+        let mut responses = Vec::new();
+        serve(&shortener, requests.as_bytes(), &mut responses).unwrap();
+        let expected = "Arch Review\nArchitecture Review\nArchitecture\n\
+            Arch Review\n10:up\tArchitecture Review\n";
+        assert_eq!(String::from_utf8(responses).unwrap(), expected);
+
+        #[cfg(feature = "serde")]
+        {
+            let requests = "rules\t{\"rules\": [{\"matcher\": {\"exact\": \"Review\"}, \
+                \"replacement\": \"rev\"}]}\n10\tArchitecture Review\nrules\t[]\n";
+            let mut responses = Vec::new();
+            serve(&shortener, requests.as_bytes(), &mut responses).unwrap();
+            let responses = String::from_utf8(responses).unwrap();
+            assert!(responses.starts_with("ok\nArchitecture Rev\nerror: "), "{responses}");
+
+            // Only the rules file sets the command the shell runs
+            let rules = ["Architecture = arch", "@fallback-cmd echo local"];
+            let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+            let shortener = RwLock::new(Shortener::with_abbreviator(10, abbreviator).unwrap());
+            let requests = "rules\t{\"rules\": [], \"fallback_command\": \"touch pwned\"}\n\
+                rules\t{\"rules\": []}\n";
+            let mut responses = Vec::new();
+            serve(&shortener, requests.as_bytes(), &mut responses).unwrap();
+            let responses = String::from_utf8(responses).unwrap();
+            let rejected = "error: unknown field `fallback_command`";
+            assert!(responses.starts_with(rejected), "{responses}");
+            assert!(responses.ends_with("\nok\n"), "{responses}");
+            let shortener = shortener.read().unwrap();
+            assert_eq!(shortener.abbreviator().fallback().unwrap().command(), "echo local");
+        }
+    }
+}
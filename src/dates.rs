@@ -0,0 +1,164 @@
+//! Dates and times for the dates pass: "Wednesday, September 25, 2024" is
+//! written in the date format (Sep 25 by default, with the month names of the
+//! locale) and "10:00 AM - 11:00 AM" as 10–11am.
+
+use crate::locale::Locale;
+use crate::shortener::Clock;
+use chrono::{Datelike, Local, NaiveDate, NaiveTime, Timelike};
+use regex::{Captures, Regex};
+use std::sync::LazyLock;
+
+const MONTH: &str = r"(jan(?:uary)?|feb(?:ruary)?|mar(?:ch)?|apr(?:il)?|may|june?|july?|aug(?:ust)?|sep(?:t(?:ember)?)?|oct(?:ober)?|nov(?:ember)?|dec(?:ember)?)\.?";
+const WEEKDAY: &str = r"(?:(?:monday|tuesday|wednesday|thursday|friday|saturday|sunday|mon|tues?|wed|thu(?:rs?)?|fri|sat|sun)\.?,?\s+)?";
+
+/// "September 25, 2024", "25 Sep 2024" and "2024-09-25", each with an
+/// optional weekday before
+static DATE: LazyLock<Regex> = LazyLock::new(|| {
+    let day = r"(\d{1,2})(?:st|nd|rd|th)?";
+    let year = r"(?:,?\s+(\d{4}))?";
+    let pattern = format!(
+        r"(?i)\b{WEEKDAY}(?:{MONTH}\s+{day}{year}|{day}\s+{MONTH}{year}|(\d{{4}})-(\d{{2}})-(\d{{2}}))\b"
+    );
+    Regex::new(&pattern).unwrap()
+});
+
+/// "10:00 AM", "14:30" or "10 - 11am", the second time of a range being optional
+static TIME: LazyLock<Regex> = LazyLock::new(|| {
+    let time = |n: usize| {
+        format!(r"(?P<h{n}>\d{{1,2}})(?::(?P<m{n}>\d{{2}}))?(?:\s*(?P<s{n}>[ap]m\b|[ap]\.m\.))?")
+    };
+    let pattern = format!(r"(?i)\b{}(?:\s*(?:-|–|—|\bto\b)\s*{})?", time(1), time(2));
+    Regex::new(&pattern).unwrap()
+});
+
+/// The text with its dates and times rewritten, or `None` when it has none.
+pub(crate) fn compress(
+    text: &str,
+    format: &str,
+    clock: Clock,
+    locale: Option<&Locale>,
+) -> Option<String> {
+    let dated = DATE.replace_all(text, |captures: &Captures<'_>| {
+        let Some(date) = date(captures) else {
+            return captures[0].to_string();
+        };
+        match locale {
+            Some(locale) => locale.format_date(date, format),
+            None => date.format(format).to_string(),
+        }
+    });
+    let timed = TIME.replace_all(&dated, |captures: &Captures<'_>| {
+        times(captures, clock).unwrap_or_else(|| captures[0].to_string())
+    });
+    (timed != text).then(|| timed.into_owned())
+}
+
+fn date(captures: &Captures<'_>) -> Option<NaiveDate> {
+    let number = |index: usize| captures.get(index)?.as_str().parse::<u32>().ok();
+    let month = |index: usize| {
+        let name = captures.get(index)?.as_str().to_lowercase();
+        let months =
+            ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+        months.iter().position(|month| name.starts_with(month)).map(|index| index as u32 + 1)
+    };
+    // Dates without a year are in the current one
+    let (year, month, day) = if captures.get(1).is_some() {
+        (number(3), month(1)?, number(2)?)
+    } else if captures.get(5).is_some() {
+        (number(6), month(5)?, number(4)?)
+    } else {
+        (number(7), number(8)?, number(9)?)
+    };
+    let year = year.map_or_else(|| Local::now().year(), |year| year as i32);
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// A time or a range of times, written for the clock. Hours without minutes
+/// nor am/pm are not times, and times without am/pm stay on the 24-hour clock
+/// (3:16 is as likely a verse as a time).
+fn times(captures: &Captures<'_>, clock: Clock) -> Option<String> {
+    let part = |name: &str| captures.name(name).map(|part| part.as_str());
+    let suffix = |name: &str| part(name).map(|suffix| suffix.to_lowercase().starts_with('p'));
+    let (start_pm, end_pm) = (suffix("s1"), suffix("s2"));
+    let clock = match (start_pm, end_pm) {
+        (None, None) => Clock::TwentyFourHour,
+        _ => clock,
+    };
+    let is_time = |n: usize| part(&format!("m{n}")).is_some() || part(&format!("s{n}")).is_some();
+    // The end of a range tells whether it is one of times, like in "10 - 11am"
+    if !is_time(if part("h2").is_some() { 2 } else { 1 }) {
+        return None;
+    }
+
+    let time = |n: usize, pm: Option<bool>| {
+        let hour: u32 = part(&format!("h{n}"))?.parse().ok()?;
+        let minute = part(&format!("m{n}")).map_or(Some(0), |minute| minute.parse().ok())?;
+        let hour = match pm {
+            Some(_) if !(1..=12).contains(&hour) => return None,
+            Some(pm) => hour % 12 + if pm { 12 } else { 0 },
+            None => hour,
+        };
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    };
+    let Some(end_hour) = part("h2") else {
+        return Some(write(time(1, start_pm)?, clock, true));
+    };
+
+    // "10 - 11am" is 10am, but "11 - 1pm" 11am
+    let end = time(2, end_pm)?;
+    let start_pm = start_pm.or_else(|| {
+        let start_hour: u32 = part("h1")?.parse().ok()?;
+        let end_hour: u32 = end_hour.parse().ok()?;
+        end_pm.map(|pm| pm && (start_hour % 12 <= end_hour % 12))
+    });
+    let start = time(1, start_pm)?;
+    let same_half = start.hour() / 12 == end.hour() / 12;
+    let start = write(start, clock, !same_half);
+    Some(format!("{start}–{}", write(end, clock, true)))
+}
+
+fn write(time: NaiveTime, clock: Clock, with_suffix: bool) -> String {
+    match clock {
+        Clock::TwentyFourHour => time.format("%-H:%M").to_string(),
+        Clock::TwelveHour => {
+            let (pm, hour) = time.hour12();
+            let minutes = match time.minute() {
+                0 => String::new(),
+                minute => format!(":{minute:02}"),
+            };
+            let suffix = match (with_suffix, pm) {
+                (false, _) => "",
+                (true, true) => "pm",
+                (true, false) => "am",
+            };
+            format!("{hour}{minutes}{suffix}")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dates::compress;
+    use crate::locale::Locale;
+    use crate::shortener::Clock;
+
+    #[test]
+    fn test_dates() {
+        let compressed = |text: &str| compress(text, "%b %-d", Clock::TwelveHour, None);
+        let expected = Some("Review Sep 25 10–11am".to_string());
+        assert_eq!(compressed("Review September 25, 2024 10:00 AM - 11:00 AM"), expected);
+        let expected = Some("Sep 25, 11am–1pm".to_string());
+        assert_eq!(compressed("Wed, 25th Sep 2024, 11 - 1pm"), expected);
+        assert_eq!(compressed("2024-09-25 at 2:30 PM"), Some("Sep 25 at 2:30pm".to_string()));
+        let expected = Some("John 3:16, 14:00–15:30".to_string());
+        assert_eq!(compressed("John 3:16, 14:00 - 15:30"), expected);
+        assert_eq!(compressed("Q3 planning for 10 - 12 people"), None);
+        assert_eq!(compressed("February 30, 2024"), None);
+        let compressed = compress("9:30am to 17:00", "%d/%m", Clock::TwentyFourHour, None);
+        assert_eq!(compressed, Some("9:30–17:00".to_string()));
+        let german = Locale::parse("de").unwrap();
+        let german = Some(&german);
+        let compressed = compress("Review 2024-03-05", "%a %-d. %b", Clock::TwelveHour, german);
+        assert_eq!(compressed, Some("Review Di 5. Mär".to_string()));
+    }
+}
@@ -0,0 +1,116 @@
+//! `--diff`: what shortening changed in each line, for reviewing a new set of
+//! rules on a corpus before adopting it.
+
+/// Writes the word-level diff between a line and its shortened version, like
+/// `git diff --word-diff`: `[-removed-]{+added+}`, or in red and green.
+pub fn word_diff(original: &str, shortened: &str, color: bool) -> String {
+    let (removed_style, added_style) = match color {
+        true => (("\x1b[31m", "\x1b[0m"), ("\x1b[32m", "\x1b[0m")),
+        false => (("[-", "-]"), ("{+", "+}")),
+    };
+
+    let mut diff = String::with_capacity(original.len() * 2);
+    let (mut removed, mut added) = (String::new(), String::new());
+    let flush = |diff: &mut String, removed: &mut String, added: &mut String| {
+        for (text, (start, end)) in [(removed, removed_style), (added, added_style)] {
+            if !text.is_empty() {
+                diff.extend([start, text.as_str(), end]);
+                text.clear();
+            }
+        }
+    };
+    for change in changes(&tokens(original), &tokens(shortened)) {
+        match change {
+            Change::Same(token) => {
+                flush(&mut diff, &mut removed, &mut added);
+                diff.push_str(token);
+            }
+            Change::Removed(token) => removed.push_str(token),
+            Change::Added(token) => added.push_str(token),
+        }
+    }
+    flush(&mut diff, &mut removed, &mut added);
+    diff
+}
+
+/// Writes a line and its shortened version like a unified diff, `-` and `+`
+/// lines when they differ, or the line prefixed by a space.
+pub fn unified_diff(original: &str, shortened: &str, color: bool) -> String {
+    match (original == shortened, color) {
+        (true, _) => format!(" {original}"),
+        (false, true) => format!("\x1b[31m-{original}\x1b[0m\n\x1b[32m+{shortened}\x1b[0m"),
+        (false, false) => format!("-{original}\n+{shortened}"),
+    }
+}
+
+/// The words and the runs of whitespace between them.
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_whitespace = None;
+    for (index, c) in text.char_indices() {
+        if in_whitespace.is_some_and(|in_whitespace| in_whitespace != c.is_whitespace()) {
+            tokens.push(&text[start..index]);
+            start = index;
+        }
+        in_whitespace = Some(c.is_whitespace());
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+enum Change<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// The changes turning one list of tokens into the other, keeping their
+/// longest common subsequence.
+fn changes<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Change<'a>> {
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut common = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = match old[i] == new[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            changes.push(Change::Same(old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if j == new.len() || (i < old.len() && common[i + 1][j] >= common[i][j + 1]) {
+            changes.push(Change::Removed(old[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(new[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::diff::{unified_diff, word_diff};
+
+    #[test]
+    fn test_diff() {
+        let original = "Rescheduled Architecture  Review (Weekly)";
+        let shortened = "Arch  Review (W)";
+        assert_eq!(
+            word_diff(original, shortened, false),
+            "[-Rescheduled Architecture-]{+Arch+}  Review [-(Weekly)-]{+(W)+}"
+        );
+        assert_eq!(word_diff("a b", "a b", true), "a b");
+        assert_eq!(unified_diff(original, shortened, false), format!("-{original}\n+{shortened}"));
+    }
+}
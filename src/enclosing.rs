@@ -0,0 +1,157 @@
+//! The characters enclosing words, like brackets and quotes.
+
+use crate::error::{ParseErrorKind, Result};
+
+/// Opening and closing characters around words, e.g. the brackets of "[Monthly]".
+///
+/// Enclosed words are abbreviated without their enclosing characters when no
+/// rule matches them as a whole, and phrases never span them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Enclosing {
+    openers: Vec<char>,
+    closers: Vec<char>,
+}
+
+/// The brackets and quotes enclosing words by default: the ASCII ones, then
+/// the CJK, fullwidth and typographic ones.
+const DEFAULT_PAIRS: [(char, char); 20] = [
+    ('(', ')'),
+    ('[', ']'),
+    ('{', '}'),
+    ('<', '>'),
+    ('"', '"'),
+    ('*', '*'),
+    ('「', '」'),
+    ('『', '』'),
+    ('【', '】'),
+    ('〔', '〕'),
+    ('《', '》'),
+    ('〈', '〉'),
+    ('（', '）'),
+    ('［', '］'),
+    ('｛', '｝'),
+    ('«', '»'),
+    ('‹', '›'),
+    ('“', '”'),
+    ('‘', '’'),
+    ('„', '“'),
+];
+
+impl Default for Enclosing {
+    fn default() -> Self {
+        let mut enclosing = Enclosing::none();
+        for (opener, closer) in DEFAULT_PAIRS {
+            enclosing.add(opener, closer);
+        }
+        enclosing
+    }
+}
+
+impl Enclosing {
+    /// No enclosing characters at all.
+    pub fn none() -> Enclosing {
+        Enclosing { openers: Vec::new(), closers: Vec::new() }
+    }
+
+    /// Adds an opener and its closer, which may be the same character (like `"`).
+    pub fn add(&mut self, opener: char, closer: char) {
+        self.openers.push(opener);
+        self.closers.push(closer);
+    }
+
+    /// The openers along with their closers, in the order they were added.
+    pub fn pairs(&self) -> impl Iterator<Item = (char, char)> + '_ {
+        self.openers.iter().copied().zip(self.closers.iter().copied())
+    }
+
+    pub fn is_opener(&self, c: char) -> bool {
+        self.openers.contains(&c)
+    }
+
+    pub fn is_closer(&self, c: char) -> bool {
+        self.closers.contains(&c)
+    }
+
+    /// The closer paired with an opener.
+    pub fn closer_of(&self, opener: char) -> Option<char> {
+        let index = self.openers.iter().position(|&c| c == opener)?;
+        Some(self.closers[index])
+    }
+
+    /// Splits the leading openers and trailing closers off a word.
+    pub(crate) fn split<'a>(&self, input: &'a str) -> EnclosedWord<'a> {
+        let opener_len = input
+            .char_indices()
+            .find(|(_, c)| !self.is_opener(*c))
+            .map_or(input.len(), |(index, _)| index);
+        let closer_start = input
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| self.is_closer(*c))
+            .last()
+            .map_or(input.len(), |(index, _)| index)
+            .max(opener_len);
+        EnclosedWord {
+            word: &input[opener_len..closer_start],
+            openers: &input[..opener_len],
+            closers: &input[closer_start..],
+        }
+    }
+}
+
+pub(crate) struct EnclosedWord<'a> {
+    pub word: &'a str,
+    pub openers: &'a str,
+    pub closers: &'a str,
+}
+
+impl EnclosedWord<'_> {
+    pub fn is_enclosed(&self) -> bool {
+        !self.openers.is_empty() || !self.closers.is_empty()
+    }
+}
+
+/// Parses whitespace-separated pairs like `«»`, where a single character
+/// (like `` ` ``) encloses on both sides.
+pub(crate) fn parse_pairs(text: &str) -> Result<Vec<(char, char)>> {
+    text.split_whitespace()
+        .map(|pair| {
+            let mut chars = pair.chars();
+            match (chars.next(), chars.next(), chars.next()) {
+                (Some(opener), None, _) => Ok((opener, opener)),
+                (Some(opener), Some(closer), None) => Ok((opener, closer)),
+                _ => Err(ParseErrorKind::InvalidEnclosingPair(pair.to_string()).into()),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::enclosing::{parse_pairs, Enclosing};
+
+    #[test]
+    fn test_enclosing() {
+        let mut enclosing = Enclosing::default();
+        for (opener, closer) in parse_pairs("「」 «» `").unwrap() {
+            enclosing.add(opener, closer);
+        }
+
+        let enclosed = enclosing.split("「Architecture」");
+        assert_eq!((enclosed.openers, enclosed.word, enclosed.closers), ("「", "Architecture", "」"));
+        let enclosed = Enclosing::default().split("«【Architecture】»");
+        assert_eq!((enclosed.openers, enclosed.word, enclosed.closers), ("«【", "Architecture", "】»"));
+        let enclosed = enclosing.split("`code`");
+        assert_eq!((enclosed.openers, enclosed.word, enclosed.closers), ("`", "code", "`"));
+        let enclosed = enclosing.split("\"");
+        assert_eq!((enclosed.openers, enclosed.word, enclosed.closers), ("\"", "", ""));
+        let enclosed = enclosing.split("«Architecture");
+        assert_eq!((enclosed.openers, enclosed.word, enclosed.closers), ("«", "Architecture", ""));
+        let enclosed = enclosing.split("Architecture」");
+        assert_eq!((enclosed.openers, enclosed.word, enclosed.closers), ("", "Architecture", "」"));
+        let enclosed = enclosing.split("「」");
+        assert_eq!((enclosed.openers, enclosed.word, enclosed.closers), ("「", "", "」"));
+        assert!(!Enclosing::none().split("(word)").is_enclosed());
+        assert!(parse_pairs("«»>").is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! The errors of the library, for callers to tell apart a missing config
+//! from an invalid rule.
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub type Result<T, E = ShortenError> = std::result::Result<T, E>;
+
+/// An error loading rules, stop words or the config.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ShortenError {
+    /// There is no config directory, e.g. because `HOME` is not set
+    ConfigNotFound,
+    /// A file could not be read
+    Io { path: PathBuf, source: io::Error },
+    /// A rule or a setting is invalid
+    Parse {
+        /// The file the rule is in, when known
+        file: Option<PathBuf>,
+        /// 1-based line number of the rule, or 0 when unknown
+        line: usize,
+        kind: ParseErrorKind,
+    },
+    /// An error in a file included by another one
+    Included { from: PathBuf, error: Box<ShortenError> },
+}
+
+/// What is wrong with a rule or a setting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseErrorKind {
+    /// A rule without the `=` between its matcher and its abbreviation
+    MissingEquals,
+    /// A `/regex/` without its closing `/`
+    UnclosedRegex,
+    InvalidRegex { pattern: String, reason: String },
+    InvalidPriority(String),
+    /// The `N` of `@iflen>N` is not a number
+    InvalidLengthCondition(String),
+    /// A `[ctx:` prefix without its closing `]`
+    UnclosedContext,
+    /// A segment rule with `!keep`
+    KeepSegment,
+    /// A whole-line rule with `!keep`
+    KeepLine,
+    /// A regex, segment or whole-line rule matching inflections
+    InflectPattern,
+    /// A regex, segment or whole-line rule with `!except`
+    ExceptPattern,
+    InvalidDateFormat(String),
+    /// An unknown name after `@case`
+    InvalidCasePolicy(String),
+    /// An unknown name after `@normalize`
+    InvalidNormalization(String),
+    InvalidEnclosingPair(String),
+    /// `@include` without a file name
+    MissingInclude,
+    /// An `@name` line that is neither a directive nor a rule
+    UnknownDirective(String),
+    IncludeNotFound(PathBuf),
+    /// Files including each other, in include order
+    IncludeCycle(Vec<PathBuf>),
+    /// `@include` in rules that don't come from a file
+    IncludeWithoutFile,
+    /// A TOML syntax error or a field of the wrong type
+    Toml(String),
+    /// A row of a spreadsheet export that can't be read
+    InvalidRow(String),
+    /// A flag of a spreadsheet export that doesn't exist
+    UnknownFlag(String),
+    /// A rule that the `abbrev.lst` format can't express
+    TomlOnly(String),
+    /// A line of an espanso match file that can't be read
+    InvalidSnippet(String),
+    /// A language tag that isn't like `de` or `pt-BR`
+    InvalidLocale(String),
+    /// A `@builtin` pack that isn't shipped
+    UnknownPack(String),
+    /// A `@fallback-timeout` that isn't like `500ms` or `2s`
+    InvalidTimeout(String),
+    /// A `@hook` without a name and a command
+    InvalidHook(String),
+    /// A `@hook` in a build without the `script-hooks` feature
+    ScriptHooksDisabled(String),
+    /// A `#test` line that isn't `#test [LENGTH] "text" => "shortened"`
+    InvalidTest,
+    /// More multi-word phrases than fit in one automaton, with the reason
+    TooManyPhrases(String),
+    /// More CJK words than fit in the dictionary of `--segment-cjk`, with the reason
+    TooManyCjkWords(String),
+}
+
+impl ShortenError {
+    /// Locates a parse error on a (0-based) line, unless it already is.
+    pub(crate) fn at_line(self, line: usize) -> ShortenError {
+        match self {
+            ShortenError::Parse { file, line: 0, kind } => {
+                ShortenError::Parse { file, line: line + 1, kind }
+            }
+            error => error,
+        }
+    }
+
+    /// Locates a parse error in a file, unless it already is.
+    pub(crate) fn in_file(self, path: &Path) -> ShortenError {
+        match self {
+            ShortenError::Parse { file: None, line, kind } => {
+                ShortenError::Parse { file: Some(path.to_path_buf()), line, kind }
+            }
+            error => error,
+        }
+    }
+}
+
+impl From<ParseErrorKind> for ShortenError {
+    fn from(kind: ParseErrorKind) -> ShortenError {
+        ShortenError::Parse { file: None, line: 0, kind }
+    }
+}
+
+impl fmt::Display for ShortenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShortenError::ConfigNotFound => {
+                f.write_str("Failed to find the config directory, HOME is not set")
+            }
+            ShortenError::Io { path, .. } => write!(f, "Failed to read {}", path.display()),
+            ShortenError::Parse { file: Some(file), line: 0, kind } => {
+                write!(f, "{}: {kind}", file.display())
+            }
+            ShortenError::Parse { file: Some(file), line, kind } => {
+                write!(f, "{}:{line}: {kind}", file.display())
+            }
+            ShortenError::Parse { file: None, line: 0, kind } => write!(f, "{kind}"),
+            ShortenError::Parse { file: None, line, kind } => write!(f, "line {line}: {kind}"),
+            ShortenError::Included { from, error } => {
+                write!(f, "In file included from {}: {error}", from.display())
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::MissingEquals => f.write_str("Invalid abbreviation, no '=' found"),
+            ParseErrorKind::UnclosedRegex => f.write_str("Invalid regex, no closing '/' found"),
+            ParseErrorKind::InvalidRegex { pattern, reason } => {
+                write!(f, "Invalid regex '{pattern}': {reason}")
+            }
+            ParseErrorKind::InvalidPriority(value) => write!(f, "Invalid rule priority '{value}'"),
+            ParseErrorKind::InvalidLengthCondition(value) => {
+                write!(f, "Invalid length condition '{value}'")
+            }
+            ParseErrorKind::UnclosedContext => f.write_str("Invalid context, no closing ']' found"),
+            ParseErrorKind::KeepSegment => f.write_str("Segment rules can't be keep rules"),
+            ParseErrorKind::KeepLine => f.write_str("Whole-line rules can't be keep rules"),
+            ParseErrorKind::InflectPattern => {
+                f.write_str("Only exact rules can match plurals and possessives")
+            }
+            ParseErrorKind::ExceptPattern => {
+                f.write_str("Exceptions are the exact text of a word or phrase, not a pattern")
+            }
+            ParseErrorKind::InvalidDateFormat(format) => {
+                write!(f, "Invalid date format '{format}'")
+            }
+            ParseErrorKind::InvalidCasePolicy(name) => write!(
+                f,
+                "Invalid case policy '{name}', expected smart, preserve, force-lower, force-upper \
+                 or mirror"
+            ),
+            ParseErrorKind::InvalidNormalization(name) => {
+                write!(f, "Invalid normalization form '{name}', expected nfc or nfkc")
+            }
+            ParseErrorKind::InvalidEnclosingPair(pair) => {
+                write!(f, "Invalid enclosing pair '{pair}', expected an opener and a closer")
+            }
+            ParseErrorKind::MissingInclude => f.write_str("Missing file name after @include"),
+            ParseErrorKind::UnknownDirective(name) => write!(f, "Unknown directive '{name}'"),
+            ParseErrorKind::IncludeNotFound(path) => {
+                write!(f, "Included file {} not found", path.display())
+            }
+            ParseErrorKind::IncludeCycle(chain) => {
+                let chain = chain.iter().map(|path| path.display().to_string());
+                write!(f, "Include cycle: {}", chain.collect::<Vec<_>>().join(" -> "))
+            }
+            ParseErrorKind::IncludeWithoutFile => {
+                f.write_str("Includes are only supported when loading rules from a file")
+            }
+            ParseErrorKind::Toml(message) => f.write_str(message),
+            ParseErrorKind::InvalidRow(message) => f.write_str(message),
+            ParseErrorKind::UnknownFlag(flag) => write!(f, "Unknown flag '{flag}'"),
+            ParseErrorKind::InvalidSnippet(message) => f.write_str(message),
+            ParseErrorKind::InvalidLocale(name) => {
+                write!(f, "Invalid locale '{name}', expected a language tag like de or pt-BR")
+            }
+            ParseErrorKind::UnknownPack(name) => {
+                let packs = crate::builtin::names().collect::<Vec<_>>().join(", ");
+                match packs.is_empty() {
+                    true => write!(f, "Unknown rule pack '{name}', built without the packs"),
+                    false => write!(f, "Unknown rule pack '{name}', expected one of {packs}"),
+                }
+            }
+            ParseErrorKind::InvalidTimeout(timeout) => {
+                write!(f, "Invalid timeout '{timeout}', expected a duration like 500ms or 2s")
+            }
+            ParseErrorKind::InvalidHook(hook) => {
+                write!(f, "Invalid hook '{hook}', expected a name and a command")
+            }
+            ParseErrorKind::ScriptHooksDisabled(name) => {
+                write!(f, "Hook '{name}' needs shorten built with the script-hooks feature")
+            }
+            ParseErrorKind::InvalidTest => {
+                f.write_str("Invalid test, expected #test [LENGTH] \"text\" => \"shortened\"")
+            }
+            ParseErrorKind::TomlOnly(what) => {
+                write!(f, "{what} can only be written in the TOML format")
+            }
+            ParseErrorKind::TooManyPhrases(reason) => {
+                write!(f, "Too many multi-word phrases: {reason}")
+            }
+            ParseErrorKind::TooManyCjkWords(reason) => {
+                write!(f, "Too many CJK words: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShortenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShortenError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl std::error::Error for ParseErrorKind {}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::error::{ParseErrorKind, ShortenError};
+    use std::path::Path;
+
+    #[test]
+    fn test_errors() {
+        let Err(err) = Abbreviator::from_lines(["Session = sesn", "!x Session = S"].into_iter()) else {
+            panic!("an invalid priority is rejected");
+        };
+        assert!(matches!(
+            &err,
+            ShortenError::Parse { file: None, line: 2, kind: ParseErrorKind::InvalidPriority(value) }
+                if value == "x"
+        ));
+        assert_eq!(err.to_string(), "line 2: Invalid rule priority 'x'");
+
+        let missing = Path::new("/nonexistent/abbrev.lst");
+        let Err(err) = Abbreviator::try_from_file(missing) else {
+            panic!("a missing file is reported");
+        };
+        assert!(matches!(&err, ShortenError::Io { path, .. } if path == missing));
+        assert!(std::error::Error::source(&err).is_some());
+    }
+}
@@ -0,0 +1,269 @@
+//! Converts between shorten rules and the snippets of the espanso text
+//! expander, so that one set of abbreviations serves both:
+//!
+//! ```yaml
+//! matches:
+//!   - trigger: ":arch"
+//!     replace: "Architecture"
+//! ```
+//!
+//! A snippet expands its trigger into its replacement, which makes it the rule
+//! `Architecture = arch` the other way around; the `:` triggers start with is
+//! not part of the abbreviation. Only the YAML these match files are written in
+//! is read: a `matches` list of maps of plain or quoted strings, where the keys
+//! other than `trigger`, `triggers` and `replace` (`word`, `vars`…) are ignored.
+
+use crate::abbrev::Abbreviator;
+use crate::error::{ParseErrorKind, Result, ShortenError};
+use crate::rule::{Matcher, Rule};
+use itertools::Itertools;
+use std::collections::HashSet;
+
+/// What the triggers of the exported snippets start with
+const TRIGGER_PREFIX: char = ':';
+
+/// A snippet of a match file, as the rules it stands for and the (0-based)
+/// line it starts on, or the reason it can't be a rule.
+pub(crate) type Snippet = (usize, std::result::Result<Vec<Rule>, String>);
+
+#[derive(Default)]
+struct Fields {
+    line: usize,
+    triggers: Vec<String>,
+    replace: Option<String>,
+    /// A field that makes the snippet more than a plain expansion
+    unsupported: Option<String>,
+}
+
+/// Reads the snippets of an espanso match file.
+pub(crate) fn read(yaml: &str) -> Result<Vec<Snippet>> {
+    let mut snippets = Vec::new();
+    let mut in_matches = false;
+    let mut current: Option<Fields> = None;
+    // The indentation of the key a multi-line value belongs to, while reading it
+    let mut block_indent = None;
+    for (index, line) in yaml.lines().enumerate() {
+        let content = line.trim();
+        if content.is_empty() || content.starts_with('#') {
+            continue;
+        }
+        let indent = line.len() - line.trim_start().len();
+        match block_indent {
+            Some(block_indent) if indent > block_indent => continue,
+            _ => block_indent = None,
+        }
+        let top_level = !line.starts_with(char::is_whitespace) && !content.starts_with('-');
+        if top_level {
+            in_matches = content.trim_end_matches(|c: char| c == ':' || c.is_whitespace())
+                == "matches";
+            snippets.extend(current.take().map(snippet));
+            continue;
+        }
+        if !in_matches {
+            continue;
+        }
+        let field = match content.strip_prefix('-') {
+            Some(item) => {
+                snippets.extend(current.take().map(snippet));
+                current = Some(Fields { line: index, ..Fields::default() });
+                item.trim_start()
+            }
+            None => content,
+        };
+        let Some(fields) = current.as_mut() else {
+            return Err(invalid(index, "Expected a list of matches"));
+        };
+        let Some((key, value)) = field.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key.trim() {
+            "trigger" => fields.triggers.push(scalar(value).map_err(|err| invalid(index, &err))?),
+            "triggers" => fields.triggers.extend(list(value).map_err(|err| invalid(index, &err))?),
+            "replace" if value.starts_with(['|', '>']) => {
+                block_indent = Some(line.trim_end().len() - field.len());
+                fields.unsupported = Some("Multi-line replacements".to_string());
+            }
+            "replace" => fields.replace = Some(scalar(value).map_err(|err| invalid(index, &err))?),
+            key @ ("regex" | "form" | "image_path" | "markdown" | "html") => {
+                fields.unsupported = Some(format!("Snippets with a '{key}'"));
+            }
+            _ => {}
+        }
+    }
+    snippets.extend(current.map(snippet));
+    Ok(snippets)
+}
+
+fn invalid(line: usize, message: &str) -> ShortenError {
+    ShortenError::from(ParseErrorKind::InvalidSnippet(message.to_string())).at_line(line)
+}
+
+/// The rules of a snippet, one per trigger.
+fn snippet(fields: Fields) -> Snippet {
+    if let Some(unsupported) = fields.unsupported {
+        return (fields.line, Err(format!("{unsupported} can't be turned into rules, skipped")));
+    }
+    let Some(replace) = fields.replace.filter(|_| !fields.triggers.is_empty()) else {
+        return (fields.line, Err("Snippet without a trigger or replacement, skipped".into()));
+    };
+    let rules = fields.triggers.iter().map(|trigger| {
+        let abbreviation = trigger.strip_prefix(TRIGGER_PREFIX).unwrap_or(trigger);
+        Rule::new(Matcher::Exact(replace.clone()), abbreviation)
+    });
+    (fields.line, Ok(rules.collect()))
+}
+
+/// A plain, single-quoted or double-quoted YAML string.
+fn scalar(value: &str) -> std::result::Result<String, String> {
+    let (text, rest) = string(value, false)?;
+    end(rest, "string").map(|()| text)
+}
+
+/// A flow list of strings, like `[":arch", ":archi"]`.
+fn list(value: &str) -> std::result::Result<Vec<String>, String> {
+    let Some(mut rest) = value.strip_prefix('[') else {
+        return Err("Expected a list like [\":a\", \":b\"]".to_string());
+    };
+    let mut items = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix(']') {
+            return end(after, "list").map(|()| items);
+        }
+        let (item, after) = string(rest, true)?;
+        items.push(item);
+        rest = after.trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest);
+    }
+}
+
+/// Checks that only a comment follows a value.
+fn end(rest: &str, what: &str) -> std::result::Result<(), String> {
+    let rest = rest.trim();
+    match rest.is_empty() || rest.starts_with('#') {
+        true => Ok(()),
+        false => Err(format!("Unexpected '{rest}' after the {what}")),
+    }
+}
+
+/// The string at the start of the value and what follows it. Plain strings
+/// end at a comment, or at a `,` or `]` inside a list.
+fn string(value: &str, in_list: bool) -> std::result::Result<(String, &str), String> {
+    let mut chars = value.char_indices();
+    match chars.next() {
+        Some((_, '"')) => {
+            let mut text = String::new();
+            while let Some((index, c)) = chars.next() {
+                match c {
+                    '"' => return Ok((text, &value[index + 1..])),
+                    '\\' => match chars.next().map(|(_, c)| c) {
+                        Some('n') => text.push('\n'),
+                        Some('t') => text.push('\t'),
+                        Some(c @ ('"' | '\\' | '/')) => text.push(c),
+                        _ => return Err("Invalid escape sequence".to_string()),
+                    },
+                    c => text.push(c),
+                }
+            }
+            Err("Unterminated string".to_string())
+        }
+        Some((_, '\'')) => {
+            let mut text = String::new();
+            let mut rest = &value[1..];
+            loop {
+                let Some(end) = rest.find('\'') else {
+                    return Err("Unterminated string".to_string());
+                };
+                text.push_str(&rest[..end]);
+                let Some(after) = rest[end + 1..].strip_prefix('\'') else {
+                    return Ok((text, &rest[end + 1..]));
+                };
+                text.push('\'');
+                rest = after;
+            }
+        }
+        _ => {
+            let mut end = value.find(" #").unwrap_or(value.len());
+            if in_list {
+                end = value[..end].find([',', ']']).unwrap_or(end);
+            }
+            Ok((value[..end].trim().to_string(), &value[end..]))
+        }
+    }
+}
+
+/// Writes the exact rules of the abbreviator as espanso snippets, expanding
+/// `:abbreviation` into the text the rule abbreviates. Other rules (regexes,
+/// removals, kept words) have no snippet.
+pub fn export(abbreviator: &Abbreviator) -> String {
+    let mut seen = HashSet::new();
+    let snippets = abbreviator
+        .rules()
+        .into_iter()
+        .sorted_by_key(|abbrev| abbrev.order)
+        .filter(|abbrev| !abbrev.rule.keep && !abbrev.rule.replacement.is_empty())
+        .filter_map(|abbrev| match &abbrev.rule.matcher {
+            Matcher::Exact(text) => Some((text, &abbrev.rule.replacement)),
+            _ => None,
+        })
+        .filter(|&pair| seen.insert(pair))
+        .map(|(text, abbreviation)| {
+            let trigger = format!("{TRIGGER_PREFIX}{abbreviation}");
+            format!("  - trigger: {}\n    replace: {}\n", quote(&trigger), quote(text))
+        });
+    format!("matches:\n{}", snippets.collect::<String>())
+}
+
+/// The text as a double-quoted YAML string.
+fn quote(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::espanso::{export, read};
+    use itertools::Itertools;
+
+    #[test]
+    fn test_espanso() {
+        let yaml = "# Work snippets\n\
+                    matches:\n  \
+                      - trigger: \":arch\"\n    \
+                        replace: \"Architecture\"\n  \
+                      - triggers: [':sesn', \":sess\"]\n    \
+                        replace: 'Learning ''Session''' # quoted\n    \
+                        word: true\n  \
+                      - trigger: :sig\n    \
+                        replace: |\n      Regards: Boaz\n\
+                    global_vars:\n  \
+                      - name: today\n";
+        let snippets = read(yaml).unwrap();
+        let rules = snippets
+            .iter()
+            .map(|(line, rules)| {
+                let rules = rules.iter().flatten();
+                let mut rules =
+                    rules.map(|rule| format!("{} = {}", rule.matcher.source(), rule.replacement));
+                (*line, rules.join(", "))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rules[0], (2, "Architecture = arch".to_string()));
+        let session = "Learning 'Session' = sesn, Learning 'Session' = sess";
+        assert_eq!(rules[1], (4, session.to_string()));
+        let skipped = "Multi-line replacements can't be turned into rules, skipped";
+        assert_eq!(snippets[2], (7, Err(skipped.to_string())));
+        assert_eq!(rules.len(), 3);
+        let err = read("matches:\n  - trigger: \"arch\n").unwrap_err();
+        assert_eq!(err.to_string(), "line 2: Unterminated string");
+
+        let lines =
+            ["Architecture = arch | A.", "Meeting =", "/^Q\\d/ = Q", "Say \"hi\" = hi"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        let expected = "matches:\n  - trigger: \":arch\"\n    replace: \"Architecture\"\n  \
+                        - trigger: \":hi\"\n    replace: \"Say \\\"hi\\\"\"\n";
+        assert_eq!(export(&abbreviator), expected);
+    }
+}
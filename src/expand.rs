@@ -0,0 +1,168 @@
+//! Reverses the exact rules, turning abbreviated text back into the words
+//! and phrases the abbreviations stand for.
+
+use crate::abbrev::{Abbrev, Abbreviator, RuleKind};
+use crate::case::{to_title_case, TextCase};
+use crate::enclosing::Enclosing;
+use crate::shortener::DEFAULT_SEPARATORS;
+use itertools::Itertools;
+use std::collections::HashMap;
+
+/// Expands abbreviations using the exact rules of an abbreviator.
+///
+/// Remove, regex and keep rules can't be reversed and are ignored, like the
+/// abbreviations with placeholders. An abbreviation standing for several
+/// different originals is ambiguous and is never expanded.
+pub struct Expander {
+    /// Lowercase abbreviations and the originals they stand for, in rule order
+    expansions: HashMap<String, Vec<Expansion>>,
+    enclosing: Enclosing,
+}
+
+struct Expansion {
+    original: String,
+    attach_to_previous: bool,
+    /// The abbreviation follows the case of the text it replaces
+    follows_case: bool,
+}
+
+/// An abbreviation shared by the rules of several different originals.
+pub struct Ambiguity {
+    pub abbrev: String,
+    pub originals: Vec<String>,
+}
+
+impl Expander {
+    pub fn new(abbreviator: &Abbreviator) -> Expander {
+        let mut expansions: HashMap<String, Vec<Expansion>> = HashMap::new();
+        let exact = |rule: &&Abbrev| rule.kind() == RuleKind::Exact && rule.template.is_none();
+        for rule in abbreviator.rules().into_iter().filter(exact) {
+            let originals = expansions.entry(rule.abbrev.to_lowercase()).or_default();
+            // The same text abbreviated the same way twice, e.g. in two groups, is not ambiguous
+            let lowercase = rule.source.to_lowercase();
+            if originals.iter().all(|expansion| expansion.original.to_lowercase() != lowercase) {
+                originals.push(Expansion {
+                    original: rule.source.clone(),
+                    attach_to_previous: rule.attach_to_previous,
+                    follows_case: abbreviator.case_of(rule).follows_case(&rule.abbrev),
+                });
+            }
+        }
+        Expander { expansions, enclosing: abbreviator.enclosing().clone() }
+    }
+
+    /// The abbreviations that can't be expanded because they stand for
+    /// several originals, sorted by abbreviation.
+    pub fn ambiguities(&self) -> Vec<Ambiguity> {
+        self.expansions
+            .iter()
+            .filter(|(_, originals)| originals.len() > 1)
+            .map(|(abbrev, originals)| Ambiguity {
+                abbrev: abbrev.clone(),
+                originals: originals.iter().map(|expansion| expansion.original.clone()).collect(),
+            })
+            .sorted_by(|a, b| a.abbrev.cmp(&b.abbrev))
+            .collect()
+    }
+
+    /// Expands every abbreviation in the text, keeping its whitespace,
+    /// separators and enclosing characters.
+    pub fn expand(&self, text: &str) -> String {
+        let mut expanded = String::with_capacity(text.len() * 2);
+        let mut rest = text;
+        while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+            expanded.push_str(&rest[..start]);
+            let end = rest[start..].find(char::is_whitespace).map_or(rest.len(), |end| start + end);
+            let word = &rest[start..end];
+            let mut part_start = 0;
+            for (index, separator) in word.match_indices(&DEFAULT_SEPARATORS[..]) {
+                self.expand_part(&word[part_start..index], &mut expanded);
+                expanded.push_str(separator);
+                part_start = index + separator.len();
+            }
+            self.expand_part(&word[part_start..], &mut expanded);
+            rest = &rest[end..];
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    fn expand_part(&self, part: &str, out: &mut String) {
+        if let Some(original) = self.lookup(part, false) {
+            out.push_str(&original);
+            return;
+        }
+
+        let enclosed = self.enclosing.split(part);
+        if enclosed.is_enclosed() {
+            if let Some(original) = self.lookup(enclosed.word, false) {
+                out.extend([enclosed.openers, &original, enclosed.closers]);
+                return;
+            }
+        }
+
+        // Abbreviations attached to the previous word, like the "課" of "Arch課",
+        // taking the longest one
+        let attached = part
+            .char_indices()
+            .skip(1)
+            .find_map(|(index, _)| Some((index, self.lookup(&part[index..], true)?)));
+        match attached {
+            Some((index, original)) => {
+                self.expand_part(&part[..index], out);
+                out.push(' ');
+                out.push_str(&original);
+            }
+            None => out.push_str(part),
+        }
+    }
+
+    /// The original an abbreviation stands for, in the case of the abbreviation
+    /// when the rule follows the case of the text.
+    fn lookup(&self, abbrev: &str, attached: bool) -> Option<String> {
+        let [expansion] = &self.expansions.get(&abbrev.to_lowercase())?[..] else {
+            return None;
+        };
+        if attached && !expansion.attach_to_previous {
+            return None;
+        }
+
+        let original = &expansion.original;
+        Some(match TextCase::of(abbrev) {
+            _ if !expansion.follows_case => original.clone(),
+            TextCase::Lower => original.to_lowercase(),
+            TextCase::Title => to_title_case(original),
+            TextCase::Upper => original.to_uppercase(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::expand::Expander;
+
+    #[test]
+    fn test_expand() {
+        let rules = [
+            "Architecture = arch",
+            "Session = sesn",
+            "Section = <+課",
+            "Monthly All Hands Meeting = MAHM",
+            "One = 1",
+            "Archive = arch",
+            "Learning = learn",
+        ];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let expander = Expander::new(&abbreviator);
+
+        assert_eq!(expander.expand("Learn課  (Sesn 1)"), "Learning Section  (Session One)");
+        assert_eq!(expander.expand("MAHM/LEARN: notes"), "Monthly All Hands Meeting/LEARNING: notes");
+        assert_eq!(expander.expand("Arch review"), "Arch review");
+
+        let ambiguities = expander.ambiguities();
+        assert_eq!(ambiguities.len(), 1);
+        assert_eq!(ambiguities[0].abbrev, "arch");
+        assert_eq!(ambiguities[0].originals, ["Architecture", "Archive"]);
+    }
+}
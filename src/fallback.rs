@@ -0,0 +1,187 @@
+//! An external command shortening the lines the rules leave too long, set in
+//! the rules with `@fallback-cmd ~/.config/shorten/llm-shorten.sh` (and
+//! `@fallback-timeout 500ms`), so that custom or ML-based shortening doesn't
+//! have to live in shorten itself.
+//!
+//! The command is run by the shell with the original line on its input and
+//! the length to shorten it to in `SHORTEN_MAX_LENGTH`, and the first line it
+//! writes replaces the shortened one. When the command fails, runs out of time
+//! or writes a line that is still too long, the line is shortened as if there
+//! was no command.
+//!
+//! `@hook ticket ~/.config/shorten/ticket.sh` registers a command as a custom
+//! pass instead, like [`crate::shortener::Shortener::hook`]: it runs right after
+//! the rules, for each word they left alone until the line fits, reading the
+//! word with how many columns the line still has to lose in `SHORTEN_OVERFLOW`.
+//! The first line it writes replaces the word, and writing nothing leaves it.
+//! `@fallback-timeout` limits hooks too. Each word starts a shell of its own,
+//! so a long line can take several process starts (or timeouts) to shorten,
+//! which is why hooks need the `script-hooks` feature.
+
+use crate::width;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+/// How long the command may run when the rules don't say
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fallback {
+    command: String,
+    timeout: Duration,
+}
+
+impl Fallback {
+    pub fn new(command: impl Into<String>) -> Fallback {
+        Fallback { command: command.into(), timeout: DEFAULT_TIMEOUT }
+    }
+
+    pub fn with_timeout(self, timeout: Duration) -> Fallback {
+        Fallback { timeout, ..self }
+    }
+
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// The line as the command shortens it, if it does within the timeout.
+    pub(crate) fn run(&self, line: &str, max_length: usize) -> Option<String> {
+        let output = self.output(line, ("SHORTEN_MAX_LENGTH", max_length))?;
+        let shortened = output.lines().next()?.trim_end();
+        let fits = !shortened.is_empty() && width::length(shortened) <= max_length;
+        fits.then(|| shortened.to_string())
+    }
+
+    /// The replacement a `@hook` command writes for a word, if any.
+    pub(crate) fn replace_word(&self, word: &str, overflow: usize) -> Option<String> {
+        let output = self.output(word, ("SHORTEN_OVERFLOW", overflow))?;
+        let replacement = output.lines().next()?.trim();
+        (!replacement.is_empty() && replacement != word).then(|| replacement.to_string())
+    }
+
+    /// What the command writes when it reads the text, with a number in an
+    /// environment variable, unless it fails or runs out of time.
+    fn output(&self, input: &str, (variable, value): (&str, usize)) -> Option<String> {
+        let mut child = shell(&self.command)
+            .env(variable, value.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()?;
+        let (mut stdin, mut stdout) = (child.stdin.take()?, child.stdout.take()?);
+        let input = format!("{input}\n");
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // Commands that don't read their input make this fail, which is fine
+            let _ = stdin.write_all(input.as_bytes());
+            drop(stdin);
+            let mut output = String::new();
+            let read = stdout.read_to_string(&mut output);
+            let _ = sender.send(read.map(|_| output));
+        });
+        let Ok(Ok(output)) = receiver.recv_timeout(self.timeout) else {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        };
+        child.wait().ok()?.success().then_some(output)
+    }
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// Parses a timeout like `500ms`, `2s` or `1.5s`.
+pub(crate) fn parse_timeout(text: &str) -> Option<Duration> {
+    let text = text.trim();
+    if let Some(millis) = text.strip_suffix("ms") {
+        return millis.parse().ok().map(Duration::from_millis);
+    }
+    let seconds: f64 = text.strip_suffix('s')?.parse().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::fallback::{parse_timeout, Fallback, DEFAULT_TIMEOUT};
+    use crate::shortener::Shortener;
+    use std::time::Duration;
+
+    #[test]
+    fn test_fallback() {
+        assert_eq!(parse_timeout("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_timeout("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_timeout("2"), None);
+
+        let rules = [
+            "Architecture = arch",
+            "@fallback-cmd tr -d aeiou | cut -c \"1-$SHORTEN_MAX_LENGTH\"",
+            "@fallback-timeout 5s",
+        ];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let fallback = abbreviator.fallback().unwrap();
+        assert_eq!(fallback.timeout(), Duration::from_secs(5));
+        let shortener = Shortener::with_abbreviator(60, abbreviator).unwrap();
+        let shorten = |text, max_length| shortener.shorten_to(text, max_length).text.into_owned();
+        assert_eq!(shorten("Architecture Meeting", 15), "Arch Meeting");
+        assert_eq!(shorten("Architecture Meeting", 10), "Archtctr M");
+
+        let abbreviator = Abbreviator::from_lines(rules.into_iter().take(2)).unwrap();
+        assert_eq!(abbreviator.fallback().unwrap().timeout(), DEFAULT_TIMEOUT);
+
+        let sleepy = Fallback::new("sleep 5; echo Arch").with_timeout(Duration::from_millis(50));
+        assert_eq!(sleepy.run("Architecture", 5), None);
+        assert_eq!(Fallback::new("echo Architecture").run("Architecture", 5), None);
+        assert_eq!(Fallback::new("exit 1").run("Architecture", 5), None);
+    }
+
+    #[test]
+    #[cfg(feature = "script-hooks")]
+    fn test_hooks() {
+        // Hooks registered in the rules run on the words the rules left alone
+        let rules = [
+            "Session = sesn",
+            "@hook ticket sed -n 's/^#\\([0-9]*\\)$/T\\1/p'",
+            "@hook overflow [ \"$(cat)\" = Fix ] && echo \"F$SHORTEN_OVERFLOW\"",
+        ];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        assert_eq!(abbreviator.hooks().len(), 2);
+        let shortener = Shortener::with_abbreviator(60, abbreviator).unwrap();
+        let shortened = shortener.shorten_to("Fix #1234 Session", 10);
+        assert_eq!(shortened.text, "F4 T1234 Sesn");
+        assert!(shortened.applied.iter().any(|rule| rule.matcher == "<ticket>"));
+        assert_eq!(shortener.shorten_to("Fix #1234 Session", 14).text, "Fix #1234 Sesn");
+        assert!(Abbreviator::from_lines(["@hook ticket"].into_iter()).is_err());
+    }
+
+    #[test]
+    #[cfg(not(feature = "script-hooks"))]
+    fn test_hooks() {
+        let rules = ["Session = sesn", "@hook ticket cat"];
+        let Err(err) = Abbreviator::from_lines(rules.into_iter()) else {
+            panic!("hook accepted without the script-hooks feature");
+        };
+        let expected = "line 2: Hook 'ticket' needs shorten built with the script-hooks feature";
+        assert_eq!(err.to_string(), expected);
+    }
+}
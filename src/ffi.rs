@@ -0,0 +1,103 @@
+//! C bindings to the shortener, for window managers and status bars written
+//! in C. Build with the `c-header` feature to generate `include/shorten.h`.
+//!
+//! Strings are NUL-terminated UTF-8. Functions return NULL on invalid input
+//! or when the rules fail to load.
+
+use crate::abbrev::Abbreviator;
+use crate::shortener::Shortener;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+/// Creates a shortener for lines of at most `max_length` characters, using
+/// the given rules in the `abbrev.lst` format, or the rules of the config
+/// directory when `rules` is NULL. Free it with `shorten_free`.
+///
+/// # Safety
+///
+/// `rules` must be NULL or a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn shorten_new(max_length: usize, rules: *const c_char) -> *mut Shortener {
+    let shortener = if rules.is_null() {
+        Shortener::new(max_length)
+    } else {
+        let Ok(rules) = CStr::from_ptr(rules).to_str() else {
+            return ptr::null_mut();
+        };
+        Abbreviator::from_lines(rules.lines())
+            .and_then(|abbreviator| Shortener::with_abbreviator(max_length, abbreviator))
+    };
+    match shortener {
+        Ok(shortener) => Box::into_raw(Box::new(shortener)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Frees a shortener created by `shorten_new`. Does nothing on NULL.
+///
+/// # Safety
+///
+/// `shortener` must be NULL or come from `shorten_new`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn shorten_free(shortener: *mut Shortener) {
+    if !shortener.is_null() {
+        drop(Box::from_raw(shortener));
+    }
+}
+
+/// Shortens a line, returning a new string to free with `shorten_string_free`.
+///
+/// # Safety
+///
+/// `shortener` must come from `shorten_new` and `text` must be a valid
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn shorten_line(
+    shortener: *const Shortener,
+    text: *const c_char,
+) -> *mut c_char {
+    if shortener.is_null() || text.is_null() {
+        return ptr::null_mut();
+    }
+    let Ok(text) = CStr::from_ptr(text).to_str() else {
+        return ptr::null_mut();
+    };
+    let shortened = (*shortener).shorten(text);
+    // The input had no NUL byte, and shortening never adds one
+    CString::new(shortened.as_bytes()).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Frees a string returned by `shorten_line`. Does nothing on NULL.
+///
+/// # Safety
+///
+/// `text` must be NULL or come from `shorten_line`, and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn shorten_string_free(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ffi::{shorten_free, shorten_line, shorten_new, shorten_string_free};
+    use std::ffi::{CStr, CString};
+
+    #[test]
+    fn test_ffi() {
+        let rules = CString::new("Architecture = arch\nSection = <+課").unwrap();
+        let text = CString::new("Architecture Section").unwrap();
+        unsafe {
+            let shortener = shorten_new(10, rules.as_ptr());
+            assert!(!shortener.is_null());
+            let shortened = shorten_line(shortener, text.as_ptr());
+            assert_eq!(CStr::from_ptr(shortened).to_str(), Ok("Arch課"));
+            shorten_string_free(shortened);
+            shorten_free(shortener);
+
+            let invalid = CString::new("Architecture").unwrap();
+            assert!(shorten_new(10, invalid.as_ptr()).is_null());
+        }
+    }
+}
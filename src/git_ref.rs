@@ -0,0 +1,61 @@
+//! Git refs for the git-ref mode: in "feature/JIRA-1234-add-session-export",
+//! the ticket ID is kept intact while the description after it, and then the
+//! directories before it, are abbreviated to fit.
+
+use crate::shortener::Shortened;
+use crate::width;
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::LazyLock;
+
+/// A ticket ID like "JIRA-1234" or an issue number at the start of the branch
+/// name, with the separator after it
+static TICKET: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(?:[A-Za-z][A-Za-z0-9]*-\d+|#?\d+)(?:[-_.]|$)").unwrap());
+
+/// Shortens the ref to `max_length` around its ticket ID, abbreviating the
+/// description first and the directories (`feature/`) next with
+/// `abbreviate`. Refs without a ticket ID are abbreviated as a whole.
+pub(crate) fn shorten_git_ref<'a>(
+    git_ref: &'a str,
+    max_length: usize,
+    abbreviate: impl Fn(&'a str, usize) -> Shortened<'a>,
+) -> Shortened<'a> {
+    let name_start = git_ref.rfind('/').map_or(0, |index| index + 1);
+    let (directories, name) = git_ref.split_at(name_start);
+    let Some(ticket) = TICKET.find(name).filter(|_| width::length(git_ref) > max_length) else {
+        return abbreviate(git_ref, max_length);
+    };
+    let (ticket, description) = name.split_at(ticket.end());
+    let room = max_length.saturating_sub(width::length(directories) + width::length(ticket));
+    let description = abbreviate(description, room);
+    let room = max_length.saturating_sub(width::length(ticket) + width::length(&description.text));
+    let directories = abbreviate(directories, room);
+    Shortened::concat([
+        (0, directories),
+        (name_start, Shortened::replacing(ticket.len(), Cow::Borrowed(ticket))),
+        (name_start + ticket.len(), description),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::shortener::{Mode, Shortener};
+
+    #[test]
+    fn test_shorten_git_ref() {
+        let rules = ["Feature = feat", "Session = sesn", "Export = exp", "Release = rel"];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(60, abbreviator).unwrap().mode(Mode::GitRef);
+        let shorten = |git_ref: &str, max_length| {
+            shortener.shorten_to(git_ref, max_length).text.into_owned()
+        };
+        let git_ref = "feature/JIRA-1234-add-session-export";
+        assert_eq!(shorten(git_ref, 40), git_ref);
+        assert_eq!(shorten(git_ref, 30), "feature/JIRA-1234-add-sesn-exp");
+        assert_eq!(shorten(git_ref, 27), "feat/JIRA-1234-add-sesn-exp");
+        assert_eq!(shorten("release/42_session-export", 20), "release/42_sesn-exp");
+        assert_eq!(shorten("release/session-export", 16), "rel/sesn-exp");
+    }
+}
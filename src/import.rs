@@ -0,0 +1,380 @@
+//! Converts a glossary kept in a spreadsheet into a rules file. Every row of
+//! the CSV or TSV export is a rule: the long form (a matcher as written in
+//! `abbrev.lst`), the short form (`arch | A.` for tiers) and optional flags:
+//!
+//! ```csv
+//! Long form,Short form,Flags
+//! Architecture,arch | A.,priority=10
+//! Section,課,"attach, group=japanese"
+//! Number,#+>,
+//! /Meeting$/i,Mtg,
+//! ProjectX,,keep
+//! ```
+//!
+//! The flags are named after the fields of the TOML format: `attach` (or
+//! `attach=next`, `attach=both`), `keep`, `inflect`, `case=POLICY`,
+//! `priority=N`, `min_overflow=N`, `group=NAME` and `context=TAG` (repeated
+//! for several contexts).
+//!
+//! The snippets of an espanso match file are imported too (see
+//! [`crate::espanso`]), each trigger becoming the abbreviation of its
+//! replacement.
+
+#[cfg(feature = "csv")]
+use crate::abbrev::{parse_inflected_matcher, parse_levels};
+use crate::case::CasePolicy;
+use crate::check::{self, Issue};
+#[cfg(feature = "csv")]
+use crate::error::ShortenError;
+use crate::error::{ParseErrorKind, Result};
+use crate::espanso;
+use crate::rule::{Matcher, Rule};
+use itertools::Itertools;
+use toml::Value;
+
+/// The format of the rules file to write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RulesFormat {
+    Lst,
+    Toml,
+}
+
+/// The converted rules file, along with the problems the check found in its
+/// rules, located on the lines of the export.
+pub struct Imported {
+    pub text: String,
+    pub issues: Vec<Issue>,
+}
+
+/// Converts the rows of a spreadsheet export, skipping the first one when it
+/// is a header. Rows with an empty long form are skipped too.
+#[cfg(feature = "csv")]
+pub fn import(
+    export: &str,
+    delimiter: u8,
+    header: bool,
+    format: RulesFormat,
+) -> Result<Imported> {
+    let rules = read_rules(export, delimiter, header)?;
+    let text = match format {
+        RulesFormat::Lst => write_lst(&rules)?,
+        RulesFormat::Toml => write_toml(&rules),
+    };
+    Ok(Imported { text, issues: check::check_rules_at(&rules) })
+}
+
+/// Converts the snippets of an espanso match file. The snippets that can't be
+/// rules (multi-line, regex or form snippets) are skipped with a warning.
+pub fn import_espanso(yaml: &str, format: RulesFormat) -> Result<Imported> {
+    let mut issues = Vec::new();
+    let mut rules = Vec::new();
+    for (line, snippet) in espanso::read(yaml)? {
+        match snippet {
+            Ok(snippet) => rules.extend(snippet.into_iter().map(|rule| (line, rule))),
+            Err(message) => issues.push(Issue {
+                line: line + 1,
+                file: None,
+                severity: check::Severity::Warning,
+                message,
+            }),
+        }
+    }
+    let text = match format {
+        RulesFormat::Lst => write_lst(&rules)?,
+        RulesFormat::Toml => write_toml(&rules),
+    };
+    issues.extend(check::check_rules_at(&rules));
+    Ok(Imported { text, issues })
+}
+
+/// The rules of the rows, along with the (0-based) line each row starts on.
+#[cfg(feature = "csv")]
+fn read_rules(export: &str, delimiter: u8, header: bool) -> Result<Vec<(usize, Rule)>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(header)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(export.as_bytes());
+
+    let mut rules = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|err| {
+            let line = err.position().map_or(0, |position| position.line() as usize - 1);
+            ShortenError::from(ParseErrorKind::InvalidRow(err.to_string())).at_line(line)
+        })?;
+        let line = record.position().map_or(0, |position| position.line() as usize - 1);
+        let (long, short, flags) = match record.iter().collect_vec()[..] {
+            [long, short] => (long, short, ""),
+            [long, short, flags] => (long, short, flags),
+            [long] => (long, "", ""),
+            _ => {
+                let message = format!("Expected 2 or 3 columns, found {}", record.len());
+                return Err(ShortenError::from(ParseErrorKind::InvalidRow(message)).at_line(line));
+            }
+        };
+        if long.is_empty() {
+            continue;
+        }
+        let rule = parse_row(long, short, flags).map_err(|err| err.at_line(line))?;
+        rules.push((line, rule));
+    }
+    Ok(rules)
+}
+
+#[cfg(feature = "csv")]
+fn parse_row(long: &str, short: &str, flags: &str) -> Result<Rule> {
+    let (short, attach_to_previous) = match short.strip_prefix("<+") {
+        Some(short) => (short, true),
+        None => (short, false),
+    };
+    let (short, attach_to_next) = match short.strip_suffix("+>") {
+        Some(short) => (short.trim_end(), true),
+        None => (short, false),
+    };
+    let (short, escalations, localized) = parse_levels(short);
+    let (matcher, inflect) = parse_inflected_matcher(long)?;
+    let mut rule = Rule::new(matcher, short);
+    rule.inflect = inflect;
+    rule.escalations = escalations;
+    rule.localized = localized;
+    rule.attach_to_previous = attach_to_previous;
+    rule.attach_to_next = attach_to_next;
+
+    let separator = |c: char| c == ',' || c.is_whitespace();
+    for flag in flags.split(separator).filter(|flag| !flag.is_empty()) {
+        match flag.split_once('=') {
+            None if flag == "attach" => rule.attach_to_previous = true,
+            Some(("attach", "previous")) => rule.attach_to_previous = true,
+            Some(("attach", "next")) => rule.attach_to_next = true,
+            Some(("attach", "both")) => (rule.attach_to_previous, rule.attach_to_next) = (true, true),
+            None if flag == "keep" => rule.keep = true,
+            None if flag == "except" => rule.except = true,
+            None if flag == "inflect" => rule.inflect = true,
+            None if flag == "deprecated" => rule.deprecated = true,
+            Some(("case", name)) if CasePolicy::from_name(name).is_some() => {
+                rule.case = CasePolicy::from_name(name);
+            }
+            Some(("priority", value)) => {
+                rule.priority = value
+                    .parse()
+                    .map_err(|_| ParseErrorKind::InvalidPriority(value.to_string()))?;
+            }
+            Some(("min_overflow", value)) => {
+                rule.min_overflow = value
+                    .parse()
+                    .map_err(|_| ParseErrorKind::InvalidLengthCondition(value.to_string()))?;
+            }
+            Some(("group", group)) => rule.group = Some(group.to_string()),
+            Some(("context", context)) => rule.contexts.push(context.to_string()),
+            _ => return Err(ParseErrorKind::UnknownFlag(flag.to_string()).into()),
+        }
+    }
+    Ok(rule)
+}
+
+/// Writes the rules in the `abbrev.lst` format, under a header for each group.
+fn write_lst(rules: &[(usize, Rule)]) -> Result<String> {
+    let mut text = String::new();
+    // The rules before the first group header are the only ones without a group
+    let groups = rules.iter().filter_map(|(_, rule)| rule.group.as_deref()).unique().collect_vec();
+    let group_index = |rule: &Rule| {
+        let group = rule.group.as_deref()?;
+        groups.iter().position(|&other| other == group)
+    };
+    let ordered = rules.iter().sorted_by_key(|(_, rule)| group_index(rule));
+    let mut current_group = None;
+    for (line, rule) in ordered {
+        if rule.group.as_deref() != current_group {
+            current_group = rule.group.as_deref();
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&format!("[group:{}]\n", current_group.unwrap_or_default()));
+        }
+        text.push_str(&lst_line(rule).map_err(|err| err.at_line(*line))?);
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+fn lst_line(rule: &Rule) -> Result<String> {
+    if let Some(case) = rule.case.filter(|&case| case != CasePolicy::Smart) {
+        return Err(ParseErrorKind::TomlOnly(format!("case={}", case.name())).into());
+    }
+    let matcher = match rule.inflect {
+        true => format!("{}~", rule.matcher.source()),
+        false => rule.matcher.source(),
+    };
+    if matches!(rule.matcher, Matcher::Exact(_)) && matcher.contains('=') {
+        let long_form = format!("The long form '{matcher}'");
+        return Err(ParseErrorKind::TomlOnly(long_form).into());
+    }
+
+    let mut line = String::new();
+    if let Some(description) = &rule.description {
+        line.push_str(&format!("# desc: {description}\n"));
+    }
+    if rule.deprecated {
+        line.push_str("# deprecated\n");
+    }
+    if rule.priority != 0 {
+        line.push_str(&format!("!{} ", rule.priority));
+    }
+    if rule.min_overflow > 0 {
+        line.push_str(&format!("@iflen>{} ", rule.min_overflow));
+    }
+    if !rule.contexts.is_empty() {
+        line.push_str(&format!("[ctx:{}] ", rule.contexts.join(",")));
+    }
+    if rule.keep {
+        line.push_str(&format!("!keep {matcher}"));
+        return Ok(line);
+    }
+    if rule.except {
+        line.push_str(&format!("!except {matcher}"));
+        return Ok(line);
+    }
+
+    let attach = if rule.attach_to_previous { "<+" } else { "" };
+    let localized = rule.localized.iter().flat_map(|(tag, levels)| {
+        levels.iter().map(move |level| format!("{tag}:{level}"))
+    });
+    let levels = [&rule.replacement].into_iter().chain(&rule.escalations).cloned();
+    let levels = levels.chain(localized).join(" | ");
+    let attach_next = if rule.attach_to_next { "+>" } else { "" };
+    line.push_str(format!("{matcher} = {attach}{levels}{attach_next}").trim_end());
+    Ok(line)
+}
+
+/// Writes the rules in the TOML format.
+fn write_toml(rules: &[(usize, Rule)]) -> String {
+    let mut text = String::new();
+    let string = |text: &str| Value::String(text.to_string()).to_string();
+    let array = |texts: &[String]| {
+        Value::Array(texts.iter().cloned().map(Value::String).collect()).to_string()
+    };
+    for (_, rule) in rules {
+        let mut fields = Vec::new();
+        let (matcher, kind, flags) = match &rule.matcher {
+            Matcher::Exact(text) => (text, None, ""),
+            Matcher::Regex { pattern, flags } => (pattern, Some("regex = true"), flags.as_str()),
+            Matcher::Segment { pattern, flags } => (pattern, Some("segment = true"), flags.as_str()),
+            Matcher::Line { pattern, flags } => (pattern, Some("line = true"), flags.as_str()),
+        };
+        fields.push(format!("matcher = {}", string(matcher)));
+        fields.extend(kind.map(str::to_string));
+        if !flags.is_empty() {
+            fields.push(format!("flags = {}", string(flags)));
+        }
+        if !rule.replacement.is_empty() {
+            fields.push(format!("replacement = {}", string(&rule.replacement)));
+        }
+        if !rule.escalations.is_empty() {
+            fields.push(format!("escalations = {}", array(&rule.escalations)));
+        }
+        if !rule.localized.is_empty() {
+            let localized = rule.localized.iter().map(|(tag, levels)| {
+                format!("{} = {}", Value::String(tag.clone()), array(levels))
+            });
+            fields.push(format!("localized = {{ {} }}", localized.format(", ")));
+        }
+        if rule.priority != 0 {
+            fields.push(format!("priority = {}", rule.priority));
+        }
+        if rule.min_overflow > 0 {
+            fields.push(format!("min_overflow = {}", rule.min_overflow));
+        }
+        match (rule.attach_to_previous, rule.attach_to_next) {
+            (true, true) => fields.push("attach = \"both\"".to_string()),
+            (true, false) => fields.push("attach = \"previous\"".to_string()),
+            (false, true) => fields.push("attach = \"next\"".to_string()),
+            (false, false) => {}
+        }
+        if let Some(case) = rule.case {
+            fields.push(format!("case = {}", string(case.name())));
+        }
+        if let Some(group) = &rule.group {
+            fields.push(format!("group = {}", string(group)));
+        }
+        if !rule.contexts.is_empty() {
+            fields.push(format!("contexts = {}", array(&rule.contexts)));
+        }
+        if rule.keep {
+            fields.push("keep = true".to_string());
+        }
+        if rule.except {
+            fields.push("except = true".to_string());
+        }
+        if rule.inflect {
+            fields.push("inflect = true".to_string());
+        }
+        if let Some(description) = &rule.description {
+            fields.push(format!("description = {}", string(description)));
+        }
+        if rule.deprecated {
+            fields.push("deprecated = true".to_string());
+        }
+
+        if !text.is_empty() {
+            text.push('\n');
+        }
+        text.push_str("[[rule]]\n");
+        for field in fields {
+            text.push_str(&field);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+#[cfg(all(test, feature = "csv"))]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::check::Severity;
+    use crate::import::{import, import_espanso, RulesFormat};
+
+    #[test]
+    fn test_import() {
+        let export = "Long form,Short form,Flags\n\
+                      Architecture,arch | A.,priority=10\n\
+                      Section,課,\"attach, group=japanese\"\n\
+                      /Meeting$/i,Mtg\n\
+                      ProjectX,,keep\n\
+                      Number,#,attach=next\n\
+                      Architecture,archi,\n";
+        let imported = import(export, b',', true, RulesFormat::Lst).unwrap();
+        let expected = "!10 Architecture = arch | A.\n/Meeting$/i = Mtg\n!keep ProjectX\n\
+                        Number = #+>\nArchitecture = archi\n\n[group:japanese]\nSection = <+課\n";
+        assert_eq!(imported.text, expected);
+        let issues =
+            imported.issues.iter().map(|issue| (issue.line, issue.severity)).collect::<Vec<_>>();
+        assert_eq!(issues, [(7, Severity::Warning)]);
+
+        let imported = import(&export.replace(',', "\t"), b'\t', true, RulesFormat::Toml).unwrap();
+        let rules = crate::toml_rules::parse(&imported.text).unwrap().rules;
+        let abbreviator = Abbreviator::from_rules(rules.into_iter().map(|(_, rule)| rule)).unwrap();
+        assert_eq!(abbreviator.abbreviate("Section").unwrap().text, "課");
+        assert_eq!(abbreviator.abbreviate("Architecture").unwrap().text, "Arch");
+        assert!(abbreviator.abbreviate("Number").unwrap().attach_to_next);
+
+        let Err(err) = import("Session,sesn,sticky\n", b',', false, RulesFormat::Lst) else {
+            panic!("unknown flags are rejected");
+        };
+        assert_eq!(err.to_string(), "line 1: Unknown flag 'sticky'");
+
+        let export = "Department,dept | de:Abt. | D.\n";
+        let imported = import(export, b',', false, RulesFormat::Lst).unwrap();
+        assert_eq!(imported.text, "Department = dept | D. | de:Abt.\n");
+        let imported = import(export, b',', false, RulesFormat::Toml).unwrap();
+        let rules = crate::toml_rules::parse(&imported.text).unwrap().rules;
+        assert_eq!(rules[0].1.localized["de"], ["Abt."]);
+
+        let yaml = "matches:\n  - trigger: :arch\n    replace: Architecture\n  \
+                    - regex: \":d(?P<n>\\\\d)\"\n    replace: \"{{n}}\"\n";
+        let imported = import_espanso(yaml, RulesFormat::Lst).unwrap();
+        assert_eq!(imported.text, "Architecture = arch\n");
+        let issues = imported.issues.iter().map(|issue| issue.line).collect::<Vec<_>>();
+        assert_eq!(issues, [4]);
+    }
+}
@@ -0,0 +1,124 @@
+//! Plural and possessive forms, for the exact rules marked with `~`:
+//! "Sessions" is looked up as "Session", and `Session~ = sesn` abbreviates it
+//! "sesns" ("Session's" is "sesn's" and "Sessions'" "sesns'").
+
+/// What an inflected word adds to its stem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Suffix {
+    Plural,
+    /// With its apostrophe, `'` or `’`
+    Possessive(char),
+    PluralPossessive(char),
+}
+
+/// How a word is inflected, and whether its suffix is in capitals, like the
+/// "S" of "SESSIONS".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Inflection {
+    suffix: Suffix,
+    upper: bool,
+}
+
+const APOSTROPHES: [char; 2] = ['\'', '’'];
+
+/// The stems the word might be an inflection of, with how it is inflected.
+pub(crate) fn stems(word: &str) -> Vec<(String, Inflection)> {
+    let mut stems = Vec::new();
+    let Some(last) = word.chars().last() else {
+        return stems;
+    };
+    if let Some(apostrophe) = APOSTROPHES.into_iter().find(|&apostrophe| last == apostrophe) {
+        let plural = &word[..word.len() - apostrophe.len_utf8()];
+        let suffix = Suffix::PluralPossessive(apostrophe);
+        stems.extend(plurals(plural).map(|(stem, upper)| (stem, Inflection { suffix, upper })));
+        return stems;
+    }
+    if !matches!(last, 's' | 'S') {
+        return stems;
+    }
+    let rest = &word[..word.len() - 1];
+    let upper = last == 'S';
+    let apostrophe = APOSTROPHES.into_iter().find(|&apostrophe| rest.ends_with(apostrophe));
+    if let Some(apostrophe) = apostrophe {
+        let stem = &rest[..rest.len() - apostrophe.len_utf8()];
+        let inflection = Inflection { suffix: Suffix::Possessive(apostrophe), upper };
+        stems.push((stem.to_string(), inflection));
+    }
+    let suffix = Suffix::Plural;
+    stems.extend(plurals(word).map(|(stem, upper)| (stem, Inflection { suffix, upper })));
+    stems
+}
+
+/// The singulars of a plural, "categories" being "category", "boxes" "box"
+/// and "sessions" "session".
+fn plurals(word: &str) -> impl Iterator<Item = (String, bool)> {
+    let upper = word.ends_with('S');
+    let ies = strip_suffix(word, "ies").map(|stem| format!("{stem}y"));
+    let es = strip_suffix(word, "es").map(str::to_string);
+    let s = strip_suffix(word, "s").map(str::to_string);
+    let stems = [ies, es, s].into_iter().flatten().filter(|stem| !stem.is_empty());
+    stems.map(move |stem| (stem, upper))
+}
+
+/// The word without its suffix in any (ASCII) case.
+fn strip_suffix<'a>(word: &'a str, suffix: &str) -> Option<&'a str> {
+    let start = word.len().checked_sub(suffix.len())?;
+    let matches = word.is_char_boundary(start) && word[start..].eq_ignore_ascii_case(suffix);
+    matches.then(|| &word[..start])
+}
+
+/// The forms a (lowercase) phrase can take, to find them in a line along with
+/// the phrase itself.
+pub(crate) fn forms(phrase: &str) -> Vec<String> {
+    let stem = phrase.strip_suffix('y').map(|stem| format!("{stem}ies"));
+    let plurals = [format!("{phrase}s"), format!("{phrase}es")].into_iter().chain(stem);
+    let plurals = plurals.collect::<Vec<_>>();
+    let possessives = APOSTROPHES.into_iter().flat_map(|apostrophe| {
+        let plurals = plurals.iter().map(move |plural| format!("{plural}{apostrophe}"));
+        [format!("{phrase}{apostrophe}s")].into_iter().chain(plurals)
+    });
+    plurals.iter().cloned().chain(possessives.collect::<Vec<_>>()).collect()
+}
+
+/// The abbreviation of the stem, inflected like the word was. Suffixes only
+/// go after letters, so `Section~ = 課` still abbreviates "Sections" "課".
+pub(crate) fn apply(abbreviation: &str, inflection: Inflection) -> String {
+    let cased = |c: char| c.is_lowercase() || c.is_uppercase();
+    if !abbreviation.chars().last().is_some_and(cased) {
+        return abbreviation.to_string();
+    }
+    let lowercase = abbreviation.to_lowercase();
+    let sibilant = ["s", "x", "z", "ch", "sh"].iter().any(|end| lowercase.ends_with(end));
+    let plural = if sibilant { "es" } else { "s" };
+    let suffix = match inflection.suffix {
+        Suffix::Plural => plural.to_string(),
+        Suffix::Possessive(apostrophe) => format!("{apostrophe}s"),
+        Suffix::PluralPossessive(apostrophe) => format!("{plural}{apostrophe}"),
+    };
+    match inflection.upper {
+        true => format!("{abbreviation}{}", suffix.to_uppercase()),
+        false => format!("{abbreviation}{suffix}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inflect::{apply, forms, stems};
+
+    #[test]
+    fn test_inflect() {
+        let inflected = |word: &str, abbreviation: &str| {
+            stems(word)
+                .into_iter()
+                .map(|(stem, inflection)| (stem, apply(abbreviation, inflection)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(inflected("Sessions", "sesn"), [("Session".into(), "sesns".into())]);
+        assert_eq!(inflected("Session's", "sesn")[0], ("Session".into(), "sesn's".into()));
+        assert_eq!(inflected("Categories'", "cat")[0], ("Category".into(), "cats'".into()));
+        assert_eq!(inflected("BOXES", "BX")[0], ("BOX".into(), "BXES".into()));
+        assert_eq!(inflected("Sections", "課"), [("Section".into(), "課".into())]);
+        assert!(stems("Session").is_empty());
+        assert!(forms("all hands meeting").contains(&"all hands meetings'".to_string()));
+    }
+}
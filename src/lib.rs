@@ -0,0 +1,37 @@
+//! Text shortening based on configurable abbreviations.
+
+pub mod abbrev;
+mod ansi;
+pub mod builtin;
+mod cache;
+pub mod case;
+pub mod check;
+#[cfg(feature = "cjk")]
+mod cjk;
+pub mod config;
+mod dates;
+pub mod enclosing;
+pub mod error;
+pub mod espanso;
+pub mod expand;
+pub mod fallback;
+pub mod ffi;
+mod git_ref;
+pub mod import;
+mod inflect;
+pub mod locale;
+mod numbers;
+mod path;
+pub mod rule;
+pub mod rule_tests;
+mod sanitize;
+pub mod shortener;
+pub mod stop_words;
+mod subject;
+pub mod suggest;
+mod template;
+mod toml_rules;
+mod url;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod width;
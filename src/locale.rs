@@ -0,0 +1,215 @@
+//! The locale the output is written for, chosen with `--locale`: it picks the
+//! replacements of the rules written for it (`Department = dept | de:Abt.`),
+//! the case conversions of the languages that have their own (the dotted and
+//! dotless i of Turkish), and the month and day names of the dates pass.
+
+use crate::case::CaseRules;
+use chrono::{Datelike, NaiveDate};
+use std::fmt;
+
+/// A language, with an optional region, like `de` or `pt-BR`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Locale {
+    language: String,
+    region: Option<String>,
+}
+
+impl Locale {
+    /// Parses a language tag like `de` or `pt-BR`, or a locale as written in
+    /// `LANG`, like `tr_TR.UTF-8`.
+    pub fn parse(name: &str) -> Option<Locale> {
+        let name = name.split(['.', '@']).next().unwrap_or_default();
+        let (language, region) = match name.split_once(['-', '_']) {
+            Some((language, region)) => (language, Some(region)),
+            None => (name, None),
+        };
+        let valid_language =
+            (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic());
+        let valid_region = region.is_none_or(|region| match region.len() {
+            2 => region.chars().all(|c| c.is_ascii_alphabetic()),
+            3 => region.chars().all(|c| c.is_ascii_digit()),
+            _ => false,
+        });
+        (valid_language && valid_region).then(|| Locale {
+            language: language.to_ascii_lowercase(),
+            region: region.map(str::to_ascii_uppercase),
+        })
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// The value of the most specific tag the locale matches: `pt-BR` before
+    /// `pt`, while `pt-PT` doesn't match at all.
+    pub(crate) fn pick<'a, T>(&self, tagged: impl IntoIterator<Item = (&'a str, T)>) -> Option<T> {
+        let specificity = |tag: &str| {
+            let tag = Locale::parse(tag).filter(|tag| tag.language == self.language)?;
+            match tag.region {
+                None => Some(1),
+                Some(region) => (self.region.as_ref() == Some(&region)).then_some(2),
+            }
+        };
+        let matching =
+            tagged.into_iter().filter_map(|(tag, value)| Some((specificity(tag)?, value)));
+        matching.max_by_key(|(specificity, _)| *specificity).map(|(_, value)| value)
+    }
+
+    pub fn case_rules(&self) -> CaseRules {
+        match self.language.as_str() {
+            "tr" | "az" => CaseRules::Turkic,
+            _ => CaseRules::Standard,
+        }
+    }
+
+    /// The date in the strftime format, with the month and day names
+    /// (`%b`, `%B`, `%a`, `%A`) of the language when it is one of the known ones.
+    pub(crate) fn format_date(&self, date: NaiveDate, format: &str) -> String {
+        let Some((_, names)) = DATE_NAMES.iter().find(|(language, _)| *language == self.language)
+        else {
+            return date.format(format).to_string();
+        };
+        let month = date.month0() as usize;
+        let weekday = date.weekday().num_days_from_monday() as usize;
+        let mut localized = String::with_capacity(format.len());
+        let mut chars = format.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                localized.push(c);
+                continue;
+            }
+            let name = match chars.next() {
+                Some('b' | 'h') => names.short_months[month],
+                Some('B') => names.months[month],
+                Some('a') => names.short_weekdays[weekday],
+                Some('A') => names.weekdays[weekday],
+                Some(specifier) => {
+                    localized.extend(['%', specifier]);
+                    continue;
+                }
+                None => {
+                    localized.push('%');
+                    break;
+                }
+            };
+            localized.push_str(name);
+        }
+        date.format(&localized).to_string()
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.language)?;
+        match &self.region {
+            Some(region) => write!(f, "-{region}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Splits a replacement written for a locale, like `de:Abt.`, into its tag
+/// and its text. The tag is written like `de` or `pt-BR`.
+pub(crate) fn split_tagged(level: &str) -> Option<(&str, &str)> {
+    let (tag, text) = level.split_once(':')?;
+    let valid = Locale::parse(tag).is_some_and(|locale| locale.to_string() == tag);
+    valid.then(|| (tag, text.trim_start()))
+}
+
+struct DateNames {
+    months: [&'static str; 12],
+    short_months: [&'static str; 12],
+    /// From Monday
+    weekdays: [&'static str; 7],
+    short_weekdays: [&'static str; 7],
+}
+
+/// The languages the dates pass writes names in, besides English
+const DATE_NAMES: [(&str, DateNames); 7] = [
+    ("de", DateNames {
+        months: ["Januar", "Februar", "März", "April", "Mai", "Juni", "Juli", "August",
+            "September", "Oktober", "November", "Dezember"],
+        short_months: ["Jan", "Feb", "Mär", "Apr", "Mai", "Jun", "Jul", "Aug", "Sep", "Okt", "Nov",
+            "Dez"],
+        weekdays: ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"],
+        short_weekdays: ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"],
+    }),
+    ("es", DateNames {
+        months: ["enero", "febrero", "marzo", "abril", "mayo", "junio", "julio", "agosto",
+            "septiembre", "octubre", "noviembre", "diciembre"],
+        short_months: ["ene", "feb", "mar", "abr", "may", "jun", "jul", "ago", "sep", "oct", "nov",
+            "dic"],
+        weekdays: ["lunes", "martes", "miércoles", "jueves", "viernes", "sábado", "domingo"],
+        short_weekdays: ["lun", "mar", "mié", "jue", "vie", "sáb", "dom"],
+    }),
+    ("fr", DateNames {
+        months: ["janvier", "février", "mars", "avril", "mai", "juin", "juillet", "août",
+            "septembre", "octobre", "novembre", "décembre"],
+        short_months: ["janv.", "févr.", "mars", "avr.", "mai", "juin", "juil.", "août", "sept.",
+            "oct.", "nov.", "déc."],
+        weekdays: ["lundi", "mardi", "mercredi", "jeudi", "vendredi", "samedi", "dimanche"],
+        short_weekdays: ["lun.", "mar.", "mer.", "jeu.", "ven.", "sam.", "dim."],
+    }),
+    ("it", DateNames {
+        months: ["gennaio", "febbraio", "marzo", "aprile", "maggio", "giugno", "luglio", "agosto",
+            "settembre", "ottobre", "novembre", "dicembre"],
+        short_months: ["gen", "feb", "mar", "apr", "mag", "giu", "lug", "ago", "set", "ott", "nov",
+            "dic"],
+        weekdays: ["lunedì", "martedì", "mercoledì", "giovedì", "venerdì", "sabato", "domenica"],
+        short_weekdays: ["lun", "mar", "mer", "gio", "ven", "sab", "dom"],
+    }),
+    ("nl", DateNames {
+        months: ["januari", "februari", "maart", "april", "mei", "juni", "juli", "augustus",
+            "september", "oktober", "november", "december"],
+        short_months: ["jan", "feb", "mrt", "apr", "mei", "jun", "jul", "aug", "sep", "okt", "nov",
+            "dec"],
+        weekdays: ["maandag", "dinsdag", "woensdag", "donderdag", "vrijdag", "zaterdag", "zondag"],
+        short_weekdays: ["ma", "di", "wo", "do", "vr", "za", "zo"],
+    }),
+    ("pt", DateNames {
+        months: ["janeiro", "fevereiro", "março", "abril", "maio", "junho", "julho", "agosto",
+            "setembro", "outubro", "novembro", "dezembro"],
+        short_months: ["jan", "fev", "mar", "abr", "mai", "jun", "jul", "ago", "set", "out", "nov",
+            "dez"],
+        weekdays: ["segunda-feira", "terça-feira", "quarta-feira", "quinta-feira", "sexta-feira",
+            "sábado", "domingo"],
+        short_weekdays: ["seg", "ter", "qua", "qui", "sex", "sáb", "dom"],
+    }),
+    ("tr", DateNames {
+        months: ["Ocak", "Şubat", "Mart", "Nisan", "Mayıs", "Haziran", "Temmuz", "Ağustos", "Eylül",
+            "Ekim", "Kasım", "Aralık"],
+        short_months: ["Oca", "Şub", "Mar", "Nis", "May", "Haz", "Tem", "Ağu", "Eyl", "Eki", "Kas",
+            "Ara"],
+        weekdays: ["Pazartesi", "Salı", "Çarşamba", "Perşembe", "Cuma", "Cumartesi", "Pazar"],
+        short_weekdays: ["Pzt", "Sal", "Çar", "Per", "Cum", "Cmt", "Paz"],
+    }),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::case::CaseRules;
+    use crate::locale::{split_tagged, Locale};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_locale() {
+        let locale = |name| Locale::parse(name).map(|locale| locale.to_string());
+        assert_eq!(locale("tr_TR.UTF-8"), Some("tr-TR".to_string()));
+        assert_eq!(locale("de"), Some("de".to_string()));
+        assert_eq!(locale("C"), None);
+        assert_eq!(split_tagged("de:Abt."), Some(("de", "Abt.")));
+        assert_eq!(split_tagged("W:$1"), None);
+
+        let brazil = Locale::parse("pt_BR").unwrap();
+        let tagged = [("pt-PT", 1), ("pt", 2), ("de", 3), ("pt-BR", 4)];
+        assert_eq!(brazil.pick(tagged), Some(4));
+        assert_eq!(brazil.pick(tagged.into_iter().take(3)), Some(2));
+        assert_eq!(Locale::parse("en-US").unwrap().pick(tagged), None);
+
+        let turkish = Locale::parse("tr").unwrap();
+        assert_eq!(turkish.case_rules(), CaseRules::Turkic);
+        let date = NaiveDate::from_ymd_opt(2024, 9, 25).unwrap();
+        assert_eq!(turkish.format_date(date, "%a %-d %b %%b"), "Çar 25 Eyl %b");
+        assert_eq!(Locale::parse("ja").unwrap().format_date(date, "%b %-d"), "Sep 25");
+    }
+}
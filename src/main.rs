@@ -1,33 +1,1139 @@
-use eyre::Context;
+use cli::{
+    BlankArg, CheckArgs, Cli, Command, CoverageArgs, DiffArg, ExportArgs, ExportFormatArg,
+    ImportArgs, ImportFormatArg, ListArgs, PadArg, RulesArgs, RunArgs, SnapshotArgs, SuggestArgs,
+    TestArgs,
+};
+use eyre::{bail, Context};
 use itertools::Itertools;
-use std::env::args;
-use std::io::stdin;
+use shorten::abbrev::{self, Abbreviator, RuleKind};
+use shorten::expand::Expander;
+use shorten::{check, config, espanso, import, rule_tests, suggest, width};
+use rayon::prelude::*;
+use shorten::shortener::{Anchor, Shortened, Shortener};
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{stdin, stdout, BufRead, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::path::Path;
 
-mod abbrev;
-mod shortener;
+mod cli;
+mod diff;
+mod repl;
+mod stats;
+#[cfg(feature = "csv")]
+mod table;
+mod tmux;
+#[cfg(unix)]
+mod daemon;
 
 fn main() -> eyre::Result<()> {
     color_eyre::install()?;
 
-    let desired_max_length = match args().skip(1).exactly_one() {
-        Ok(arg) => arg,
-        Err(_) => {
-            eprintln!("Usage: shortener <desired_max_length>");
-            std::process::exit(1);
+    let cli = Cli::parse_args();
+    match cli.command {
+        Command::Run(args) => run(&cli.rules, args),
+        Command::Check(args) => check(&cli.rules, args),
+        Command::Rules(args) => rules(&cli.rules, args),
+        #[cfg(unix)]
+        Command::Daemon(args) => daemon::daemon(&cli.rules, args),
+        #[cfg(unix)]
+        Command::Client(args) => daemon::client(args),
+        Command::Expand => expand(&cli.rules),
+        Command::TmuxWindowName(args) => tmux::tmux_window_name(&cli.rules, args),
+        Command::Suggest(args) => suggest(&cli.rules, args),
+        Command::Coverage(args) => coverage(&cli.rules, args),
+        Command::Snapshot(args) => snapshot(&cli.rules, args),
+        Command::Test(args) => test(&cli.rules, args),
+        Command::Import(args) => import(args),
+        Command::Export(args) => export(&cli.rules, args),
+        Command::Repl(args) => repl::repl(&cli.rules, args),
+    }
+}
+
+fn run(rules: &RulesArgs, args: RunArgs) -> eyre::Result<()> {
+    if rules.reads_stdin() && args.files.is_empty() {
+        bail!("The rules are read from stdin with --config -, give the files to shorten instead");
+    }
+    if args.watch_config && !rules.has_file() {
+        bail!("--watch-config needs the rules in a file");
+    }
+    let abbreviator = rules.load()?;
+    let watcher = args
+        .watch_config
+        .then(|| config::ConfigWatcher::new(rules.config.as_deref(), &abbreviator))
+        .transpose()?;
+    // Without a default length, lines lacking a length prefix pass through unchanged
+    let desired_max_length = args.desired_max_length.unwrap_or(usize::MAX);
+    let placeholder = match &args.blank {
+        BlankArg::Placeholder(placeholder) => Some(placeholder.clone()),
+        BlankArg::Keep | BlankArg::Drop => None,
+    };
+    let shortener =
+        args.options.shortener(abbreviator, desired_max_length)?.blank_placeholder(placeholder);
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(args.jobs).build()?;
+    let mapping = args.mapping.as_ref().map(|path| {
+        File::create(path).wrap_err_with(|| format!("Failed to create {}", path.display()))
+    });
+    let mapping = mapping.transpose()?.map(BufWriter::new);
+    let mut run = Run {
+        rules,
+        args: &args,
+        shortener,
+        watcher,
+        pool,
+        desired_max_length,
+        color: stdout().is_terminal() && args.in_place.is_none(),
+        stats: stats::Stats::default(),
+        warned: HashSet::new(),
+        mapping,
+    };
+
+    if args.files.is_empty() {
+        let mut output = BufWriter::new(stdout().lock());
+        run.shorten_stream(BufReader::new(stdin().lock()), &mut output)?;
+        output.flush()?;
+    }
+    for path in &args.files {
+        let input = BufReader::new(
+            File::open(path).wrap_err_with(|| format!("Failed to read {}", path.display()))?,
+        );
+        match &args.in_place {
+            Some(suffix) => run.shorten_in_place(input, path, suffix)?,
+            None => {
+                let mut output = BufWriter::new(stdout().lock());
+                run.shorten_stream(input, &mut output)?;
+                output.flush()?;
+            }
+        }
+    }
+    if let Some(mapping) = &mut run.mapping {
+        mapping.flush().wrap_err("Failed to write the mapping")?;
+    }
+    report(&run.shortener, &args, &run.stats)
+}
+
+/// The UTF-8 byte order mark
+const BOM: &[u8] = b"\xef\xbb\xbf";
+
+/// How many records each thread gets per chunk with `--jobs`
+const RECORDS_PER_JOB: usize = 256;
+
+/// The state of `shorten run` across its inputs.
+struct Run<'a> {
+    rules: &'a RulesArgs,
+    args: &'a RunArgs,
+    shortener: Shortener,
+    watcher: Option<config::ConfigWatcher>,
+    pool: rayon::ThreadPool,
+    desired_max_length: usize,
+    color: bool,
+    stats: stats::Stats,
+    /// The deprecated rules already warned about
+    warned: HashSet<String>,
+    /// The `--mapping` file
+    mapping: Option<BufWriter<File>>,
+}
+
+impl Run<'_> {
+    /// Shortens a file into a temporary file next to it, which then replaces
+    /// it, so the file is never left half written. A non-empty suffix keeps a
+    /// copy of the original file under its name with the suffix.
+    fn shorten_in_place(
+        &mut self,
+        input: BufReader<File>,
+        path: &Path,
+        suffix: &str,
+    ) -> eyre::Result<()> {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let temporary =
+            path.with_file_name(format!(".{file_name}.shorten-{}.tmp", std::process::id()));
+        let written = (|| {
+            let mut output = BufWriter::new(File::create(&temporary)?);
+            self.shorten_stream(input, &mut output)?;
+            let file = output.into_inner()?;
+            file.set_permissions(fs::metadata(path)?.permissions())?;
+            file.sync_all()?;
+            eyre::Ok(())
+        })();
+        if let Err(err) = written {
+            let _ = fs::remove_file(&temporary);
+            return Err(err.wrap_err(format!("Failed to write {}", temporary.display())));
+        }
+
+        if !suffix.is_empty() {
+            let backup = path.with_file_name(format!("{file_name}{suffix}"));
+            fs::copy(path, &backup)
+                .wrap_err_with(|| format!("Failed to back up {}", path.display()))?;
+        }
+        fs::rename(&temporary, path)
+            .wrap_err_with(|| format!("Failed to replace {}", path.display()))
+    }
+
+    fn shorten_stream<R: Read>(
+        &mut self,
+        mut input: BufReader<R>,
+        output: &mut impl Write,
+    ) -> eyre::Result<()> {
+        let args = self.args;
+        let desired_max_length = self.desired_max_length;
+        // The byte order mark Windows tools start files with is not part of the first line
+        if input.fill_buf()?.starts_with(BOM) {
+            input.consume(BOM.len());
+        }
+        #[cfg(feature = "csv")]
+        if args.csv {
+            return table::shorten_columns(
+                &self.shortener,
+                args,
+                desired_max_length,
+                &mut self.stats,
+                (input, output),
+            );
+        }
+
+        let delimiter = if args.null { b'\0' } else { b'\n' };
+        let ending: &[u8] = match (args.null, args.crlf) {
+            (true, _) => b"\0",
+            (false, true) => b"\r\n",
+            (false, false) => b"\n",
+        };
+        let mut directives = Directives { max_length: desired_max_length, skip: false };
+        let chunk_size = match args.jobs {
+            1 => 1,
+            _ => self.pool.current_num_threads() * RECORDS_PER_JOB,
+        };
+        let mut records = vec![Vec::new(); chunk_size];
+        loop {
+            // Fill a chunk, without waiting on more input than what is already available
+            let mut count = 0;
+            while count < chunk_size && read_record(&mut input, delimiter, &mut records[count])? {
+                count += 1;
+                if input.buffer().is_empty() {
+                    break;
+                }
+            }
+            if count == 0 {
+                break;
+            }
+
+            if let Some(reloaded) = self.watcher.as_mut().and_then(|watcher| watcher.poll()) {
+                match reloaded.and_then(|abbreviator| self.rules.select(abbreviator)) {
+                    Ok(abbreviator) => self.shortener.set_abbreviator(abbreviator),
+                    Err(err) => eprintln!("Keeping previous abbreviations: {err:#}"),
+                }
+            }
+
+            // The lines to shorten, along with the fields around them, and the
+            // skipped ones, which have no request
+            let mut lines = Vec::with_capacity(count);
+            for record in &records[..count] {
+                let line = std::str::from_utf8(record).context("Input is not valid UTF-8")?;
+                if args.directives {
+                    if directives.apply(line, desired_max_length)? {
+                        continue;
+                    }
+                    if directives.skip {
+                        lines.push((line, None, ""));
+                        continue;
+                    }
+                }
+                let max_length = directives.max_length;
+                let (max_length, anchor, line) = match args.length_prefix {
+                    true => split_length_prefix(line).unwrap_or((max_length, None, line)),
+                    false => (max_length, None, line),
+                };
+                if args.blank == BlankArg::Drop && line.trim().is_empty() {
+                    continue;
+                }
+                let (before, text, after) = match args.field {
+                    Some(field) => split_field(line, args.delimiter.unwrap_or('\t'), field),
+                    None => ("", line, ""),
+                };
+                lines.push((before, Some((max_length, anchor, text)), after));
+            }
+            let shortener = &self.shortener;
+            let shorten = |request: Option<_>| {
+                request.map(|request| shorten_request(shortener, request))
+            };
+            let shortened: Vec<_> = match lines.len() {
+                0 | 1 => lines.iter().map(|&(_, request, _)| shorten(request)).collect(),
+                _ => self.pool.install(|| {
+                    lines.par_iter().map(|&(_, request, _)| shorten(request)).collect()
+                }),
+            };
+
+            for ((before, request, after), shortened) in lines.iter().zip(&shortened) {
+                output.write_all(before.as_bytes())?;
+                let mut edits = serde_json::Value::Array(Vec::new());
+                if let (Some((max_length, _, text)), Some(shortened)) = (request, shortened) {
+                    self.stats.record(text, shortened, *max_length);
+                    self.warn_deprecated(shortened);
+                    write_record(output, args, self.color, (text, *max_length), shortened)?;
+                    let fill = args.pad.map(|side| {
+                        (side, padding(&shortened.text, *max_length, args.pad_char).len())
+                    });
+                    edits = mapping(text, shortened, before.len(), fill);
+                }
+                if let Some(mapping) = &mut self.mapping {
+                    serde_json::to_writer(&mut *mapping, &edits)?;
+                    mapping.write_all(b"\n")?;
+                }
+                output.write_all(after.as_bytes())?;
+                output.write_all(ending)?;
+            }
+
+            // Don't hold back output while waiting for more input, e.g. in a prompt loop
+            if input.buffer().is_empty() {
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Warns about the deprecated rules that shortened the line, the first time
+    /// each of them fires.
+    fn warn_deprecated(&mut self, shortened: &Shortened<'_>) {
+        let abbreviator = self.shortener.abbreviator();
+        for applied in &shortened.applied {
+            let matcher = &applied.matcher;
+            if !abbreviator.is_deprecated(matcher) || !self.warned.insert(matcher.clone()) {
+                continue;
+            }
+            let rules = abbreviator.rules();
+            let Some(rule) = rules.iter().find(|rule| rule.source == applied.matcher) else {
+                continue;
+            };
+            match &rule.rule.description {
+                Some(description) => eprintln!(
+                    "warning: rule '{}' at {} is deprecated: {description}",
+                    rule.source, rule.location
+                ),
+                None => eprintln!(
+                    "warning: rule '{}' at {} is deprecated",
+                    rule.source, rule.location
+                ),
+            }
+        }
+    }
+}
+
+/// The settings that `#!shorten` lines change for the lines after them, with
+/// `--directives`.
+struct Directives {
+    max_length: usize,
+    /// Whether lines pass through untouched, until `#!shorten resume`
+    skip: bool,
+}
+
+impl Directives {
+    /// Applies a `#!shorten` line, returning false when the line is not one.
+    fn apply(&mut self, line: &str, default_max_length: usize) -> eyre::Result<bool> {
+        let Some(settings) = line.strip_prefix("#!shorten") else {
+            return Ok(false);
+        };
+        if !settings.is_empty() && !settings.starts_with(char::is_whitespace) {
+            return Ok(false);
+        }
+        for setting in settings.split_whitespace() {
+            match setting.split_once('=') {
+                Some(("len", "default")) => self.max_length = default_max_length,
+                Some(("len", length)) => {
+                    self.max_length = length
+                        .parse()
+                        .wrap_err_with(|| format!("Invalid length in directive '{line}'"))?;
+                }
+                None if setting == "skip" => self.skip = true,
+                None if setting == "resume" => self.skip = false,
+                _ => bail!("Unknown setting '{setting}' in directive '{line}'"),
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Reports the stats once the input is processed, failing when lines are
+/// still too long with `--fail-over-budget`.
+fn report(shortener: &Shortener, args: &RunArgs, stats: &stats::Stats) -> eyre::Result<()> {
+    if args.stats {
+        stats.report(shortener.abbreviator(), &mut std::io::stderr().lock())?;
+    }
+    if args.fail_over_budget && stats.over_budget > 0 {
+        bail!("{} of {} lines are still too long", stats.over_budget, stats.lines);
+    }
+    Ok(())
+}
+
+/// Reads the next record without its delimiter (or `\r\n` line ending, also
+/// when the last line lacks its `\n`), returning false at the end of the input.
+fn read_record(input: &mut impl BufRead, delimiter: u8, record: &mut Vec<u8>) -> eyre::Result<bool> {
+    record.clear();
+    if input.read_until(delimiter, record)? == 0 {
+        return Ok(false);
+    }
+    if record.last() == Some(&delimiter) {
+        record.pop();
+    }
+    if delimiter == b'\n' && record.last() == Some(&b'\r') {
+        record.pop();
+    }
+    Ok(true)
+}
+
+/// Writes the output for one record, without the delimiter.
+fn write_record(
+    output: &mut impl Write,
+    args: &RunArgs,
+    color: bool,
+    (text, max_length): (&str, usize),
+    shortened: &Shortened<'_>,
+) -> eyre::Result<()> {
+    if args.json {
+        let length = width::length(&shortened.text);
+        let mut json = serde_json::json!({
+            "original": text,
+            "shortened": shortened.text,
+            "length": length,
+            "over_budget": length > max_length,
+            "truncated": shortened.truncated,
+            "elided": shortened.elided,
+        });
+        if args.explain {
+            json["rules"] = shortened
+                .applied
+                .iter()
+                .map(|rule| {
+                    serde_json::json!({
+                        "matcher": rule.matcher,
+                        "original": rule.original,
+                        "replacement": rule.replacement,
+                        "position": rule.position,
+                    })
+                })
+                .collect();
+        }
+        serde_json::to_writer(output, &json)?;
+    } else {
+        for rule in shortened.applied.iter().filter(|_| args.explain) {
+            eprintln!(
+                "{}: {:?} -> {:?} (rule: {})",
+                rule.position, rule.original, rule.replacement, rule.matcher
+            );
         }
+        let diff = match args.diff {
+            Some(DiffArg::Word) => Some(diff::word_diff(text, &shortened.text, color)),
+            Some(DiffArg::Unified) => Some(diff::unified_diff(text, &shortened.text, color)),
+            None => None,
+        };
+        let fill = match args.pad {
+            Some(_) => padding(&shortened.text, max_length, args.pad_char),
+            None => String::new(),
+        };
+        if args.pad == Some(PadArg::Left) {
+            output.write_all(fill.as_bytes())?;
+        }
+        output.write_all(diff.as_deref().unwrap_or(&shortened.text).as_bytes())?;
+        if args.pad == Some(PadArg::Right) {
+            output.write_all(fill.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// The `--mapping` of a shortened text found `offset` bytes into its output
+/// line: the edits that restore the original text, as `[start, end, original]`
+/// arrays locating the replacement in the line. The bytes of `--pad` fill on
+/// one side of the text are an edit removing them.
+fn mapping(
+    text: &str,
+    shortened: &Shortened<'_>,
+    mut offset: usize,
+    fill: Option<(PadArg, usize)>,
+) -> serde_json::Value {
+    let mut edits = Vec::new();
+    if let Some((PadArg::Left, fill)) = fill.filter(|&(_, fill)| fill > 0) {
+        edits.push(serde_json::json!([offset, offset + fill, ""]));
+        offset += fill;
+    }
+    edits.extend(shortened.edits(text).into_iter().map(|edit| {
+        let (start, end) = (offset + edit.shortened.start, offset + edit.shortened.end);
+        serde_json::json!([start, end, &text[edit.original]])
+    }));
+    if let Some((PadArg::Right, fill)) = fill.filter(|&(_, fill)| fill > 0) {
+        let end = offset + shortened.text.len();
+        edits.push(serde_json::json!([end, end + fill, ""]));
+    }
+    serde_json::Value::Array(edits)
+}
+
+/// The fill that brings the text to its maximum length with `--pad`. Lines
+/// passed through without a length are not padded.
+fn padding(text: &str, max_length: usize, fill: char) -> String {
+    match max_length {
+        usize::MAX => String::new(),
+        _ => fill.to_string().repeat(max_length.saturating_sub(width::length(text))),
+    }
+}
+
+/// Splits a `<len>\t<text>` line into its length and text, along with the
+/// anchor of a `<len>:left\t` or `<len>:right\t` prefix.
+fn split_length_prefix(line: &str) -> Option<(usize, Option<Anchor>, &str)> {
+    let (prefix, text) = line.split_once('\t')?;
+    let (length, anchor) = match prefix.split_once(':') {
+        Some((length, "left")) => (length, Some(Anchor::Left)),
+        Some((length, "right")) => (length, Some(Anchor::Right)),
+        Some(_) => return None,
+        None => (prefix, None),
     };
+    Some((length.trim().parse().ok()?, anchor, text))
+}
 
-    let desired_max_length = desired_max_length
-        .parse::<usize>()
-        .context("Failed to parse desired max length as an integer")?;
+/// Splits a line around its Nth (1-based) field, into the text before the
+/// field, the field and the text after it, delimiters included. A line
+/// without that field is all before an empty one.
+fn split_field(line: &str, delimiter: char, field: usize) -> (&str, &str, &str) {
+    let mut starts = line.match_indices(delimiter).map(|(index, _)| index + delimiter.len_utf8());
+    let start = match field {
+        1 => Some(0),
+        _ => starts.nth(field - 2),
+    };
+    let Some(start) = start else {
+        return (line, "", "");
+    };
+    let end = line[start..].find(delimiter).map_or(line.len(), |index| start + index);
+    (&line[..start], &line[start..end], &line[end..])
+}
+
+/// A line to shorten, with its length and anchor
+type Request<'a> = (usize, Option<Anchor>, &'a str);
+
+/// Shortens a line to its length, protecting the end of the line it asks for
+/// or the shortener's.
+fn shorten_request<'a>(
+    shortener: &Shortener,
+    (max_length, anchor, text): Request<'a>,
+) -> Shortened<'a> {
+    match anchor {
+        Some(anchor) => shortener.shorten_anchored(text, max_length, Some(anchor)),
+        None => shortener.shorten_to(text, max_length),
+    }
+}
 
-    let shortener = shortener::Shortener::new(desired_max_length)?;
-    let input = stdin().lines();
-    for line in input {
+fn check(rules_args: &RulesArgs, args: CheckArgs) -> eyre::Result<()> {
+    let (rules, path) = match rules_args.ad_hoc_rules()? {
+        Some(ad_hoc) => ad_hoc,
+        None => {
+            let path = match &rules_args.config {
+                Some(path) => path.clone(),
+                None => config::default_abbrev_path()?,
+            };
+            let rules = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read abbreviations file {}", path.display()))?;
+            (abbrev::strip_bom(&rules).to_string(), path)
+        }
+    };
+
+    let mut issues = if path.extension().is_some_and(|extension| extension == "toml") {
+        check::check_toml(&rules)
+    } else {
+        check::check_lines(rules.lines())
+    };
+    // The rules that don't load are reported already
+    let loaded = (args.lint || args.verify_idempotent).then(|| match rules_args.has_file() {
+        true => Abbreviator::try_from_file(&path),
+        false => Abbreviator::from_lines(rules.lines()),
+    });
+    if let Some(Ok(abbreviator)) = loaded {
+        if args.lint {
+            issues.extend(check::lint(&abbreviator, args.cascades));
+        }
+        if args.verify_idempotent {
+            let tests = match rules_args.has_file() {
+                true => rule_tests::load(&path)?,
+                false => rule_tests::parse(&rules)?,
+            };
+            let shortener = args.options.shortener(abbreviator, usize::MAX)?;
+            issues.extend(check::verify_idempotent(&shortener, &tests));
+        }
+    }
+    for issue in &issues {
+        // Lint issues can be in included files
+        let file = issue.file.as_deref().unwrap_or(&path).display();
+        match issue.line {
+            0 => println!("{file}: {}: {}", issue.severity, issue.message),
+            line => println!("{file}:{line}: {}: {}", issue.severity, issue.message),
+        }
+    }
+    if !issues.is_empty() {
+        bail!("{} problems found in {}", issues.len(), path.display());
+    }
+
+    println!("OK: {}", path.display());
+    Ok(())
+}
+
+fn rules(rules: &RulesArgs, args: ListArgs) -> eyre::Result<()> {
+    let abbreviator = rules.load()?;
+    list_rules(&mut stdout().lock(), &abbreviator, args)
+}
+
+/// Writes the rules that pass the filters of `args`, one per line.
+fn list_rules(
+    output: &mut impl Write,
+    abbreviator: &Abbreviator,
+    args: ListArgs,
+) -> eyre::Result<()> {
+    let kinds = args.kind.into_iter().map(RuleKind::from).collect::<Vec<_>>();
+    let matcher = args.matcher.map(|matcher| matcher.to_lowercase());
+    let in_groups = |group: &Option<String>| {
+        args.group.is_empty() || group.as_ref().is_some_and(|group| args.group.contains(group))
+    };
+    let listed = abbreviator.rules().into_iter().filter(|rule| {
+        in_groups(&rule.group)
+            && (kinds.is_empty() || kinds.contains(&rule.kind()))
+            && matcher.as_ref().is_none_or(|matcher| rule.source.to_lowercase().contains(matcher))
+    });
+    let listed = listed.collect::<Vec<_>>();
+    let group_width = listed.iter().filter_map(|rule| rule.group.as_ref()).map(String::len).max();
+
+    for rule in listed {
+        // Keep rules and exceptions have no replacement
+        let protects = matches!(rule.kind(), RuleKind::Keep | RuleKind::Except);
+        if args.json {
+            let escalations = rule.escalations.iter().map(|escalation| &escalation.abbrev);
+            let json = serde_json::json!({
+                "matcher": rule.source,
+                "type": rule.kind().to_string(),
+                "replacement": (!protects).then_some(&rule.abbrev),
+                "escalations": escalations.collect::<Vec<_>>(),
+                "attach_to_previous": rule.attach_to_previous,
+                "attach_to_next": rule.attach_to_next,
+                "priority": rule.priority,
+                "min_overflow": rule.min_overflow,
+                "group": rule.group,
+                "contexts": rule.contexts,
+                "location": rule.location.to_string(),
+                "description": rule.rule.description,
+                "deprecated": rule.rule.deprecated,
+            });
+            writeln!(output, "{json}")?;
+            continue;
+        }
+        // The group column is only shown when some rules have a group
+        let group = match group_width {
+            Some(width) => format!("{:<width$}  ", rule.group.as_deref().unwrap_or("-")),
+            None => String::new(),
+        };
+        let metadata = match (&rule.rule.description, rule.rule.deprecated) {
+            (Some(description), true) => format!("  # deprecated: {description}"),
+            (Some(description), false) => format!("  # {description}"),
+            (None, true) => "  # deprecated".to_string(),
+            (None, false) => String::new(),
+        };
+        if protects {
+            let (priority, kind, source) = (rule.priority, rule.kind(), &rule.source);
+            writeln!(output, "{priority:>4}  {kind:<7}  {group}{source}{metadata}")?;
+            continue;
+        }
+        let attach = if rule.attach_to_previous { "<+" } else { "" };
+        let levels = rule.escalations.iter().map(|escalation| format!(" | {}", escalation.abbrev));
+        let attach_next = if rule.attach_to_next { "+>" } else { "" };
+        writeln!(
+            output,
+            "{:>4}  {:<7}  {group}{} = {}{}{}{}{metadata}",
+            rule.priority,
+            rule.kind(),
+            rule.source,
+            attach,
+            rule.abbrev,
+            levels.collect::<String>(),
+            attach_next
+        )?;
+    }
+    Ok(())
+}
+
+fn expand(rules: &RulesArgs) -> eyre::Result<()> {
+    let expander = Expander::new(&rules.load()?);
+    for ambiguity in expander.ambiguities() {
+        eprintln!(
+            "warning: '{}' is ambiguous, it abbreviates {}",
+            ambiguity.abbrev,
+            ambiguity.originals.join(", ")
+        );
+    }
+
+    let mut output = BufWriter::new(stdout().lock());
+    for line in stdin().lock().lines() {
+        writeln!(output, "{}", expander.expand(&line?))?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+fn suggest(rules: &RulesArgs, args: SuggestArgs) -> eyre::Result<()> {
+    let abbreviator = rules.load()?;
+    let corpus = std::io::read_to_string(stdin())?;
+    let suggestions = suggest::suggest(&abbreviator, corpus.lines(), args.min_count);
+    for suggestion in suggestions.iter().take(args.limit) {
+        println!("{}", suggestion.to_rule_lines());
+    }
+    Ok(())
+}
+
+fn coverage(rules: &RulesArgs, args: CoverageArgs) -> eyre::Result<()> {
+    let abbreviator = rules.load()?;
+    let shortener = args.options.shortener(abbreviator, args.desired_max_length)?;
+    let mut coverage = stats::Coverage::default();
+    for line in stdin().lock().lines() {
         let line = line?;
-        let shortened = shortener.shorten(&line);
-        println!("{}", shortened);
+        let shortened = shortener.shorten_to(&line, args.desired_max_length);
+        coverage.record(&line, &shortened, args.desired_max_length);
     }
+    let report = coverage.to_json(shortener.abbreviator());
+    serde_json::to_writer_pretty(stdout().lock(), &report)?;
+    println!();
+    Ok(())
+}
 
+fn snapshot(rules: &RulesArgs, args: SnapshotArgs) -> eyre::Result<()> {
+    let corpus = fs::read_to_string(&args.input)
+        .with_context(|| format!("Failed to read {}", args.input.display()))?;
+    let abbreviator = rules.load()?;
+    let shortener = args.options.shortener(abbreviator, args.desired_max_length)?;
+    let mut shortened = String::with_capacity(corpus.len());
+    for line in corpus.lines() {
+        shortened.push_str(&shortener.shorten_to(line, args.desired_max_length).text);
+        shortened.push('\n');
+    }
+    if args.update {
+        fs::write(&args.expected, &shortened)
+            .with_context(|| format!("Failed to write {}", args.expected.display()))?;
+        println!("Updated {}", args.expected.display());
+        return Ok(());
+    }
+
+    let expected = fs::read_to_string(&args.expected)
+        .with_context(|| format!("Failed to read {}", args.expected.display()))?;
+    let color = stdout().is_terminal();
+    let mut changed = 0;
+    // Missing lines at the end of either file compare as empty ones
+    for (index, lines) in expected.lines().zip_longest(shortened.lines()).enumerate() {
+        let (expected, shortened) = lines.or("", "");
+        if expected != shortened {
+            changed += 1;
+            println!("{}:{}:", args.expected.display(), index + 1);
+            println!("{}", diff::unified_diff(expected, shortened, color));
+        }
+    }
+    if changed > 0 {
+        bail!("{changed} lines changed, run with --update to accept them");
+    }
+    println!("OK: {}", args.expected.display());
     Ok(())
-}
\ No newline at end of file
+}
+
+fn test(rules: &RulesArgs, args: TestArgs) -> eyre::Result<()> {
+    let (path, tests, abbreviator) = match rules.ad_hoc_rules()? {
+        Some((text, path)) => {
+            let abbreviator = rules.checked(Abbreviator::from_lines(text.lines())?)?;
+            (path, rule_tests::parse(&text)?, abbreviator)
+        }
+        None => {
+            let path = match &rules.config {
+                Some(path) => path.clone(),
+                None => config::default_abbrev_path()?,
+            };
+            (path.clone(), rule_tests::load(&path)?, rules.load()?)
+        }
+    };
+    let shortener = args.options.shortener(abbreviator, usize::MAX)?;
+    let mut failed = 0;
+    for test in &tests {
+        if let Err(shortened) = test.run(&shortener) {
+            failed += 1;
+            let file = test.file.as_deref().unwrap_or(&path);
+            println!(
+                "{}:{}: \"{}\" was shortened to \"{}\", expected \"{}\"",
+                file.display(),
+                test.line,
+                test.text,
+                shortened,
+                test.expected
+            );
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {} tests failed", tests.len());
+    }
+    println!("{} tests passed", tests.len());
+    Ok(())
+}
+
+fn import(args: ImportArgs) -> eyre::Result<()> {
+    let export = std::fs::read_to_string(&args.file)
+        .with_context(|| format!("Failed to read {}", args.file.display()))?;
+    let export = abbrev::strip_bom(&export);
+    let format = args.format.unwrap_or_else(|| {
+        match args.file.extension().and_then(|extension| extension.to_str()) {
+            Some("tsv") => ImportFormatArg::Tsv,
+            Some("yml" | "yaml") => ImportFormatArg::Espanso,
+            _ => ImportFormatArg::Csv,
+        }
+    });
+    let imported = match format {
+        #[cfg(feature = "csv")]
+        ImportFormatArg::Csv => import::import(export, b',', args.header, args.to.into()),
+        #[cfg(feature = "csv")]
+        ImportFormatArg::Tsv => import::import(export, b'\t', args.header, args.to.into()),
+        #[cfg(not(feature = "csv"))]
+        ImportFormatArg::Csv | ImportFormatArg::Tsv => {
+            bail!("Importing CSV and TSV exports needs the csv feature")
+        }
+        ImportFormatArg::Espanso => import::import_espanso(export, args.to.into()),
+    }
+    .wrap_err_with(|| args.file.display().to_string())?;
+
+    for issue in &imported.issues {
+        match issue.line {
+            0 => eprintln!("{}: {}: {}", args.file.display(), issue.severity, issue.message),
+            line => eprintln!(
+                "{}:{}: {}: {}",
+                args.file.display(),
+                line,
+                issue.severity,
+                issue.message
+            ),
+        }
+    }
+    let errors = imported.issues.iter().filter(|issue| issue.severity == check::Severity::Error);
+    if errors.count() > 0 {
+        bail!("The rules of {} don't load", args.file.display());
+    }
+
+    print!("{}", imported.text);
+    Ok(())
+}
+
+fn export(rules: &RulesArgs, args: ExportArgs) -> eyre::Result<()> {
+    let abbreviator = rules.load()?;
+    match args.format {
+        ExportFormatArg::Espanso => print!("{}", espanso::export(&abbreviator)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cli::{Cli, Command, ModeArg, PadArg};
+    use crate::{check, list_rules, mapping, padding, run, snapshot, split_field};
+    use crate::split_length_prefix;
+    use crate::write_record;
+    use crate::Directives;
+    use clap::Parser;
+    use shorten::abbrev::Abbreviator;
+    use shorten::shortener::{Anchor, Shortener};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_run_in_place() {
+        let dir = std::env::temp_dir().join(format!("shorten-in-place-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("abbrev.lst"), "Architecture = arch\n").unwrap();
+        fs::write(dir.join("titles.txt"), "Architecture Review\nShort\n").unwrap();
+
+        let (config, titles) = (dir.join("abbrev.lst"), dir.join("titles.txt"));
+        let args = ["shorten".as_ref(), "--config".as_ref(), config.as_os_str(), "run".as_ref()];
+        let cli = Cli::parse_from(args.into_iter().chain(
+            ["--in-place=.bak".as_ref(), "15".as_ref(), titles.as_os_str()],
+        ));
+        let Command::Run(args) = cli.command else {
+            panic!("not a run command");
+        };
+        run(&cli.rules, args).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("titles.txt")).unwrap(), "Arch Review\nShort\n");
+        let backup = fs::read_to_string(dir.join("titles.txt.bak")).unwrap();
+        assert_eq!(backup, "Architecture Review\nShort\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_subcommands() {
+        let parse = |args: &[&str]| Cli::parse_args_from(args.iter().map(Into::into).collect());
+
+        // A length alone runs the shortener, like it did before the subcommands
+        for args in [&["shorten", "20"][..], &["shorten", "run", "20"]] {
+            let Command::Run(args) = parse(args).command else {
+                panic!("not a run command");
+            };
+            assert_eq!(args.desired_max_length, Some(20));
+        }
+        let cli = parse(&["shorten", "--config", "abbrev.lst", "rules"]);
+        assert!(matches!(cli.command, Command::Rules(_)));
+        assert_eq!(cli.rules.config.as_deref(), Some(Path::new("abbrev.lst")));
+        assert!(Cli::try_parse_from(["shorten", "run"]).is_err());
+        assert!(Cli::try_parse_from(["shorten", "20"]).is_err());
+
+        let dir = std::env::temp_dir().join(format!("shorten-check-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("valid.lst"), "Architecture = arch\n").unwrap();
+        fs::write(dir.join("invalid.lst"), "Architecture = arch\n/(/ = x\n").unwrap();
+        for (file, valid) in [("valid.lst", true), ("invalid.lst", false)] {
+            let config = dir.join(file);
+            let cli = parse(&["shorten", "--config", config.to_str().unwrap(), "check"]);
+            let Command::Check(args) = cli.command else {
+                panic!("not a check command");
+            };
+            assert_eq!(check(&cli.rules, args).is_ok(), valid, "{file}");
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_list_rules() {
+        let lines = r#"
+            Architecture = arch
+            /^Meet(ing)?s?$/i = Mtg
+            Rescheduled =
+
+            [group:japanese]
+            Section = <+課
+            Weekly = 毎週 | W
+        "#;
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let list = |args: &[&str]| {
+            let args = ["shorten", "rules"].iter().chain(args);
+            let Command::Rules(args) = Cli::parse_from(args).command else {
+                panic!("not a rules command");
+            };
+            let mut output = Vec::new();
+            list_rules(&mut output, &abbreviator, args).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(list(&["--type", "regex"]), "   0  regex    /^Meet(ing)?s?$/i = Mtg\n");
+        let listed = list(&["--type", "remove", "--matcher", "SCHED"]);
+        assert_eq!(listed, "   0  remove   Rescheduled = \n");
+        // The group column is only shown when some of the listed rules have a group
+        let listed = list(&["--group", "japanese", "--matcher", "section"]);
+        assert_eq!(listed, "   0  exact    japanese  Section = <+課\n");
+        let listed = list(&["--type", "exact", "--matcher", "e"]);
+        assert!(listed.starts_with("   0  exact    -         Architecture = arch\n"), "{listed}");
+        assert_eq!(list(&["--group", "corp"]), "");
+
+        let listed = list(&["--json", "--matcher", "week"]);
+        let json: serde_json::Value = serde_json::from_str(&listed).unwrap();
+        assert_eq!(json["type"], "exact");
+        assert_eq!(json["replacement"], "毎週");
+        assert_eq!(json["escalations"], serde_json::json!(["W"]));
+        assert_eq!(json["group"], "japanese");
+    }
+
+    #[test]
+    fn test_length_prefix() {
+        let line = "12\tArchitecture Review";
+        assert_eq!(split_length_prefix(line), Some((12, None, "Architecture Review")));
+        let line = "8:right\tArchitecture Review";
+        let expected = Some((8, Some(Anchor::Right), "Architecture Review"));
+        assert_eq!(split_length_prefix(line), expected);
+        assert_eq!(split_length_prefix("8:top\tArchitecture"), None);
+        assert_eq!(split_length_prefix("Architecture\tReview"), None);
+        assert_eq!(split_length_prefix("Architecture Review"), None);
+
+        let dir = std::env::temp_dir().join(format!("shorten-prefix-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("abbrev.lst"), "Architecture = arch\nReview = rvw\n").unwrap();
+        let input = "20\tArchitecture Review\n12\tArchitecture Review\nArchitecture Review\n";
+        fs::write(dir.join("titles.txt"), input).unwrap();
+
+        let (config, titles) = (dir.join("abbrev.lst"), dir.join("titles.txt"));
+        let args = ["shorten".as_ref(), "--config".as_ref(), config.as_os_str(), "run".as_ref()];
+        let cli = Cli::parse_from(args.into_iter().chain(
+            ["--in-place".as_ref(), "--length-prefix".as_ref(), "8".as_ref(), titles.as_os_str()],
+        ));
+        let Command::Run(args) = cli.command else {
+            panic!("not a run command");
+        };
+        run(&cli.rules, args).unwrap();
+        let output = fs::read_to_string(dir.join("titles.txt")).unwrap();
+        assert_eq!(output, "Architecture Review\nArch Rvw\nArch Rvw\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_run_jobs() {
+        let dir = std::env::temp_dir().join(format!("shorten-jobs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("abbrev.lst"), "Architecture = arch\n").unwrap();
+        // Several chunks of records, the last one partly filled
+        let lines = (0..2000).map(|index| format!("Architecture Review {index}\n"));
+        fs::write(dir.join("titles.txt"), lines.collect::<String>()).unwrap();
+
+        let (config, titles) = (dir.join("abbrev.lst"), dir.join("titles.txt"));
+        let args = ["shorten".as_ref(), "--config".as_ref(), config.as_os_str(), "run".as_ref()];
+        let cli = Cli::parse_from(args.into_iter().chain(
+            ["--in-place".as_ref(), "--jobs=3".as_ref(), "20".as_ref(), titles.as_os_str()],
+        ));
+        let Command::Run(args) = cli.command else {
+            panic!("not a run command");
+        };
+        run(&cli.rules, args).unwrap();
+        let expected = (0..2000).map(|index| format!("Arch Review {index}\n"));
+        let output = fs::read_to_string(dir.join("titles.txt")).unwrap();
+        assert_eq!(output, expected.collect::<String>());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_env() {
+        // The other tests read the variables too, so they are only set for a child process
+        if std::env::var_os("SHORTEN_TEST_ENV").is_none() {
+            let output = std::process::Command::new(std::env::current_exe().unwrap())
+                .args(["--exact", "tests::test_env"])
+                .env("SHORTEN_TEST_ENV", "1")
+                .env("SHORTEN_CONFIG", "/etc/shorten/abbrev.lst")
+                .env("SHORTEN_MAX_LENGTH", "20")
+                .env("SHORTEN_MODE", "path")
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+            return;
+        }
+
+        // `shorten` alone is `shorten run`, with the length of the environment
+        let cli = Cli::parse_args_from(vec!["shorten".into()]);
+        let Command::Run(args) = cli.command else {
+            panic!("not a run command");
+        };
+        assert_eq!(cli.rules.config.as_deref(), Some(Path::new("/etc/shorten/abbrev.lst")));
+        assert_eq!(args.desired_max_length, Some(20));
+        assert!(matches!(args.options.mode, ModeArg::Path));
+
+        // The command line wins
+        let args = ["shorten", "--mode", "text", "30"].map(Into::into).to_vec();
+        let cli = Cli::parse_args_from(args);
+        let Command::Run(args) = cli.command else {
+            panic!("not a run command");
+        };
+        assert_eq!(args.desired_max_length, Some(30));
+        assert!(matches!(args.options.mode, ModeArg::Text));
+
+        // Files follow the length of the environment
+        for args in [&["shorten", "paths.txt"][..], &["shorten", "run", "paths.txt"]] {
+            let args = args.iter().map(Into::into).collect();
+            let Command::Run(args) = Cli::parse_args_from(args).command else {
+                panic!("not a run command");
+            };
+            assert_eq!(args.desired_max_length, Some(20));
+            assert_eq!(args.files, [Path::new("paths.txt")]);
+        }
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let dir = std::env::temp_dir().join(format!("shorten-snapshot-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("abbrev.lst"), "Architecture = arch\n").unwrap();
+        fs::write(dir.join("corpus.txt"), "Architecture Review\nShort\n").unwrap();
+
+        let (config, corpus) = (dir.join("abbrev.lst"), dir.join("corpus.txt"));
+        let expected = dir.join("expected.txt");
+        let snapshot_with = |flags: &[&str]| {
+            let args = ["shorten".as_ref(), "--config".as_ref(), config.as_os_str()];
+            let args = args.into_iter().chain(["snapshot".as_ref(), "15".as_ref()]);
+            let files = ["--input".as_ref(), corpus.as_os_str(), "--expected".as_ref()];
+            let args = args.chain(files).chain([expected.as_os_str()]);
+            let cli = Cli::parse_from(args.chain(flags.iter().map(|flag| flag.as_ref())));
+            let Command::Snapshot(args) = cli.command else {
+                panic!("not a snapshot command");
+            };
+            snapshot(&cli.rules, args)
+        };
+        snapshot_with(&["--update"]).unwrap();
+        assert_eq!(fs::read_to_string(&expected).unwrap(), "Arch Review\nShort\n");
+        snapshot_with(&[]).unwrap();
+        fs::write(&expected, "Arch Review\n").unwrap();
+        let err = snapshot_with(&[]).unwrap_err();
+        assert_eq!(err.to_string(), "1 lines changed, run with --update to accept them");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_directives() {
+        let mut directives = Directives { max_length: 30, skip: false };
+        assert!(!directives.apply("Architecture Review", 30).unwrap());
+        assert!(!directives.apply("#!shortening", 30).unwrap());
+        assert!(directives.apply("#!shorten len=20 skip", 30).unwrap());
+        assert_eq!((directives.max_length, directives.skip), (20, true));
+        assert!(directives.apply("#!shorten resume len=default", 30).unwrap());
+        assert_eq!((directives.max_length, directives.skip), (30, false));
+        let err = directives.apply("#!shorten len=twenty", 30).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid length in directive '#!shorten len=twenty'");
+        assert!(directives.apply("#!shorten width=20", 30).is_err());
+    }
+
+    #[test]
+    fn test_padding() {
+        assert_eq!(padding("Arch課", 8, ' '), "  ");
+        assert_eq!(padding("Architecture", 8, '.'), "");
+        assert_eq!(padding("Arch", usize::MAX, ' '), "");
+    }
+
+    #[test]
+    fn test_split_field() {
+        let line = "12:00\tArchitecture Review\tok";
+        assert_eq!(split_field(line, '\t', 2), ("12:00\t", "Architecture Review", "\tok"));
+        assert_eq!(split_field(line, '\t', 1), ("", "12:00", "\tArchitecture Review\tok"));
+        assert_eq!(split_field(line, '\t', 3), ("12:00\tArchitecture Review\t", "ok", ""));
+        assert_eq!(split_field(line, '\t', 4), (line, "", ""));
+        assert_eq!(split_field("a;;b", ';', 2), ("a;", "", ";b"));
+    }
+
+    #[test]
+    fn test_json() {
+        let abbreviator = Abbreviator::from_lines(["Architecture = arch"].into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+        let cli = Cli::parse_from(["shorten", "run", "--json", "10"]);
+        let Command::Run(args) = cli.command else {
+            panic!("not a run command");
+        };
+        let json = |text| {
+            let mut output = Vec::new();
+            let shortened = shortener.shorten_detailed(text);
+            write_record(&mut output, &args, false, (text, 10), &shortened).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&output).unwrap()
+        };
+        let record = json("Architecture Review");
+        assert_eq!((&record["length"], &record["over_budget"]), (&11.into(), &true.into()));
+        let record = json("Architecture");
+        assert_eq!((&record["shortened"], &record["over_budget"]), (&"Arch".into(), &false.into()));
+    }
+
+    #[test]
+    fn test_mapping() {
+        let rules = ["Architecture = arch", "Rescheduled ="];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(20, abbreviator).unwrap();
+        let text = "Architecture Rescheduled Session";
+        let shortened = shortener.shorten_detailed(text);
+        let edits = mapping(text, &shortened, 6, None);
+        assert_eq!(edits.to_string(), r#"[[6,10,"Architecture"],[10,10," Rescheduled"]]"#);
+
+        let restore = |mut line: String, edits: serde_json::Value| {
+            for edit in edits.as_array().unwrap().iter().rev() {
+                let (start, end) = (edit[0].as_u64().unwrap(), edit[1].as_u64().unwrap());
+                line.replace_range(start as usize..end as usize, edit[2].as_str().unwrap());
+            }
+            line
+        };
+        let line = format!("09:00\t{}", shortened.text);
+        assert_eq!(restore(line, edits), format!("09:00\t{text}"));
+
+        let fill = padding(&shortened.text, 20, '·');
+        let edits = mapping(text, &shortened, 0, Some((PadArg::Left, fill.len())));
+        let expected = r#"[[0,16,""],[16,20,"Architecture"],[20,20," Rescheduled"]]"#;
+        assert_eq!(edits.to_string(), expected);
+        assert_eq!(restore(format!("{fill}{}", shortened.text), edits), text);
+        let edits = mapping(text, &shortened, 0, Some((PadArg::Right, fill.len())));
+        assert_eq!(restore(format!("{}{fill}", shortened.text), edits), text);
+    }
+}
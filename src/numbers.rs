@@ -0,0 +1,192 @@
+//! Spelled-out numbers and thousands separators, for the numbers pass:
+//! "twenty-third" is written 23rd, "One Hundred and Five" 105 and 1,250,000
+//! 1250000.
+
+/// What the last word of a number was, deciding which words can follow it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Last {
+    Start,
+    /// One to nine
+    Unit,
+    /// Ten to nineteen
+    Teen,
+    /// Twenty, thirty…
+    Tens,
+    Hundred,
+    /// Thousand, million or billion
+    Scale,
+    And,
+}
+
+#[derive(Clone, Copy)]
+struct Number {
+    total: u64,
+    /// The part below the last scale word
+    current: u64,
+    /// The smallest scale word so far, as later ones must be smaller
+    scale: u64,
+    last: Last,
+    ordinal: bool,
+}
+
+const UNITS: [&str; 10] =
+    ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine"];
+const TEENS: [&str; 10] = [
+    "ten", "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen",
+    "eighteen", "nineteen",
+];
+const TENS: [&str; 8] =
+    ["twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+const ORDINAL_UNITS: [&str; 10] = [
+    "zeroth", "first", "second", "third", "fourth", "fifth", "sixth", "seventh", "eighth", "ninth",
+];
+const ORDINAL_TEENS: [&str; 10] = [
+    "tenth", "eleventh", "twelfth", "thirteenth", "fourteenth", "fifteenth", "sixteenth",
+    "seventeenth", "eighteenth", "nineteenth",
+];
+const ORDINAL_TENS: [&str; 8] = [
+    "twentieth", "thirtieth", "fortieth", "fiftieth", "sixtieth", "seventieth", "eightieth",
+    "ninetieth",
+];
+const SCALES: [(&str, &str, u64); 3] = [
+    ("thousand", "thousandth", 1_000),
+    ("million", "millionth", 1_000_000),
+    ("billion", "billionth", 1_000_000_000),
+];
+
+impl Number {
+    /// Adds a word of the number (a part of a hyphenated one), or returns
+    /// `None` when it can't follow the previous ones.
+    fn push(mut self, token: &str) -> Option<Number> {
+        if self.ordinal {
+            return None;
+        }
+        let find = |cardinals: &[&str], ordinals: &[&str]| {
+            let position = |words: &[&str]| words.iter().position(|&word| word == token);
+            let cardinal = position(cardinals).map(|index| (index as u64, false));
+            cardinal.or_else(|| position(ordinals).map(|index| (index as u64, true)))
+        };
+        // Units, teens and tens start a number or follow a larger word
+        let after_larger =
+            matches!(self.last, Last::Start | Last::Hundred | Last::Scale | Last::And);
+
+        let unit = find(&UNITS, &ORDINAL_UNITS);
+        let (current, last, ordinal) = if let Some((value, ordinal)) = unit {
+            let fits = after_larger || self.last == Last::Tens;
+            if !fits || (value == 0 && self.last != Last::Start) {
+                return None;
+            }
+            (self.current + value, Last::Unit, ordinal)
+        } else if let Some((value, ordinal)) = find(&TEENS, &ORDINAL_TEENS) {
+            if !after_larger {
+                return None;
+            }
+            (self.current + 10 + value, Last::Teen, ordinal)
+        } else if let Some((value, ordinal)) = find(&TENS, &ORDINAL_TENS) {
+            if !after_larger {
+                return None;
+            }
+            (self.current + 20 + 10 * value, Last::Tens, ordinal)
+        } else if token == "hundred" || token == "hundredth" {
+            if !matches!(self.last, Last::Unit | Last::Teen) || self.current >= 100 {
+                return None;
+            }
+            (self.current * 100, Last::Hundred, token == "hundredth")
+        } else if let Some(&(_, ordinal_name, scale)) =
+            SCALES.iter().find(|(cardinal, ordinal, _)| token == *cardinal || token == *ordinal)
+        {
+            let counted = matches!(self.last, Last::Unit | Last::Teen | Last::Tens | Last::Hundred);
+            if !counted || scale >= self.scale {
+                return None;
+            }
+            self.total = self.total.checked_add(self.current.checked_mul(scale)?)?;
+            self.scale = scale;
+            (0, Last::Scale, token == ordinal_name)
+        } else if token == "and" && matches!(self.last, Last::Hundred | Last::Scale) {
+            (self.current, Last::And, false)
+        } else {
+            return None;
+        };
+        (self.current, self.last, self.ordinal) = (current, last, ordinal);
+        Some(self)
+    }
+
+    fn write(&self) -> String {
+        let value = self.total + self.current;
+        if !self.ordinal {
+            return value.to_string();
+        }
+        let suffix = match (value % 10, value % 100) {
+            (_, 11..=13) => "th",
+            (1, _) => "st",
+            (2, _) => "nd",
+            (3, _) => "rd",
+            _ => "th",
+        };
+        format!("{value}{suffix}")
+    }
+}
+
+/// The longest run of words at the start that spells out a number, as the
+/// number of words and the number in digits. Punctuation after the last word
+/// is kept, and ends the number.
+pub(crate) fn parse(words: &[&str]) -> Option<(usize, String)> {
+    let mut number =
+        Number { total: 0, current: 0, scale: u64::MAX, last: Last::Start, ordinal: false };
+    let mut best = None;
+    for (index, word) in words.iter().enumerate() {
+        let spelled = word.trim_end_matches(|c: char| !c.is_alphanumeric());
+        let punctuation = &word[spelled.len()..];
+        let spelled = spelled.to_lowercase();
+        let Some(next) = spelled.split('-').try_fold(number, Number::push) else {
+            break;
+        };
+        number = next;
+        if number.last != Last::And {
+            best = Some((index + 1, format!("{}{punctuation}", number.write())));
+        }
+        if !punctuation.is_empty() {
+            break;
+        }
+    }
+    best
+}
+
+/// The number without its thousands separators, like 1250000 for
+/// "1,250,000", or `None` when the word is not such a number.
+pub(crate) fn strip_separators(word: &str) -> Option<String> {
+    let end = word.find(|c: char| c != ',' && !c.is_ascii_digit()).unwrap_or(word.len());
+    let (integer, rest) = word.split_at(end);
+    let mut groups = integer.split(',');
+    let first = groups.next()?;
+    let valid = (1..=3).contains(&first.len())
+        && integer.contains(',')
+        && groups.all(|group| group.len() == 3);
+    let fraction = rest.strip_prefix('.').is_none_or(|rest| {
+        rest.trim_end_matches(|c: char| !c.is_alphanumeric()).chars().all(|c| c.is_ascii_digit())
+    });
+    (valid && fraction).then(|| format!("{}{rest}", integer.replace(',', "")))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::numbers::{parse, strip_separators};
+
+    #[test]
+    fn test_numbers() {
+        let parsed = |text: &str| parse(&text.split(' ').collect::<Vec<_>>());
+        assert_eq!(parsed("twenty-third"), Some((1, "23rd".to_string())));
+        assert_eq!(parsed("One Meeting"), Some((1, "1".to_string())));
+        assert_eq!(parsed("one hundred and five people"), Some((4, "105".to_string())));
+        assert_eq!(parsed("nineteen hundred eighty-four"), Some((3, "1984".to_string())));
+        assert_eq!(parsed("two thousand twenty-four,"), Some((3, "2024,".to_string())));
+        assert_eq!(parsed("eleventh hour"), Some((1, "11th".to_string())));
+        assert_eq!(parsed("one two"), Some((1, "1".to_string())));
+        assert_eq!(parsed("hundred days"), None);
+        assert_eq!(parsed("Thousand Oaks"), None);
+        assert_eq!(strip_separators("1,250,000"), Some("1250000".to_string()));
+        assert_eq!(strip_separators("$1,250.50)"), None);
+        assert_eq!(strip_separators("1,250.50)"), Some("1250.50)".to_string()));
+        assert_eq!(strip_separators("12,50"), None);
+    }
+}
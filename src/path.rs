@@ -0,0 +1,96 @@
+//! Fish-style shortening of filesystem paths.
+
+use crate::abbrev::Abbreviator;
+use crate::shortener::{AppliedRule, Shortened, Span};
+use crate::width;
+use std::borrow::Cow;
+use std::iter::zip;
+
+/// Shortens the intermediate components of a path, left to right, until the
+/// path fits `max_length`. A component is replaced by its abbreviation when a
+/// rule matches, and by its first character otherwise. The last component and
+/// kept components are always left intact.
+pub(crate) fn shorten_path<'a>(
+    abbreviator: &Abbreviator,
+    path: &'a str,
+    max_length: usize,
+    is_kept: impl Fn(&str) -> bool,
+) -> Shortened<'a> {
+    if width::length(path) <= max_length {
+        return Shortened {
+            text: Cow::Borrowed(path),
+            truncated: false,
+            elided: false,
+            applied: vec![],
+            spans: vec![Span { original: 0..path.len(), shortened: 0..path.len() }],
+        };
+    }
+
+    let components = path.split('/').collect::<Vec<_>>();
+    let mut shortened = components.iter().map(|component| Cow::Borrowed(*component)).collect::<Vec<_>>();
+    let mut applied = Vec::new();
+    let mut position = 0;
+    for (index, component) in components.iter().enumerate().take(components.len() - 1) {
+        let length = width::length(&shortened.join("/"));
+        if length <= max_length {
+            break;
+        }
+
+        if !component.is_empty() && *component != "~" && !is_kept(component) {
+            shortened[index] = match abbreviator.abbreviate(component) {
+                Some(abbrev)
+                    if !abbrev.text.is_empty() && length - max_length > abbrev.min_overflow =>
+                {
+                    applied.push(AppliedRule {
+                        matcher: abbrev.matcher.to_string(),
+                        original: component.to_string(),
+                        replacement: abbrev.text.to_string(),
+                        position,
+                    });
+                    Cow::Owned(abbrev.text.to_string())
+                }
+                _ => Cow::Borrowed(first_char(component)),
+            };
+        }
+        position += component.len() + 1;
+    }
+
+    let mut spans = Vec::with_capacity(components.len());
+    let (mut original, mut output) = (0, 0);
+    for (component, short) in zip(&components, &shortened) {
+        let shortened = output..output + short.len();
+        spans.push(Span { original: original..original + component.len(), shortened });
+        original += component.len() + 1;
+        output += short.len() + 1;
+    }
+
+    let text = Cow::Owned(shortened.join("/"));
+    Shortened { text, truncated: false, elided: false, applied, spans }
+}
+
+/// The first character of a path component, keeping the leading dot of hidden files.
+fn first_char(component: &str) -> &str {
+    let skip = usize::from(component.starts_with('.'));
+    let first = width::graphemes(&component[skip..]).next().unwrap_or_default();
+    &component[..skip + first.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::path::shorten_path;
+
+    #[test]
+    fn test_shorten_path() {
+        let abbreviator = Abbreviator::from_lines(["Projects = proj", "!keep work"].into_iter()).unwrap();
+        let shorten = |path, max_length| {
+            shorten_path(&abbreviator, path, max_length, |component| abbreviator.is_kept(component)).text
+        };
+
+        assert_eq!(shorten("~/.config/shorten/abbrev.lst", 30), "~/.config/shorten/abbrev.lst");
+        assert_eq!(shorten("~/.config/shorten/abbrev.lst", 20), "~/.c/s/abbrev.lst");
+        assert_eq!(shorten("~/.config/shorten/abbrev.lst", 25), "~/.c/shorten/abbrev.lst");
+        assert_eq!(shorten("/home/user/Projects/shorten", 10), "/h/u/Proj/shorten");
+        assert_eq!(shorten("/home/user/work/shorten", 10), "/h/u/work/shorten");
+    }
+}
@@ -0,0 +1,116 @@
+//! `shorten repl`: shortening lines as they are typed, to try out rules.
+
+use crate::cli::{ReplArgs, RulesArgs};
+use shorten::abbrev::Abbreviator;
+use shorten::shortener::Shortener;
+use shorten::width;
+use std::io::{stdin, stdout, BufRead, Write};
+
+const HELP: &str = "\
+Type a line to shorten it, or a command:
+  :set len N  shorten to N characters
+  :reload     load the rules again
+  :explain    show which rules fired (again to hide them)
+  :quit       leave (or end the input)";
+
+pub fn repl(rules: &RulesArgs, args: ReplArgs) -> eyre::Result<()> {
+    let shortener = args.options.shortener(rules.load()?, args.desired_max_length)?;
+    let mut session = Session { shortener, max_length: args.desired_max_length, explain: false };
+    println!("{HELP}");
+    session.run(|| rules.load(), stdin().lock(), stdout().lock())
+}
+
+struct Session {
+    shortener: Shortener,
+    max_length: usize,
+    explain: bool,
+}
+
+impl Session {
+    fn run(
+        &mut self,
+        reload: impl Fn() -> eyre::Result<Abbreviator>,
+        mut input: impl BufRead,
+        mut output: impl Write,
+    ) -> eyre::Result<()> {
+        let mut line = String::new();
+        loop {
+            write!(output, "{}> ", self.max_length)?;
+            output.flush()?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                writeln!(output)?;
+                return Ok(());
+            }
+
+            let line = line.trim_end_matches(['\n', '\r']);
+            match line.trim() {
+                ":quit" | ":q" => return Ok(()),
+                ":help" => writeln!(output, "{HELP}")?,
+                ":reload" => match reload() {
+                    Ok(abbreviator) => {
+                        self.shortener.set_abbreviator(abbreviator);
+                        writeln!(output, "Reloaded the rules")?;
+                    }
+                    Err(err) => writeln!(output, "Keeping previous abbreviations: {err:#}")?,
+                },
+                ":explain" => {
+                    self.explain = !self.explain;
+                    let state = if self.explain { "on" } else { "off" };
+                    writeln!(output, "Explanations {state}")?;
+                }
+                command if command.starts_with(":set ") => {
+                    let setting = command[":set ".len()..].split_whitespace().collect::<Vec<_>>();
+                    match setting[..] {
+                        ["len", length] => match length.parse() {
+                            Ok(length) => self.max_length = length,
+                            Err(_) => writeln!(output, "Invalid length '{length}'")?,
+                        },
+                        _ => writeln!(output, "Unknown setting, :help lists them")?,
+                    }
+                }
+                command if command.starts_with(':') => {
+                    writeln!(output, "Unknown command, :help lists them")?;
+                }
+                _ => self.shorten(line, &mut output)?,
+            }
+        }
+    }
+
+    fn shorten(&self, line: &str, output: &mut impl Write) -> eyre::Result<()> {
+        let shortened = self.shortener.shorten_to(line, self.max_length);
+        let length = width::length(&shortened.text);
+        writeln!(output, "{}  ({}/{})", shortened.text, length, self.max_length)?;
+        for rule in shortened.applied.iter().filter(|_| self.explain) {
+            writeln!(
+                output,
+                "  {}: {:?} -> {:?} (rule: {})",
+                rule.position, rule.original, rule.replacement, rule.matcher
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repl::Session;
+    use shorten::abbrev::Abbreviator;
+    use shorten::shortener::Shortener;
+
+    #[test]
+    fn test_repl() {
+        let abbreviator = || Ok(Abbreviator::from_lines(["Architecture = arch"].into_iter())?);
+        let shortener = Shortener::with_abbreviator(10, abbreviator().unwrap()).unwrap();
+        let mut session = Session { shortener, max_length: 30, explain: false };
+        let input = "Architecture Review\n:set len 12\n:explain\n:reload\n\
+                     Architecture Review\n:set width 3\n";
+        let mut output = Vec::new();
+        session.run(abbreviator, input.as_bytes(), &mut output).unwrap();
+        let expected = "30> Architecture Review  (19/30)\n30> 12> Explanations on\n\
+                        12> Reloaded the rules\n12> Arch Review  (11/12)\n\
+                        \x20 0: \"Architecture\" -> \"Arch\" (rule: Architecture)\n\
+                        12> Unknown setting, :help lists them\n12> \n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
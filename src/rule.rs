@@ -0,0 +1,132 @@
+//! The format-independent description of a rule, before it is compiled into
+//! an [`Abbrev`](crate::abbrev::Abbrev).
+
+use crate::case::CasePolicy;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Matcher {
+    /// Case-insensitive match of a word or phrase
+    Exact(String),
+    /// Regex with its flags (`i` for case-insensitive)
+    Regex {
+        pattern: String,
+        #[cfg_attr(feature = "serde", serde(default))]
+        flags: String,
+    },
+    /// Regex matched against whole enclosed segments, like "[Moved to Friday]",
+    /// however many words they span
+    Segment {
+        pattern: String,
+        #[cfg_attr(feature = "serde", serde(default))]
+        flags: String,
+    },
+    /// Regex matched against the whole line, like `^Weekly sync with (.*)$`,
+    /// before its words are abbreviated. `$1` in the replacement stands for a
+    /// group of the match
+    Line {
+        pattern: String,
+        #[cfg_attr(feature = "serde", serde(default))]
+        flags: String,
+    },
+}
+
+impl Matcher {
+    /// The matcher as it would be written in an `abbrev.lst` file.
+    pub fn source(&self) -> String {
+        match self {
+            Matcher::Exact(text) => text.clone(),
+            Matcher::Regex { pattern, flags } => format!("/{pattern}/{flags}"),
+            Matcher::Segment { pattern, flags } => format!("(/{pattern}/{flags})"),
+            // The flags go inside the anchor, which marks whole-line rules
+            Matcher::Line { pattern, flags } if flags.contains('i') => {
+                format!("^(?i){}", pattern.strip_prefix('^').unwrap_or(pattern))
+            }
+            Matcher::Line { pattern, .. } => pattern.clone(),
+        }
+    }
+}
+
+/// A rule, as written in any of the rules formats. With the `serde` feature,
+/// rules serialize to objects with the same fields, and every field but the
+/// matcher can be left out when deserializing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Rule {
+    pub matcher: Matcher,
+    /// The abbreviation; empty to remove the matched text
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub replacement: String,
+    /// More aggressive abbreviations, used in order when the line still doesn't fit
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub escalations: Vec<String>,
+    /// The replacement and escalations to use instead in a locale (`de`,
+    /// `pt-BR`), like the `de:Abt.` of `dept | de:Abt.`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub localized: BTreeMap<String, Vec<String>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub attach_to_previous: bool,
+    /// The abbreviation is glued to the next word, like the `#` of `#5`
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub attach_to_next: bool,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub priority: i32,
+    /// The rule only fires on lines more than this many characters too long
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub min_overflow: usize,
+    /// How the abbreviation follows the case of the matched text, the
+    /// abbreviator's policy if unset
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub case: Option<CasePolicy>,
+    /// The named group the rule belongs to, if any
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub group: Option<String>,
+    /// The rule only applies when one of these contexts is active (always, if empty)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub contexts: Vec<String>,
+    /// Words matching the rule are never altered, and the replacement is unused
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub keep: bool,
+    /// The exact text is an exception to the regex rules and to the rules
+    /// matching inside its words: only exact rules alter it (`!except`)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub except: bool,
+    /// The exact matcher also matches its plurals and possessives, which
+    /// inflect the abbreviation the same way (`Session~ = sesn`)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub inflect: bool,
+    /// What the rule is for, from a `# desc:` comment
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub description: Option<String>,
+    /// The rule still works but is on its way out, and warns the first time
+    /// it fires (`# deprecated`)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub deprecated: bool,
+}
+
+impl Rule {
+    pub fn new(matcher: Matcher, replacement: impl Into<String>) -> Rule {
+        Rule {
+            matcher,
+            replacement: replacement.into(),
+            escalations: Vec::new(),
+            localized: BTreeMap::new(),
+            attach_to_previous: false,
+            attach_to_next: false,
+            priority: 0,
+            min_overflow: 0,
+            case: None,
+            group: None,
+            contexts: Vec::new(),
+            keep: false,
+            except: false,
+            inflect: false,
+            description: None,
+            deprecated: false,
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Regression tests kept next to the rules, as comments of the rules file:
+//!
+//! ```text
+//! Architecture = arch
+//! Section = <+課
+//! #test "Architecture Section" => "Arch課"
+//! #test 20 "Architecture Section Notes" => "Arch課 Notes"
+//! ```
+//!
+//! A test shortens its text to the given length, or to the length of the
+//! expected text by default, and fails unless the result is the expected text.
+//! `shorten test` runs the tests of the rules file and of the files it
+//! includes.
+
+use crate::abbrev;
+use crate::error::{ParseErrorKind, Result, ShortenError};
+use crate::shortener::Shortener;
+use crate::width;
+use std::path::{Path, PathBuf};
+
+const DIRECTIVE: &str = "#test ";
+
+/// A `#test` line of a rules file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleTest {
+    /// The file the test is in, when known
+    pub file: Option<PathBuf>,
+    /// 1-based line number of the test
+    pub line: usize,
+    /// The length to shorten to, when not the length of the expected text
+    pub max_length: Option<usize>,
+    pub text: String,
+    pub expected: String,
+}
+
+impl RuleTest {
+    /// Shortens the text of the test, returning what it was shortened to when
+    /// that isn't the expected text.
+    pub fn run(&self, shortener: &Shortener) -> std::result::Result<(), String> {
+        let max_length = self.max_length.unwrap_or_else(|| width::length(&self.expected));
+        let shortened = shortener.shorten_to(&self.text, max_length).text;
+        match shortened == self.expected {
+            true => Ok(()),
+            false => Err(shortened.into_owned()),
+        }
+    }
+}
+
+/// Reads the tests of the lines of a rules file.
+pub fn parse(text: &str) -> Result<Vec<RuleTest>> {
+    let mut tests = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let Some(test) = line.trim().strip_prefix(DIRECTIVE) else {
+            continue;
+        };
+        let (max_length, text, expected) = parse_test(test.trim())
+            .ok_or_else(|| ShortenError::from(ParseErrorKind::InvalidTest).at_line(index))?;
+        tests.push(RuleTest { file: None, line: index + 1, max_length, text, expected });
+    }
+    Ok(tests)
+}
+
+/// Reads the tests of a rules file and of the files it includes, in order.
+pub fn load(path: &Path) -> Result<Vec<RuleTest>> {
+    let mut tests = Vec::new();
+    load_file(path, &mut Vec::new(), &mut tests)?;
+    Ok(tests)
+}
+
+fn load_file(path: &Path, loaded: &mut Vec<PathBuf>, tests: &mut Vec<RuleTest>) -> Result<()> {
+    let canonical = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    // Include cycles are reported when loading the rules
+    if loaded.contains(&canonical) {
+        return Ok(());
+    }
+    loaded.push(canonical);
+
+    let file = std::fs::read_to_string(path)
+        .map_err(|source| ShortenError::Io { path: path.to_path_buf(), source })?;
+    let file = abbrev::strip_bom(&file);
+    let parsed = parse(file).map_err(|err| err.in_file(path))?;
+    let file_path = Some(path.to_path_buf());
+    tests.extend(parsed.into_iter().map(|test| RuleTest { file: file_path.clone(), ..test }));
+    if path.extension().is_some_and(|extension| extension == "toml") {
+        return Ok(());
+    }
+    for (index, line) in file.lines().enumerate() {
+        let Some(include) = line.trim().strip_prefix("@include") else {
+            continue;
+        };
+        let included = abbrev::resolve_include(path, Path::new(include.trim()))
+            .map_err(|err| err.at_line(index).in_file(path))?;
+        load_file(&included, loaded, tests)?;
+    }
+    Ok(())
+}
+
+/// The length, text and expected text of `[LENGTH] "text" => "expected"`.
+fn parse_test(test: &str) -> Option<(Option<usize>, String, String)> {
+    let (max_length, test) = match test.split_once(' ') {
+        Some((length, rest)) if length.bytes().all(|b| b.is_ascii_digit()) => {
+            (Some(length.parse().ok()?), rest.trim_start())
+        }
+        _ => (None, test),
+    };
+    let (text, rest) = quoted(test)?;
+    let (expected, rest) = quoted(rest.trim_start().strip_prefix("=>")?.trim_start())?;
+    rest.trim().is_empty().then_some((max_length, text, expected))
+}
+
+/// The double-quoted string at the start of the text, where `\"` and `\\`
+/// stand for a quote and a backslash, and what follows it.
+fn quoted(text: &str) -> Option<(String, &str)> {
+    let mut chars = text.strip_prefix('"')?.char_indices();
+    let mut string = String::new();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((string, &text[index + 2..])),
+            '\\' => string.push(chars.next().map(|(_, c)| c)?),
+            c => string.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::rule_tests::parse;
+    use crate::shortener::Shortener;
+
+    #[test]
+    fn test_rule_tests() {
+        let rules = "Architecture = arch\n\
+                     Section = <+課\n\
+                     #test \"Architecture Section\" => \"Arch課\"\n\
+                     # A comment\n\
+                     #test  20 \"Architecture Section Notes\" => \"Arch Section Notes\"\n\
+                     #test \"Say \\\"Section\\\"\" => \"Say \\\"Section\\\"\"\n";
+        let tests = parse(rules).unwrap();
+        let lines = tests.iter().map(|test| (test.line, test.max_length)).collect::<Vec<_>>();
+        assert_eq!(lines, [(3, None), (5, Some(20)), (6, None)]);
+        assert_eq!(tests[2].text, "Say \"Section\"");
+
+        let abbreviator = Abbreviator::from_lines(rules.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(60, abbreviator).unwrap();
+        assert_eq!(tests[0].run(&shortener), Ok(()));
+        assert_eq!(tests[1].run(&shortener), Err("Arch課 Notes".to_string()));
+        assert_eq!(tests[2].run(&shortener), Ok(()));
+
+        let err = parse("#test \"Section\" => 課\n").unwrap_err();
+        let expected = "line 1: Invalid test, expected #test [LENGTH] \"text\" => \"shortened\"";
+        assert_eq!(err.to_string(), expected);
+    }
+}
@@ -0,0 +1,115 @@
+//! The preprocessing of `--expand-tabs` and `--strip-controls`, run on a line
+//! before it is measured: a tab takes no column of its own and control
+//! characters none at all, while terminals and status bars show them as
+//! anything from several columns to garbage.
+
+use crate::ansi;
+use crate::width;
+use std::ops::Range;
+
+/// A text with its tabs expanded or its control characters dropped.
+pub(crate) struct Sanitized {
+    pub text: String,
+    /// The byte ranges of the replaced characters in the sanitized text and
+    /// in the original one, in order
+    edits: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl Sanitized {
+    /// The byte offset in the original text of an offset in the sanitized text.
+    pub fn original_offset(&self, offset: usize) -> usize {
+        let Some((sanitized, original)) = self.edits.iter().rfind(|(edit, _)| edit.start <= offset)
+        else {
+            return offset;
+        };
+        match offset < sanitized.end {
+            true => original.start,
+            false => original.end + offset - sanitized.end,
+        }
+    }
+}
+
+/// Expands the tabs to the next stop every `tab_width` columns and drops the
+/// control characters, or returns `None` when the text is left as it is. The
+/// escape sequences of the text are dropped whole, unless they are kept for
+/// `--ansi`; a tab that isn't expanded becomes a space.
+pub(crate) fn sanitize(
+    text: &str,
+    tab_width: Option<usize>,
+    strip_controls: bool,
+    keep_escapes: bool,
+) -> Option<Sanitized> {
+    let is_replaced = |c: char| match c {
+        '\t' => tab_width.is_some() || strip_controls,
+        '\x1b' => strip_controls && !keep_escapes,
+        c => strip_controls && c.is_control(),
+    };
+    if !text.contains(is_replaced) {
+        return None;
+    }
+
+    let mut sanitized = String::with_capacity(text.len());
+    let mut edits = Vec::new();
+    let mut copied = 0;
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        if !is_replaced(c) {
+            continue;
+        }
+        sanitized.push_str(&text[copied..index]);
+        let end = match c {
+            '\x1b' => index + ansi::sequence_len(&text[index..]),
+            c => index + c.len_utf8(),
+        };
+        let start = sanitized.len();
+        match (c, tab_width) {
+            ('\t', Some(tab_width)) => {
+                let tab_width = tab_width.max(1);
+                let line = sanitized.rsplit('\n').next().unwrap_or_default();
+                let column = match ansi::strip(line) {
+                    Some(stripped) => width::length(&stripped.plain),
+                    None => width::length(line),
+                };
+                sanitized.extend(std::iter::repeat_n(' ', tab_width - column % tab_width));
+            }
+            ('\t', None) => sanitized.push(' '),
+            _ => {}
+        }
+        edits.push((start..sanitized.len(), index..end));
+        while chars.offset() < end {
+            chars.next();
+        }
+        copied = end;
+    }
+    sanitized.push_str(&text[copied..]);
+    Some(Sanitized { text: sanitized, edits })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::sanitize::sanitize;
+    use crate::shortener::Shortener;
+
+    #[test]
+    fn test_sanitize() {
+        let expand = |text| sanitize(text, Some(4), false, false).map(|sanitized| sanitized.text);
+        assert_eq!(expand("a\tbc\t課\td").as_deref(), Some("a   bc  課  d"));
+        assert_eq!(expand("abc\r\x07"), None);
+
+        let text = "\x1b[1mArch\x1b[0m\tSesn\r\x07";
+        let stripped = sanitize(text, None, true, false).unwrap();
+        assert_eq!(stripped.text, "Arch Sesn");
+        assert_eq!(stripped.original_offset(5), 13);
+        let stripped = sanitize(text, Some(8), true, true).unwrap();
+        assert_eq!(stripped.text, "\x1b[1mArch\x1b[0m    Sesn");
+
+        let abbreviator = Abbreviator::from_lines(["Architecture = arch"].into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(15, abbreviator).unwrap().expand_tabs(Some(8));
+        let shortened = shortener.shorten_detailed("Sesn\tArchitecture");
+        assert_eq!(shortened.text, "Sesn Arch");
+        assert_eq!(shortened.applied[0].position, 5);
+        let shortener = shortener.expand_tabs(None).strip_controls(true);
+        assert_eq!(shortener.shorten("Architecture\x07\tReview\r"), "Arch Review");
+    }
+}
@@ -1,126 +1,1970 @@
-use crate::abbrev::{Abbreviation, Abbreviator};
-use eyre::Context;
+use crate::abbrev::{Abbreviation, Abbreviator, WordPriority};
+use crate::ansi;
+use crate::cache::LruCache;
+use crate::case::{CasePolicy, CaseRules, TextCase};
+#[cfg(feature = "cjk")]
+use crate::cjk;
+use crate::config;
+use crate::dates;
+use crate::enclosing::Enclosing;
+use crate::error::{ParseErrorKind, Result};
+use crate::git_ref;
+use crate::numbers;
+use crate::path;
+use crate::sanitize;
+use crate::stop_words::StopWords;
+use crate::subject;
+use crate::url;
+use crate::width;
+use chrono::format::{Item, StrftimeItems};
+use itertools::{Either, Itertools};
+use rayon::prelude::*;
 use std::borrow::Cow;
-use xdg::BaseDirectories;
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::iter::{self, zip};
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
 
+/// A custom pass over the words of lines that are still too long after the
+/// rules, for what rules can't express (like compacting the ticket IDs of one
+/// tracker): it gets a word no rule replaced and how many columns the line
+/// still has to lose, and returns what to replace the word with, if anything.
+pub type WordHook = Arc<dyn Fn(&str, usize) -> Option<String> + Send + Sync>;
+
+/// How the shortener chooses which of the available abbreviations to apply.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Abbreviate every word that has a matching rule.
+    #[default]
+    All,
+    /// Apply abbreviations one at a time and stop as soon as the line fits,
+    /// keeping as much of the original text as possible. Higher priority
+    /// rules are applied first, otherwise abbreviations go left-to-right.
+    Minimal,
+    /// Apply the fewest abbreviations that make the line fit, and among those
+    /// the ones that keep the most characters, wherever they are in the line.
+    Optimal,
+}
+
+/// What kind of text the shortener is dealing with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Free text, abbreviated word by word.
+    #[default]
+    Text,
+    /// Filesystem paths, where intermediate directories are shortened
+    /// fish-style and the last component is kept intact.
+    Path,
+    /// Code identifiers, whose words are also split at their case changes
+    /// ("parseHttpResponse") on top of the separators ("parse_http_response"),
+    /// and joined back the same way. The abbreviations follow the case of
+    /// each word, as in free text.
+    Identifier,
+    /// URLs, which lose their scheme, "www." and query before their path is
+    /// abbreviated, the host being kept intact.
+    Url,
+    /// Mail subjects, whose reply and forward prefixes ("Re: Re: Fwd:")
+    /// collapse into one and whose tags (`[EXTERNAL]`) are kept once each.
+    Subject,
+    /// Git branches and other refs, whose ticket ID ("feature/JIRA-1234-…")
+    /// is kept intact while the words around it are abbreviated.
+    GitRef,
+}
+
+/// Which end of the line is protected when words have to be dropped or cut.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Anchor {
+    /// Keep the start, like for sentences: words are dropped from the end
+    /// first, and truncation cuts the end.
+    Left,
+    /// Keep the end, like for paths and breadcrumbs: words are dropped from
+    /// the start first, and truncation cuts the start.
+    Right,
+}
+
+/// Collapsing runs of consecutive unabbreviated words into their initials,
+/// e.g. "Quarterly Business Review" into "QBR".
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Acronyms {
+    /// Shorter runs of words are left alone
+    pub min_words: usize,
+    /// Longer runs are split
+    pub max_words: usize,
+}
+
+impl Default for Acronyms {
+    fn default() -> Self {
+        Acronyms { min_words: 2, max_words: 4 }
+    }
+}
+
+/// Writing the dates and times of a line compactly, e.g. "September 25,
+/// 2024 10:00 AM - 11:00 AM" as "Sep 25 10–11am".
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dates {
+    format: String,
+    clock: Clock,
+}
+
+/// How the dates pass writes times.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Clock {
+    /// 10–11am, 9:30am–1pm
+    #[default]
+    TwelveHour,
+    /// 10:00–11:00, 9:30–13:00
+    TwentyFourHour,
+}
+
+/// The format of dates by default, like Sep 25
+pub const DEFAULT_DATE_FORMAT: &str = "%b %-d";
+
+impl Dates {
+    /// Writes dates in a strftime `format` and times on the `clock`.
+    pub fn new(format: &str, clock: Clock) -> Result<Dates> {
+        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+            return Err(ParseErrorKind::InvalidDateFormat(format.to_string()).into());
+        }
+        Ok(Dates { format: format.to_string(), clock })
+    }
+}
+
+impl Default for Dates {
+    fn default() -> Self {
+        Dates { format: DEFAULT_DATE_FORMAT.to_string(), clock: Clock::default() }
+    }
+}
+
+/// Shortens lines with the rules of an [`Abbreviator`] and the passes it is
+/// configured with.
+///
+/// A shortener is `Send` and `Sync`, so one loaded ruleset can serve many
+/// threads, and cheap to clone: the clones share the compiled rules and the
+/// stop words, and only get a cache of their own.
+#[derive(Clone)]
 pub struct Shortener {
     desired_max_length: usize,
     abbreviator: Abbreviator,
+    strategy: Strategy,
+    mode: Mode,
+    stop_words: Option<Arc<StopWords>>,
+    numbers: bool,
+    dates: Option<Dates>,
+    drop_vowels: Option<usize>,
+    acronyms: Option<Acronyms>,
+    /// Lowercase words that are never altered, on top of the `!keep` rules
+    kept: HashSet<String>,
+    preserve_spacing: bool,
+    preserve_unmatched: bool,
+    idempotent: bool,
+    /// What blank lines are shortened to, instead of themselves
+    blank_placeholder: Option<String>,
+    /// The columns between tab stops, when tabs are expanded
+    expand_tabs: Option<usize>,
+    strip_controls: bool,
+    separators: Vec<char>,
+    truncate: bool,
+    elision_marker: Option<String>,
+    anchor: Option<Anchor>,
+    unicode_spaces: bool,
+    #[cfg(feature = "cjk")]
+    cjk_segmentation: bool,
+    /// The custom passes with their names, in the order they run
+    hooks: Vec<(String, WordHook)>,
+    /// Replaces the `@case` policy of the rules, even after they are reloaded
+    case: Option<CasePolicy>,
+    ansi: bool,
+    /// Recently shortened texts
+    cache: Option<Cache>,
+}
+
+/// A shortened text, with its maximum length and anchor.
+type CacheKey = (String, usize, Option<Anchor>);
+
+/// The result of shortening a line, along with details on how it was shortened.
+#[derive(Clone)]
+pub struct Shortened<'a> {
+    pub text: Cow<'a, str>,
+    /// Whether the text had to be cut because abbreviations were not enough
+    pub truncated: bool,
+    /// Whether words were dropped from the text, e.g. by remove rules or stop words
+    pub elided: bool,
+    /// The rules that were applied, in output order
+    pub applied: Vec<AppliedRule>,
+    /// Where the words of the original text ended up in the shortened text
+    pub(crate) spans: Vec<Span>,
+}
+
+/// A word or phrase of the original text and the text that replaced it, as
+/// byte ranges. Dropped words are replaced by an empty range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Span {
+    pub original: Range<usize>,
+    pub shortened: Range<usize>,
+}
+
+/// A rule that fired while shortening a line.
+#[derive(Clone)]
+pub struct AppliedRule {
+    /// The matcher of the rule, as written in the rules
+    pub matcher: String,
+    /// The original text that was replaced
+    pub original: String,
+    pub replacement: String,
+    /// Byte offset of the replaced text in the original line
+    pub position: usize,
+}
+
+/// A change from the original text to the shortened one: the text of the
+/// `original` byte range was replaced by `replacement`, found at the
+/// `shortened` byte range of the shortened text. Dropped text has an empty
+/// replacement.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edit {
+    pub original: Range<usize>,
+    pub shortened: Range<usize>,
+    pub replacement: String,
+}
+
+impl<'a> Shortened<'a> {
+    /// A part of the text, as it is.
+    pub(crate) fn unchanged(text: &'a str, part: &'a str) -> Shortened<'a> {
+        let start = part.as_ptr() as usize - text.as_ptr() as usize;
+        Shortened {
+            text: Cow::Borrowed(part),
+            truncated: false,
+            elided: false,
+            applied: vec![],
+            spans: vec![Span { original: start..start + part.len(), shortened: 0..part.len() }],
+        }
+    }
+
+    /// A text standing for the first `length` bytes of the original one.
+    pub(crate) fn replacing(length: usize, text: Cow<'a, str>) -> Shortened<'a> {
+        let shortened = 0..text.len();
+        Shortened {
+            text,
+            truncated: false,
+            elided: false,
+            applied: vec![],
+            spans: vec![Span { original: 0..length, shortened }],
+        }
+    }
+
+    /// The parts of a text shortened separately, each with its byte offset in
+    /// the original text, put back together in order.
+    pub(crate) fn concat(parts: impl IntoIterator<Item = (usize, Shortened<'a>)>) -> Shortened<'a> {
+        let mut text = String::new();
+        let (mut truncated, mut elided) = (false, false);
+        let (mut applied, mut spans) = (Vec::new(), Vec::new());
+        for (offset, part) in parts {
+            let output = text.len();
+            text.push_str(&part.text);
+            (truncated, elided) = (truncated || part.truncated, elided || part.elided);
+            let shift = |rule: AppliedRule| AppliedRule { position: offset + rule.position, ..rule };
+            applied.extend(part.applied.into_iter().map(shift));
+            spans.extend(part.spans.into_iter().map(|span| Span {
+                original: span.original.start + offset..span.original.end + offset,
+                shortened: span.shortened.start + output..span.shortened.end + output,
+            }));
+        }
+        Shortened { text: Cow::Owned(text), truncated, elided, applied, spans }
+    }
+
+    /// The edits turning the original text into the shortened one, in order:
+    /// one for each changed word or phrase and for the changed spaces between
+    /// them, the adjacent deletions merged so a word and the space dropped with
+    /// it make one edit. Lines rewritten as a whole, by `--ansi`, idempotent
+    /// passes or the fallback command, are a single edit.
+    pub fn edits(&self, original: &str) -> Vec<Edit> {
+        let mut edits: Vec<Edit> = Vec::new();
+        let mut push = |original_range: Range<usize>, shortened: Range<usize>| {
+            let replacement = &self.text[shortened.clone()];
+            if original[original_range.clone()] == *replacement {
+                return;
+            }
+            match edits.last_mut() {
+                Some(last)
+                    if last.original.end == original_range.start
+                        && last.replacement.is_empty()
+                        && replacement.is_empty() =>
+                {
+                    last.original.end = original_range.end;
+                }
+                _ => edits.push(Edit {
+                    original: original_range,
+                    shortened,
+                    replacement: replacement.to_string(),
+                }),
+            }
+        };
+
+        let in_order = self.spans.iter().zip(self.spans.iter().skip(1)).all(|(span, next)| {
+            span.original.end <= next.original.start && span.shortened.end <= next.shortened.start
+        });
+        if !in_order {
+            push(0..original.len(), 0..self.text.len());
+            return edits;
+        }
+        let (mut original_end, mut shortened_end) = (0, 0);
+        for span in &self.spans {
+            // The spaces and enclosing characters between the words
+            push(original_end..span.original.start, shortened_end..span.shortened.start);
+            push(span.original.clone(), span.shortened.clone());
+            (original_end, shortened_end) = (span.original.end, span.shortened.end);
+        }
+        push(original_end..original.len(), shortened_end..self.text.len());
+        edits
+    }
+
+    /// The same result without borrowing from the original text.
+    pub fn into_owned(self) -> Shortened<'static> {
+        Shortened {
+            text: Cow::Owned(self.text.into_owned()),
+            truncated: self.truncated,
+            elided: self.elided,
+            applied: self.applied,
+            spans: self.spans,
+        }
+    }
+}
+
+impl Shortener {
+    pub fn new(desired_max_length: usize) -> Result<Shortener> {
+        let abbreviator = config::load_abbreviator(None)?;
+        Self::with_abbreviator(desired_max_length, abbreviator)
+    }
+
+    pub fn with_abbreviator(
+        desired_max_length: usize,
+        abbreviator: Abbreviator,
+    ) -> Result<Shortener> {
+        Ok(Shortener {
+            desired_max_length,
+            abbreviator,
+            strategy: Strategy::default(),
+            mode: Mode::default(),
+            stop_words: None,
+            numbers: false,
+            dates: None,
+            drop_vowels: None,
+            acronyms: None,
+            kept: HashSet::new(),
+            preserve_spacing: false,
+            preserve_unmatched: false,
+            idempotent: false,
+            blank_placeholder: None,
+            expand_tabs: None,
+            strip_controls: false,
+            separators: DEFAULT_SEPARATORS.to_vec(),
+            truncate: false,
+            elision_marker: None,
+            anchor: None,
+            unicode_spaces: false,
+            #[cfg(feature = "cjk")]
+            cjk_segmentation: false,
+            hooks: Vec::new(),
+            case: None,
+            ansi: false,
+            cache: None,
+        })
+    }
+
+    /// The rules used for shortening.
+    pub fn abbreviator(&self) -> &Abbreviator {
+        &self.abbreviator
+    }
+
+    /// Replaces the rules used for shortening, e.g. after the rules file changed.
+    pub fn set_abbreviator(&mut self, abbreviator: Abbreviator) {
+        self.abbreviator = abbreviator;
+        if let Some(case) = self.case {
+            self.abbreviator.set_case(case);
+        }
+        if let Some(cache) = &self.cache {
+            lock(&cache.0).clear();
+        }
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Write spelled-out numbers in digits ("twenty-third" as 23rd) and drop
+    /// thousands separators, left to right, in lines that are still too long
+    /// after abbreviation. This pass comes before the ones that lose words.
+    pub fn numbers(mut self, numbers: bool) -> Self {
+        self.numbers = numbers;
+        self
+    }
+
+    /// Rewrite the dates and times of lines that are too long, before
+    /// abbreviating their words. Lines rewritten by a whole-line rule are left
+    /// to it.
+    pub fn dates(mut self, dates: Option<Dates>) -> Self {
+        self.dates = dates;
+        self
+    }
+
+    /// Drop these words, left to right, from lines that are still too long
+    /// after abbreviation.
+    pub fn stop_words(mut self, stop_words: Option<StopWords>) -> Self {
+        self.stop_words = stop_words.map(Arc::new);
+        self
+    }
+
+    /// Drop the vowels of unabbreviated words longer than `min_length`, left to
+    /// right, in lines that are still too long after abbreviation and dropping
+    /// stop words.
+    pub fn drop_vowels(mut self, min_length: Option<usize>) -> Self {
+        self.drop_vowels = min_length;
+        self
+    }
+
+    /// Collapse words into acronyms, left to right, in lines that are still
+    /// too long after all the other passes.
+    ///
+    /// Words that are already short, enclosed or abbreviated are kept as-is
+    /// and break up the runs of words.
+    pub fn acronyms(mut self, acronyms: Option<Acronyms>) -> Self {
+        self.acronyms = acronyms;
+        self
+    }
+
+    /// Adds a custom pass, which runs right after the rules and their
+    /// escalations, after the hooks added before it. The words it replaces
+    /// are reported as replaced by the rule `<name>`.
+    pub fn hook(
+        mut self,
+        name: &str,
+        hook: impl Fn(&str, usize) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.push((name.to_string(), Arc::new(hook)));
+        self
+    }
+
+    /// Never alter these words (e.g. proper nouns and ticket IDs), in addition
+    /// to the ones protected by `!keep` rules. Kept words are left out of every
+    /// pass, and truncation drops them whole rather than cutting them.
+    pub fn keep<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, words: I) -> Self {
+        self.kept = words.into_iter().map(|word| word.as_ref().to_lowercase()).collect();
+        self
+    }
+
+    /// Only replace the abbreviated words in the original line, keeping its
+    /// tabs, runs of spaces and leading and trailing whitespace, instead of
+    /// joining all the words with single spaces.
+    pub fn preserve_spacing(mut self, preserve_spacing: bool) -> Self {
+        self.preserve_spacing = preserve_spacing;
+        self
+    }
+
+    /// Return the lines where no rule fires and that need no truncation
+    /// exactly as they are, even when they are too long, instead of with
+    /// their whitespace normalized.
+    pub fn preserve_unmatched(mut self, preserve_unmatched: bool) -> Self {
+        self.preserve_unmatched = preserve_unmatched;
+        self
+    }
+
+    /// Shorten the lines that are still too long again, until they stop
+    /// changing, so that shortening the output once more (e.g. in a pipeline
+    /// that processes it twice) leaves it as it is. The rules applied by the
+    /// later passes are located at the start of the line.
+    pub fn idempotent(mut self, idempotent: bool) -> Self {
+        self.idempotent = idempotent;
+        self
+    }
+
+    /// Characters that split words that no rule matches as a whole, like the
+    /// `/` in "Architecture/Section" or the `-` in "Learning-Session", so each
+    /// part can be abbreviated on its own. The separators are kept in the
+    /// output. Defaults to `/`, `|`, `:`, `·`, `-` and `_`.
+    pub fn separators(mut self, separators: &str) -> Self {
+        self.separators = separators.chars().collect();
+        self
+    }
+
+    /// Cut lines that still exceed the desired length after abbreviation.
+    pub fn truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Append this marker (like `…`) to lines that lost words or were cut, so
+    /// the reader can tell that text was elided. Truncation leaves room for it.
+    pub fn elision_marker(mut self, marker: Option<String>) -> Self {
+        self.elision_marker = marker;
+        self
+    }
+
+    /// Protect one end of the line from the passes dropping or altering words
+    /// and from truncation. By default, words are dropped left to right and
+    /// truncation keeps the start of the line.
+    pub fn anchor(mut self, anchor: Option<Anchor>) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// Treat no-break spaces (U+00A0, U+2007 and U+202F) as part of the words
+    /// they join, so "10\u{a0}km" is never split or dropped in half, and put
+    /// ideographic spaces (U+3000) back between words instead of ASCII spaces.
+    pub fn unicode_spaces(mut self, unicode_spaces: bool) -> Self {
+        self.unicode_spaces = unicode_spaces;
+        self
+    }
+
+    /// Look for the CJK words of the rules inside unspaced CJK text, so
+    /// `定例会議 = 定例` also shortens "第3回定例会議のお知らせ". The text around
+    /// the words found is kept as-is.
+    #[cfg(feature = "cjk")]
+    pub fn cjk_segmentation(mut self, cjk_segmentation: bool) -> Self {
+        self.cjk_segmentation = cjk_segmentation;
+        self
+    }
+
+    /// The case policy of the rules that don't have their own, instead of the
+    /// one the rules set with `@case`.
+    pub fn case(mut self, case: Option<CasePolicy>) -> Self {
+        self.case = case;
+        if let Some(case) = case {
+            self.abbreviator.set_case(case);
+        }
+        self
+    }
+
+    /// Shorten blank lines (empty or whitespace-only) to this text. Without a
+    /// placeholder, they are kept as they are when they fit (or with
+    /// [`Shortener::preserve_unmatched`]) and shortened to an empty line
+    /// otherwise, whatever the mode and the other passes.
+    pub fn blank_placeholder(mut self, placeholder: Option<String>) -> Self {
+        self.blank_placeholder = placeholder;
+        self
+    }
+
+    /// Replace the tabs of the text with spaces up to the next stop every
+    /// `tab_width` columns before measuring it, like a terminal shows them.
+    pub fn expand_tabs(mut self, tab_width: Option<usize>) -> Self {
+        self.expand_tabs = tab_width;
+        self
+    }
+
+    /// Drop the control characters of the text, like carriage returns and
+    /// bells, before measuring it, with the escape sequences they start unless
+    /// they are kept with [`Shortener::ansi`]. Tabs that aren't expanded become
+    /// spaces.
+    pub fn strip_controls(mut self, strip_controls: bool) -> Self {
+        self.strip_controls = strip_controls;
+        self
+    }
+
+    /// Leave the ANSI escape sequences of the text, like the colors of prompt
+    /// strings, out of the length, and put them back around the shortened
+    /// words instead of abbreviating or cutting through them.
+    pub fn ansi(mut self, ansi: bool) -> Self {
+        self.ansi = ansi;
+        self
+    }
+
+    /// Remember the results for the last `capacity` texts (and lengths), so
+    /// texts that keep coming back, like in a status bar, are only shortened
+    /// once. The cache is shared by all the threads shortening with it.
+    pub fn cache(mut self, capacity: usize) -> Self {
+        self.cache = (capacity > 0).then(|| Cache(Mutex::new(LruCache::new(capacity))));
+        self
+    }
+
+    pub fn shorten<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        self.shorten_detailed(text).text
+    }
+
+    /// Appends the shortened text to `out`, for callers writing many lines to
+    /// one buffer. Lines that need no shortening are copied without allocating,
+    /// but a shortened line is still built in a string of its own first.
+    pub fn shorten_into(&self, text: &str, out: &mut String) {
+        out.push_str(&self.shorten(text));
+    }
+
+    /// Shortens many texts in parallel, returning the results in the same order.
+    pub fn shorten_batch<'a, S: AsRef<str> + Sync>(&self, texts: &'a [S]) -> Vec<Cow<'a, str>> {
+        texts.par_iter().map(|text| self.shorten(text.as_ref())).collect()
+    }
+
+    pub fn shorten_detailed<'a>(&self, text: &'a str) -> Shortened<'a> {
+        self.shorten_to(text, self.desired_max_length)
+    }
+
+    /// Shortens the text along with the edits that made the shortened text
+    /// out of it, for editors mapping cursor positions or highlighting the
+    /// abbreviated parts.
+    pub fn shorten_with_spans<'a>(&self, text: &'a str) -> (Cow<'a, str>, Vec<Edit>) {
+        let shortened = self.shorten_detailed(text);
+        let edits = shortened.edits(text);
+        (shortened.text, edits)
+    }
+
+    /// Shortens the text to `max_length` instead of the shortener's desired length.
+    pub fn shorten_to<'a>(&self, text: &'a str, max_length: usize) -> Shortened<'a> {
+        self.shorten_anchored(text, max_length, self.anchor)
+    }
+
+    /// Shortens the text to `max_length`, protecting the given end of the line
+    /// instead of the shortener's.
+    pub fn shorten_anchored<'a>(
+        &self,
+        text: &'a str,
+        max_length: usize,
+        anchor: Option<Anchor>,
+    ) -> Shortened<'a> {
+        let Some(cache) = &self.cache else {
+            return self.shorten_passes(text, max_length, anchor);
+        };
+
+        let key = (text.to_string(), max_length, anchor);
+        if let Some(shortened) = lock(&cache.0).get(&key) {
+            return shortened;
+        }
+        let shortened = self.shorten_passes(text, max_length, anchor);
+        lock(&cache.0).insert(key, shortened.clone().into_owned());
+        shortened
+    }
+
+    /// Shortens the text once, or until it stops changing when idempotent.
+    fn shorten_passes<'a>(
+        &self,
+        text: &'a str,
+        max_length: usize,
+        anchor: Option<Anchor>,
+    ) -> Shortened<'a> {
+        if text.trim().is_empty() {
+            return self.shorten_blank(text, max_length);
+        }
+        let first = self.shorten_uncached(text, max_length, anchor);
+        if !self.idempotent {
+            return first;
+        }
+        let mut shortened = first.clone();
+        for _ in 0..MAX_PASSES {
+            let again = self.shorten_uncached(&shortened.text, max_length, anchor).into_owned();
+            if again.text == shortened.text {
+                break;
+            }
+            let applied = again.applied.into_iter().map(|rule| AppliedRule { position: 0, ..rule });
+            shortened.applied.extend(applied);
+            shortened.text = again.text;
+            shortened.truncated |= again.truncated;
+            shortened.elided |= again.elided;
+        }
+        if shortened.text == first.text {
+            return first;
+        }
+        let span = Span { original: 0..text.len(), shortened: 0..shortened.text.len() };
+        Shortened { spans: vec![span], ..shortened }
+    }
+
+    fn shorten_blank<'a>(&self, text: &'a str, max_length: usize) -> Shortened<'a> {
+        if let Some(placeholder) = &self.blank_placeholder {
+            return Shortened::replacing(text.len(), Cow::Owned(placeholder.clone()));
+        }
+        match self.preserve_unmatched || width::length(text) <= max_length {
+            true => Shortened::unchanged(text, text),
+            false => Shortened::unchanged(text, &text[..0]),
+        }
+    }
+
+    fn shorten_uncached<'a>(
+        &self,
+        text: &'a str,
+        max_length: usize,
+        anchor: Option<Anchor>,
+    ) -> Shortened<'a> {
+        let sanitized = sanitize::sanitize(text, self.expand_tabs, self.strip_controls, self.ansi);
+        let Some(sanitized) = sanitized else {
+            return self.shorten_styled(text, max_length, anchor);
+        };
+
+        let shortened = self.shorten_styled(&sanitized.text, max_length, anchor).into_owned();
+        let offset = |offset| sanitized.original_offset(offset);
+        let applied = shortened
+            .applied
+            .into_iter()
+            .map(|rule| AppliedRule { position: offset(rule.position), ..rule })
+            .collect();
+        let spans = shortened.spans.into_iter().map(|span| Span {
+            original: offset(span.original.start)..offset(span.original.end),
+            shortened: span.shortened,
+        });
+        Shortened { applied, spans: spans.collect(), ..shortened }
+    }
+
+    /// Shortens a text that may have escape sequences.
+    fn shorten_styled<'a>(
+        &self,
+        text: &'a str,
+        max_length: usize,
+        anchor: Option<Anchor>,
+    ) -> Shortened<'a> {
+        let Some(stripped) = self.ansi.then(|| ansi::strip(text)).flatten() else {
+            return self.shorten_plain(text, max_length, anchor);
+        };
+
+        let shortened = self.shorten_plain(&stripped.plain, max_length, anchor);
+        let applied = shortened
+            .applied
+            .into_iter()
+            .map(|rule| AppliedRule { position: stripped.original_offset(rule.position), ..rule })
+            .collect();
+        let styled = ansi::restyle(&stripped, &shortened.text, &shortened.spans);
+        let span = Span { original: 0..text.len(), shortened: 0..styled.len() };
+        Shortened {
+            text: Cow::Owned(styled),
+            truncated: shortened.truncated,
+            elided: shortened.elided,
+            applied,
+            spans: vec![span],
+        }
+    }
+
+    /// Shortens a text without escape sequences.
+    fn shorten_plain<'a>(
+        &self,
+        text: &'a str,
+        max_length: usize,
+        anchor: Option<Anchor>,
+    ) -> Shortened<'a> {
+        let mut shortened = match self.mode {
+            Mode::Text | Mode::Identifier => self.abbreviate_line(text, max_length, anchor),
+            Mode::Path => {
+                path::shorten_path(&self.abbreviator, text, max_length, |word| self.is_kept(word))
+            }
+            Mode::Url => url::shorten_url(text, max_length, |part, max_length| {
+                self.abbreviate_line(part, max_length, anchor)
+            }),
+            Mode::Subject => subject::shorten_subject(text, max_length, |part, max_length| {
+                self.abbreviate_line(part, max_length, anchor)
+            }),
+            Mode::GitRef => git_ref::shorten_git_ref(text, max_length, |part, max_length| {
+                self.abbreviate_line(part, max_length, anchor)
+            }),
+        };
+        if let Some(fallback) = self.abbreviator.fallback() {
+            if width::length(&shortened.text) > max_length {
+                if let Some(replacement) = fallback.run(text, max_length) {
+                    shortened = Shortened::replacing(text.len(), Cow::Owned(replacement.clone()));
+                    shortened.applied.push(AppliedRule {
+                        matcher: format!("@fallback-cmd {}", fallback.command()),
+                        original: text.to_string(),
+                        replacement,
+                        position: 0,
+                    });
+                }
+            }
+        }
+        // Lines that truncation cuts are changed anyway
+        let untouched = shortened.applied.is_empty()
+            && !shortened.elided
+            && (!self.truncate || width::length(text) <= max_length);
+        if self.preserve_unmatched && untouched {
+            return Shortened::unchanged(text, text);
+        }
+        let too_long = width::length(&shortened.text) > max_length;
+        // Without truncation to make room for it, the marker is only added when it fits
+        let marker = self.elision_marker.as_deref().filter(|marker| {
+            (shortened.elided || (too_long && self.truncate))
+                && (self.truncate
+                    || width::length(&shortened.text) + width::length(marker) <= max_length)
+        });
+        if self.truncate {
+            let room = max_length.saturating_sub(marker.map_or(0, width::length));
+            if width::length(&shortened.text) > room {
+                let is_kept = |word: &str| self.is_kept(word);
+                let len = shortened.text.len();
+                if anchor == Some(Anchor::Right) {
+                    shortened.text = truncate_start(shortened.text, room, is_kept);
+                    let cut = len - shortened.text.len();
+                    for span in &mut shortened.spans {
+                        let (start, end) = (span.shortened.start, span.shortened.end);
+                        span.shortened = start.saturating_sub(cut)..end.saturating_sub(cut);
+                    }
+                } else {
+                    shortened.text = truncate(shortened.text, room, is_kept);
+                    let end = shortened.text.len();
+                    for span in &mut shortened.spans {
+                        span.shortened = span.shortened.start.min(end)..span.shortened.end.min(end);
+                    }
+                }
+                shortened.truncated = true;
+            }
+        }
+        match marker {
+            Some(marker) if anchor == Some(Anchor::Right) => {
+                shortened.text.to_mut().insert_str(0, marker);
+                for span in &mut shortened.spans {
+                    let shortened = &mut span.shortened;
+                    *shortened = shortened.start + marker.len()..shortened.end + marker.len();
+                }
+            }
+            Some(marker) => shortened.text.to_mut().push_str(marker),
+            None => {}
+        }
+        shortened
+    }
+
+    fn abbreviate_line<'a>(
+        &self,
+        text: &'a str,
+        max_length: usize,
+        anchor: Option<Anchor>,
+    ) -> Shortened<'a> {
+        if width::length(text) <= max_length {
+            return Shortened::unchanged(text, text);
+        }
+
+        let trimmed = text.trim();
+        if width::length(trimmed) <= max_length {
+            return Shortened::unchanged(text, trimmed);
+        }
+
+        let rewrite = self.abbreviator.rewrite_line(trimmed, max_length).or_else(|| {
+            let dates = self.dates.as_ref()?;
+            let locale = self.abbreviator.locale();
+            let text = dates::compress(trimmed, &dates.format, dates.clock, locale)?;
+            Some(Abbreviation {
+                text: Cow::Owned(text),
+                escalations: Vec::new(),
+                attach_to_previous: false,
+                attach_to_next: false,
+                priority: 0,
+                min_overflow: 0,
+                matcher: DATES_MATCHER,
+            })
+        });
+        if let Some(rewrite) = rewrite {
+            return self.abbreviate_rewritten(text, trimmed, rewrite, max_length, anchor);
+        }
+        self.abbreviate_words(text, trimmed, max_length, anchor)
+    }
+
+    /// Abbreviates the words of a line rewritten by a whole-line rule or the
+    /// dates pass. The rules applied to the rewritten line are located at the
+    /// start of the original line, like the rewrite.
+    fn abbreviate_rewritten<'a>(
+        &self,
+        text: &'a str,
+        trimmed: &'a str,
+        rewrite: Abbreviation<'_>,
+        max_length: usize,
+        anchor: Option<Anchor>,
+    ) -> Shortened<'a> {
+        let start = trimmed.as_ptr() as usize - text.as_ptr() as usize;
+        let line_rule = AppliedRule {
+            matcher: rewrite.matcher.to_string(),
+            original: trimmed.to_string(),
+            replacement: rewrite.text.to_string(),
+            position: start,
+        };
+        let rewritten = rewrite.text.trim();
+        let shortened = match width::length(rewritten) <= max_length {
+            true => Shortened {
+                text: Cow::Borrowed(rewritten),
+                truncated: false,
+                elided: false,
+                applied: vec![],
+                spans: vec![],
+            },
+            false => self.abbreviate_words(rewritten, rewritten, max_length, anchor),
+        };
+        let applied =
+            shortened.applied.into_iter().map(|rule| AppliedRule { position: start, ..rule });
+        let span = Span { original: start..start + trimmed.len(), shortened: 0..shortened.text.len() };
+        Shortened {
+            text: Cow::Owned(shortened.text.into_owned()),
+            truncated: false,
+            elided: shortened.elided,
+            applied: [line_rule].into_iter().chain(applied).collect(),
+            spans: vec![span],
+        }
+    }
+
+    /// Abbreviates the words of a line that doesn't fit.
+    fn abbreviate_words<'a>(
+        &self,
+        text: &'a str,
+        trimmed: &'a str,
+        max_length: usize,
+        anchor: Option<Anchor>,
+    ) -> Shortened<'a> {
+        let line = self.tokenize(text, trimmed);
+        let words = &line.words;
+        let mut segments = self.plan(&line);
+        if self.mode == Mode::Identifier {
+            match_hump_case(words, &mut segments, self.abbreviator.case_rules());
+        }
+        // Rules with a length condition are held back, even with the `All` strategy
+        let mut applied = segments
+            .iter()
+            .map(|segment| {
+                self.strategy == Strategy::All
+                    && segment.replacement.as_ref().is_none_or(|r| r.min_overflow == 0)
+            })
+            .collect_vec();
+
+        if self.strategy == Strategy::Optimal {
+            select_optimal(&line, &segments, &mut applied, max_length);
+        }
+
+        // Apply candidates one at a time, by priority then left-to-right, until the line fits
+        let candidates = segments
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !applied[*index])
+            .filter_map(|(index, segment)| Some((index, segment.replacement.as_ref()?)))
+            .sorted_by_key(|(index, replacement)| (Reverse(replacement.priority), *index))
+            .collect_vec();
+        for (index, replacement) in candidates {
+            let length = width::length(&line.assemble(&segments, &applied));
+            if length <= max_length {
+                break;
+            }
+            applied[index] = length - max_length > replacement.min_overflow;
+        }
+
+        escalate(&line, &mut segments, &applied, max_length);
+
+        for (name, hook) in &self.hooks {
+            let passes = (&mut segments[..], &mut applied[..]);
+            run_hook(&line, passes, name, &**hook, max_length, anchor);
+        }
+        for (name, command) in self.abbreviator.hooks() {
+            let hook = |word: &str, overflow| command.replace_word(word, overflow);
+            let passes = (&mut segments[..], &mut applied[..]);
+            run_hook(&line, passes, name, &hook, max_length, anchor);
+        }
+
+        if self.numbers {
+            convert_numbers(&line, &mut segments, &mut applied, max_length);
+        }
+
+        if let Some(stop_words) = &self.stop_words {
+            let passes = (&mut segments[..], &mut applied[..]);
+            let stop_word = |segment: &Segment, word: &str| {
+                segment.replacement.is_none() && stop_words.contains(word)
+            };
+            self.drop_words(&line, passes, stop_word, STOP_WORDS_MATCHER, max_length, anchor);
+        }
+
+        let priorities = self.abbreviator.has_word_priorities();
+        if priorities {
+            let passes = (&mut segments[..], &mut applied[..]);
+            let low = |_: &Segment, word: &str| {
+                self.abbreviator.word_priority(word) == Some(WordPriority::Low)
+            };
+            self.drop_words(&line, passes, low, LOW_WORDS_MATCHER, max_length, anchor);
+        }
+
+        if let Some(min_length) = self.drop_vowels {
+            let passes = (&mut segments[..], &mut applied[..]);
+            drop_words_vowels(&line, passes, min_length, max_length, anchor);
+        }
+
+        if let Some(acronyms) = self.acronyms {
+            let case_rules = self.abbreviator.case_rules();
+            collapse_acronyms(&line, &mut segments, &mut applied, acronyms, case_rules, max_length);
+        }
+
+        // With priorities, the words without one go before the line is truncated
+        if priorities {
+            let passes = (&mut segments[..], &mut applied[..]);
+            let medium = |_: &Segment, word: &str| self.abbreviator.word_priority(word).is_none();
+            self.drop_words(&line, passes, medium, PRIORITY_MATCHER, max_length, anchor);
+        }
+
+        let applied_rules = segments
+            .iter()
+            .zip(&applied)
+            .filter(|(_, &applied)| applied)
+            .filter_map(|(segment, _)| {
+                let replacement = segment.replacement.as_ref()?;
+                let words = &words[segment.words.clone()];
+                Some(AppliedRule {
+                    matcher: replacement.matcher.clone(),
+                    original: words.join(" "),
+                    replacement: replacement.text.clone(),
+                    position: words[0].as_ptr() as usize - text.as_ptr() as usize,
+                })
+            })
+            .collect();
+
+        let elided = segments.iter().zip(&applied).any(|(segment, &applied)| {
+            applied && segment.replacement.as_ref().is_some_and(|r| r.text.is_empty())
+        });
+
+        let mut spans = Vec::with_capacity(words.len());
+        Shortened {
+            text: Cow::Owned(line.assemble_with_spans(&segments, &applied, Some(&mut spans))),
+            truncated: false,
+            elided,
+            applied: applied_rules,
+            spans,
+        }
+    }
+
+    /// Whether the word, without surrounding punctuation, must not be altered.
+    fn is_kept(&self, word: &str) -> bool {
+        let word = word.trim_matches(|c: char| !c.is_alphanumeric());
+        !word.is_empty()
+            && (self.kept.contains(&word.to_lowercase()) || self.abbreviator.is_kept(word))
+    }
+
+    /// Splits the line into words, and the words no rule matches into the
+    /// parts between separators (and the CJK words of the rules).
+    fn tokenize<'a>(&'a self, text: &'a str, trimmed: &'a str) -> Line<'a> {
+        let mut line = Line {
+            words: Vec::new(),
+            glued: Vec::new(),
+            ideographic: Vec::new(),
+            text,
+            preserve_spacing: self.preserve_spacing,
+            enclosing: self.abbreviator.enclosing(),
+        };
+        let is_space = |c: char| {
+            c.is_whitespace() && !(self.unicode_spaces && NO_BREAK_SPACES.contains(&c))
+        };
+        let mut end = 0;
+        for word in trimmed.split(is_space).filter(|word| !word.is_empty()) {
+            let start = word.as_ptr() as usize - trimmed.as_ptr() as usize;
+            let gap = &trimmed[end..start];
+            let ideographic = self.unicode_spaces && gap.contains(IDEOGRAPHIC_SPACE);
+            end = start + word.len();
+            // Hyphenated numbers like "twenty-third" are left whole for the numbers pass
+            let splits = (word.contains(&self.separators[..]) || self.mode == Mode::Identifier)
+                && !self.is_kept(word)
+                && self.abbreviator.exception_length(&[word]).is_none()
+                && self.abbreviator.abbreviate(word).is_none()
+                && !(self.numbers && numbers::parse(&[word]).is_some());
+            if !splits {
+                self.push_word(&mut line, word, false, ideographic);
+                continue;
+            }
+
+            let mut start = 0;
+            for (index, separator) in word.match_indices(&self.separators[..]) {
+                let parts = [&word[start..index], separator];
+                for part in parts.into_iter().filter(|part| !part.is_empty()) {
+                    let first = part.as_ptr() == word.as_ptr();
+                    self.push_part(&mut line, part, !first, ideographic && first);
+                }
+                start = index + separator.len();
+            }
+            if start < word.len() {
+                let first = start == 0;
+                self.push_part(&mut line, &word[start..], !first, ideographic && first);
+            }
+        }
+        line
+    }
+
+    /// Adds a part of a split word to the line, itself split at its case
+    /// changes in the identifier mode.
+    fn push_part<'a>(&self, line: &mut Line<'a>, part: &'a str, glued: bool, ideographic: bool) {
+        let humps = match self.mode {
+            Mode::Identifier => split_humps(part),
+            Mode::Text | Mode::Path | Mode::Url | Mode::Subject | Mode::GitRef => vec![part],
+        };
+        for (index, hump) in humps.into_iter().enumerate() {
+            self.push_word(line, hump, glued || index > 0, ideographic && index == 0);
+        }
+    }
+
+    /// Adds a word to the line, split into the CJK words of the rules it
+    /// contains when segmenting CJK text.
+    fn push_word<'a>(&self, line: &mut Line<'a>, word: &'a str, glued: bool, ideographic: bool) {
+        #[cfg(not(feature = "cjk"))]
+        let segments = vec![word];
+        #[cfg(feature = "cjk")]
+        let segments = match self.abbreviator.cjk_dictionary().filter(|_| self.cjk_segmentation) {
+            Some(dictionary)
+                if word.chars().any(cjk::is_cjk)
+                    && !self.is_kept(word)
+                    && self.abbreviator.exception_length(&[word]).is_none()
+                    && self.abbreviator.abbreviate(word).is_none() =>
+            {
+                cjk::segment(word, dictionary)
+            }
+            _ => vec![word],
+        };
+        for (index, segment) in segments.into_iter().enumerate() {
+            line.words.push(segment);
+            line.glued.push(glued || index > 0);
+            line.ideographic.push(ideographic && index == 0);
+        }
+    }
+
+    /// Pass that removes the standalone words `dropped` picks until the line
+    /// fits, like unabbreviated stop words. Kept words, `@high` words and the
+    /// last word left are never removed.
+    fn drop_words(
+        &self,
+        line: &Line<'_>,
+        (segments, applied): (&mut [Segment], &mut [bool]),
+        dropped: impl Fn(&Segment, &str) -> bool,
+        matcher: &str,
+        max_length: usize,
+        anchor: Option<Anchor>,
+    ) {
+        let is_dropped = |segment: &Segment, applied: bool| {
+            applied && segment.replacement.as_ref().is_some_and(|r| r.text.is_empty())
+        };
+        for index in pass_order(segments.len(), anchor) {
+            if width::length(&line.assemble(segments, applied)) <= max_length {
+                break;
+            }
+
+            let left = zip(&*segments, &*applied)
+                .filter(|&(segment, &applied)| !is_dropped(segment, applied))
+                .count();
+            let segment = &mut segments[index];
+            let word = line.words[segment.words.start];
+            if left > 1
+                && !is_dropped(segment, applied[index])
+                && !segment.kept
+                && segment.words.len() == 1
+                && line.is_standalone(segment.words.start)
+                && self.abbreviator.word_priority(word) != Some(WordPriority::High)
+                && dropped(segment, word)
+            {
+                segment.replacement = Some(Replacement {
+                    text: String::new(),
+                    escalations: Vec::new(),
+                    attach_to_previous: true,
+                    attach_to_next: false,
+                    priority: 0,
+                    min_overflow: 0,
+                    matcher: matcher.to_string(),
+                });
+                applied[index] = true;
+            }
+        }
+    }
+
+    /// Splits the words into segments, each covering one or more words along
+    /// with the abbreviation that would replace them (if any rule matches).
+    ///
+    /// Phrases are matched greedily: the longest run of words matching a rule
+    /// is tried first, falling back to shorter runs (pairs of words are always
+    /// tried, for regex rules) and finally to the single word.
+    fn plan(&self, line: &Line<'_>) -> Vec<Segment> {
+        let words = &line.words;
+        let enclosing = self.abbreviator.enclosing();
+        let window = self.abbreviator.max_phrase_words();
+        let phrases = self.abbreviator.phrase_lengths(words);
+        let exceptions =
+            (0..words.len()).map(|index| self.abbreviator.exception_length(&words[index..]));
+        let exceptions = exceptions.collect_vec();
+        let mut segments = Vec::with_capacity(words.len());
+        let mut index = 0;
+        'words: while index < words.len() {
+            let word = words[index];
+            let enclosed = enclosing.split(word);
+
+            if self.is_kept(word) {
+                segments.push(Segment { words: index..index + 1, replacement: None, kept: true });
+                index += 1;
+                continue;
+            }
+
+            // Exceptions are resolved first, and only the exact rules apply to their words
+            if let Some(len) = exceptions[index] {
+                self.plan_exact(words, index..index + len, &mut segments);
+                index += len;
+                continue;
+            }
+
+            if let Some((len, replacement)) = self.enclosed_segment(line, index) {
+                let replacement = Some(replacement);
+                segments.push(Segment { words: index..index + len, replacement, kept: false });
+                index += len;
+                continue;
+            }
+
+            if let Some((len, replacement)) = self.enclosed_phrase(line, index) {
+                let replacement = Some(replacement);
+                segments.push(Segment { words: index..index + len, replacement, kept: false });
+                index += len;
+                continue;
+            }
+
+            // Attempt raw enclosed abbreviation before removing enclosing
+            if enclosed.is_enclosed() {
+                let replacement = self
+                    .abbreviator
+                    .abbreviate(word)
+                    .map(Replacement::from)
+                    .or_else(|| {
+                        // Remove enclosing and try word individually
+                        let abbrev = self.abbreviator.abbreviate(enclosed.word)?;
+                        Some(enclosed_replacement(enclosed.openers, enclosed.closers, abbrev))
+                    });
+                segments.push(Segment { words: index..index + 1, replacement, kept: false });
+                index += 1;
+                continue;
+            }
+
+            // Phrases never span enclosed or kept words, separators nor exceptions
+            let phrase_len = words[index..]
+                .iter()
+                .zip(&line.glued[index..])
+                .take(window)
+                .enumerate()
+                .take_while(|(offset, (word, &glued))| {
+                    !enclosing.split(word).is_enclosed()
+                        && !self.is_kept(word)
+                        && (*offset == 0 || (!glued && exceptions[index + offset].is_none()))
+                })
+                .count();
+            let lengths = phrases[index]
+                .iter()
+                .copied()
+                .chain([2])
+                .filter(|len| (2..=phrase_len).contains(len))
+                .sorted_unstable_by_key(|&len| Reverse(len))
+                .dedup();
+            for len in lengths {
+                let phrase = index..index + len;
+                if let Some(abbrev) = self.abbreviator.abbreviate_words(&words[phrase.clone()]) {
+                    let replacement = Some(abbrev.into());
+                    segments.push(Segment { words: phrase, replacement, kept: false });
+                    index += len;
+                    continue 'words;
+                }
+            }
+
+            // Attempt to abbreviate the word on its own
+            let replacement = self.abbreviator.abbreviate(word).map(Replacement::from);
+            segments.push(Segment { words: index..index + 1, replacement, kept: false });
+            index += 1;
+        }
+
+        segments
+    }
+
+    /// Splits the words of an exception into segments like [`Shortener::plan`],
+    /// trying the exact rules only.
+    fn plan_exact(&self, words: &[&str], range: Range<usize>, segments: &mut Vec<Segment>) {
+        let mut index = range.start;
+        while index < range.end {
+            let (len, replacement) = (1..=range.end - index)
+                .rev()
+                .find_map(|len| {
+                    let abbrev = self.abbreviator.abbreviate_exact(&words[index..index + len])?;
+                    Some((len, Some(abbrev.into())))
+                })
+                .unwrap_or((1, None));
+            segments.push(Segment { words: index..index + len, replacement, kept: false });
+            index += len;
+        }
+    }
+
+    /// Matches the segment rules against the enclosed segment starting at a
+    /// word, like "[Moved to Friday, rescheduled]", brackets included and
+    /// however many words it spans. Segments holding a kept word are left alone.
+    /// Returns the number of words spanned.
+    fn enclosed_segment(&self, line: &Line<'_>, start: usize) -> Option<(usize, Replacement)> {
+        if !self.abbreviator.has_segment_rules() || line.glued[start] {
+            return None;
+        }
+
+        let (words, enclosing) = (&line.words, line.enclosing);
+        let opener = enclosing.split(words[start]).openers.chars().next()?;
+        let closer = enclosing.closer_of(opener)?;
+        let end = (start..words.len())
+            .take_while(|&index| !self.is_kept(words[index]))
+            .find(|&index| enclosing.split(words[index]).closers.contains(closer))?;
+
+        let mut segment = String::new();
+        let span = zip(&words[start..=end], &line.glued[start..=end]);
+        for (index, (word, &glued)) in span.enumerate() {
+            if index > 0 && !glued {
+                segment.push(' ');
+            }
+            segment.push_str(word);
+        }
+        let abbrev = self.abbreviator.abbreviate_segment(&segment)?;
+        Some((end - start + 1, abbrev.into()))
+    }
+
+    /// Abbreviates brackets spanning several words, like "(the long session)",
+    /// as a whole: either along with the brackets or as the phrase inside them.
+    /// Returns the number of words spanned.
+    fn enclosed_phrase(&self, line: &Line<'_>, start: usize) -> Option<(usize, Replacement)> {
+        let (words, enclosing) = (&line.words, line.enclosing);
+        let first = enclosing.split(words[start]);
+        let opener = first.openers.chars().next()?;
+        let closer = enclosing.closer_of(opener)?;
+        if !first.closers.is_empty() {
+            return None;
+        }
+
+        let window = self.abbreviator.max_phrase_words();
+        let end = (start + 1..words.len().min(start + window))
+            .take_while(|&index| !line.glued[index] && !self.is_kept(words[index]))
+            .find(|&index| enclosing.split(words[index]).closers.contains(closer))?;
+        let span = &words[start..=end];
+        if let Some(abbrev) = self.abbreviator.abbreviate_words(span) {
+            return Some((span.len(), abbrev.into()));
+        }
+
+        let last = enclosing.split(words[end]);
+        let mut inner = span.to_vec();
+        inner[0] = first.word;
+        inner[span.len() - 1] = last.word;
+        let abbrev = self.abbreviator.abbreviate_words(&inner)?;
+        Some((span.len(), enclosed_replacement(first.openers, last.closers, abbrev)))
+    }
+}
+
+/// The replacement of an enclosed word or phrase, put back between its
+/// enclosing characters unless it is removed.
+fn enclosed_replacement(openers: &str, closers: &str, abbrev: Abbreviation<'_>) -> Replacement {
+    if abbrev.text.is_empty() {
+        return Replacement::from(abbrev);
+    }
+    Replacement {
+        text: format!("{openers}{}{closers}", abbrev.text),
+        escalations: abbrev
+            .escalations
+            .iter()
+            .map(|escalation| format!("{openers}{escalation}{closers}"))
+            .collect(),
+        attach_to_previous: openers.is_empty() && abbrev.attach_to_previous,
+        attach_to_next: closers.is_empty() && abbrev.attach_to_next,
+        priority: abbrev.priority,
+        min_overflow: abbrev.min_overflow,
+        matcher: abbrev.matcher.to_string(),
+    }
+}
+
+/// The results cache of a shortener. The clones of a shortener don't share
+/// it, as their settings may diverge: they start with an empty one.
+struct Cache(Mutex<LruCache<CacheKey, Shortened<'static>>>);
+
+impl Clone for Cache {
+    fn clone(&self) -> Cache {
+        Cache(Mutex::new(LruCache::new(lock(&self.0).capacity())))
+    }
+}
+
+/// Locks the cache, which stays usable even if a thread panicked while holding it.
+fn lock<T>(cache: &Mutex<T>) -> MutexGuard<'_, T> {
+    cache.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Spaces that join the words around them with `unicode_spaces`
+const NO_BREAK_SPACES: [char; 3] = ['\u{a0}', '\u{2007}', '\u{202f}'];
+
+/// The space between CJK words, kept with `unicode_spaces`
+const IDEOGRAPHIC_SPACE: char = '\u{3000}';
+
+/// How many times idempotent shortening shortens a line again at most, which
+/// takes as many rules chained one after the other
+const MAX_PASSES: usize = 8;
+
+/// The separators splitting words by default
+pub(crate) const DEFAULT_SEPARATORS: [char; 6] = ['/', '|', ':', '·', '-', '_'];
+
+/// The matcher reported for lines with their dates and times rewritten
+const DATES_MATCHER: &str = "<dates>";
+
+/// The matcher reported for numbers written in digits
+const NUMBERS_MATCHER: &str = "<number>";
+
+/// The matcher reported for words dropped by the stop words pass
+const STOP_WORDS_MATCHER: &str = "<stop word>";
+
+/// The matcher reported for the `@low` words dropped from a line
+const LOW_WORDS_MATCHER: &str = "<low>";
+
+/// The matcher reported for the words without a priority dropped from a line
+const PRIORITY_MATCHER: &str = "<priority>";
+
+/// The matcher reported for words shortened by dropping their vowels
+const VOWELS_MATCHER: &str = "<vowels>";
+
+/// The matcher reported for words collapsed into an acronym
+const ACRONYM_MATCHER: &str = "<acronym>";
+
+/// Words at most this long are never collapsed into an acronym.
+const SHORT_WORD_LENGTH: usize = 3;
+
+/// The order in which the passes go through the segments: away from the
+/// anchored end, and left to right by default.
+fn pass_order(len: usize, anchor: Option<Anchor>) -> impl Iterator<Item = usize> {
+    match anchor {
+        Some(Anchor::Left) => Either::Left((0..len).rev()),
+        _ => Either::Right(0..len),
+    }
+}
+
+/// Pass that drops the vowels of long unabbreviated words until the line fits.
+fn drop_words_vowels(
+    line: &Line<'_>,
+    (segments, applied): (&mut [Segment], &mut [bool]),
+    min_length: usize,
+    max_length: usize,
+    anchor: Option<Anchor>,
+) {
+    for index in pass_order(segments.len(), anchor) {
+        if width::length(&line.assemble(segments, applied)) <= max_length {
+            break;
+        }
+
+        let segment = &mut segments[index];
+        let word = line.words[segment.words.start];
+        if (segment.replacement.is_none() || !applied[index])
+            && !segment.kept
+            && segment.words.len() == 1
+            && width::length(word) > min_length
+            && word.chars().all(char::is_alphabetic)
+        {
+            segment.replacement = Some(Replacement {
+                text: drop_vowels(word),
+                escalations: Vec::new(),
+                attach_to_previous: false,
+                attach_to_next: false,
+                priority: 0,
+                min_overflow: 0,
+                matcher: VOWELS_MATCHER.to_string(),
+            });
+            applied[index] = true;
+        }
+    }
+}
+
+/// Applies the smallest set of abbreviations saving enough characters for the
+/// line to fit, preferring the sets that save the fewest characters among
+/// those of the same size: a 0/1 knapsack over the savings of each candidate.
+///
+/// The savings are measured one abbreviation at a time, so they may be off
+/// when abbreviations interact, in which case the line is left for the
+/// following passes to fit.
+fn select_optimal(line: &Line<'_>, segments: &[Segment], applied: &mut [bool], max_length: usize) {
+    let length = width::length(&line.assemble(segments, applied));
+    if length <= max_length {
+        return;
+    }
+
+    let mut candidates = Vec::new();
+    for (index, segment) in segments.iter().enumerate() {
+        let conditional = segment.replacement.as_ref().map(|r| r.min_overflow);
+        if applied[index] || conditional.is_none_or(|min| length - max_length <= min) {
+            continue;
+        }
+        applied[index] = true;
+        let saving = length.saturating_sub(width::length(&line.assemble(segments, applied)));
+        applied[index] = false;
+        if saving > 0 {
+            candidates.push((index, saving));
+        }
+    }
+    let excess = length - max_length;
+    let total = candidates.iter().map(|(_, saving)| saving).sum::<usize>();
+    if total < excess {
+        return;
+    }
+
+    // fewest[i][s]: the fewest of the first i candidates saving exactly s characters
+    let mut fewest = vec![vec![usize::MAX; total + 1]; candidates.len() + 1];
+    fewest[0][0] = 0;
+    for (i, &(_, saving)) in candidates.iter().enumerate() {
+        for s in 0..=total {
+            let skip = fewest[i][s];
+            let take = s.checked_sub(saving).map_or(usize::MAX, |rest| fewest[i][rest]);
+            fewest[i + 1][s] = skip.min(take.saturating_add(1));
+        }
+    }
+    let best = (excess..=total)
+        .filter(|&s| fewest[candidates.len()][s] != usize::MAX)
+        .min_by_key(|&s| (fewest[candidates.len()][s], s));
+    let Some(mut saved) = best else {
+        return;
+    };
+    for (i, &(index, saving)) in candidates.iter().enumerate().rev() {
+        if fewest[i + 1][saved] != fewest[i][saved] {
+            applied[index] = true;
+            saved -= saving;
+        }
+    }
+}
+
+/// Pass that replaces the applied abbreviations by their more aggressive
+/// levels until the line fits: every rule goes to its second level (by
+/// priority, then left-to-right) before any goes to its third.
+fn escalate(line: &Line<'_>, segments: &mut [Segment], applied: &[bool], max_length: usize) {
+    let mut order = (0..segments.len())
+        .filter(|&index| applied[index])
+        .filter_map(|index| Some((index, segments[index].replacement.as_ref()?)))
+        .filter(|(_, replacement)| !replacement.escalations.is_empty())
+        .sorted_by_key(|(index, replacement)| (Reverse(replacement.priority), *index))
+        .map(|(index, _)| index)
+        .collect_vec();
+    let mut fits = false;
+    while !order.is_empty() {
+        order.retain(|&index| {
+            fits = fits || width::length(&line.assemble(segments, applied)) <= max_length;
+            if fits {
+                return false;
+            }
+            let replacement =
+                segments[index].replacement.as_mut().expect("only replacements escalate");
+            replacement.text = replacement.escalations.remove(0);
+            !replacement.escalations.is_empty()
+        });
+    }
+}
+
+const VOWELS: [&str; 10] = ["a", "e", "i", "o", "u", "A", "E", "I", "O", "U"];
+
+/// Drops the vowels after the first letter and collapses repeated letters,
+/// e.g. "Management" becomes "Mngmnt" and "Committee" becomes "Cmt".
+pub fn drop_vowels(word: &str) -> String {
+    let mut graphemes = width::graphemes(word);
+    let first = graphemes.next().into_iter();
+    first
+        .chain(graphemes.filter(|grapheme| !VOWELS.contains(grapheme)))
+        .dedup()
+        .collect()
+}
+
+/// Pass that replaces the words a hook has a replacement for, away from the
+/// anchor, until the line fits.
+fn run_hook(
+    line: &Line<'_>,
+    (segments, applied): (&mut [Segment], &mut [bool]),
+    name: &str,
+    hook: &dyn Fn(&str, usize) -> Option<String>,
+    max_length: usize,
+    anchor: Option<Anchor>,
+) {
+    for index in pass_order(segments.len(), anchor) {
+        let length = width::length(&line.assemble(segments, applied));
+        if length <= max_length {
+            break;
+        }
+        let segment = &segments[index];
+        let replaced = segment.replacement.is_some() && applied[index];
+        if segment.kept || segment.words.len() != 1 || replaced {
+            continue;
+        }
+        let Some(text) = hook(line.words[segment.words.start], length - max_length) else {
+            continue;
+        };
+        segments[index].replacement = Some(Replacement {
+            text,
+            escalations: Vec::new(),
+            attach_to_previous: false,
+            attach_to_next: false,
+            priority: 0,
+            min_overflow: 0,
+            matcher: format!("<{name}>"),
+        });
+        applied[index] = true;
+    }
+}
+
+/// Pass that writes spelled-out numbers in digits and drops thousands
+/// separators until the line fits.
+fn convert_numbers(
+    line: &Line<'_>,
+    segments: &mut Vec<Segment>,
+    applied: &mut Vec<bool>,
+    max_length: usize,
+) {
+    let convertible = |segment: &Segment, applied: bool| {
+        segment.words.len() == 1
+            && !segment.kept
+            && (segment.replacement.is_none() || !applied)
+            && line.is_standalone(segment.words.start)
+    };
+
+    let mut index = 0;
+    while index < segments.len() {
+        if width::length(&line.assemble(segments, applied)) <= max_length {
+            break;
+        }
+
+        let run = segments[index..]
+            .iter()
+            .zip(&applied[index..])
+            .take_while(|(segment, &applied)| convertible(segment, applied))
+            .map(|(segment, _)| line.words[segment.words.start])
+            .collect_vec();
+        let converted = numbers::parse(&run).or_else(|| {
+            let word = run.first()?;
+            Some((1, numbers::strip_separators(word)?))
+        });
+        let Some((run_len, text)) = converted else {
+            index += 1;
+            continue;
+        };
+
+        let run = index..index + run_len;
+        let replacement = Replacement {
+            text,
+            escalations: Vec::new(),
+            attach_to_previous: false,
+            attach_to_next: false,
+            priority: 0,
+            min_overflow: 0,
+            matcher: NUMBERS_MATCHER.to_string(),
+        };
+        let words = segments[index].words.start..segments[run.end - 1].words.end;
+        let number = Segment { words, replacement: Some(replacement), kept: false };
+        segments.splice(run.clone(), [number]);
+        applied.splice(run, [true]);
+        index += 1;
+    }
+}
+
+/// Last pass that replaces runs of words by their initials until the line fits.
+fn collapse_acronyms(
+    line: &Line<'_>,
+    segments: &mut Vec<Segment>,
+    applied: &mut Vec<bool>,
+    acronyms: Acronyms,
+    case_rules: CaseRules,
+    max_length: usize,
+) {
+    let words = &line.words;
+    let collapsible = |segment: &Segment, applied: bool| {
+        let word = words[segment.words.start];
+        segment.words.len() == 1
+            && !segment.kept
+            && (segment.replacement.is_none() || !applied)
+            && width::length(word) > SHORT_WORD_LENGTH
+            && word.chars().next().is_some_and(char::is_alphabetic)
+            && !line.enclosing.split(word).is_enclosed()
+    };
+
+    let mut index = 0;
+    while index < segments.len() {
+        if width::length(&line.assemble(segments, applied)) <= max_length {
+            break;
+        }
+
+        let run_len = segments[index..]
+            .iter()
+            .zip(&applied[index..])
+            .take(acronyms.max_words)
+            .take_while(|(segment, &applied)| collapsible(segment, applied))
+            .count();
+        if run_len == 0 || run_len < acronyms.min_words {
+            index += run_len.max(1);
+            continue;
+        }
+
+        let run = index..index + run_len;
+        let run_words = segments[index].words.start..segments[run.end - 1].words.end;
+        let text = words[run_words.clone()]
+            .iter()
+            .filter_map(|word| width::graphemes(word).next())
+            .map(|initial| case_rules.to_uppercase(initial))
+            .collect();
+        let replacement = Replacement {
+            text,
+            escalations: Vec::new(),
+            attach_to_previous: false,
+            attach_to_next: false,
+            priority: 0,
+            min_overflow: 0,
+            matcher: ACRONYM_MATCHER.to_string(),
+        };
+        let acronym = Segment { words: run_words, replacement: Some(replacement), kept: false };
+        segments.splice(run.clone(), [acronym]);
+        applied.splice(run, [true]);
+        index += 1;
+    }
+}
+
+/// A run of consecutive words and the abbreviation that may replace them.
+struct Segment {
+    words: Range<usize>,
+    replacement: Option<Replacement>,
+    /// The word is protected from every pass
+    kept: bool,
+}
+
+struct Replacement {
+    text: String,
+    /// The more aggressive replacements that were not used yet, in order
+    escalations: Vec<String>,
+    attach_to_previous: bool,
+    attach_to_next: bool,
+    priority: i32,
+    min_overflow: usize,
+    matcher: String,
+}
+
+impl From<Abbreviation<'_>> for Replacement {
+    fn from(abbrev: Abbreviation<'_>) -> Self {
+        Replacement {
+            text: abbrev.text.to_string(),
+            escalations: abbrev.escalations.into_iter().map(Cow::into_owned).collect(),
+            attach_to_previous: abbrev.attach_to_previous,
+            attach_to_next: abbrev.attach_to_next,
+            priority: abbrev.priority,
+            min_overflow: abbrev.min_overflow,
+            matcher: abbrev.matcher.to_string(),
+        }
+    }
 }
 
-impl Shortener {
-    pub fn new(desired_max_length: usize) -> eyre::Result<Shortener> {
-        let base_dirs =
-            BaseDirectories::with_prefix("shorten").context("Failed to get base directories")?;
-
-        let abbrev_path = base_dirs.get_config_file("abbrev.lst");
-        let abbreviator = if abbrev_path.exists() {
-            Abbreviator::try_from_file(&abbrev_path).context("Failed to load abbreviations")?
-        } else {
-            Abbreviator::default()
-        };
+/// The words of a line being shortened.
+struct Line<'a> {
+    words: Vec<&'a str>,
+    /// Whether each word is glued to the previous one by a separator, without whitespace
+    glued: Vec<bool>,
+    /// Whether each word follows an ideographic space, to put back in its place
+    ideographic: Vec<bool>,
+    /// The original line the words were taken from
+    text: &'a str,
+    preserve_spacing: bool,
+    enclosing: &'a Enclosing,
+}
 
-        Self::with_abbreviator(desired_max_length, abbreviator)
+impl Line<'_> {
+    /// Whether the word is not glued to any other by a separator.
+    fn is_standalone(&self, word: usize) -> bool {
+        !self.glued[word] && !self.glued.get(word + 1).copied().unwrap_or(false)
     }
 
-    pub fn with_abbreviator(
-        desired_max_length: usize,
-        abbreviator: Abbreviator,
-    ) -> eyre::Result<Shortener> {
-        Ok(Shortener {
-            desired_max_length,
-            abbreviator,
-        })
+    /// Builds the output line, using the replacement of every applied segment
+    /// and the original words of all the others.
+    fn assemble(&self, segments: &[Segment], applied: &[bool]) -> String {
+        self.assemble_with_spans(segments, applied, None)
     }
 
-    pub fn shorten<'a>(&self, text: &'a str) -> Cow<'a, str> {
-        if text.len() <= self.desired_max_length {
-            return Cow::Borrowed(text);
-        }
-
-        let trimmed = text.trim();
-        if trimmed.len() <= self.desired_max_length {
-            return Cow::Borrowed(trimmed);
+    /// Builds the output line, recording where each segment ended up.
+    fn assemble_with_spans(
+        &self,
+        segments: &[Segment],
+        applied: &[bool],
+        spans: Option<&mut Vec<Span>>,
+    ) -> String {
+        let mut no_spans = Vec::new();
+        let spans = spans.unwrap_or(&mut no_spans);
+        if self.preserve_spacing {
+            self.assemble_in_place(segments, applied, spans)
+        } else {
+            self.assemble_collapsed(segments, applied, spans)
         }
+    }
 
-        let words = trimmed.split_whitespace();
-        let mut prev_word: Option<&str> = None;
-        let mut abbreviated = String::with_capacity(trimmed.len());
-        for word in words {
-            let enclosed = process_enclosed_word(word);
+    /// The byte range of the words in the original line.
+    fn range(&self, words: &Range<usize>) -> Range<usize> {
+        let offset = |word: &str| word.as_ptr() as usize - self.text.as_ptr() as usize;
+        let last = self.words[words.end - 1];
+        offset(self.words[words.start])..offset(last) + last.len()
+    }
 
-            // Attempt raw enclosed abbreviation before removing enclosing
-            if enclosed.is_enclosed() {
-                if let Some(found_prev_word) = prev_word {
-                    self.abbrev_or_add(&mut abbreviated, found_prev_word);
-                    prev_word = None;
+    /// Joins the segments with single spaces.
+    fn assemble_collapsed(
+        &self,
+        segments: &[Segment],
+        applied: &[bool],
+        spans: &mut Vec<Span>,
+    ) -> String {
+        let mut abbreviated = String::with_capacity(self.words.iter().map(|w| w.len() + 1).sum());
+        let mut push_span = |words: Range<usize>, abbreviated: &str, len: usize| {
+            let end = abbreviated.len();
+            spans.push(Span { original: self.range(&words), shortened: end - len..end });
+        };
+        let space = |index: usize| if self.ideographic[index] { IDEOGRAPHIC_SPACE } else { ' ' };
+        // Set by an abbreviation attached to the next word, until a word follows it
+        let mut attach_next = false;
+        for (segment, &applied) in segments.iter().zip(applied) {
+            let glued = self.glued[segment.words.start] || attach_next;
+            match &segment.replacement {
+                Some(replacement) if applied => {
+                    if !replacement.text.is_empty() {
+                        attach_next = replacement.attach_to_next;
+                    }
+                    abbreviated.add_abbrev(
+                        Abbreviation {
+                            text: Cow::Borrowed(&replacement.text),
+                            escalations: Vec::new(),
+                            attach_to_previous: replacement.attach_to_previous || glued,
+                            attach_to_next: replacement.attach_to_next,
+                            priority: replacement.priority,
+                            min_overflow: replacement.min_overflow,
+                            matcher: &replacement.matcher,
+                        },
+                        space(segment.words.start),
+                        self.enclosing,
+                    );
+                    push_span(segment.words.clone(), &abbreviated, replacement.text.len());
                 }
-                if !self.attempt_abbrev(&mut abbreviated, word) {
-                    // Remove enclosing and try word individually
-                    let abbrev = self.abbreviator.abbreviate(enclosed.word);
-                    match abbrev {
-                        Some(abbrev) => {
-                            if !abbrev.text.is_empty() {
-                                abbreviated.add_with_space(enclosed.openers);
-                                abbreviated.add_abbrev(abbrev);
-                                abbreviated.push_str(enclosed.closers);
-                            }
-                        }
-                        None => {
-                            abbreviated.add_with_space(enclosed.openers);
-                            self.abbrev_or_add(&mut abbreviated, enclosed.word);
-                            abbreviated.push_str(enclosed.closers);
+                _ => {
+                    for index in segment.words.clone() {
+                        let attached = std::mem::take(&mut attach_next);
+                        if self.glued[index] || attached {
+                            abbreviated.push_str(self.words[index]);
+                        } else {
+                            let space = space(index);
+                            abbreviated.add_with_space(self.words[index], space, self.enclosing);
                         }
+                        push_span(index..index + 1, &abbreviated, self.words[index].len());
                     }
                 }
-                continue;
             }
+        }
+        abbreviated
+    }
 
-            let Some(found_prev_word) = prev_word else {
-                prev_word = Some(word);
-                continue;
+    /// Replaces the byte ranges of the applied segments in the original line,
+    /// keeping the whitespace between words as it was. Removed words take the
+    /// whitespace before them along (or after them, at the start of the line).
+    fn assemble_in_place(
+        &self,
+        segments: &[Segment],
+        applied: &[bool],
+        spans: &mut Vec<Span>,
+    ) -> String {
+        let original = self.text;
+        let mut abbreviated = String::with_capacity(original.len());
+        let mut end = 0;
+        let mut has_words = false;
+        let mut skip_gap = false;
+        let mut attach_next = false;
+        for (segment, &applied) in segments.iter().zip(applied) {
+            let range = self.range(&segment.words);
+            let gap = if skip_gap || attach_next { "" } else { &original[end..range.start] };
+            skip_gap = false;
+            let text = match &segment.replacement {
+                Some(replacement) if applied && replacement.text.is_empty() => {
+                    if !has_words {
+                        abbreviated.push_str(gap);
+                        skip_gap = true;
+                    }
+                    ""
+                }
+                Some(replacement) if applied => {
+                    if !(replacement.attach_to_previous && has_words) {
+                        abbreviated.push_str(gap);
+                    }
+                    has_words = true;
+                    attach_next = replacement.attach_to_next;
+                    &replacement.text
+                }
+                _ => {
+                    abbreviated.push_str(gap);
+                    has_words = true;
+                    attach_next = false;
+                    &original[range.clone()]
+                }
             };
+            let start = abbreviated.len();
+            abbreviated.push_str(text);
+            end = range.end;
+            spans.push(Span { original: range, shortened: start..abbreviated.len() });
+        }
+        abbreviated.push_str(&original[end..]);
+        abbreviated
+    }
+}
 
-            let pair_of_words = format!("{} {}", found_prev_word, word);
-            if self.attempt_abbrev(&mut abbreviated, &pair_of_words) {
-                prev_word = None;
-            } else {
-                // Attempt to abbreviate the previous word and save the current word for later
-                self.abbrev_or_add(&mut abbreviated, found_prev_word);
-                prev_word = Some(word);
-            }
+/// Splits an identifier before its capitals that follow a lowercase letter or
+/// a digit, and before the last capital of a run followed by a lowercase
+/// letter: "parseHTTPResponse2" is "parse", "HTTP" and "Response2".
+fn split_humps(word: &str) -> Vec<&str> {
+    let chars = word.char_indices().collect_vec();
+    let mut humps = Vec::new();
+    let mut start = 0;
+    for (position, window) in chars.windows(2).enumerate() {
+        let [(_, previous), (index, c)] = *window else {
+            unreachable!("windows of two characters");
+        };
+        let next = chars.get(position + 2);
+        let next_is_lowercase = next.is_some_and(|(_, next)| next.is_lowercase());
+        let boundary = c.is_uppercase()
+            && (previous.is_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_uppercase() && next_is_lowercase));
+        if boundary {
+            humps.push(&word[start..index]);
+            start = index;
         }
+    }
+    humps.push(&word[start..]);
+    humps
+}
 
-        // If there's a word left over, add it to the output (abbreviated or not)
-        if let Some(prev_word) = prev_word {
-            self.abbrev_or_add(&mut abbreviated, prev_word);
+/// Writes the replacements of the humps of identifiers in the case of the
+/// humps they replace, however the rules write them, so that
+/// "architecture_session" stays snake case and "ARCHITECTURE_SESSION" stays
+/// upper case.
+fn match_hump_case(words: &[&str], segments: &mut [Segment], rules: CaseRules) {
+    for segment in segments {
+        let Some(replacement) = &mut segment.replacement else {
+            continue;
+        };
+        let case = TextCase::of(words[segment.words.start]);
+        for text in iter::once(&mut replacement.text).chain(&mut replacement.escalations) {
+            *text = CasePolicy::Mirror.apply(text, case, rules).into_owned();
         }
+    }
+}
 
-        Cow::Owned(abbreviated)
+/// Cuts the text to at most `max_length` without splitting a grapheme cluster,
+/// dropping a kept word whole rather than cutting it.
+fn truncate(text: Cow<'_, str>, max_length: usize, is_kept: impl Fn(&str) -> bool) -> Cow<'_, str> {
+    let mut end = width::truncate(&text, max_length).len();
+    let word_start = text[..end].trim_end_matches(|c: char| !c.is_whitespace()).len();
+    let word_end = text[end..].find(char::is_whitespace).map_or(text.len(), |index| end + index);
+    if word_start < end && end < word_end && is_kept(&text[word_start..word_end]) {
+        end = word_start;
     }
 
-    fn attempt_abbrev(&self, abbreviated: &mut String, text: &str) -> bool {
-        self.abbreviator
-            .abbreviate(text)
-            .map(|abbrev| abbreviated.add_abbrev(abbrev))
-            .is_some()
+    match text {
+        Cow::Borrowed(text) => Cow::Borrowed(text[..end].trim_end()),
+        Cow::Owned(text) => Cow::Owned(text[..end].trim_end().to_string()),
     }
+}
 
-    fn abbrev_or_add(&self, abbreviated: &mut String, text: &str) {
-        match self.abbreviator.abbreviate(text) {
-            Some(abbrev) => abbreviated.add_abbrev(abbrev),
-            None => abbreviated.add_with_space(text),
-        }
+/// Cuts the start of the text so it is at most `max_length` long, like
+/// [`truncate`] does with the end.
+fn truncate_start(
+    text: Cow<'_, str>,
+    max_length: usize,
+    is_kept: impl Fn(&str) -> bool,
+) -> Cow<'_, str> {
+    let mut start = text.len() - width::truncate_start(&text, max_length).len();
+    let mut spaces = text[..start].rmatch_indices(char::is_whitespace);
+    let word_start = spaces.next().map_or(0, |(index, space)| index + space.len());
+    let word_end = text[start..].find(char::is_whitespace).map_or(text.len(), |index| start + index);
+    if word_start < start && start < word_end && is_kept(&text[word_start..word_end]) {
+        start = word_end;
+    }
+
+    match text {
+        Cow::Borrowed(text) => Cow::Borrowed(text[start..].trim_start()),
+        Cow::Owned(text) => Cow::Owned(text[start..].trim_start().to_string()),
     }
 }
 
 trait AddWithSpace {
-    fn add_with_space(&mut self, s: &str);
-    fn add_abbrev(&mut self, abbrev: Abbreviation);
+    fn add_with_space(&mut self, s: &str, space: char, enclosing: &Enclosing);
+    fn add_abbrev(&mut self, abbrev: Abbreviation<'_>, space: char, enclosing: &Enclosing);
 }
 
 impl AddWithSpace for String {
-    fn add_with_space(&mut self, s: &str) {
+    fn add_with_space(&mut self, s: &str, space: char, enclosing: &Enclosing) {
         if s.is_empty() {
             return;
         }
@@ -128,77 +1972,41 @@ impl AddWithSpace for String {
         let is_opener = self
             .chars()
             .last()
-            .is_some_and(|c| c.is_opener());
+            .is_some_and(|c| enclosing.is_opener(c));
 
         if !is_opener && !self.is_empty() {
-            self.push(' ');
+            self.push(space);
         }
         self.push_str(s);
     }
 
-    fn add_abbrev(&mut self, abbrev: Abbreviation) {
+    fn add_abbrev(&mut self, abbrev: Abbreviation<'_>, space: char, enclosing: &Enclosing) {
         if abbrev.text.is_empty() {
             return;
         }
         if abbrev.attach_to_previous {
-            self.push_str(abbrev.text);
+            self.push_str(&abbrev.text);
         } else {
-            self.add_with_space(abbrev.text);
+            self.add_with_space(&abbrev.text, space, enclosing);
         }
     }
 }
 
-trait Enclosing {
-    fn is_opener(&self) -> bool;
-    fn is_closer(&self) -> bool;
-}
-
-impl Enclosing for char {
-    fn is_opener(&self) -> bool {
-        matches!(
-            self,
-            '(' | '[' | '{' | '<' | '"' | '*'
-        )
-    }
-    fn is_closer(&self) -> bool {
-        matches!(
-            self,
-            ')' | ']' | '}' | '>' | '"' | '*'
-        )
-    }
-}
-
-struct EnclosedWord<'a> {
-    word: &'a str,
-    openers: &'a str,
-    closers: &'a str,
-}
-
-impl EnclosedWord<'_> {
-    fn is_enclosed(&self) -> bool {
-        !self.openers.is_empty() || !self.closers.is_empty()
-    }
-}
-
-fn process_enclosed_word(input: &str) -> EnclosedWord {
-    let opener_len = input.chars().take_while(|c| c.is_opener()).count();
-    let closer_len = input.chars().rev().take_while(|c| c.is_closer()).count();
-    EnclosedWord {
-        word: &input[opener_len..input.len() - closer_len],
-        openers: &input[..opener_len],
-        closers: &input[input.len() - closer_len..],
-    }
-}
-
 #[cfg(test)]
 mod tests {
+    use std::borrow::Cow;
     use std::iter::zip;
-    use crate::abbrev::Abbreviator;
-    use crate::shortener::Shortener;
+    use crate::abbrev::{Abbreviator, AbbreviatorBuilder};
+    use crate::case::CasePolicy;
+    use crate::rule::{Matcher, Rule};
+    use crate::shortener::{
+        drop_vowels, Acronyms, Anchor, Clock, Dates, Edit, Mode, Shortener, Strategy,
+        ACRONYM_MATCHER, DATES_MATCHER, NUMBERS_MATCHER, VOWELS_MATCHER,
+    };
+    use crate::stop_words::StopWords;
+    use crate::width;
 
-    #[test]
-    fn test_shorten() {
-        let lines: Vec<&str> = r#"
+    const TEST_ABBREVIATIONS: &str = r#"
             Architecture              = arch
             Learning                  = learn
             Audience                  = audn
@@ -215,15 +2023,24 @@ mod tests {
             Rescheduled =
             [Monthly] = [M]
             [Weekly] = [W]
-        "#.split('\n').collect();
-        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
-        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+            Monthly All Hands Meeting = MAHM
+        "#;
+
+    fn test_shortener(desired_max_length: usize) -> Shortener {
+        let abbreviator = Abbreviator::from_lines(TEST_ABBREVIATIONS.lines()).unwrap();
+        Shortener::with_abbreviator(desired_max_length, abbreviator).unwrap()
+    }
+
+    #[test]
+    fn test_shorten() {
+        let shortener = test_shortener(10);
 
         let input = vec![
             "Architecture Section Learning Session",
             "*Rescheduled* [W] MPD Architecture Excellence Group Weekly Connect",
             "[Monthly] CLSD All Hands Meeting *Rescheduled*",
             "RIAM Tech Camp (Session one)",
+            "CLSD Monthly All Hands Meeting",
         ];
 
         let expected= vec![
@@ -231,6 +2048,7 @@ mod tests {
             "[W] MPD Arch Excl Group 毎週 Connect",
             "[M] CLSD All Hands Meeting",
             "RIAM Tech Camp (Sesn 1)",
+            "CLSD MAHM",
         ];
 
         for (input, expected) in zip(input, expected) {
@@ -238,4 +2056,641 @@ mod tests {
             assert_eq!(shortened, expected);
         }
     }
+
+    #[test]
+    fn test_shorten_into() {
+        let shortener = test_shortener(10);
+        let mut out = String::from("> ");
+        for line in ["Architecture Section", "Short"] {
+            shortener.shorten_into(line, &mut out);
+            out.push('\n');
+        }
+        assert_eq!(out, "> Arch課\nShort\n");
+        shortener.shorten_into("", &mut out);
+        assert_eq!(out, "> Arch課\nShort\n");
+        assert!(matches!(shortener.shorten("Short"), Cow::Borrowed("Short")));
+
+        let lines = vec!["Architecture Section"; 100];
+        assert!(shortener.shorten_batch(&lines).iter().all(|line| line == "Arch課"));
+    }
+
+    #[test]
+    fn test_shorten_cache() {
+        let mut shortener = test_shortener(10).cache(2);
+        assert_eq!(shortener.shorten("Architecture Section"), "Arch課");
+        assert_eq!(shortener.shorten("Architecture Section"), "Arch課");
+
+        let abbreviator = Abbreviator::from_lines(["Architecture = archi"].into_iter()).unwrap();
+        shortener.set_abbreviator(abbreviator);
+        assert_eq!(shortener.shorten("Architecture Section"), "Archi Section");
+    }
+
+    #[test]
+    fn test_shorten_threads() {
+        fn assert_send_sync<T: Send + Sync + Clone>() {}
+        assert_send_sync::<Abbreviator>();
+        assert_send_sync::<Shortener>();
+
+        // The clones share the rules but not the cache
+        let shortener = test_shortener(10).cache(2);
+        assert_eq!(shortener.shorten("Architecture Section"), "Arch課");
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let shortener = shortener.clone();
+                let shortened = move || shortener.shorten("Learning Session");
+                scope.spawn(move || assert_eq!(shortened(), "Learn Sesn"));
+            }
+            scope.spawn(|| assert_eq!(shortener.shorten("Architecture Section"), "Arch課"));
+        });
+    }
+
+    #[test]
+    fn test_shorten_batch() {
+        let shortener = test_shortener(10);
+        let texts = ["Architecture Section", "Short", "Learning Session", ""].repeat(100);
+        let shortened = shortener.shorten_batch(&texts);
+        assert_eq!(shortened.len(), texts.len());
+        for (text, shortened) in zip(&texts, shortened) {
+            assert_eq!(shortened, shortener.shorten(text));
+        }
+        assert!(shortener.shorten_batch::<&str>(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_shorten_minimal() {
+        let shortener = test_shortener(32).strategy(Strategy::Minimal);
+
+        // Stops after the first abbreviation since the line already fits
+        let shortened = shortener.shorten("Architecture Learning Audience Session");
+        assert_eq!(shortened, "Arch Learning Audience Session");
+
+        // Falls back to abbreviating everything when the line never fits
+        let shortener = test_shortener(10).strategy(Strategy::Minimal);
+        let shortened = shortener.shorten("Architecture Learning Audience Session");
+        assert_eq!(shortened, "Arch Learn Audn Sesn");
+    }
+
+    #[test]
+    fn test_shorten_optimal() {
+        let shortener = test_shortener(35).strategy(Strategy::Optimal);
+        let text = "Architecture Learning Audience Session";
+
+        // One abbreviation saving 3 characters is enough, wherever it is
+        assert_eq!(shortener.shorten(text), "Architecture Learn Audience Session");
+        // One abbreviation beats two that would keep more characters
+        assert_eq!(shortener.shorten_to(text, 31).text, "Arch Learning Audience Session");
+        assert_eq!(shortener.shorten_to(text, 10).text, "Arch Learn Audn Sesn");
+    }
+
+    #[test]
+    fn test_shorten_length_condition() {
+        let rules = ["Architecture = arch", "@iflen>10 Learning = L"];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(15, abbreviator).unwrap();
+
+        // Still 6 characters too long after the other rules
+        assert_eq!(shortener.shorten("Architecture Learning Session"), "Arch Learning Session");
+        let shortened = shortener.shorten("Architecture Learning Audience Session Notes");
+        assert_eq!(shortened, "Arch L Audience Session Notes");
+        assert!(Abbreviator::from_lines(["@iflen>x Learning = L"].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_shorten_escalations() {
+        let rules = ["Architecture = arch | A.", "Learning = learn | L.", "Session = sesn"];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(15, abbreviator).unwrap();
+        let text = "Architecture Learning Session";
+
+        assert_eq!(shortener.shorten_to(text, 15).text, "Arch Learn Sesn");
+        assert_eq!(shortener.shorten_to(text, 13).text, "A. Learn Sesn");
+        assert_eq!(shortener.shorten_to(text, 10).text, "A. L. Sesn");
+        assert_eq!(shortener.shorten_to("ARCHITECTURE", 3).text, "A.");
+    }
+
+    #[test]
+    fn test_shorten_truncate() {
+        let shortener = test_shortener(10).truncate(true);
+
+        let shortened = shortener.shorten_detailed("Architecture Learning Audience Session");
+        assert_eq!(shortened.text, "Arch Learn");
+        assert!(shortened.truncated);
+
+        let shortened = shortener.shorten_detailed("Architecture Section");
+        assert_eq!(shortened.text, "Arch課");
+        assert!(!shortened.truncated);
+    }
+
+    #[test]
+    fn test_shorten_elision_marker() {
+        let shortener = test_shortener(16).truncate(true).elision_marker(Some("…".to_string()));
+        assert_eq!(shortener.shorten("Architecture Learning Audience"), "Arch Learn Audn");
+        assert_eq!(shortener.shorten("Rescheduled Architecture Learning"), "Arch Learn…");
+        assert_eq!(shortener.shorten("Architecture Learning Audience Session"), "Arch Learn Audn…");
+
+        let shortened = test_shortener(16).shorten_detailed("Rescheduled Architecture Sync");
+        assert!(shortened.elided);
+
+        for (max_length, expected) in [(4, "Arch"), (5, "Arch…")] {
+            let shortener = test_shortener(max_length).elision_marker(Some("…".to_string()));
+            let shortened = shortener.shorten("Rescheduled Architecture");
+            assert_eq!(shortened, expected);
+            assert!(width::length(&shortened) <= max_length);
+        }
+    }
+
+    #[test]
+    fn test_shorten_applied_rules() {
+        let shortener = test_shortener(10);
+
+        let shortened = shortener.shorten_detailed("  Weekly Architecture Meeting");
+        let applied = shortened
+            .applied
+            .iter()
+            .map(|rule| (rule.matcher.as_str(), rule.original.as_str(), rule.replacement.as_str(), rule.position))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            applied,
+            vec![("Weekly", "Weekly", "毎週", 2), ("Architecture", "Architecture", "Arch", 9)]
+        );
+    }
+
+    #[test]
+    fn test_shorten_drop_vowels() {
+        assert_eq!(drop_vowels("Management"), "Mngmnt");
+        assert_eq!(drop_vowels("Committee"), "Cmt");
+
+        let text = "Management Committee Architecture Update";
+        let shortened = test_shortener(25).drop_vowels(Some(6)).shorten_detailed(text);
+        assert_eq!(shortened.text, "Mngmnt Cmt Arch Update");
+        assert_eq!(shortened.applied[1].matcher, VOWELS_MATCHER);
+
+        // Words are only shortened until the line fits
+        let shortened = test_shortener(30).drop_vowels(Some(6)).shorten(text);
+        assert_eq!(shortened, "Mngmnt Committee Arch Update");
+    }
+
+    #[test]
+    fn test_shorten_keep() {
+        let abbreviator = Abbreviator::from_lines(
+            [TEST_ABBREVIATIONS, "!keep Architecture", "!keep /^[A-Z]+-\\d+$/"].join("\n").lines(),
+        )
+        .unwrap();
+        let shortener = Shortener::with_abbreviator(20, abbreviator)
+            .unwrap()
+            .keep(["ProjectX"])
+            .truncate(true);
+        let shorten = |shortener: &Shortener, text, max_length| {
+            shortener.shorten_to(text, max_length).text
+        };
+
+        assert_eq!(shorten(&shortener, "Architecture Session", 18), "Architecture Sesn");
+
+        // Truncation drops a kept word rather than cutting it
+        let shortener = shortener.drop_vowels(Some(4));
+        assert_eq!(shorten(&shortener, "ProjectX Planning OPS-1234", 20), "ProjectX Plng");
+
+        let shortener = shortener.drop_vowels(None).acronyms(Some(Acronyms::default()));
+        let shortened = shorten(&shortener, "Quarterly ProjectX Business Review", 21);
+        assert_eq!(shortened, "Quarterly ProjectX BR");
+    }
+
+    #[test]
+    fn test_shorten_preserve_spacing() {
+        let shortener = test_shortener(30).preserve_spacing(true);
+        let text = "  Architecture\t|  Learning   Meeeting Notes  ";
+        assert_eq!(shortener.shorten(text), "  Arch\t|  Learn Notes  ");
+
+        let shortened = shortener.shorten_detailed("Meeeting  Architecture\tLearning Audience");
+        assert_eq!(shortened.text, "Arch\tLearn Audn");
+        assert_eq!(shortened.applied[1].position, 10);
+    }
+
+    #[test]
+    fn test_shorten_attach_next() {
+        let lines = [TEST_ABBREVIATIONS, "Number = #+>", "Quarter = Q +>"].join("\n");
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+        assert_eq!(shortener.shorten("Architecture Number 5 Quarter 3"), "Arch #5 Q3");
+        assert_eq!(shortener.shorten("Number Meeeting 5"), "#5");
+        assert_eq!(shortener.shorten("(Number) 5 Architecture"), "(#) 5 Arch");
+        let shortener = shortener.preserve_spacing(true);
+        assert_eq!(shortener.shorten("Session  Number\t5 Notes"), "Sesn  #5 Notes");
+    }
+
+    #[test]
+    fn test_shorten_line_rules() {
+        let lines = [
+            TEST_ABBREVIATIONS,
+            "^Weekly sync with (.*)$ = Weekly sync: $1 | W:$1",
+            "^(?i)(.*) standup$ = $1 SU",
+            "@iflen>30 ^(.*)$ = -",
+        ]
+        .join("\n");
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(20, abbreviator).unwrap();
+        assert_eq!(shortener.shorten("Weekly sync with Alice"), "Weekly sync: Alice");
+        let shortened = shortener.shorten_detailed("  Weekly sync with Architecture Learning");
+        assert_eq!(shortened.text, "W:Arch Learn");
+        let applied = shortened.applied.iter().map(|rule| (rule.matcher.as_str(), rule.position));
+        let expected = [("^Weekly sync with (.*)$", 2), ("Architecture", 2), ("Learning", 2)];
+        assert_eq!(applied.collect::<Vec<_>>(), expected);
+        assert_eq!(shortener.shorten("Weekly Architecture STANDUP"), "毎週 Arch SU");
+        assert_eq!(shortener.shorten(&"Session ".repeat(10)), "-");
+    }
+
+    #[test]
+    fn test_shorten_preserve_unmatched() {
+        let shortener = test_shortener(10).preserve_unmatched(true);
+        assert_eq!(shortener.shorten("  Weird\t spacing  here "), "  Weird\t spacing  here ");
+        assert_eq!(shortener.shorten(" Short\t "), " Short\t ");
+        assert_eq!(shortener.shorten("Architecture\t Review"), "Arch Review");
+        let shortener = shortener.truncate(true);
+        assert_eq!(shortener.shorten("  Weird\t spacing  here "), "Weird spac");
+        assert_eq!(test_shortener(10).shorten("  Weird\t spacing  here "), "Weird spacing here");
+    }
+
+    #[test]
+    fn test_shorten_blank() {
+        let shortener = test_shortener(4).mode(Mode::Path).truncate(true);
+        assert_eq!(shortener.shorten(" \t "), " \t ");
+        assert_eq!(shortener.shorten("      "), "");
+        assert_eq!(shortener.clone().preserve_unmatched(true).shorten("      "), "      ");
+        let shortener = shortener.blank_placeholder(Some("-".to_string()));
+        assert_eq!(shortener.shorten(""), "-");
+        assert_eq!(shortener.shorten("      "), "-");
+        assert_eq!(shortener.shorten("Architecture"), "Arch");
+    }
+
+    #[test]
+    fn test_shorten_exceptions() {
+        let rules = [
+            "/^Arch/i = A",
+            "/Security$/i = Sec",
+            "Cloud Security = CS",
+            "Review = Rvw",
+            "!except Security Architecture",
+            "!except Review-Board",
+            "Board = Bd",
+        ];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+        assert_eq!(shortener.shorten("Cloud Security Architecture"), "Cloud Security Architecture");
+        assert_eq!(shortener.shorten("Cloud Security Review"), "CS Rvw");
+        assert_eq!(shortener.shorten("Security Architecture Review"), "Security Architecture Rvw");
+        assert_eq!(shortener.shorten("Review-Board Meeting"), "Review-Board Meeting");
+
+        let abbreviator = Abbreviator::from_lines(rules.into_iter().chain(["Security = Sec"]));
+        let shortener = Shortener::with_abbreviator(10, abbreviator.unwrap()).unwrap();
+        assert_eq!(shortener.shorten("Security Architecture"), "Sec Architecture");
+        let Err(err) = Abbreviator::from_lines(["!except /^Arch/"].into_iter()) else {
+            panic!("pattern exception accepted");
+        };
+        let expected = "line 1: Exceptions are the exact text of a word or phrase, not a pattern";
+        assert_eq!(err.to_string(), expected);
+    }
+
+    #[test]
+    fn test_shorten_identifiers() {
+        let shortener = test_shortener(15).mode(Mode::Identifier);
+        assert_eq!(shortener.shorten("ArchitectureLearningSession"), "ArchLearnSesn");
+        assert_eq!(shortener.shorten("architectureLearning_session"), "archLearn_sesn");
+        assert_eq!(shortener.shorten("AUDIENCE_HTTPSession_ID"), "AUDN_HTTPSesn_ID");
+        assert_eq!(shortener.shorten("architectureLearning"), "archLearn");
+        let text = "ArchitectureLearningSession";
+        assert_eq!(test_shortener(15).shorten(text), text);
+
+        let lines = ["Architecture = Arch", "Learning = Learn", "Session = SESN"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(15, abbreviator).unwrap();
+        let shortener = shortener.mode(Mode::Identifier);
+        assert_eq!(shortener.shorten("architecture_learning_session"), "arch_learn_sesn");
+        assert_eq!(shortener.shorten("ARCHITECTURE_LEARNING_SESSION"), "ARCH_LEARN_SESN");
+        assert_eq!(shortener.shorten("architectureLearningSession"), "archLearnSesn");
+    }
+
+    #[test]
+    fn test_shorten_enclosing() {
+        let lines = [TEST_ABBREVIATIONS, "@enclose 「」"].join("\n");
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(20, abbreviator).unwrap();
+        assert_eq!(shortener.shorten("「Architecture」 Learning Session"), "「Arch」 Learn Sesn");
+        let shortener = test_shortener(20);
+        assert_eq!(shortener.shorten("«Architecture» “Learning” Session"), "«Arch» “Learn” Sesn");
+
+        // Multi-byte openers and closers at the ends of words used to be sliced mid-character
+        let shortener = test_shortener(10);
+        assert_eq!(shortener.shorten("「Architecture Learning"), "「Arch Learn");
+        assert_eq!(shortener.shorten("«Architecture Learning»"), "«Arch Learn»");
+        assert_eq!(shortener.shorten("Architecture」 Learning"), "Arch」 Learn");
+        assert_eq!(shortener.shorten("「」 Architecture Learning"), "「」 Arch Learn");
+    }
+
+    #[test]
+    fn test_shorten_enclosed_phrases() {
+        let shortener = test_shortener(15);
+        assert_eq!(shortener.shorten("Review (Monthly All Hands Meeting)"), "Review (MAHM)");
+        assert_eq!(shortener.shorten("[Monthly All Hands Meeting] Notes"), "[MAHM] Notes");
+        assert_eq!(shortener.shorten("(Architecture Learning) Session"), "(Arch Learn) Sesn");
+    }
+
+    #[test]
+    fn test_shorten_bench_fixtures() {
+        let rules = include_str!("../benches/fixtures/regex.lst");
+        let abbreviator = Abbreviator::from_lines(rules.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(30, abbreviator).unwrap();
+        for title in include_str!("../benches/fixtures/titles.txt").lines() {
+            assert!(width::length(&shortener.shorten(title)) <= width::length(title));
+        }
+        let title = "Weekly Sync: Platform Reliability and Incident Response";
+        assert_eq!(shortener.shorten(title), "W: Platform Rel and Incident Response");
+        let title = "Customer Experience Department Planning Meeting";
+        assert_eq!(shortener.shorten(title), "Customer Exp Dept Mtg");
+    }
+
+    #[test]
+    fn test_shorten_never_lengthens() {
+        let lines = [TEST_ABBREVIATIONS, "/^Meet(ing)?s?$/i = Mtg", "「Weekly」 = 「W」"].join("\n");
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(0, abbreviator).unwrap();
+        let lines = [
+            "", " ", "«", "「", "」", "「」", "«»", "(「Weekly」)", "「Weekly", "Weekly」",
+            "«Architecture Learning", "Architecture」 Learning", "[Monthly」 Meeting",
+            "e\u{301} Architecture", "Sesión «Architecture» 部 Meetings", "\t«\u{3000}»",
+        ];
+        for line in lines {
+            for max_length in 0..=width::length(line) + 1 {
+                let shortened = shortener.shorten_to(line, max_length).text;
+                assert!(
+                    width::length(&shortened) <= width::length(line),
+                    "{line:?} was lengthened to {shortened:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_shorten_longest_phrase() {
+        let lines = [TEST_ABBREVIATIONS, "Monthly All = MA", "All Hands = AH"].join("\n");
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+        assert_eq!(shortener.shorten("Monthly All Hands Meeting Notes"), "MAHM Notes");
+        assert_eq!(shortener.shorten("Monthly All Hands Notes"), "MA Hands Notes");
+        assert_eq!(shortener.shorten("Weekly All Hands Meeting"), "毎週 AH Meeting");
+        assert_eq!(shortener.shorten("Monthly Architecture Meeting"), "毎月 Arch Meeting");
+    }
+
+    #[test]
+    fn test_shorten_segments() {
+        let lines = [TEST_ABBREVIATIONS, r"(/\[.*rescheduled.*\]/i) ="].join("\n");
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(20, abbreviator).unwrap();
+        let text = "[Moved: Rescheduled to Friday] Architecture Sync";
+        assert_eq!(shortener.shorten(text), "Arch Sync");
+        let text = "Architecture [rescheduled twice, see notes] Sync";
+        assert_eq!(shortener.shorten(text), "Arch Sync");
+        assert_eq!(shortener.shorten("Architecture Sync [rescheduled]"), "Arch Sync");
+        assert_eq!(shortener.shorten("Architecture [Monthly] Session"), "Arch [M] Sesn");
+
+        let shortener = shortener.preserve_spacing(true);
+        let text = "[rescheduled x2]  Architecture\tSync  [Rescheduled]";
+        assert_eq!(shortener.shorten(text), "Arch\tSync");
+    }
+
+    #[test]
+    fn test_shorten_separators() {
+        let shortener = test_shortener(20);
+        assert_eq!(shortener.shorten("Architecture/Session|Audience: notes"), "Arch/Sesn|Audn: notes");
+        assert_eq!(shortener.shorten("app · Architecture:Learning"), "app · Arch:Learn");
+
+        let text = "Learning-Session_Audience notes";
+        assert_eq!(shortener.shorten(text), "Learn-Sesn_Audn notes");
+
+        // Compounds a rule matches whole, and hyphenated numbers, are not split
+        let rules = [TEST_ABBREVIATIONS, "Follow-up = f/u"].join("\n");
+        let abbreviator = Abbreviator::from_lines(rules.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(15, abbreviator).unwrap();
+        assert_eq!(shortener.shorten("Follow-up Architecture-Session"), "F/u Arch-Sesn");
+        let shortener = test_shortener(20).numbers(true);
+        assert_eq!(shortener.shorten("Twenty-Third Architecture-Meetup"), "23rd Arch-Meetup");
+
+        let shortener = test_shortener(20).separators("");
+        assert_eq!(shortener.shorten("Architecture/Session notes"), "Architecture/Session notes");
+    }
+
+    #[test]
+    fn test_shorten_acronyms() {
+        let shortener = test_shortener(20).acronyms(Some(Acronyms::default()));
+        assert_eq!(shortener.shorten("Quarterly Business Review"), "QBR");
+        assert_eq!(shortener.shorten("Quarterly Business Review of Q3"), "QBR of Q3");
+        assert_eq!(shortener.shorten("Architecture Quarterly Review Sync"), "Arch QRS");
+
+        let shortener = test_shortener(12).acronyms(Some(Acronyms { min_words: 2, max_words: 2 }));
+        let shortened = shortener.shorten_detailed("Quarterly Business Review Meeting");
+        assert_eq!(shortened.text, "QB RM");
+        assert_eq!(shortened.applied[0].matcher, ACRONYM_MATCHER);
+        assert_eq!(shortened.applied[0].original, "Quarterly Business");
+    }
+
+    #[test]
+    fn test_shorten_numbers() {
+        let shortener = test_shortener(30).numbers(true);
+        let text = "Architecture Twenty-Third Session, Three Hundred and Five people";
+        assert_eq!(shortener.shorten(text), "Arch 23rd Session, 305 people");
+        let shortened = shortener.shorten_to("Learning budget of 1,250,000 for Audience", 32);
+        assert_eq!(shortened.text, "Learn budget of 1250000 for Audn");
+        assert_eq!(shortened.applied[1].matcher, NUMBERS_MATCHER);
+        let unconverted = "Arch Twenty-Third Session, Three Hundred and Five people";
+        assert_eq!(test_shortener(60).shorten(text), unconverted);
+    }
+
+    #[test]
+    fn test_shorten_hooks() {
+        let hashes = |word: &str, overflow: usize| {
+            let hash = word.len() > 7 && word.chars().all(|c| c.is_ascii_hexdigit());
+            hash.then(|| word[..if overflow > 5 { 4 } else { 7 }].to_string())
+        };
+        let shortener = test_shortener(26).hook("hash", hashes);
+        let shortened = shortener.shorten_detailed("Fix a1b2c3d4e5f6 in Architecture Session");
+        assert_eq!(shortened.text, "Fix a1b2c3d in Arch Sesn");
+        assert!(shortened.applied.iter().any(|rule| rule.matcher == "<hash>"));
+        let shortened = shortener.shorten_to("a1b2c3d4e5f6 Architecture Session", 15);
+        assert_eq!(shortened.text, "a1b2 Arch Sesn");
+        assert_eq!(shortener.shorten("Fix a1b2c3d4e5f6 Session"), "Fix a1b2c3d4e5f6 Session");
+
+        // Hooks start from the end away from the anchor, like the other passes
+        let text = "a1b2c3d4e5f6 b1b2c3d4e5f6";
+        assert_eq!(shortener.shorten_to(text, 20).text, "a1b2c3d b1b2c3d4e5f6");
+        let shortened = shortener.shorten_anchored(text, 20, Some(Anchor::Left));
+        assert_eq!(shortened.text, "a1b2c3d4e5f6 b1b2c3d");
+    }
+
+    #[test]
+    fn test_shorten_dates() {
+        let shortener = test_shortener(30).dates(Some(Dates::default()));
+        let text = "Architecture Review September 25, 2024 10:00 AM - 11:00 AM";
+        let shortened = shortener.shorten_detailed(text);
+        assert_eq!(shortened.text, "Arch Review Sep 25 10–11am");
+        let matchers = shortened.applied.iter().map(|rule| &rule.matcher[..]).collect::<Vec<_>>();
+        assert_eq!(matchers, [DATES_MATCHER, "Architecture"]);
+        assert_eq!(shortener.shorten("Review September 25, 2024"), "Review September 25, 2024");
+
+        let dates = Dates::new("%d/%m", Clock::TwentyFourHour).unwrap();
+        let shortener = test_shortener(20).dates(Some(dates));
+        assert_eq!(shortener.shorten("Tuesday, 1 October 2024 9:30am - 1pm"), "01/10 9:30–13:00");
+        assert!(Dates::new("%Q", Clock::TwelveHour).is_err());
+    }
+
+    #[test]
+    fn test_shorten_stop_words() {
+        let stop_words = StopWords::from_lines(["the", "of", "for"].into_iter());
+        let shortener = test_shortener(25).stop_words(Some(stop_words));
+
+        // Short enough after abbreviation, so stop words are kept
+        let shortened = shortener.shorten("Architecture of the Session");
+        assert_eq!(shortened, "Arch of the Sesn");
+
+        let shortened = shortener.shorten("State of the Art for the Tech Camp");
+        assert_eq!(shortened, "State Art the Tech Camp");
+
+        let shortener = test_shortener(25).stop_words(Some(StopWords::droppable()));
+        let shortened = shortener.shorten("State of the Art for the Tech Camp");
+        assert_eq!(shortened, "State Art the Tech Camp");
+        let shortened = shortener.shorten("Tips and Tricks from the State Camp");
+        assert_eq!(shortened, "Tips Tricks State Camp");
+    }
+
+    #[test]
+    fn test_shorten_word_priorities() {
+        let rules = [TEST_ABBREVIATIONS, "@low the, of, for", "@high ProjectX"].join("\n");
+        let abbreviator = Abbreviator::from_lines(rules.lines()).unwrap();
+        let shortener = |max_length| Shortener::with_abbreviator(max_length, abbreviator.clone());
+        let text = "State of the Art for ProjectX Review Camp";
+        assert_eq!(shortener(30).unwrap().shorten(text), "State Art ProjectX Review Camp");
+        assert_eq!(shortener(25).unwrap().shorten(text), "Art ProjectX Review Camp");
+        assert_eq!(shortener(15).unwrap().shorten(text), "ProjectX Camp");
+        assert_eq!(shortener(15).unwrap().shorten("Architecture Session"), "Arch Sesn");
+        let abbreviator = abbreviator.with_groups::<&str>(&[], &[]).unwrap();
+        let shortener = Shortener::with_abbreviator(25, abbreviator).unwrap();
+        assert_eq!(shortener.shorten(text), "Art ProjectX Review Camp");
+    }
+
+    #[test]
+    fn test_shorten_unicode_spaces() {
+        let stop_words = || Some(StopWords::from_lines(["km"].into_iter()));
+        let text = "Architecture Learning Audience 10\u{a0}km";
+        assert_eq!(test_shortener(20).stop_words(stop_words()).shorten(text), "Arch Learn Audn 10");
+
+        let shortener = test_shortener(20).stop_words(stop_words()).unicode_spaces(true);
+        assert_eq!(shortener.shorten(text), "Arch Learn Audn 10\u{a0}km");
+        let text = "Architecture\u{3000}Learning Session";
+        assert_eq!(shortener.shorten(text), "Arch\u{3000}Learn Sesn");
+    }
+
+    #[test]
+    fn test_shorten_unicode_normalization() {
+        let lines = [TEST_ABBREVIATIONS, "Café = C"].join("\n");
+        let abbreviator = Abbreviator::from_lines(lines.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(20, abbreviator).unwrap();
+        // The words left as they are keep their decomposed accents
+        let text = "Cafe\u{301} Architecture Rene\u{301}e";
+        assert_eq!(shortener.shorten(text), "C Arch Rene\u{301}e");
+    }
+
+    #[test]
+    fn test_shorten_inflections() {
+        let lines = [TEST_ABBREVIATIONS, "Session~ = sesn", "Monthly All Hands Meeting~ = MAHM"];
+        let abbreviator = Abbreviator::from_lines(lines.join("\n").lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+        assert_eq!(shortener.shorten("Learning Sessions"), "Learn Sesns");
+        assert_eq!(shortener.shorten("Monthly All Hands Meetings"), "MAHMs");
+        assert_eq!(shortener.shorten("Session's Audiences"), "Sesn's Audiences");
+    }
+
+    #[cfg(feature = "cjk")]
+    #[test]
+    fn test_shorten_cjk_segmentation() {
+        let abbreviator = Abbreviator::from_lines(["Meeting = Mtg"].into_iter()).unwrap();
+        assert!(abbreviator.cjk_dictionary().is_none());
+
+        let rules = ["定例会議 = 定例", "お知らせ = 通知"];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(8, abbreviator).unwrap();
+        let text = "第3回定例会議のお知らせ";
+        assert_eq!(shortener.shorten(text), text);
+
+        let shortener = shortener.cjk_segmentation(true);
+        assert_eq!(shortener.shorten(text), "第3回定例の通知");
+    }
+
+    #[test]
+    fn test_shorten_case_policy() {
+        let review = Rule::new(Matcher::Exact("Review".into()), "rev");
+        let review = Rule { case: Some(CasePolicy::Preserve), ..review };
+        let abbreviator = AbbreviatorBuilder::new()
+            .exact("Architecture", "Arch")
+            .exact("Learning", "LRN")
+            .exact("Session", "sesn")
+            .rule(review)
+            .build()
+            .unwrap();
+        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+        let text = "architecture Learning SESSION Review";
+        assert_eq!(shortener.shorten(text), "Arch LRN SESN rev");
+        let shortener = shortener.case(Some(CasePolicy::Mirror));
+        assert_eq!(shortener.shorten(text), "arch Lrn SESN rev");
+
+        let rules = "@case force-lower\nArchitecture = Arch\nLearning = LRN";
+        let abbreviator = Abbreviator::from_lines(rules.lines()).unwrap();
+        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+        assert_eq!(shortener.shorten("ARCHITECTURE Learning"), "arch lrn");
+    }
+
+    #[test]
+    fn test_shorten_anchor() {
+        let stop_words = StopWords::from_lines(["the", "of", "for"].into_iter());
+        let shortener = test_shortener(25).stop_words(Some(stop_words));
+        let text = "State of the Art for the Tech Camp";
+        let shortened = shortener.shorten_anchored(text, 25, Some(Anchor::Left));
+        assert_eq!(shortened.text, "State of Art Tech Camp");
+
+        let shortener = test_shortener(20).truncate(true).anchor(Some(Anchor::Right));
+        let text = "Architecture Learning Audience Session Notes";
+        assert_eq!(shortener.shorten(text), "earn Audn Sesn Notes");
+        let shortener = shortener.elision_marker(Some("…".to_string()));
+        assert_eq!(shortener.shorten(text), "…arn Audn Sesn Notes");
+
+        // Cutting next to a multi-byte space
+        let shortener = test_shortener(10).truncate(true).anchor(Some(Anchor::Right));
+        let shortener = shortener.preserve_spacing(true);
+        assert_eq!(shortener.shorten("Planning\u{3000}Review Sessions"), "w Sessions");
+    }
+
+    #[test]
+    fn test_shorten_with_spans() {
+        let shortener = test_shortener(10);
+        let text = " Architecture Rescheduled Session Notes";
+        let (shortened, edits) = shortener.shorten_with_spans(text);
+        assert_eq!(shortened, "Arch Sesn Notes");
+        let edit = |original, shortened, replacement: &str| Edit {
+            original,
+            shortened,
+            replacement: replacement.to_string(),
+        };
+        let expected = [
+            edit(0..1, 0..0, ""),
+            edit(1..13, 0..4, "Arch"),
+            edit(13..25, 4..4, ""),
+            edit(26..33, 5..9, "Sesn"),
+        ];
+        assert_eq!(edits, expected);
+        let mut applied = text.to_string();
+        for edit in edits.iter().rev() {
+            applied.replace_range(edit.original.clone(), &edit.replacement);
+        }
+        assert_eq!(applied, shortened);
+
+        let (_, edits) = shortener.ansi(true).shorten_with_spans("\x1b[1mArchitecture\x1b[0m");
+        assert_eq!(edits, [edit(0..20, 0..12, "\x1b[1mArch\x1b[0m")]);
+    }
 }
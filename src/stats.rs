@@ -0,0 +1,184 @@
+//! `--stats`: a summary of how the input was shortened, to tell how well a set
+//! of rules fits a corpus.
+
+use itertools::Itertools;
+use shorten::abbrev::{Abbrev, Abbreviator, RuleKind};
+use shorten::shortener::Shortened;
+use shorten::width;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+
+/// How many of the most frequently fired rules the summary lists
+const TOP_RULES: usize = 10;
+
+#[derive(Default)]
+pub struct Stats {
+    pub lines: usize,
+    pub changed: usize,
+    /// The lines still longer than their maximum length once shortened
+    pub over_budget: usize,
+    /// How many times each rule fired, by matcher
+    fired: HashMap<String, usize>,
+}
+
+impl Stats {
+    pub fn record(&mut self, text: &str, shortened: &Shortened<'_>, max_length: usize) {
+        self.lines += 1;
+        self.changed += usize::from(shortened.text != text);
+        self.over_budget += usize::from(width::length(&shortened.text) > max_length);
+        for rule in &shortened.applied {
+            *self.fired.entry(rule.matcher.clone()).or_default() += 1;
+        }
+    }
+
+    /// The rules of the abbreviator (without keep rules, which protect words
+    /// rather than fire) along with how many times they fired, once each.
+    fn rule_counts<'a>(&self, abbreviator: &'a Abbreviator) -> Vec<(&'a Abbrev, usize)> {
+        let mut seen = HashSet::new();
+        abbreviator
+            .rules()
+            .into_iter()
+            .filter(|rule| rule.kind() != RuleKind::Keep && seen.insert(&rule.source))
+            .map(|rule| (rule, self.fired.get(&rule.source).copied().unwrap_or_default()))
+            .collect()
+    }
+
+    /// Writes the summary, listing the rules of the abbreviator that never fired.
+    pub fn report(
+        &self,
+        abbreviator: &Abbreviator,
+        output: &mut impl Write,
+    ) -> std::io::Result<()> {
+        writeln!(output, "Lines processed: {}", self.lines)?;
+        writeln!(output, "Lines changed: {}", self.changed)?;
+        writeln!(output, "Lines still too long: {}", self.over_budget)?;
+
+        let most_fired = self
+            .fired
+            .iter()
+            .sorted_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)))
+            .take(TOP_RULES);
+        writeln!(output, "Most fired rules:")?;
+        for (matcher, count) in most_fired {
+            writeln!(output, "{count:>8}  {matcher}")?;
+        }
+
+        let never_fired = self
+            .rule_counts(abbreviator)
+            .into_iter()
+            .filter_map(|(rule, count)| (count == 0).then_some(rule))
+            .collect::<Vec<_>>();
+        writeln!(output, "Rules that never fired: {}", never_fired.len())?;
+        for rule in never_fired {
+            writeln!(output, "          {} ({})", rule.source, rule.location)?;
+        }
+        Ok(())
+    }
+}
+
+/// `shorten coverage`: the stats of a corpus, along with the lines still too
+/// long and how many lines ended up at each length.
+#[derive(Default)]
+pub struct Coverage {
+    stats: Stats,
+    /// The 1-based line number, text and shortened text of the lines still too long
+    too_long: Vec<(usize, String, String)>,
+    lengths: BTreeMap<usize, usize>,
+}
+
+impl Coverage {
+    pub fn record(&mut self, text: &str, shortened: &Shortened<'_>, max_length: usize) {
+        self.stats.record(text, shortened, max_length);
+        let length = width::length(&shortened.text);
+        *self.lengths.entry(length).or_default() += 1;
+        if length > max_length {
+            self.too_long.push((self.stats.lines, text.to_string(), shortened.text.to_string()));
+        }
+    }
+
+    /// The report as JSON. The rules are listed from the most fired, followed
+    /// by the passes (like `<stop word>`) that changed lines.
+    pub fn to_json(&self, abbreviator: &Abbreviator) -> serde_json::Value {
+        let counts = self.stats.rule_counts(abbreviator);
+        let sources = counts.iter().map(|(rule, _)| rule.source.as_str()).collect::<HashSet<_>>();
+        let rules = counts
+            .iter()
+            .sorted_by_key(|(_, count)| std::cmp::Reverse(*count))
+            .map(|(rule, count)| {
+                let location = rule.location.to_string();
+                json!({ "matcher": rule.source, "location": location, "fired": count })
+            });
+        let passes = self
+            .stats
+            .fired
+            .iter()
+            .filter(|(matcher, _)| !sources.contains(matcher.as_str()))
+            .sorted()
+            .map(|(matcher, count)| {
+                json!({ "matcher": matcher, "location": null, "fired": count })
+            });
+        let too_long = self.too_long.iter().map(|(line, text, shortened)| {
+            let length = width::length(shortened);
+            json!({ "line": line, "original": text, "shortened": shortened, "length": length })
+        });
+        let lengths = self.lengths.iter();
+        let lengths = lengths.map(|(length, lines)| json!({ "length": length, "lines": lines }));
+        json!({
+            "lines": self.stats.lines,
+            "changed": self.stats.changed,
+            "over_budget": self.stats.over_budget,
+            "rules": rules.chain(passes).collect::<Vec<_>>(),
+            "too_long": too_long.collect::<Vec<_>>(),
+            "lengths": lengths.collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::{Coverage, Stats};
+    use shorten::abbrev::Abbreviator;
+    use shorten::shortener::Shortener;
+
+    #[test]
+    fn test_stats() {
+        let lines = ["Architecture = arch", "Session = sesn", "!keep ProjectX"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+        let mut stats = Stats::default();
+        for text in ["Architecture", "Architecture Review", "Review"] {
+            stats.record(text, &shortener.shorten_to(text, 10), 10);
+        }
+
+        let mut report = Vec::new();
+        stats.report(shortener.abbreviator(), &mut report).unwrap();
+        let expected = "Lines processed: 3\nLines changed: 2\nLines still too long: 1\n\
+                        Most fired rules:\n       2  Architecture\n\
+                        Rules that never fired: 1\n          Session (line 2)\n";
+        assert_eq!(String::from_utf8(report).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_coverage() {
+        let lines = ["Architecture = arch", "Session = sesn"];
+        let abbreviator = Abbreviator::from_lines(lines.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+        let mut coverage = Coverage::default();
+        for text in ["Architecture", "Architecture Review", "Review"] {
+            coverage.record(text, &shortener.shorten_to(text, 10), 10);
+        }
+
+        let report = coverage.to_json(shortener.abbreviator());
+        assert_eq!((&report["lines"], &report["over_budget"]), (&3.into(), &1.into()));
+        let fired = report["rules"].as_array().unwrap().iter().map(|rule| &rule["fired"]);
+        assert_eq!(fired.collect::<Vec<_>>(), [&2, &0]);
+        assert_eq!(report["too_long"][0]["line"], 2);
+        assert_eq!(report["too_long"][0]["shortened"], "Arch Review");
+        assert_eq!(report["lengths"], serde_json::json!([
+            { "length": 4, "lines": 1 },
+            { "length": 6, "lines": 1 },
+            { "length": 11, "lines": 1 },
+        ]));
+    }
+}
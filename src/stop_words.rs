@@ -0,0 +1,43 @@
+use crate::abbrev;
+use crate::error::{Result, ShortenError};
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Words that may be dropped from lines that are still too long after abbreviation.
+pub struct StopWords {
+    words: HashSet<String>,
+}
+
+impl StopWords {
+    /// The stop words shipped with shorten.
+    pub fn builtin() -> StopWords {
+        Self::from_lines(include_str!("../stop-words.txt").lines())
+    }
+
+    /// The articles, prepositions and conjunctions shipped with shorten, the
+    /// words dropped from lines by default.
+    pub fn droppable() -> StopWords {
+        Self::from_lines(include_str!("../dropped-words.txt").lines())
+    }
+
+    /// One stop word per line; blank lines and `#` comments are ignored.
+    pub fn from_lines<'a, I: Iterator<Item = &'a str>>(lines: I) -> StopWords {
+        let words = lines
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_lowercase)
+            .collect();
+        StopWords { words }
+    }
+
+    pub fn try_from_file(file_path: &Path) -> Result<StopWords> {
+        let file = std::fs::read_to_string(file_path)
+            .map_err(|source| ShortenError::Io { path: file_path.to_path_buf(), source })?;
+
+        Ok(Self::from_lines(abbrev::strip_bom(&file).lines()))
+    }
+
+    pub fn contains(&self, word: &str) -> bool {
+        self.words.contains(&word.to_lowercase())
+    }
+}
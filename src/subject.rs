@@ -0,0 +1,86 @@
+//! Mail subjects for the subject mode: the reply and forward prefixes piling
+//! up in front ("Re: Fwd: RE[2]:", "AW:" and "WG:" in German clients) collapse
+//! into one, and the tags (`[EXTERNAL]`, mailing-list tags like `[rust-users]`)
+//! are kept once each, before the rest of the subject is abbreviated.
+
+use crate::shortener::Shortened;
+use crate::width;
+use itertools::Itertools;
+use regex::Regex;
+use std::borrow::Cow;
+use std::sync::LazyLock;
+
+/// A reply prefix (first group) or a forward prefix (second group), with an
+/// optional count like "Re[2]:"
+static PREFIX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)^(?:(re|aw|sv|antw|rif)|(fwd?|wg|tr))\s*(?:\[\d+\]|\(\d+\))?\s*:\s*").unwrap()
+});
+
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\[[^\]\s][^\]]*\]\s*").unwrap());
+
+/// Shortens the subject to `max_length`, collapsing its prefixes and tags
+/// first and abbreviating the rest with `abbreviate`. A subject that fits, or
+/// has neither prefixes nor tags, is abbreviated as a whole.
+pub(crate) fn shorten_subject<'a>(
+    subject: &'a str,
+    max_length: usize,
+    abbreviate: impl Fn(&'a str, usize) -> Shortened<'a>,
+) -> Shortened<'a> {
+    let start = subject.trim_start();
+    let mut rest = start;
+    let (mut tags, mut reply, mut forward) = (Vec::<&str>::new(), false, false);
+    loop {
+        if let Some(tag) = TAG.find(rest) {
+            let name = tag.as_str().trim_end();
+            if !tags.iter().any(|other| other.eq_ignore_ascii_case(name)) {
+                tags.push(name);
+            }
+            rest = &rest[tag.end()..];
+        } else if let Some(captures) = PREFIX.captures(rest) {
+            reply |= captures.get(1).is_some();
+            forward |= captures.get(2).is_some();
+            rest = &rest[captures[0].len()..];
+        } else {
+            break;
+        }
+    }
+    if rest.len() == start.len() || rest.is_empty() || width::length(subject) <= max_length {
+        return abbreviate(subject, max_length);
+    }
+
+    let marker = match (reply, forward) {
+        (true, _) => Some("Re:"),
+        (false, true) => Some("Fwd:"),
+        (false, false) => None,
+    };
+    let prefix = tags.into_iter().chain(marker).join(" ");
+    let room = max_length.saturating_sub(width::length(&prefix) + 1);
+    let offset = |part: &str| part.as_ptr() as usize - subject.as_ptr() as usize;
+    let prefix = Cow::Owned(format!("{prefix} "));
+    let prefix = Shortened::replacing(offset(rest) - offset(start), prefix);
+    Shortened::concat([(offset(start), prefix), (offset(rest), abbreviate(rest, room))])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::shortener::{Mode, Shortener};
+
+    #[test]
+    fn test_shorten_subject() {
+        let rules = ["Architecture = arch", "Session = sesn"];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(60, abbreviator).unwrap().mode(Mode::Subject);
+        let shorten = |subject: &str, max_length| {
+            shortener.shorten_to(subject, max_length).text.into_owned()
+        };
+        let subject = "Re: RE[2]: Fw: Architecture Session";
+        assert_eq!(shorten(subject, 40), subject);
+        assert_eq!(shorten(subject, 30), "Re: Architecture Session");
+        assert_eq!(shorten(subject, 20), "Re: Arch Sesn");
+        let subject = "[EXTERNAL] AW: [rust-users] WG: [external] Architecture Session";
+        assert_eq!(shorten(subject, 50), "[EXTERNAL] [rust-users] Re: Architecture Session");
+        assert_eq!(shorten("Fwd: FW: Architecture Session", 25), "Fwd: Architecture Session");
+        assert_eq!(shorten("Review: Architecture Session", 20), "Review: Arch Sesn");
+    }
+}
@@ -0,0 +1,153 @@
+//! Proposes abbreviations for the long and frequent words and phrases of a
+//! corpus that the loaded rules don't cover yet.
+
+use crate::abbrev::Abbreviator;
+use crate::shortener::drop_vowels;
+use crate::stop_words::StopWords;
+use crate::width;
+use itertools::Itertools;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Words and phrases shorter than this are not worth abbreviating.
+const MIN_LENGTH: usize = 7;
+
+/// The longest phrases looked for, in words.
+const MAX_PHRASE_WORDS: usize = 3;
+
+/// A word or phrase worth abbreviating.
+pub struct Suggestion {
+    /// The word or phrase, as it first appeared in the corpus
+    pub text: String,
+    pub count: usize,
+    /// Candidate abbreviations, the preferred one first
+    pub candidates: Vec<String>,
+}
+
+impl Suggestion {
+    /// The suggestion as a ready-to-paste `abbrev.lst` rule, preceded by a
+    /// comment with the number of occurrences and the other candidates.
+    pub fn to_rule_lines(&self) -> String {
+        let alternatives = match &self.candidates[1..] {
+            [] => String::new(),
+            others => format!(", alternatives: {}", others.join(", ")),
+        };
+        format!(
+            "# {} occurrences{alternatives}\n{} = {}",
+            self.count, self.text, self.candidates[0]
+        )
+    }
+}
+
+/// Finds the words and phrases seen at least `min_count` times that no rule
+/// matches, most characters saved first.
+pub fn suggest<'a, I: IntoIterator<Item = &'a str>>(
+    abbreviator: &Abbreviator,
+    lines: I,
+    min_count: usize,
+) -> Vec<Suggestion> {
+    let stop_words = StopWords::builtin();
+    let mut seen: HashMap<String, (String, usize)> = HashMap::new();
+    // Phrases never run over punctuation
+    let clauses = lines
+        .into_iter()
+        .flat_map(|line| line.split([',', '.', ';', ':', '!', '?', '(', ')', '"']));
+    for clause in clauses {
+        let words = clause
+            .split_whitespace()
+            .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()))
+            .collect_vec();
+        for start in 0..words.len() {
+            for len in 1..=MAX_PHRASE_WORDS.min(words.len() - start) {
+                let phrase = &words[start..start + len];
+                // Nor over numbers, and they don't start or end with a stop word
+                if !phrase.iter().all(|word| is_plain_word(word)) {
+                    break;
+                }
+                let edges = [phrase[0], phrase[len - 1]];
+                if len > 1 && edges.iter().any(|word| stop_words.contains(word)) {
+                    continue;
+                }
+                let text = phrase.join(" ");
+                let entry = seen.entry(text.to_lowercase()).or_insert_with(|| (text, 0));
+                entry.1 += 1;
+            }
+        }
+    }
+
+    seen.into_values()
+        .filter(|(text, count)| *count >= min_count && width::length(text) >= MIN_LENGTH)
+        .filter(|(text, _)| {
+            let words = text.split(' ').collect_vec();
+            abbreviator.abbreviate_words(&words).is_none()
+                && (words.len() > 1 || !stop_words.contains(text))
+        })
+        .filter_map(|(text, count)| {
+            let candidates = candidates(&text);
+            (!candidates.is_empty()).then_some(Suggestion { text, count, candidates })
+        })
+        .sorted_by_key(|suggestion| {
+            let saved = width::length(&suggestion.text) - width::length(&suggestion.candidates[0]);
+            (Reverse(saved * suggestion.count), suggestion.text.to_lowercase())
+        })
+        .collect()
+}
+
+fn is_plain_word(word: &str) -> bool {
+    !word.is_empty() && word.chars().all(char::is_alphabetic)
+}
+
+/// An acronym for phrases, and the first letters or the vowel-dropped word
+/// for single words.
+fn candidates(text: &str) -> Vec<String> {
+    let candidates = if text.contains(' ') {
+        let acronym = text
+            .split(' ')
+            .filter_map(|word| word.chars().next())
+            .flat_map(char::to_uppercase)
+            .collect();
+        vec![acronym]
+    } else {
+        let lowercase = text.to_lowercase();
+        vec![
+            width::truncate(&lowercase, 4).to_string(),
+            drop_vowels(&lowercase),
+        ]
+    };
+
+    candidates
+        .into_iter()
+        .filter(|candidate| width::length(candidate) < width::length(text))
+        .unique()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::suggest::suggest;
+
+    #[test]
+    fn test_suggest() {
+        let abbreviator = Abbreviator::from_lines(["Architecture = arch"].into_iter()).unwrap();
+        let corpus = [
+            "Architecture review with the Platform Engineering team",
+            "Platform Engineering: planning, 2 sessions",
+            "Architecture of the planning sessions",
+        ];
+        let suggestions = suggest(&abbreviator, corpus, 2)
+            .iter()
+            .map(|suggestion| suggestion.to_rule_lines())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            suggestions,
+            [
+                "# 2 occurrences\nPlatform Engineering = PE",
+                "# 2 occurrences, alternatives: engnrng\nEngineering = engi",
+                "# 2 occurrences, alternatives: plng\nplanning = plan",
+                "# 2 occurrences, alternatives: pltfrm\nPlatform = plat",
+                "# 2 occurrences, alternatives: sns\nsessions = sess",
+            ]
+        );
+    }
+}
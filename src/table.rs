@@ -0,0 +1,123 @@
+//! `shorten run --csv`: shortening some columns of CSV records, which are
+//! parsed and written back with their quoting, unlike `--field`.
+
+use crate::cli::RunArgs;
+use crate::stats::Stats;
+use csv::StringRecord;
+use eyre::{bail, eyre};
+use shorten::shortener::Shortener;
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+pub fn shorten_columns(
+    shortener: &Shortener,
+    args: &RunArgs,
+    max_length: usize,
+    stats: &mut Stats,
+    (input, output): (impl Read, impl Write),
+) -> eyre::Result<()> {
+    let delimiter = args.delimiter.unwrap_or(',');
+    if !delimiter.is_ascii() {
+        bail!("The CSV delimiter must be an ASCII character, found {delimiter:?}");
+    }
+    let named = args.column.iter().any(|column| column.parse::<usize>().is_err());
+    let header = args.header || named;
+    let options = (&args.column[..], header, delimiter as u8, args.crlf);
+    shorten_records(shortener, options, max_length, stats, input, output)
+}
+
+/// Shortens the fields of the columns, given by (1-based) index or by name
+/// when the input starts with a header.
+fn shorten_records(
+    shortener: &Shortener,
+    (columns, header, delimiter, crlf): (&[String], bool, u8, bool),
+    max_length: usize,
+    stats: &mut Stats,
+    input: impl Read,
+    output: impl Write,
+) -> eyre::Result<()> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(input);
+    let terminator = if crlf { csv::Terminator::CRLF } else { csv::Terminator::Any(b'\n') };
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .terminator(terminator)
+        .flexible(true)
+        .from_writer(output);
+    let mut records = reader.records();
+
+    let names = match header {
+        true => records.next().transpose()?,
+        false => None,
+    };
+    if let Some(names) = &names {
+        writer.write_record(names)?;
+    }
+    let indexes = column_indexes(columns, names.as_ref())?;
+    for record in records {
+        let record = record?;
+        let mut fields = Vec::with_capacity(record.len());
+        for (index, field) in record.iter().enumerate() {
+            if !indexes.contains(&index) {
+                fields.push(Cow::Borrowed(field));
+                continue;
+            }
+            let shortened = shortener.shorten_to(field, max_length);
+            stats.record(field, &shortened, max_length);
+            fields.push(shortened.text);
+        }
+        writer.write_record(fields.iter().map(|field| field.as_bytes()))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The 0-based indexes of the columns. A column is an index when it is a
+/// number, even if the header has a column of that name.
+fn column_indexes(columns: &[String], names: Option<&StringRecord>) -> eyre::Result<Vec<usize>> {
+    columns
+        .iter()
+        .map(|column| match column.parse::<usize>() {
+            Ok(0) => bail!("Column indexes start at 1"),
+            Ok(index) => Ok(index - 1),
+            Err(_) => names
+                .and_then(|names| names.iter().position(|name| name == column))
+                .ok_or_else(|| eyre!("No column named {column:?} in the header")),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::Stats;
+    use crate::table::shorten_records;
+    use shorten::abbrev::Abbreviator;
+    use shorten::shortener::Shortener;
+
+    #[test]
+    fn test_shorten_records() {
+        let rules = ["Architecture = arch", "Session = sesn"];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(10, abbreviator).unwrap();
+        let input = "when,title,room\n\
+                     09:00,\"Team, Architecture Session\",\"Room 1, East Wing\"\n\
+                     10:00,Architecture Session\n";
+        let mut output = Vec::new();
+        let mut stats = Stats::default();
+        let options = (&["title".to_string()][..], true, b',', false);
+        shorten_records(&shortener, options, 12, &mut stats, input.as_bytes(), &mut output).unwrap();
+        let expected = "when,title,room\n\
+                        09:00,\"Team, Arch Sesn\",\"Room 1, East Wing\"\n\
+                        10:00,Arch Sesn\n";
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+        assert_eq!(stats.lines, 2);
+
+        let options = (&["room".to_string()][..], false, b',', true);
+        let result =
+            shorten_records(&shortener, options, 12, &mut stats, input.as_bytes(), Vec::new());
+        assert_eq!(result.unwrap_err().to_string(), "No column named \"room\" in the header");
+    }
+}
@@ -0,0 +1,111 @@
+//! Placeholders in replacements, like `{match}`, `{env:USER}` or `{date:%a}`,
+//! expanded every time the rule fires.
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::Local;
+use crate::error::{ParseErrorKind, Result};
+
+/// A replacement with placeholders.
+#[derive(Clone)]
+pub(crate) struct Template {
+    pieces: Vec<Piece>,
+}
+
+#[derive(Clone)]
+enum Piece {
+    Text(String),
+    /// The text the rule matched
+    Match,
+    /// The value of an environment variable, empty when it is not set
+    Env(String),
+    /// The current local date and time, in the strftime format
+    Date(String),
+}
+
+/// The format of `{date}` without a format
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+impl Template {
+    /// Parses the placeholders of a replacement, or returns `None` when it has
+    /// none. Anything else in braces, like the `{M}` of `{Monthly} = {M}`, is
+    /// kept as written.
+    pub fn parse(replacement: &str) -> Result<Option<Template>> {
+        let mut pieces = Vec::new();
+        let mut rest = replacement;
+        while let Some(start) = rest.find('{') {
+            let Some(end) = rest[start..].find('}').map(|end| start + end) else {
+                break;
+            };
+            let piece = match &rest[start + 1..end] {
+                "match" => Piece::Match,
+                "date" => Piece::Date(DEFAULT_DATE_FORMAT.to_string()),
+                name => match name.split_once(':') {
+                    Some(("env", var)) => Piece::Env(var.to_string()),
+                    Some(("date", format)) => {
+                        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                            let format = format.to_string();
+                            return Err(ParseErrorKind::InvalidDateFormat(format).into());
+                        }
+                        Piece::Date(format.to_string())
+                    }
+                    _ => {
+                        push_text(&mut pieces, &rest[..=start]);
+                        rest = &rest[start + 1..];
+                        continue;
+                    }
+                },
+            };
+            push_text(&mut pieces, &rest[..start]);
+            pieces.push(piece);
+            rest = &rest[end + 1..];
+        }
+
+        if pieces.iter().all(|piece| matches!(piece, Piece::Text(_))) {
+            return Ok(None);
+        }
+        push_text(&mut pieces, rest);
+        Ok(Some(Template { pieces }))
+    }
+
+    /// The replacement of the matched text.
+    pub fn expand(&self, matched: &str) -> String {
+        let mut expanded = String::new();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Text(text) => expanded.push_str(text),
+                Piece::Match => expanded.push_str(matched),
+                Piece::Env(var) => expanded.push_str(&std::env::var(var).unwrap_or_default()),
+                Piece::Date(format) => expanded.push_str(&Local::now().format(format).to_string()),
+            }
+        }
+        expanded
+    }
+}
+
+fn push_text(pieces: &mut Vec<Piece>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match pieces.last_mut() {
+        Some(Piece::Text(last)) => last.push_str(text),
+        _ => pieces.push(Piece::Text(text.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::template::Template;
+
+    #[test]
+    fn test_template() {
+        let expand = |replacement, matched| {
+            Template::parse(replacement).unwrap().map(|template| template.expand(matched))
+        };
+        assert_eq!(expand("{M} sync", "Monthly"), None);
+        assert_eq!(expand("[{match}] {M}", "Weekly"), Some("[Weekly] {M}".to_string()));
+        let home = std::env::var("HOME").unwrap_or_default();
+        assert_eq!(expand("{env:HOME}/{env:SHORTEN_UNSET_VAR}", ""), Some(format!("{home}/")));
+        assert_eq!(expand("{date:%Y}", "").map(|year| year.len()), Some(4));
+        assert!(Template::parse("{date:%Q}").is_err());
+    }
+}
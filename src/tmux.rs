@@ -0,0 +1,99 @@
+//! `shorten tmux-window-name`: a short window name from the pane's title or
+//! working directory, for tmux's `automatic-rename-format`.
+
+use crate::cli::{RulesArgs, TmuxArgs};
+use eyre::{bail, Context};
+use shorten::shortener::{Mode, Shortener};
+use std::process::Command;
+
+pub fn tmux_window_name(rules: &RulesArgs, args: TmuxArgs) -> eyre::Result<()> {
+    let pane = match (args.title, args.path) {
+        (None, None) => query_pane()?,
+        (title, path) => Pane {
+            title: title.unwrap_or_default(),
+            path: path.unwrap_or_default(),
+            host: host_name(),
+        },
+    };
+    let home = std::env::var("HOME").unwrap_or_default();
+    let shortener = Shortener::with_abbreviator(args.width, rules.load()?)?.truncate(true);
+    println!("{}", window_name(shortener, &pane, &home));
+    Ok(())
+}
+
+/// What tmux knows about the current pane.
+struct Pane {
+    title: String,
+    path: String,
+    /// The host name, which tmux uses as the title of panes that didn't set one
+    host: String,
+}
+
+/// The name of this machine, without asking tmux.
+fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .map(|host| host.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Asks tmux about the current pane.
+fn query_pane() -> eyre::Result<Pane> {
+    if std::env::var_os("TMUX").is_none() {
+        bail!("Not running inside tmux, pass --title or --path");
+    }
+    let output = Command::new("tmux")
+        .args(["display-message", "-p", "#{pane_title}\t#{pane_current_path}\t#{host}"])
+        .output()
+        .context("Failed to run tmux")?;
+    if !output.status.success() {
+        bail!("tmux failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut fields = output.trim_end_matches('\n').splitn(3, '\t').map(str::to_string);
+    let mut next = || fields.next().unwrap_or_default();
+    Ok(Pane { title: next(), path: next(), host: next() })
+}
+
+/// The pane's title shortened as text when a program set one, and its path
+/// shortened fish-style otherwise, with `#` escaped for tmux formats.
+fn window_name(shortener: Shortener, pane: &Pane, home: &str) -> String {
+    let title = pane.title.trim();
+    let name = if !title.is_empty() && title != pane.host {
+        shortener.shorten(title).into_owned()
+    } else {
+        let path = match pane.path.strip_prefix(home) {
+            Some(rest) if !home.is_empty() && (rest.is_empty() || rest.starts_with('/')) => {
+                format!("~{rest}")
+            }
+            _ => pane.path.clone(),
+        };
+        shortener.mode(Mode::Path).shorten(&path).into_owned()
+    };
+    name.replace('#', "##")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tmux::{window_name, Pane};
+    use shorten::abbrev::Abbreviator;
+    use shorten::shortener::Shortener;
+
+    #[test]
+    fn test_window_name() {
+        let shortener = || {
+            let abbreviator = Abbreviator::from_lines(["Projects = proj"].into_iter()).unwrap();
+            Shortener::with_abbreviator(16, abbreviator).unwrap().truncate(true)
+        };
+        let pane = |title: &str, path: &str| Pane {
+            title: title.to_string(),
+            path: path.to_string(),
+            host: "laptop".to_string(),
+        };
+
+        let pane_in_project = pane("laptop", "/home/me/Projects/shorten");
+        assert_eq!(window_name(shortener(), &pane_in_project, "/home/me"), "~/Proj/shorten");
+        let name = window_name(shortener(), &pane("vim #42 notes.md", "/home/me"), "/home/me");
+        assert_eq!(name, "vim ##42 notes.md");
+    }
+}
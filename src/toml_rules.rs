@@ -0,0 +1,285 @@
+//! The `abbrev.toml` rules format, where every rule is a table with explicit
+//! fields:
+//!
+//! ```toml
+//! enclose = ["⟦⟧", "`"]
+//!
+//! [[rule]]
+//! matcher = "Architecture"
+//! replacement = "arch"
+//! escalations = ["A."]
+//! localized = { de = ["Archit.", "A."] }
+//! priority = 10
+//! min_overflow = 5
+//!
+//! [[rule]]
+//! matcher = "Meeting$"
+//! regex = true
+//! flags = "i"
+//! replacement = "Mtg"
+//!
+//! [[rule]]
+//! matcher = '\[.*rescheduled.*\]'
+//! segment = true
+//! flags = "i"
+//!
+//! [[rule]]
+//! matcher = "Section"
+//! replacement = "課"
+//! attach = "previous"
+//! case = "preserve"
+//! group = "japanese"
+//! contexts = ["work"]
+//!
+//! [[rule]]
+//! matcher = "ProjectX"
+//! keep = true
+//! ```
+
+use crate::abbrev::{Normalization, Setting, WordPriority};
+use crate::builtin;
+use crate::case::CasePolicy;
+use crate::enclosing::parse_pairs;
+use crate::error::{ParseErrorKind, Result, ShortenError};
+use crate::fallback;
+use crate::rule::{Matcher, Rule};
+use crate::locale::Locale;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use toml::Spanned;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default)]
+    rule: Vec<TomlRule>,
+    /// Like `@enclose`, as opener/closer pairs
+    #[serde(default)]
+    enclose: Vec<String>,
+    /// Like `@enclose-only`
+    enclose_only: Option<Vec<String>>,
+    /// Like `@case`
+    case: Option<CasePolicy>,
+    /// Like `@normalize`
+    normalize: Option<Normalization>,
+    /// Like `@ignore-diacritics`
+    #[serde(default)]
+    ignore_diacritics: bool,
+    /// Like `@low`
+    #[serde(default)]
+    low: Vec<String>,
+    /// Like `@high`
+    #[serde(default)]
+    high: Vec<String>,
+    /// Like `@fallback-cmd`
+    fallback_cmd: Option<String>,
+    /// Like `@fallback-timeout`
+    fallback_timeout: Option<String>,
+    /// Like `@hook`, the commands by name
+    #[serde(default)]
+    hooks: BTreeMap<String, String>,
+    /// Like `@builtin`
+    #[serde(default)]
+    builtin: Vec<String>,
+}
+
+/// The rules along with the (0-based) line each rule's matcher is on, and the settings.
+pub struct ParsedRules {
+    pub rules: Vec<(usize, Rule)>,
+    pub settings: Vec<Setting>,
+    /// The built-in packs the rules come after
+    pub builtin: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlRule {
+    matcher: Spanned<String>,
+    #[serde(default)]
+    replacement: String,
+    /// Like `arch | A.`, the replacements after the first one
+    #[serde(default)]
+    escalations: Vec<String>,
+    /// Like `dept | de:Abt.`, the levels for other locales
+    #[serde(default)]
+    localized: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    regex: bool,
+    #[serde(default)]
+    flags: String,
+    /// The regex matches whole enclosed segments
+    #[serde(default)]
+    segment: bool,
+    /// The regex matches whole lines
+    #[serde(default)]
+    line: bool,
+    #[serde(default)]
+    priority: i32,
+    /// Like `@iflen>N`
+    #[serde(default)]
+    min_overflow: usize,
+    #[serde(default)]
+    attach: Attach,
+    case: Option<CasePolicy>,
+    group: Option<String>,
+    #[serde(default)]
+    contexts: Vec<String>,
+    #[serde(default)]
+    keep: bool,
+    /// Like `!except`
+    #[serde(default)]
+    except: bool,
+    /// Like the `~` of `Session~`
+    #[serde(default)]
+    inflect: bool,
+    /// Like `# desc:`
+    description: Option<String>,
+    #[serde(default)]
+    deprecated: bool,
+}
+
+#[derive(Deserialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Attach {
+    #[default]
+    None,
+    Previous,
+    Next,
+    /// Both the previous and the next word
+    Both,
+}
+
+pub fn parse(text: &str) -> Result<ParsedRules> {
+    let file: RulesFile = toml::from_str(text).map_err(|err| {
+        let line = err.span().map_or(0, |span| line_of(text, span.start));
+        ShortenError::from(ParseErrorKind::Toml(err.message().to_string())).at_line(line)
+    })?;
+    let rules = file
+        .rule
+        .into_iter()
+        .map(|rule| {
+            let line = line_of(text, rule.matcher.span().start);
+            let mut localized = BTreeMap::new();
+            for (tag, levels) in rule.localized {
+                let Some(locale) = Locale::parse(&tag) else {
+                    let err = ShortenError::from(ParseErrorKind::InvalidLocale(tag));
+                    return Err(err.at_line(line));
+                };
+                localized.insert(locale.to_string(), levels);
+            }
+            let matcher = rule.matcher.into_inner();
+            let matcher = if rule.line {
+                Matcher::Line { pattern: matcher, flags: rule.flags }
+            } else if rule.segment {
+                Matcher::Segment { pattern: matcher, flags: rule.flags }
+            } else if rule.regex {
+                Matcher::Regex { pattern: matcher, flags: rule.flags }
+            } else {
+                Matcher::Exact(matcher)
+            };
+            let rule = Rule {
+                matcher,
+                replacement: rule.replacement,
+                escalations: rule.escalations,
+                localized,
+                attach_to_previous: matches!(rule.attach, Attach::Previous | Attach::Both),
+                attach_to_next: matches!(rule.attach, Attach::Next | Attach::Both),
+                priority: rule.priority,
+                min_overflow: rule.min_overflow,
+                case: rule.case,
+                group: rule.group,
+                contexts: rule.contexts,
+                keep: rule.keep,
+                except: rule.except,
+                inflect: rule.inflect,
+                description: rule.description,
+                deprecated: rule.deprecated,
+            };
+            Ok((line, rule))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut settings = Vec::new();
+    if let Some(pairs) = file.enclose_only {
+        settings.push(Setting::EncloseOnly(parse_pairs(&pairs.join(" "))?));
+    }
+    if !file.enclose.is_empty() {
+        settings.push(Setting::Enclose(parse_pairs(&file.enclose.join(" "))?));
+    }
+    settings.extend(file.case.map(Setting::Case));
+    settings.extend(file.normalize.map(Setting::Normalization));
+    if file.ignore_diacritics {
+        settings.push(Setting::IgnoreDiacritics);
+    }
+    for (priority, words) in [(WordPriority::Low, file.low), (WordPriority::High, file.high)] {
+        if !words.is_empty() {
+            settings.push(Setting::WordPriority(priority, words));
+        }
+    }
+    settings.extend(file.fallback_cmd.map(Setting::FallbackCommand));
+    for (name, command) in file.hooks {
+        settings.push(Setting::hook(name, command)?);
+    }
+    if let Some(timeout) = file.fallback_timeout {
+        let duration = fallback::parse_timeout(&timeout);
+        settings.push(Setting::FallbackTimeout(
+            duration.ok_or(ParseErrorKind::InvalidTimeout(timeout))?,
+        ));
+    }
+    let builtin = builtin::parse_names(&file.builtin.join(","))?;
+    Ok(ParsedRules { rules, settings, builtin })
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::{Abbreviator, Setting};
+    use crate::case::CasePolicy;
+    use crate::toml_rules::parse;
+
+    #[test]
+    fn test_toml_rules() {
+        let parsed = parse(
+            r#"
+                enclose = ["«»"]
+                case = "mirror"
+                [[rule]]
+                matcher = "Architecture"
+                replacement = "arch"
+
+                [[rule]]
+                matcher = "^meet"
+                regex = true
+                flags = "i"
+                replacement = "mtg"
+                case = "preserve"
+
+                [[rule]]
+                matcher = "Section"
+                replacement = "課"
+                attach = "previous"
+                priority = 3
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(
+            &parsed.settings[..],
+            [Setting::Enclose(pairs), Setting::Case(CasePolicy::Mirror)] if pairs == &[('«', '»')]
+        ));
+        let rules = parsed.rules;
+        assert_eq!(rules.iter().map(|(line, _)| *line).collect::<Vec<_>>(), vec![4, 8, 15]);
+        let abbreviator = Abbreviator::from_rules(rules.into_iter().map(|(_, rule)| rule)).unwrap();
+
+        let abbreviate = |text| abbreviator.abbreviate(text).map(|abbrev| (abbrev.text, abbrev.attach_to_previous));
+        assert_eq!(abbreviate("Architecture"), Some(("Arch".into(), false)));
+        assert_eq!(abbreviate("Meeting"), Some(("mtg".into(), false)));
+        assert_eq!(abbreviate("section"), Some(("課".into(), true)));
+        let Err(err) = parse("[[rule]]\nreplacement = \"x\"") else {
+            panic!("a rule without a matcher is rejected");
+        };
+        assert!(err.to_string().starts_with("line 1: missing field `matcher`"), "{err}");
+    }
+}
@@ -0,0 +1,97 @@
+//! URLs for the URL mode: "https://www.example.com/docs/getting-started?tab=2"
+//! loses its scheme, then its "www.", then its query ("?…") until it fits, and
+//! only then are its path segments abbreviated. The host is left intact, so
+//! that the site stays recognizable.
+
+use crate::shortener::Shortened;
+use crate::width;
+use std::borrow::Cow;
+
+/// The parts of a URL, in order, without the scheme.
+struct Url<'a> {
+    host: &'a str,
+    path: &'a str,
+    /// The query (with its `?`) and the fragment (with its `#`)
+    rest: &'a str,
+}
+
+/// Shortens the URL to `max_length`, abbreviating its path with `abbreviate`
+/// when dropping parts is not enough. Texts that are not URLs (without a
+/// scheme nor a leading "www.") are abbreviated as a whole.
+pub(crate) fn shorten_url<'a>(
+    url: &'a str,
+    max_length: usize,
+    abbreviate: impl Fn(&'a str, usize) -> Shortened<'a>,
+) -> Shortened<'a> {
+    let Some(parts) = parse(url).filter(|_| width::length(url) > max_length) else {
+        return abbreviate(url, max_length);
+    };
+    let offset = |part: &str| part.as_ptr() as usize - url.as_ptr() as usize;
+    let mut host = parts.host;
+    let mut rest = Cow::Borrowed(parts.rest);
+    let length = |host: &str, rest: &str| {
+        width::length(host) + width::length(parts.path) + width::length(rest)
+    };
+    if length(host, &rest) > max_length {
+        host = strip_prefix(host, "www.").unwrap_or(host);
+    }
+    if length(host, &rest) > max_length {
+        rest = Cow::Borrowed(if parts.rest.starts_with('?') { "?…" } else { "" });
+    }
+    let room = max_length.saturating_sub(width::length(host) + width::length(&rest));
+    let path = match width::length(parts.path) > room {
+        true => abbreviate(parts.path, room),
+        false => Shortened::unchanged(parts.path, parts.path),
+    };
+    Shortened::concat([
+        (offset(host), Shortened::replacing(host.len(), Cow::Borrowed(host))),
+        (offset(parts.path), path),
+        (offset(parts.rest), Shortened::replacing(parts.rest.len(), rest)),
+    ])
+}
+
+/// Splits the URL, or returns `None` when the text is not one.
+fn parse(text: &str) -> Option<Url<'_>> {
+    if text.contains(char::is_whitespace) {
+        return None;
+    }
+    let without_scheme = text.split_once("://").and_then(|(scheme, rest)| {
+        let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        valid.then_some(rest)
+    });
+    let rest = without_scheme.or_else(|| strip_prefix(text, "www.").map(|_| text))?;
+    let host_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let (host, rest) = rest.split_at(host_end);
+    let path_end = rest.find(['?', '#']).unwrap_or(rest.len());
+    let (path, rest) = rest.split_at(path_end);
+    (!host.is_empty()).then_some(Url { host, path, rest })
+}
+
+/// The text without its prefix in any (ASCII) case.
+fn strip_prefix<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let matches = text.get(..prefix.len()).is_some_and(|start| start.eq_ignore_ascii_case(prefix));
+    matches.then(|| &text[prefix.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::abbrev::Abbreviator;
+    use crate::shortener::{Mode, Shortener};
+
+    #[test]
+    fn test_shorten_url() {
+        let rules = ["Documentation = docs", "Getting = get", "Started = start"];
+        let abbreviator = Abbreviator::from_lines(rules.into_iter()).unwrap();
+        let shortener = Shortener::with_abbreviator(60, abbreviator).unwrap().mode(Mode::Url);
+        let shorten =
+            |url: &str, max_length| shortener.shorten_to(url, max_length).text.into_owned();
+        let url = "https://www.example.com/documentation/getting-started?tab=2#install";
+        assert_eq!(shorten(url, 70), url);
+        assert_eq!(shorten(url, 60), "www.example.com/documentation/getting-started?tab=2#install");
+        assert_eq!(shorten(url, 50), "example.com/documentation/getting-started?…");
+        assert_eq!(shorten(url, 35), "example.com/docs/get-start?…");
+        assert_eq!(shorten("http://example.com/#Documentation", 20), "example.com/");
+        assert_eq!(shorten("Documentation Started", 15), "Docs Start");
+    }
+}
@@ -0,0 +1,54 @@
+//! The JS API of the WebAssembly build, enabled by the `wasm` feature:
+//!
+//! ```js
+//! const shortener = new Shortener(20, "Architecture = arch\nSection = <+課");
+//! shortener.shorten("Architecture Section Learning"); // "Arch課 Learning"
+//! ```
+
+use crate::abbrev::Abbreviator;
+use crate::shortener::Shortener;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen(js_name = Shortener)]
+pub struct WasmShortener {
+    shortener: Shortener,
+}
+
+#[wasm_bindgen(js_class = Shortener)]
+impl WasmShortener {
+    /// Creates a shortener for lines of at most `maxLength` characters, with
+    /// rules in the `abbrev.lst` format.
+    #[wasm_bindgen(constructor)]
+    pub fn new(max_length: usize, rules: &str) -> Result<WasmShortener, JsError> {
+        let shortener = Abbreviator::from_lines(rules.lines())
+            .and_then(|abbreviator| Shortener::with_abbreviator(max_length, abbreviator))
+            .map_err(|err| JsError::new(&format!("{err:#}")))?;
+        Ok(WasmShortener { shortener })
+    }
+
+    pub fn shorten(&self, text: &str) -> String {
+        self.shortener.shorten(text).into_owned()
+    }
+
+    /// Shortens the text to `maxLength` instead of the shortener's length.
+    #[wasm_bindgen(js_name = shortenTo)]
+    pub fn shorten_to(&self, text: &str, max_length: usize) -> String {
+        self.shortener.shorten_to(text, max_length).text.into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wasm::WasmShortener;
+
+    #[test]
+    fn test_shortener() {
+        let Ok(shortener) = WasmShortener::new(20, "Architecture = arch\nSection = <+課") else {
+            panic!("valid rules rejected");
+        };
+        assert_eq!(shortener.shorten("Architecture Section Learning"), "Arch課 Learning");
+        assert_eq!(shortener.shorten("Short"), "Short");
+        let text = "Architecture Section Learning";
+        assert_eq!(shortener.shorten_to(text, 40), text);
+    }
+}
@@ -0,0 +1,123 @@
+//! Length accounting and truncation in the columns text takes in a terminal,
+//! never splitting grapheme clusters, so emoji ZWJ sequences and combining
+//! marks are always kept whole.
+//!
+//! A grapheme cluster takes two columns when it starts with a wide (CJK,
+//! fullwidth) character or an emoji, none when it is only made of zero-width
+//! characters (marks, joiners, variation selectors, controls), and one
+//! otherwise: "café" is 4 columns even with a combining accent, and a family
+//! emoji joined by ZWJs is 2.
+
+use unicode_normalization::char::is_combining_mark;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Iterates over the grapheme clusters of the text.
+pub fn graphemes(text: &str) -> impl DoubleEndedIterator<Item = &str> {
+    text.graphemes(true)
+}
+
+/// The width of the text, in columns.
+pub fn length(text: &str) -> usize {
+    graphemes(text).map(grapheme_width).sum()
+}
+
+/// The longest prefix of the text that is at most `max_length` wide.
+pub fn truncate(text: &str, max_length: usize) -> &str {
+    let mut width = 0;
+    for (index, grapheme) in text.grapheme_indices(true) {
+        width += grapheme_width(grapheme);
+        if width > max_length {
+            return &text[..index];
+        }
+    }
+    text
+}
+
+/// The longest suffix of the text that is at most `max_length` wide.
+pub fn truncate_start(text: &str, max_length: usize) -> &str {
+    let mut width = 0;
+    for (index, grapheme) in text.grapheme_indices(true).rev() {
+        width += grapheme_width(grapheme);
+        if width > max_length {
+            return &text[index + grapheme.len()..];
+        }
+    }
+    text
+}
+
+/// The columns a grapheme cluster takes.
+fn grapheme_width(grapheme: &str) -> usize {
+    let mut chars = grapheme.chars().skip_while(|&c| is_zero_width(c));
+    match chars.next() {
+        None => 0,
+        Some(c) if is_wide(c) => 2,
+        // Text-style symbols become emoji with VS16, like "❤️"
+        Some(_) if grapheme.contains('\u{fe0f}') => 2,
+        Some(_) => 1,
+    }
+}
+
+fn is_zero_width(c: char) -> bool {
+    is_combining_mark(c)
+        || c.is_control()
+        || matches!(c,
+            '\u{ad}' | '\u{200b}'..='\u{200f}' | '\u{2028}'..='\u{202e}' | '\u{2060}'..='\u{2064}'
+            | '\u{fe00}'..='\u{fe0f}' | '\u{feff}' | '\u{e0000}'..='\u{e0fff}')
+}
+
+/// The East Asian wide and fullwidth characters and the emoji shown as such
+/// by default, in a few ranges.
+fn is_wide(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{115f}' | '\u{231a}'..='\u{231b}' | '\u{2329}'..='\u{232a}'
+        | '\u{23e9}'..='\u{23ec}' | '\u{23f0}' | '\u{23f3}' | '\u{25fd}'..='\u{25fe}'
+        | '\u{2614}'..='\u{2615}' | '\u{2648}'..='\u{2653}' | '\u{267f}' | '\u{2693}' | '\u{26a1}'
+        | '\u{26aa}'..='\u{26ab}' | '\u{26bd}'..='\u{26be}' | '\u{26c4}'..='\u{26c5}' | '\u{26ce}'
+        | '\u{26d4}' | '\u{26ea}' | '\u{26f2}'..='\u{26f3}' | '\u{26f5}' | '\u{26fa}' | '\u{26fd}'
+        | '\u{2705}' | '\u{270a}'..='\u{270b}' | '\u{2728}' | '\u{274c}' | '\u{274e}'
+        | '\u{2753}'..='\u{2755}' | '\u{2757}' | '\u{2795}'..='\u{2797}' | '\u{27b0}' | '\u{27bf}'
+        | '\u{2b1b}'..='\u{2b1c}' | '\u{2b50}' | '\u{2b55}' | '\u{2e80}'..='\u{303e}'
+        | '\u{3041}'..='\u{33ff}' | '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}'
+        | '\u{a000}'..='\u{a4cf}' | '\u{a960}'..='\u{a97f}' | '\u{ac00}'..='\u{d7a3}'
+        | '\u{f900}'..='\u{faff}' | '\u{fe10}'..='\u{fe19}' | '\u{fe30}'..='\u{fe6f}'
+        | '\u{ff00}'..='\u{ff60}' | '\u{ffe0}'..='\u{ffe6}' | '\u{1f004}' | '\u{1f0cf}'
+        | '\u{1f18e}' | '\u{1f191}'..='\u{1f19a}' | '\u{1f1e6}'..='\u{1f1ff}'
+        | '\u{1f200}'..='\u{1f251}' | '\u{1f300}'..='\u{1f320}' | '\u{1f32d}'..='\u{1f335}'
+        | '\u{1f337}'..='\u{1f37c}' | '\u{1f37e}'..='\u{1f393}' | '\u{1f3a0}'..='\u{1f3ca}'
+        | '\u{1f3cf}'..='\u{1f3d3}' | '\u{1f3e0}'..='\u{1f3f0}' | '\u{1f3f4}'
+        | '\u{1f3f8}'..='\u{1f43e}' | '\u{1f440}' | '\u{1f442}'..='\u{1f4fc}'
+        | '\u{1f4ff}'..='\u{1f53d}' | '\u{1f54b}'..='\u{1f54e}' | '\u{1f550}'..='\u{1f567}'
+        | '\u{1f57a}' | '\u{1f595}'..='\u{1f596}' | '\u{1f5a4}' | '\u{1f5fb}'..='\u{1f64f}'
+        | '\u{1f680}'..='\u{1f6c5}' | '\u{1f6cc}' | '\u{1f6d0}'..='\u{1f6d2}'
+        | '\u{1f6d5}'..='\u{1f6d7}' | '\u{1f6dc}'..='\u{1f6df}' | '\u{1f6eb}'..='\u{1f6ec}'
+        | '\u{1f6f4}'..='\u{1f6fc}' | '\u{1f7e0}'..='\u{1f7eb}' | '\u{1f7f0}'
+        | '\u{1f90c}'..='\u{1f93a}' | '\u{1f93c}'..='\u{1f945}' | '\u{1f947}'..='\u{1f9ff}'
+        | '\u{1fa70}'..='\u{1faff}' | '\u{20000}'..='\u{2fffd}' | '\u{30000}'..='\u{3fffd}')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::width::{length, truncate, truncate_start};
+
+    #[test]
+    fn test_graphemes() {
+        let family = "👨\u{200d}👩\u{200d}👧";
+        let text = format!("cafe\u{301} {family}!");
+        assert_eq!(length(&text), 8);
+        assert_eq!(truncate(&text, 4), "cafe\u{301}");
+        assert_eq!(truncate(&text, 6), "cafe\u{301} ");
+        assert_eq!(truncate(&text, 7), format!("cafe\u{301} {family}"));
+        assert_eq!(truncate(&text, 100), text);
+        assert_eq!(truncate_start(&text, 2), "!");
+        assert_eq!(truncate_start(&text, 3), format!("{family}!"));
+    }
+
+    #[test]
+    fn test_widths() {
+        assert_eq!(length("a\u{200b}b\u{feff}"), 2);
+        assert_eq!(length("❤\u{fe0f} ok"), 5);
+        assert_eq!(length("定例会議"), 8);
+        assert_eq!(length("ＡＢ"), 4);
+        assert_eq!(length("🇯🇵"), 2);
+    }
+}